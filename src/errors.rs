@@ -1,3 +1,4 @@
+use std::net::{IpAddr, SocketAddr};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -12,14 +13,22 @@ pub enum ScannerError {
     CantFindGateway,
     #[error("Cannot find an IP address for the gateway.")]
     CantFindGatewayIp,
+    #[error("Cannot resolve the MAC address of {0}.")]
+    CantResolveMac(IpAddr),
+    #[error("Cannot find a route to {0}.")]
+    CantFindRoute(IpAddr),
     #[error("Cannot create an Ethernet packet.")]
     CantCreateEthernetPacket,
     #[error("Cannot create an IPv4 packet.")]
     CantCreateIpv4Packet,
+    #[error("Cannot create an IPv6 packet.")]
+    CantCreateIpv6Packet,
     #[error("Cannot create a TCP packet.")]
     CantCreateTcpPacket,
     #[error("Cannot create an ICMP packet.")]
     CantCreateIcmpPacket,
+    #[error("Cannot create a UDP packet.")]
+    CantCreateUdpPacket,
     #[error("This IP protocol version is not supported.")]
     UnsupportedIpVersion,
     #[error("Unexpected TCP flags set.")]
@@ -28,8 +37,51 @@ pub enum ScannerError {
     UnexpectedIcmpResponse,
     #[error("Unexpected Protocol response.")]
     UnexpectedProtocolResponse,
+    #[error("Permission denied to use a raw socket, try running as root.")]
+    RawSocketPermissionDenied,
+    #[error("Sending on a raw socket failed: {0}.")]
+    RawSocketSendFailed(String),
+    #[cfg(feature = "pcap")]
+    #[error("Opening a pcap capture failed: {0}.")]
+    PcapFailed(String),
     #[error("Could not write results to a file.")]
     CouldNotWriteResults,
+    #[error("Invalid port specification: '{0}'.")]
+    InvalidPortSpec(String),
+    #[error("Invalid source port range {0}-{1}, it must lie within 1-65535 and not be empty.")]
+    InvalidSourcePortRange(u16, u16),
+    #[error("Invalid delay of {0}-{1} milliseconds, the first bound must not exceed the second.")]
+    InvalidDelay(u64, u64),
+    #[error("Invalid tarpit threshold {0}, it must be greater than 0 and at most 1.")]
+    InvalidTarpitThreshold(f64),
+    #[error("No port numbers to scan.")]
+    NoPortNumbers,
+    #[error("Invalid IP protocol number {0}, it must be at most 255.")]
+    InvalidProtocolNumber(u16),
+    #[error("Cannot resolve the host name '{0}'.")]
+    CantResolveHost(String),
+    #[error("Invalid subnet: '{0}'.")]
+    InvalidSubnet(String),
+    #[error("Cannot read the targets file '{0}'.")]
+    CantReadTargets(String),
+    #[error("Cannot read the previous results '{0}'.")]
+    CantReadResults(String),
+    #[error("Too many decoys, at most {0} are supported.")]
+    TooManyDecoys(usize),
+    #[error("No zombie host configured for the idle scan.")]
+    MissingZombie,
+    #[error("The zombie host {0} does not respond.")]
+    ZombieNotResponding(IpAddr),
+    #[error("The IP identification of the zombie increased by {0}, it is not idle or has no global counter.")]
+    UnsuitableZombie(u16),
+    #[error("A dry run of {0} is not supported, it would send packets anyway.")]
+    DryRunUnsupported(&'static str),
+    #[error("Cannot connect to the SOCKS5 proxy {0}.")]
+    ProxyUnreachable(SocketAddr),
+    #[error("The SOCKS5 proxy {0} requires authentication, which is not supported.")]
+    ProxyAuthentication(SocketAddr),
+    #[error("The SOCKS5 proxy {0} refused to connect to the target.")]
+    ProxyRefused(SocketAddr),
 }
 
 #[derive(Error, Debug)]
@@ -1,7 +1,10 @@
 use super::{icmp_flood::icmp_flood, udp_flood::udp_flood};
 use crate::{
     flooder::tcp_flood::tcp_flood,
-    networking::{interface::Interface, socket_iterator::SocketIterator},
+    networking::{
+        interface::{Interface, Routes},
+        socket_iterator::SocketIterator,
+    },
 };
 use futures::{stream::FuturesUnordered, StreamExt};
 use log::info;
@@ -34,6 +37,7 @@ impl Flooder {
         let total_packets = total_ports * number_of_packets;
 
         let sockets = SocketIterator::new(ip_addresses, port_numbers);
+        let routes = Routes::resolve(interface, src_ip, ip_addresses);
 
         let flood_method = match method {
             FloodMethod::Tcp => tcp_flood,
@@ -51,16 +55,20 @@ impl Flooder {
                 rng.gen_range(1..=65535)
             };
 
+            let route = routes.source(socket.ip());
             futures.push(tokio::task::spawn_blocking(move || {
-                flood_method(
-                    interface,
-                    src_ip,
-                    origin_port,
-                    socket.ip(),
-                    socket.port(),
-                    number_of_packets,
-                )
-                .map(|scan| (socket, scan))
+                route
+                    .and_then(|(src_ip, interface)| {
+                        flood_method(
+                            interface,
+                            src_ip,
+                            origin_port,
+                            socket.ip(),
+                            socket.port(),
+                            number_of_packets,
+                        )
+                    })
+                    .map(|scan| (socket, scan))
             }))
         });
 
@@ -1,9 +1,13 @@
 use crate::{
     errors::ScannerError,
-    networking::{icmp::Icmp, interface::Interface, osi_layers::DatalinkLayer},
+    networking::{
+        arp::Arp, icmp::Icmp, interface::Interface, osi_layers::DatalinkLayer, probe::ProbeOptions,
+    },
+    scanner::engine::DEFAULT_TIMEOUT,
 };
 use anyhow::Result;
 use pnet::packet::ethernet::EtherTypes;
+use rand::Rng;
 use std::net::IpAddr;
 
 /// Sends a `number_of_packets` amount of ICMO echo requests to the specified destination.
@@ -29,11 +33,13 @@ pub fn icmp_flood(
         _ => Err(ScannerError::UnsupportedIpVersion)?,
     };
 
-    let dest_mac = interface.gateway.mac;
+    let dest_mac = Arp::next_hop_mac(interface, ipv4_dest, DEFAULT_TIMEOUT)?;
 
     let iface = interface.convert_interface()?;
 
-    let packet = Icmp::build_icmp_packet(ipv4_src, ipv4_dest);
+    let identifier = rand::thread_rng().gen();
+    let packet =
+        Icmp::build_icmp_packet(ipv4_src, ipv4_dest, identifier, 0, &ProbeOptions::default())?;
 
     DatalinkLayer::send_flood(
         iface,
@@ -1,6 +1,9 @@
 use crate::{
     errors::ScannerError,
-    networking::{interface::Interface, osi_layers::DatalinkLayer, tcp::Tcp},
+    networking::{
+        arp::Arp, interface::Interface, osi_layers::DatalinkLayer, probe::ProbeOptions, tcp::Tcp,
+    },
+    scanner::engine::DEFAULT_TIMEOUT,
 };
 use anyhow::Result;
 use pnet::packet::{ethernet::EtherTypes, tcp::TcpFlags};
@@ -31,11 +34,20 @@ pub fn tcp_flood(
         _ => Err(ScannerError::UnsupportedIpVersion)?,
     };
 
-    let dest_mac = interface.gateway.mac;
+    let dest_mac = Arp::next_hop_mac(interface, ipv4_dest, DEFAULT_TIMEOUT)?;
 
     let iface = interface.convert_interface()?;
 
-    let packet = Tcp::build_tcp_packet(ipv4_src, src_port, ipv4_dest, dest_port, TcpFlags::SYN);
+    let (packet, _) = Tcp::build_tcp_packet(
+        ipv4_src,
+        src_port,
+        ipv4_dest,
+        dest_port,
+        TcpFlags::SYN,
+        &[],
+        &[],
+        &ProbeOptions::default(),
+    )?;
 
     DatalinkLayer::send_flood(
         iface,
@@ -1,6 +1,13 @@
 use crate::{
     errors::ScannerError,
-    networking::{interface::Interface, osi_layers::DatalinkLayer, udp::Udp},
+    networking::{
+        arp::Arp,
+        interface::Interface,
+        osi_layers::DatalinkLayer,
+        probe::ProbeOptions,
+        udp::{Udp, UDP_PAYLOAD},
+    },
+    scanner::engine::DEFAULT_TIMEOUT,
 };
 use anyhow::Result;
 use pnet::packet::ethernet::EtherTypes;
@@ -29,11 +36,18 @@ pub fn udp_flood(
         _ => Err(ScannerError::UnsupportedIpVersion)?,
     };
 
-    let dest_mac = interface.gateway.mac;
+    let dest_mac = Arp::next_hop_mac(interface, ipv4_dest, DEFAULT_TIMEOUT)?;
 
     let iface = interface.convert_interface()?;
 
-    let packet = Udp::build_udp_packet(ipv4_src, src_port, ipv4_dest, dest_port);
+    let packet = Udp::build_udp_packet(
+        ipv4_src,
+        src_port,
+        ipv4_dest,
+        dest_port,
+        &UDP_PAYLOAD,
+        &ProbeOptions::default(),
+    )?;
 
     DatalinkLayer::send_flood(
         iface,
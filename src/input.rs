@@ -1,17 +1,231 @@
+use crate::{
+    errors::ScannerError,
+    networking::{
+        matcher::DEFAULT_RECEIVE_WORKERS,
+        probe::{ProbeOptions, MAX_DECOYS},
+    },
+    output::read_port_results,
+    ports::parse_port_spec,
+    scanner::{
+        budget::Budget,
+        engine::{DEFAULT_CONCURRENCY, DEFAULT_RANDOMIZE_ORDER, DEFAULT_TIMEOUT},
+        results::ScanResult,
+        tarpit::DEFAULT_TARPIT_THRESHOLD,
+        tcp_scan::DEFAULT_TCP_PING_PORTS,
+        traceroute::DEFAULT_MAX_HOPS,
+    },
+};
 use anyhow::Result;
+use netdev::ip::Ipv4Net;
 use serde::Deserialize;
-use std::{fs, net::IpAddr};
-use subnetwork::Ipv4Pool;
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs},
+    time::{Duration, Instant},
+};
 
 #[derive(Deserialize, Debug)]
 pub struct Input {
-    pub src_ip: IpAddr,
+    pub src_ip: Option<IpAddr>,
+    pub interface: Option<String>,
     pub src_port: u16,
+    pub source_port_range: Option<(u16, u16)>,
     pub port_numbers: Vec<String>,
     pub ip_addresses: Vec<String>,
-    pub timeout: u64,
+    pub targets_file: Option<String>,
+    pub previous_results: Option<String>,
+    pub timeout: Option<u64>,
     pub number_of_packets: usize,
     pub should_randomize_ports: bool,
+    pub ttl: Option<u8>,
+    pub tos: Option<u8>,
+    pub window: Option<u16>,
+    pub urgent_pointer: Option<u16>,
+    pub retries: Option<u8>,
+    pub tcp_options: Option<bool>,
+    pub mss: Option<u16>,
+    pub mtu: Option<usize>,
+    pub dont_fragment: Option<bool>,
+    pub concurrency: Option<usize>,
+    pub randomize_order: Option<bool>,
+    pub max_packets: Option<u64>,
+    pub max_bytes: Option<u64>,
+    pub max_duration: Option<u64>,
+    pub tarpit_threshold: Option<f64>,
+    pub receive_workers: Option<usize>,
+    pub exclude_hosts: Option<Vec<String>>,
+    pub exclude_ports: Option<Vec<String>>,
+    pub rate: Option<u32>,
+    pub delay: Option<(u64, u64)>,
+    pub max_hops: Option<u8>,
+    pub decoys: Option<Vec<IpAddr>>,
+    pub banner_probe: Option<String>,
+    pub bad_checksum: Option<bool>,
+    pub tcp_payload: Option<String>,
+    pub zombie: Option<SocketAddr>,
+    pub proxy: Option<SocketAddr>,
+    pub reset_connections: Option<bool>,
+    pub tcp_ping_ports: Option<Vec<u16>>,
+}
+
+impl Input {
+    /// Returns the probe options given in the config, falling back to the defaults.
+    ///
+    /// May fail if more than `MAX_DECOYS` decoys are given.
+    pub fn probe_options(&self) -> Result<ProbeOptions> {
+        let defaults = ProbeOptions::default();
+
+        let mut decoys = defaults.decoys;
+        let given_decoys = self.decoys.as_deref().unwrap_or_default();
+        if given_decoys.len() > MAX_DECOYS {
+            Err(ScannerError::TooManyDecoys(MAX_DECOYS))?;
+        }
+        for (decoy, given_decoy) in decoys.iter_mut().zip(given_decoys) {
+            *decoy = Some(*given_decoy);
+        }
+
+        if let Some((first, last)) = self.source_port_range {
+            if first == 0 || first > last {
+                Err(ScannerError::InvalidSourcePortRange(first, last))?;
+            }
+        }
+
+        if let Some((first, last)) = self.delay {
+            if first > last {
+                Err(ScannerError::InvalidDelay(first, last))?;
+            }
+        }
+
+        Ok(ProbeOptions {
+            ttl: self.ttl.unwrap_or(defaults.ttl),
+            tos: self.tos.unwrap_or(defaults.tos),
+            window: self.window.unwrap_or(defaults.window),
+            urgent_pointer: self.urgent_pointer.unwrap_or(defaults.urgent_pointer),
+            retries: self.retries.unwrap_or(defaults.retries),
+            tcp_options: self.tcp_options.unwrap_or(defaults.tcp_options),
+            mss: self.mss.or(defaults.mss),
+            mtu: self.mtu.or(defaults.mtu),
+            dont_fragment: self.dont_fragment.unwrap_or(defaults.dont_fragment),
+            decoys,
+            bad_checksum: self.bad_checksum.unwrap_or(defaults.bad_checksum),
+            tcp_payload: self
+                .tcp_payload
+                .clone()
+                .map_or(defaults.tcp_payload, |payload| payload.into_bytes().into()),
+            source_port_range: self.source_port_range.or(defaults.source_port_range),
+            delay: self
+                .delay
+                .map(|(first, last)| (Duration::from_millis(first), Duration::from_millis(last)))
+                .or(defaults.delay),
+            zombie: self.zombie.or(defaults.zombie),
+            proxy: self.proxy.or(defaults.proxy),
+            reset_connections: self.reset_connections.unwrap_or(defaults.reset_connections),
+            dry_run: defaults.dry_run,
+        })
+    }
+
+    /// Returns the time to wait for responses, falling back to the default.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TIMEOUT)
+    }
+
+    /// Returns the maximum number of hops of a traceroute, falling back to the default.
+    pub fn max_hops(&self) -> u8 {
+        self.max_hops.unwrap_or(DEFAULT_MAX_HOPS)
+    }
+
+    /// Returns the ports probed by a TCP ping, falling back to the defaults.
+    pub fn tcp_ping_ports(&self) -> Vec<u16> {
+        self.tcp_ping_ports
+            .clone()
+            .unwrap_or_else(|| DEFAULT_TCP_PING_PORTS.to_vec())
+    }
+
+    /// Returns the request sent to open ports before their banner is read, if given.
+    pub fn banner_probe(&self) -> Option<Vec<u8>> {
+        self.banner_probe
+            .as_ref()
+            .map(|probe| probe.as_bytes().to_vec())
+    }
+
+    /// Returns the number of sockets to scan at the same time, falling back to the default.
+    pub fn concurrency(&self) -> usize {
+        self.concurrency.unwrap_or(DEFAULT_CONCURRENCY)
+    }
+
+    /// Returns the limits on the traffic and duration of port scans. Limits that are not given are
+    /// not enforced. The deadline counts from now, so it should be called once at the start.
+    pub fn budget(&self) -> Budget {
+        Budget {
+            packets: self.max_packets,
+            bytes: self.max_bytes,
+            deadline: self
+                .max_duration
+                .map(|seconds| Instant::now() + Duration::from_secs(seconds)),
+        }
+    }
+
+    /// Returns the fraction of open ports above which a host is suspected to be a tarpit,
+    /// falling back to the default. May fail if it doesn't lie within 0 and 1.
+    pub fn tarpit_threshold(&self) -> Result<f64> {
+        match self.tarpit_threshold {
+            // Written so that NaN, which TOML accepts as `nan`, is rejected as well.
+            Some(threshold) if !(threshold > 0.0 && threshold <= 1.0) => {
+                Err(ScannerError::InvalidTarpitThreshold(threshold))?
+            }
+            Some(threshold) => Ok(threshold),
+            None => Ok(DEFAULT_TARPIT_THRESHOLD),
+        }
+    }
+
+    /// Returns whether ports are probed in a random order, falling back to the default.
+    pub fn randomize_order(&self) -> bool {
+        self.randomize_order.unwrap_or(DEFAULT_RANDOMIZE_ORDER)
+    }
+
+    /// Returns the hosts that are never scanned, see `parse_excluded_hosts`.
+    pub fn exclude_hosts(&self) -> Result<Vec<Ipv4Net>> {
+        parse_excluded_hosts(self.exclude_hosts.as_deref().unwrap_or_default())
+    }
+
+    /// Returns the ports that are never scanned, given like `port_numbers`.
+    pub fn exclude_ports(&self) -> Result<Vec<u16>> {
+        match &self.exclude_ports {
+            Some(ports) => parse_port_numbers(ports.clone()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns the targets listed in the targets file and warnings about its malformed lines,
+    /// see `read_targets`. Returns no targets if no file is given.
+    ///
+    /// May fail if the file can't be opened.
+    pub fn file_targets(&self) -> Result<(Vec<IpAddr>, Vec<String>)> {
+        let Some(path) = &self.targets_file else {
+            return Ok((Vec::new(), Vec::new()));
+        };
+        let file = File::open(path).map_err(|_| ScannerError::CantReadTargets(path.clone()))?;
+        Ok(read_targets(BufReader::new(file)))
+    }
+
+    /// Reads the results of the earlier scan to compare with, see `read_port_results`.
+    ///
+    /// Returns `None` if no earlier results are given. May fail if the file can't be opened.
+    pub fn previous_results(&self) -> Result<Option<(Vec<ScanResult>, Vec<String>)>> {
+        let Some(path) = &self.previous_results else {
+            return Ok(None);
+        };
+        let file = File::open(path).map_err(|_| ScannerError::CantReadResults(path.clone()))?;
+        Ok(Some(read_port_results(BufReader::new(file))))
+    }
+
+    /// Returns the number of threads that match captured packets, falling back to the default.
+    pub fn receive_workers(&self) -> usize {
+        self.receive_workers.unwrap_or(DEFAULT_RECEIVE_WORKERS)
+    }
 }
 
 pub fn load_config(path: &str) -> Result<Input> {
@@ -21,30 +235,325 @@ pub fn load_config(path: &str) -> Result<Input> {
     Ok(input)
 }
 
+/// Parses the port numbers given in the config.
+///
+/// Each entry may be a single port, a range or a comma-separated list of both.
 pub fn parse_port_numbers(ports: Vec<String>) -> Result<Vec<u16>> {
-    let mut port_numbers = Vec::with_capacity(ports.len());
-    for port in ports {
-        if port.contains('-') {
-            let range = port.split('-').collect::<Vec<&str>>();
-            let start = range[0].parse::<u16>()?;
-            let end = range[1].parse::<u16>()?;
-            port_numbers.extend(start..=end);
-        } else {
-            port_numbers.push(port.parse()?);
-        }
-    }
-    Ok(port_numbers)
+    parse_port_spec(&ports.join(","))
 }
 
+/// Parses the targets given in the config, see `parse_target`.
 pub fn parse_ip_addresses(ips: Vec<String>) -> Result<Vec<IpAddr>> {
     let mut ip_addresses = Vec::with_capacity(ips.len());
     for ip in ips {
-        if ip.contains('/') {
-            let subnet = Ipv4Pool::from(&ip)?;
-            ip_addresses.extend(subnet.map(IpAddr::V4));
-        } else {
-            ip_addresses.push(ip.parse()?);
-        }
+        ip_addresses.extend(parse_target(&ip)?);
     }
     Ok(ip_addresses)
 }
+
+/// Parses a single target, which may be an IP address, an IPv4 subnet in CIDR notation
+/// or a host name.
+pub fn parse_target(target: &str) -> Result<Vec<IpAddr>> {
+    if target.contains('/') {
+        let subnet = parse_subnet(target)?;
+        Ok(subnet.into_iter().map(IpAddr::V4).collect())
+    } else if let Ok(ip) = target.parse() {
+        Ok(vec![ip])
+    } else {
+        resolve_host(target)
+    }
+}
+
+/// Reads targets from a list with one target per line, see `parse_target`.
+///
+/// Everything after a `#` is a comment, and blank lines are skipped. A malformed line doesn't
+/// spoil the whole list, since lists of large scans are often maintained by hand. Instead it is
+/// skipped, and a warning with its line number is returned along with the targets.
+pub fn read_targets(reader: impl BufRead) -> (Vec<IpAddr>, Vec<String>) {
+    let mut ip_addresses = Vec::new();
+    let mut warnings = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                warnings.push(format!("line {}: {}", index + 1, e));
+                continue;
+            }
+        };
+        let target = line.split('#').next().unwrap_or_default().trim();
+        if target.is_empty() {
+            continue;
+        }
+        match parse_target(target) {
+            Ok(targets) => ip_addresses.extend(targets),
+            Err(e) => warnings.push(format!("line {}: {}", index + 1, e)),
+        }
+    }
+
+    (ip_addresses, warnings)
+}
+
+/// Resolves a host name like `"example.com"` to its IP addresses with the resolver of the system.
+///
+/// Returns the addresses of both A and AAAA records, so that every IP version is scanned
+/// that the host can be reached with. Scans that only support IPv4 skip the others.
+///
+/// May fail if the name does not resolve to any address.
+pub fn resolve_host(name: &str) -> Result<Vec<IpAddr>> {
+    let cant_resolve = || ScannerError::CantResolveHost(name.to_string());
+
+    let mut ip_addresses = Vec::new();
+    for socket in (name, 0).to_socket_addrs().map_err(|_| cant_resolve())? {
+        if !ip_addresses.contains(&socket.ip()) {
+            ip_addresses.push(socket.ip());
+        }
+    }
+
+    match ip_addresses.is_empty() {
+        true => Err(cant_resolve().into()),
+        false => Ok(ip_addresses),
+    }
+}
+
+/// Returns the host addresses of an IPv4 subnet in CIDR notation like `"192.168.1.0/24"`.
+///
+/// The network and broadcast addresses are skipped, since hosts don't answer on them.
+/// Subnets with a prefix length of 31 or 32 have no such addresses (RFC 3021).
+pub fn parse_subnet(subnet: &str) -> Result<Vec<Ipv4Addr>> {
+    let subnet = parse_cidr(subnet)?;
+
+    let network = u32::from(subnet.network());
+    let broadcast = u32::from(subnet.broadcast());
+
+    let hosts = match subnet.prefix_len {
+        31 | 32 => network..=broadcast,
+        _ => network + 1..=broadcast - 1,
+    };
+
+    Ok(hosts.map(Ipv4Addr::from).collect())
+}
+
+/// Parses an IPv4 subnet in CIDR notation like `"192.168.1.0/24"`.
+pub fn parse_cidr(subnet: &str) -> Result<Ipv4Net> {
+    let invalid = || ScannerError::InvalidSubnet(subnet.to_string());
+
+    let (ip, prefix_len) = subnet.split_once('/').ok_or_else(invalid)?;
+    let ip: Ipv4Addr = ip.trim().parse().map_err(|_| invalid())?;
+    let prefix_len: u8 = prefix_len
+        .trim()
+        .parse()
+        .ok()
+        .filter(|prefix_len| *prefix_len <= 32)
+        .ok_or_else(invalid)?;
+
+    Ok(Ipv4Net::new(ip, prefix_len))
+}
+
+/// Parses the hosts excluded from scans, each an IPv4 address or a subnet in CIDR notation.
+///
+/// Single addresses are returned as subnets with a prefix length of 32.
+pub fn parse_excluded_hosts(hosts: &[String]) -> Result<Vec<Ipv4Net>> {
+    hosts
+        .iter()
+        .map(|host| match host.contains('/') {
+            true => parse_cidr(host),
+            false => host
+                .trim()
+                .parse::<Ipv4Addr>()
+                .map(Ipv4Net::from)
+                .map_err(|_| ScannerError::InvalidSubnet(host.to_string()).into()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_subnet() -> Result<()> {
+        let hosts = parse_subnet("192.168.1.0/24")?;
+        assert_eq!(hosts.len(), 254);
+        assert_eq!(hosts.first(), Some(&Ipv4Addr::new(192, 168, 1, 1)));
+        assert_eq!(hosts.last(), Some(&Ipv4Addr::new(192, 168, 1, 254)));
+
+        // The host bits of the given address are ignored.
+        assert_eq!(parse_subnet("10.0.0.77/30")?, parse_subnet("10.0.0.76/30")?);
+        assert_eq!(
+            parse_subnet("10.0.0.77/30")?,
+            vec![Ipv4Addr::new(10, 0, 0, 77), Ipv4Addr::new(10, 0, 0, 78)]
+        );
+
+        // Point-to-point links and single hosts have no network and broadcast addresses.
+        assert_eq!(parse_subnet("10.0.0.0/31")?.len(), 2);
+        assert_eq!(
+            parse_subnet("10.0.0.1/32")?,
+            vec![Ipv4Addr::new(10, 0, 0, 1)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_malformed_subnet() {
+        for subnet in [
+            "192.168.1.0",
+            "192.168.1.0/33",
+            "192.168.1/24",
+            "abc/24",
+            "10.0.0.0/",
+        ] {
+            assert!(
+                parse_subnet(subnet).is_err(),
+                "{subnet:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_excluded_hosts() -> Result<()> {
+        let hosts = ["192.168.1.1", "10.0.0.0/24"].map(String::from);
+        let excluded = parse_excluded_hosts(&hosts)?;
+        assert_eq!(excluded[0], Ipv4Net::from(Ipv4Addr::new(192, 168, 1, 1)));
+        assert!(excluded[1].contains(Ipv4Addr::new(10, 0, 0, 42)));
+        assert!(!excluded[1].contains(Ipv4Addr::new(10, 0, 1, 42)));
+
+        assert!(parse_excluded_hosts(&["router".to_string()]).is_err());
+        assert!(parse_excluded_hosts(&[]).unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_targets() {
+        let list = "\
+# Lab network
+192.168.178.1
+10.0.0.0/30 # Printers
+
+  2001:db8::1
+10.0.0.0/33
+";
+        let (targets, warnings) = read_targets(list.as_bytes());
+        assert_eq!(targets.len(), 4);
+        assert_eq!(targets[0], IpAddr::V4(Ipv4Addr::new(192, 168, 178, 1)));
+        assert_eq!(targets[3], "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("line 6: "));
+    }
+
+    /// Config with only the required options.
+    const MINIMAL_CONFIG: &str = r#"
+        src_ip = "192.168.178.2"
+        src_port = 0
+        port_numbers = ["80"]
+        ip_addresses = ["192.168.178.1"]
+        number_of_packets = 1
+        should_randomize_ports = false
+    "#;
+
+    #[test]
+    fn test_resolve_host() -> Result<()> {
+        let ip_addresses = resolve_host("localhost")?;
+        assert!(!ip_addresses.is_empty());
+        assert!(ip_addresses.iter().all(|ip| ip.is_loopback()));
+
+        // Numeric addresses and host names can be mixed.
+        let targets = vec!["127.0.0.1".to_string(), "localhost".to_string()];
+        let ip_addresses = parse_ip_addresses(targets)?;
+        assert_eq!(ip_addresses[0], IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert!(ip_addresses.len() > 1);
+
+        let error = resolve_host("not a host name").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ScannerError>(),
+            Some(ScannerError::CantResolveHost(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeout() -> Result<()> {
+        let mut input: Input = toml::from_str(MINIMAL_CONFIG)?;
+        assert_eq!(input.timeout(), DEFAULT_TIMEOUT);
+
+        input.timeout = Some(3);
+        assert_eq!(input.timeout(), Duration::from_secs(3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_probe_options_decoys() -> Result<()> {
+        let mut input: Input = toml::from_str(MINIMAL_CONFIG)?;
+        assert!(input.probe_options()?.decoys.iter().all(Option::is_none));
+
+        let decoy: IpAddr = "192.168.178.10".parse()?;
+        input.decoys = Some(vec![decoy]);
+        let decoys = input.probe_options()?.decoys;
+        assert_eq!(decoys[0], Some(decoy));
+        assert!(decoys[1..].iter().all(Option::is_none));
+
+        input.decoys = Some(vec![decoy; MAX_DECOYS + 1]);
+        assert!(input.probe_options().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_probe_options_source_port_range() -> Result<()> {
+        let config = format!("{}\nsource_port_range = [40000, 40100]", MINIMAL_CONFIG);
+        let mut input: Input = toml::from_str(&config)?;
+        assert_eq!(
+            input.probe_options()?.source_port_range,
+            Some((40000, 40100))
+        );
+
+        // Empty ranges and port 0 are rejected.
+        input.source_port_range = Some((40100, 40000));
+        assert!(input.probe_options().is_err());
+        input.source_port_range = Some((0, 100));
+        assert!(input.probe_options().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_probe_options_delay() -> Result<()> {
+        let config = format!("{}\ndelay = [10, 50]", MINIMAL_CONFIG);
+        let mut input: Input = toml::from_str(&config)?;
+        assert_eq!(
+            input.probe_options()?.delay,
+            Some((Duration::from_millis(10), Duration::from_millis(50)))
+        );
+
+        // A fixed delay is fine, an empty range is rejected.
+        input.delay = Some((20, 20));
+        assert!(input.probe_options().is_ok());
+        input.delay = Some((50, 10));
+        assert!(input.probe_options().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tarpit_threshold() -> Result<()> {
+        let mut input: Input = toml::from_str(MINIMAL_CONFIG)?;
+        assert_eq!(input.tarpit_threshold()?, DEFAULT_TARPIT_THRESHOLD);
+
+        input.tarpit_threshold = Some(1.0);
+        assert_eq!(input.tarpit_threshold()?, 1.0);
+
+        // Hosts can't open more than all of their ports, nor be flagged for none.
+        input.tarpit_threshold = Some(1.5);
+        assert!(input.tarpit_threshold().is_err());
+        input.tarpit_threshold = Some(0.0);
+        assert!(input.tarpit_threshold().is_err());
+        input.tarpit_threshold = Some(f64::NAN);
+        assert!(input.tarpit_threshold().is_err());
+
+        Ok(())
+    }
+}
@@ -1,19 +1,48 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use env_logger::{Builder, WriteStyle};
+use errors::ScannerError;
 use flooder::engine::{FloodMethod, Flooder};
 use input::{load_config, parse_ip_addresses, parse_port_numbers};
-use log::{error, info};
-use networking::interface::Interface;
-use output::{save_arp_results, save_icmp_results, save_port_results};
+use log::{error, info, warn};
+#[cfg(feature = "pcap")]
+use networking::osi_layers::Backend;
+use networking::{
+    icmp::IcmpProbe,
+    interface::{Interface, ROUTED_SOURCE},
+    matcher::ResponseMatcher,
+    packet_log::PACKET_LOG_TARGET,
+};
+#[cfg(feature = "json")]
+use output::save_port_results_json;
+use output::{
+    save_arp_results, save_icmp_results, save_ip_id_results, save_ndp_results, save_os_results,
+    save_port_results, save_profile_results, save_tcp_ping_results, save_traceroute_results,
+    save_version_results,
+};
 use rand::seq::SliceRandom;
-use scanner::engine::{ScanMethod, Scanner};
+use scanner::{
+    diff::diff,
+    engine::{ScanMethod, Scanner},
+    results::{HostNote, PortState, Protocol, ScanResult, RESULT_TABLE_HEADER},
+};
+use std::{
+    net::IpAddr,
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::mpsc;
 mod errors;
 mod flooder;
 mod input;
 mod networking;
 mod output;
+mod ports;
 mod scanner;
+mod vendors;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -23,9 +52,41 @@ struct Cli {
     /// ARP scan.
     #[arg(long)]
     arp: bool,
+    /// Neighbor discovery scan of IPv6 link-local hosts.
+    #[arg(long)]
+    ndp: bool,
     /// ICMP scan.
     #[arg(long)]
     ping: bool,
+    /// ICMP scan with timestamp requests.
+    #[arg(long)]
+    ping_timestamp: bool,
+    /// ICMP scan with address mask requests.
+    #[arg(long)]
+    ping_mask: bool,
+    /// TCP ping with SYN and ACK probes.
+    #[arg(long)]
+    tcp_ping: bool,
+    /// Traceroute with ICMP echo requests.
+    #[arg(long)]
+    traceroute: bool,
+    /// Scan open TCP ports with SYN probes and grab their banners.
+    #[arg(long)]
+    profile: bool,
+    /// Log sent and received packets as hex dumps.
+    #[arg(long)]
+    hexdump: bool,
+    /// Build and log packets without sending them, except for floods, ARP and NDP scans.
+    #[arg(long)]
+    dry_run: bool,
+    /// Save port scan results as JSON instead of CSV.
+    #[cfg(feature = "json")]
+    #[arg(long)]
+    json: bool,
+    /// Send and capture packets with libpcap instead of raw sockets.
+    #[cfg(feature = "pcap")]
+    #[arg(long)]
+    pcap: bool,
     /// Subcommands.
     #[command(subcommand)]
     command: Option<Commands>,
@@ -59,9 +120,39 @@ enum Commands {
         /// TCP Maimon scan.
         #[arg(long)]
         tcp_maimon: bool,
+        /// TCP idle scan through the zombie host.
+        #[arg(long)]
+        tcp_idle: bool,
         /// UDP scan.
         #[arg(long)]
         udp: bool,
+        /// SCTP INIT scan.
+        #[arg(long)]
+        sctp_init: bool,
+        /// IP protocol scan, taking the ports as protocol numbers.
+        #[arg(long)]
+        ip_protocol: bool,
+        /// Grab banners of open TCP ports.
+        #[arg(long)]
+        banners: bool,
+        /// Identify the versions of services on open TCP ports.
+        #[arg(long)]
+        versions: bool,
+        /// Guess the operating systems of hosts with open TCP ports.
+        #[arg(long)]
+        os: bool,
+        /// Classify the IP identifications of hosts to spot load balancers.
+        #[arg(long)]
+        ip_id: bool,
+        /// Skip hosts that don't answer an ICMP echo request.
+        #[arg(long)]
+        ping_first: bool,
+        /// Probe a likely closed port first to tell filtered from silent open ports.
+        #[arg(long)]
+        calibrate: bool,
+        /// Look up the host names of scanned hosts, with the name server of /etc/resolv.conf.
+        #[arg(long)]
+        resolve: bool,
     },
     /// Flood hosts.
     Flood {
@@ -79,27 +170,96 @@ enum Commands {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Cli::parse();
+
+    // Hex dumps of every packet are too verbose to show by default.
+    // A dry run only logs the packets, so it shows them too.
+    let packet_log_level = match args.hexdump || args.dry_run {
+        true => log::LevelFilter::Trace,
+        false => log::LevelFilter::Off,
+    };
     Builder::from_default_env()
         .write_style(WriteStyle::Always)
         .filter_level(log::LevelFilter::Trace)
+        .filter_module(PACKET_LOG_TARGET, packet_log_level)
         .init();
 
-    let args = Cli::parse();
+    #[cfg(feature = "pcap")]
+    if args.pcap {
+        Backend::Pcap.select();
+    }
+
+    // Floods, ARP and NDP scans send their frames directly instead of through the network layer.
+    if args.dry_run {
+        let unsupported = [
+            (
+                matches!(args.command, Some(Commands::Flood { .. })),
+                "floods",
+            ),
+            (args.arp, "ARP scans"),
+            (args.ndp, "NDP scans"),
+        ];
+        if let Some((_, name)) = unsupported.iter().find(|(enabled, _)| *enabled) {
+            Err(ScannerError::DryRunUnsupported(name))?;
+        }
+    }
+
     let input = load_config(&args.config)?;
 
-    let interface = Interface::new()?;
+    ResponseMatcher::set_workers(input.receive_workers());
 
-    let src_ip = input.src_ip;
     let src_port = input.src_port;
 
-    let timeout = std::time::Duration::from_secs(input.timeout);
+    let timeout = input.timeout();
+
+    let mut probe_options = input.probe_options()?;
+    probe_options.dry_run = args.dry_run;
+    let concurrency = input.concurrency();
+    let randomize_order = input.randomize_order();
+    let budget = input.budget();
+    let tarpit_threshold = input.tarpit_threshold()?;
+    let exclude_hosts = input.exclude_hosts()?;
+    let exclude_ports = input.exclude_ports()?;
+    let rate = input.rate;
+    let max_hops = input.max_hops();
+    let banner_probe = input.banner_probe();
+    let tcp_ping_ports = input.tcp_ping_ports();
+    let (file_targets, malformed_targets) = input.file_targets()?;
+    for warning in malformed_targets {
+        warn!("Skipped a target of the targets file, {}", warning);
+    }
+    let previous_results = match input.previous_results()? {
+        Some((results, malformed_results)) => {
+            for warning in malformed_results {
+                warn!("Skipped a result of the previous results, {}", warning);
+            }
+            Some(results)
+        }
+        None => None,
+    };
 
     let mut rng = rand::thread_rng();
 
-    let ip_addresses = parse_ip_addresses(input.ip_addresses)?;
+    let mut ip_addresses = parse_ip_addresses(input.ip_addresses)?;
+    ip_addresses.extend(file_targets);
     let mut port_numbers = parse_port_numbers(input.port_numbers)?;
 
-    port_numbers.shuffle(&mut rng);
+    // A configured interface bypasses the detection.
+    // Without a configured source, each target is reached from the source of its route.
+    // The route to the first target still picks the interface of local ARP and NDP scans.
+    let (src_ip, interface) = match (&input.interface, input.src_ip, ip_addresses.first()) {
+        (Some(name), src_ip, _) => {
+            let interface = Interface::by_name(name)?;
+            (src_ip.unwrap_or(IpAddr::V4(interface.ip.addr)), interface)
+        }
+        (None, Some(src_ip), _) => (src_ip, Interface::pick(Some(src_ip))?),
+        (None, None, Some(dest_ip)) => (ROUTED_SOURCE, Interface::resolve_source(*dest_ip)?.1),
+        (None, None, None) => (ROUTED_SOURCE, Interface::new()?),
+    };
+    match src_ip == ROUTED_SOURCE {
+        true => info!("Sending from the source of the route to each target."),
+        false => info!("Sending from {}.", src_ip),
+    }
 
     if let Some(command) = &args.command {
         match command {
@@ -112,7 +272,17 @@ async fn main() -> Result<()> {
                 tcp_null,
                 tcp_window,
                 tcp_maimon,
+                tcp_idle,
                 udp,
+                sctp_init,
+                ip_protocol,
+                banners,
+                os,
+                ip_id,
+                versions,
+                ping_first,
+                calibrate,
+                resolve,
             } => {
                 let scan_methods = [
                     (*tcp_syn, ScanMethod::TcpSyn),
@@ -123,25 +293,184 @@ async fn main() -> Result<()> {
                     (*tcp_null, ScanMethod::TcpNull),
                     (*tcp_window, ScanMethod::TcpWindow),
                     (*tcp_maimon, ScanMethod::TcpMaimon),
+                    (*tcp_idle, ScanMethod::TcpIdle),
                     (*udp, ScanMethod::Udp),
+                    (*sctp_init, ScanMethod::SctpInit),
+                    (*ip_protocol, ScanMethod::IpProtocol),
                 ];
 
+                // Stop the scan on the first Ctrl-C and save the results gathered so far.
+                // A second Ctrl-C exits right away.
+                let stop = Arc::new(AtomicBool::new(false));
+                let flag = Arc::clone(&stop);
+                tokio::spawn(async move {
+                    if tokio::signal::ctrl_c().await.is_ok() {
+                        info!("Stopping the scan, press Ctrl-C again to exit immediately.");
+                        flag.store(true, Ordering::Relaxed);
+                    }
+                    if tokio::signal::ctrl_c().await.is_ok() {
+                        process::exit(130);
+                    }
+                });
+
+                // Open ports are reported as soon as they are found, long before the scan ends.
+                let (discovered, mut discoveries) = mpsc::unbounded_channel::<ScanResult>();
+                tokio::spawn(async move {
+                    while let Some(scan) = discoveries.recv().await {
+                        if scan.state == PortState::Open {
+                            info!(
+                                "Discovered open port {}/{} on {}.",
+                                scan.port, scan.protocol, scan.ip
+                            );
+                        }
+                    }
+                });
+
+                let scanner = Scanner::new(interface)
+                    .source(src_ip)
+                    .source_port(src_port)
+                    .ports(port_numbers.clone())
+                    .options(probe_options.clone())
+                    .concurrency(concurrency)
+                    .rate(rate)
+                    .timeout(timeout)
+                    .ping_first(*ping_first)
+                    .calibrate(*calibrate)
+                    .resolve(*resolve)
+                    .randomize_order(randomize_order)
+                    .budget(budget)
+                    .tarpit_threshold(tarpit_threshold)
+                    .exclude_hosts(exclude_hosts.clone())
+                    .exclude_ports(exclude_ports.clone())
+                    .stop_flag(Arc::clone(&stop))
+                    .stream(discovered);
+
                 for (enabled, method) in scan_methods {
                     if enabled {
-                        let hosts = Scanner::scan(
-                            interface,
-                            method,
-                            src_ip,
-                            src_port,
-                            &ip_addresses,
-                            &port_numbers,
-                            timeout,
-                        )
-                        .await;
-                        match save_port_results(hosts).await {
+                        let is_tcp = method.protocol() == Protocol::Tcp;
+                        let scanner = scanner.clone().scan_type(method);
+                        let reports = match scanner.scan_targets(&ip_addresses).await {
+                            Ok((reports, stats)) => {
+                                info!("Scan statistics: {}.", stats);
+                                reports
+                            }
+                            Err(e) => {
+                                error!("Failed to scan ports: {}", e);
+                                continue;
+                            }
+                        };
+                        for report in &reports {
+                            let open = report
+                                .results
+                                .iter()
+                                .filter(|scan| scan.state == PortState::Open)
+                                .collect::<Vec<_>>();
+                            info!("{} has {} open ports.", report, open.len());
+                            // Thousands of open ports of a tarpit would drown the real ones.
+                            if report.notes.contains(&HostNote::SuspectedTarpit) {
+                                warn!(
+                                    "{} is a {}, its open ports are saved but not listed.",
+                                    report,
+                                    HostNote::SuspectedTarpit
+                                );
+                            } else if !open.is_empty() {
+                                info!("{}", RESULT_TABLE_HEADER);
+                                for scan in open {
+                                    info!("{}", scan);
+                                }
+                            }
+                        }
+                        let mut hosts = reports
+                            .into_iter()
+                            .flat_map(|report| report.results)
+                            .collect::<Vec<_>>();
+                        let stopped = stop.load(Ordering::Relaxed);
+                        if *os && is_tcp && !stopped {
+                            let guesses = Scanner::detect_os(
+                                interface,
+                                src_ip,
+                                src_port,
+                                &hosts,
+                                probe_options.clone(),
+                                timeout,
+                            )
+                            .await;
+                            match save_os_results(guesses).await {
+                                Ok(path) => info!("OS detection results saved to: {}.", path),
+                                Err(e) => error!("Failed to save OS detection results: {}", e),
+                            }
+                        }
+                        if *ip_id && is_tcp && !stopped {
+                            let patterns = Scanner::analyze_ip_ids(
+                                interface,
+                                src_ip,
+                                src_port,
+                                &hosts,
+                                probe_options.clone(),
+                                timeout,
+                            )
+                            .await;
+                            match save_ip_id_results(patterns).await {
+                                Ok(path) => info!("IP ID results saved to: {}.", path),
+                                Err(e) => error!("Failed to save IP ID results: {}", e),
+                            }
+                        }
+                        if *versions && is_tcp && !stopped {
+                            let services =
+                                Scanner::detect_versions(&hosts, concurrency, timeout).await;
+                            match save_version_results(services).await {
+                                Ok(path) => info!("Version detection results saved to: {}.", path),
+                                Err(e) => error!("Failed to save version detection results: {}", e),
+                            }
+                        }
+                        if *banners && is_tcp && !stopped {
+                            hosts = Scanner::grab_banners(
+                                hosts,
+                                banner_probe.clone(),
+                                concurrency,
+                                timeout,
+                            )
+                            .await;
+                        }
+                        if let Some(previous_results) = &previous_results {
+                            let changes = diff(previous_results, &hosts);
+                            match changes.is_empty() {
+                                true => info!("No port changed since the previous scan."),
+                                false => info!("Changes since the previous scan: {}.", changes),
+                            }
+                            for scan in &changes.opened {
+                                info!(
+                                    "Port {}/{} on {} opened.",
+                                    scan.port, scan.protocol, scan.ip
+                                );
+                            }
+                            for scan in &changes.closed {
+                                info!(
+                                    "Port {}/{} on {} is not open anymore, but {}.",
+                                    scan.port, scan.protocol, scan.ip, scan.state
+                                );
+                            }
+                            for (state, scan) in &changes.changed {
+                                info!(
+                                    "Port {}/{} on {} changed from {} to {}.",
+                                    scan.port, scan.protocol, scan.ip, state, scan.state
+                                );
+                            }
+                        }
+                        #[cfg(feature = "json")]
+                        let saved = match args.json {
+                            true => save_port_results_json(hosts).await,
+                            false => save_port_results(hosts).await,
+                        };
+                        #[cfg(not(feature = "json"))]
+                        let saved = save_port_results(hosts).await;
+                        match saved {
                             Ok(path) => info!("Port scan results saved to: {}.", path),
                             Err(e) => error!("Failed to save port scan results: {}", e),
                         }
+                        if stopped {
+                            break;
+                        }
                     }
                 }
             }
@@ -152,6 +481,8 @@ async fn main() -> Result<()> {
                     (*icmp, FloodMethod::Icmp),
                 ];
 
+                port_numbers.shuffle(&mut rng);
+
                 for (enabled, method) in flood_methods {
                     if enabled {
                         Flooder::flood(
@@ -171,11 +502,98 @@ async fn main() -> Result<()> {
         }
     }
 
-    if args.ping {
-        let hosts = Scanner::ping(interface, src_ip, ip_addresses.clone(), timeout).await;
-        match save_icmp_results(hosts).await {
-            Ok(path) => info!("ICMP scan results saved to: {}.", path),
-            Err(e) => error!("Failed to save ICMP scan results: {}", e),
+    let icmp_probes = [
+        (args.ping, IcmpProbe::Echo),
+        (args.ping_timestamp, IcmpProbe::Timestamp),
+        (args.ping_mask, IcmpProbe::AddressMask),
+    ];
+
+    for (enabled, probe) in icmp_probes {
+        if enabled {
+            let hosts = Scanner::ping(
+                interface,
+                src_ip,
+                ip_addresses.clone(),
+                probe,
+                probe_options.clone(),
+                timeout,
+            )
+            .await;
+            match save_icmp_results(hosts, probe).await {
+                Ok(path) => info!("ICMP scan results saved to: {}.", path),
+                Err(e) => error!("Failed to save ICMP scan results: {}", e),
+            }
+        }
+    }
+
+    if args.tcp_ping {
+        let hosts = Scanner::tcp_ping(
+            interface,
+            src_ip,
+            src_port,
+            ip_addresses.clone(),
+            &tcp_ping_ports,
+            probe_options.clone(),
+            timeout,
+        )
+        .await;
+        match save_tcp_ping_results(hosts).await {
+            Ok(path) => info!("TCP ping results saved to: {}.", path),
+            Err(e) => error!("Failed to save TCP ping results: {}", e),
+        }
+    }
+
+    if args.traceroute {
+        let hops = Scanner::traceroute(
+            interface,
+            src_ip,
+            ip_addresses.clone(),
+            max_hops,
+            probe_options.clone(),
+            timeout,
+        )
+        .await;
+        match save_traceroute_results(hops).await {
+            Ok(path) => info!("Traceroute results saved to: {}.", path),
+            Err(e) => error!("Failed to save traceroute results: {}", e),
+        }
+    }
+
+    if args.profile {
+        let scanner = Scanner::new(interface)
+            .source(src_ip)
+            .source_port(src_port)
+            .ports(port_numbers.clone())
+            .options(probe_options)
+            .concurrency(concurrency)
+            .rate(rate)
+            .timeout(timeout)
+            .randomize_order(randomize_order)
+            .budget(budget)
+            .exclude_hosts(exclude_hosts.clone())
+            .exclude_ports(exclude_ports.clone())
+            .banner_probe(banner_probe.clone());
+        let mut profiles = Vec::new();
+        for ip in ip_addresses.clone() {
+            match scanner.profile(ip).await {
+                Ok(profile) => {
+                    info!("Profiled {}, scan statistics: {}.", profile, profile.stats);
+                    profiles.push(profile);
+                }
+                Err(e) => error!("Failed to profile {}: {}", ip, e),
+            }
+        }
+        match save_profile_results(profiles).await {
+            Ok(path) => info!("Host profiles saved to: {}.", path),
+            Err(e) => error!("Failed to save host profiles: {}", e),
+        }
+    }
+
+    if args.ndp {
+        let hosts = Scanner::ndp(interface, ip_addresses.clone(), timeout).await;
+        match save_ndp_results(hosts).await {
+            Ok(path) => info!("NDP scan results saved to: {}.", path),
+            Err(e) => error!("Failed to save NDP scan results: {}", e),
         }
     }
 
@@ -2,6 +2,7 @@ use super::{
     interface::Interface,
     osi_layers::{DatalinkLayer, Layer, NetworkLayer},
 };
+use crate::errors::ScannerError;
 use anyhow::Result;
 use pnet::{
     packet::{
@@ -11,7 +12,15 @@ use pnet::{
     },
     util::MacAddr,
 };
-use std::{net::Ipv4Addr, time::Duration};
+use std::{
+    collections::HashMap,
+    net::Ipv4Addr,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+/// MAC addresses resolved so far, shared by all probes, since neighbors rarely change during a scan.
+static NEIGHBORS: OnceLock<Mutex<HashMap<Ipv4Addr, MacAddr>>> = OnceLock::new();
 
 pub struct Arp;
 
@@ -61,6 +70,7 @@ impl Arp {
             datalink_layer: Some(data_link_layer),
             src_addr: Some(dest_ip.into()),
             dest_addr: Some(src_ip.into()),
+            protocol: None,
         };
 
         let layer = Layer::Three(network_layer);
@@ -69,7 +79,7 @@ impl Arp {
             &iface,
             MacAddr::broadcast(),
             ethernet_type,
-            &arp_packet,
+            &[arp_packet.to_vec()],
             layer,
             timeout,
         )?;
@@ -80,14 +90,50 @@ impl Arp {
         }
     }
 
-    /// Extracts the MAC address from an ARP packet.
+    /// Returns the MAC address that frames to `dest_ip` must be sent to.
+    ///
+    /// Hosts on the subnet of the interface are addressed directly, so their MAC address is
+    /// resolved via ARP. Frames to all other hosts go to the gateway, which routes them further.
+    /// The operating system usually knows the MAC address of the gateway, otherwise it is
+    /// resolved via ARP as well.
+    ///
+    /// May fail if the next hop does not answer the ARP request, e.g. because the host is down.
+    pub fn next_hop_mac(
+        interface: Interface,
+        dest_ip: Ipv4Addr,
+        timeout: Duration,
+    ) -> Result<MacAddr> {
+        let next_hop = match interface.is_on_link(dest_ip) {
+            true => dest_ip,
+            false if interface.gateway.mac != MacAddr::zero() => return Ok(interface.gateway.mac),
+            false => interface.gateway.ip,
+        };
+
+        let neighbors = NEIGHBORS.get_or_init(Default::default);
+        if let Some(mac) = neighbors.lock().unwrap().get(&next_hop) {
+            return Ok(*mac);
+        }
+
+        let (mac, _) = Arp::send_request(interface, interface.ip.addr, next_hop, timeout)?;
+        let mac = mac.ok_or(ScannerError::CantResolveMac(next_hop.into()))?;
+
+        neighbors.lock().unwrap().insert(next_hop, mac);
+
+        Ok(mac)
+    }
+
+    /// Extracts the MAC address of the sender from an ARP reply.
+    ///
+    /// ARP requests are ignored, since their sender is not necessarily the host we asked for.
     pub fn get_mac_address(packet: &[u8]) -> Option<MacAddr> {
         let response = EthernetPacket::new(packet)?;
-        if response.get_ethertype() == EtherTypes::Arp {
-            let arp_packet = ArpPacket::new(response.payload())?;
-            Some(arp_packet.get_sender_hw_addr())
-        } else {
-            None
+        if response.get_ethertype() != EtherTypes::Arp {
+            return None;
+        }
+        let arp_packet = ArpPacket::new(response.payload())?;
+        match arp_packet.get_operation() {
+            ArpOperations::Reply => Some(arp_packet.get_sender_hw_addr()),
+            _ => None,
         }
     }
 }
@@ -97,6 +143,41 @@ mod tests {
     use super::*;
     use std::net::Ipv4Addr;
 
+    /// Encapsulates an ARP packet in an Ethernet frame.
+    fn ethernet_frame(arp_packet: &[u8]) -> Vec<u8> {
+        DatalinkLayer::build_ethernet_frame(
+            MacAddr::zero(),
+            MacAddr::broadcast(),
+            EtherTypes::Arp,
+            arp_packet,
+        )
+    }
+
+    #[test]
+    fn test_get_mac_address() {
+        let src_mac = MacAddr::new(0, 1, 2, 3, 4, 5);
+        let target_mac = MacAddr::new(6, 7, 8, 9, 10, 11);
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let target_ip = Ipv4Addr::new(192, 168, 1, 2);
+
+        // Our own request does not reveal the MAC address of the target.
+        let request = Arp::build_arp_packet(src_mac, src_ip, target_ip);
+        let request_packet = ArpPacket::new(&request).unwrap();
+        assert_eq!(request_packet.get_operation(), ArpOperations::Request);
+        assert_eq!(request_packet.get_target_proto_addr(), target_ip);
+        assert_eq!(Arp::get_mac_address(&ethernet_frame(&request)), None);
+
+        // The reply of the target carries its MAC address as sender.
+        let mut reply = Arp::build_arp_packet(target_mac, target_ip, src_ip);
+        MutableArpPacket::new(&mut reply)
+            .unwrap()
+            .set_operation(ArpOperations::Reply);
+        assert_eq!(
+            Arp::get_mac_address(&ethernet_frame(&reply)),
+            Some(target_mac)
+        );
+    }
+
     #[test]
     fn test_send_request_and_get_mac() -> Result<()> {
         // Get the default interface.
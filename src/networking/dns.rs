@@ -0,0 +1,280 @@
+use log::debug;
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+/// Time to wait for the answer of the name server to a single lookup.
+pub const LOOKUP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Configuration of the system resolver, which lists the name servers.
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+const DNS_PORT: u16 = 53;
+
+/// Size of the DNS header, and the maximum size of a DNS message over UDP, see RFC 1035.
+const HEADER_SIZE: usize = 12;
+const MAX_MESSAGE_SIZE: usize = 512;
+
+/// Flags of the DNS header.
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const RESPONSE_CODE_MASK: u16 = 0x000F;
+
+/// Record type of domain name pointers and the record class of the internet.
+const TYPE_PTR: u16 = 12;
+const CLASS_IN: u16 = 1;
+
+/// Upper two bits of a length byte that mark a pointer to a name elsewhere in the message.
+const POINTER_MASK: u8 = 0xC0;
+/// Maximum number of pointers followed in a name, which guards against pointer loops.
+const MAX_POINTERS: usize = 16;
+
+/// Looks up the host name of an IP address, e.g. `router.local` for `192.168.1.1`.
+///
+/// Asks the name server, usually the `system_nameserver`, for the PTR record of the address.
+/// Waits at most `LOOKUP_TIMEOUT`, so a slow name server doesn't stall the caller for long.
+///
+/// Returns `None` if the address has no name, e.g. NXDOMAIN, or if the lookup fails or times out.
+pub fn reverse_lookup(nameserver: SocketAddr, ip: IpAddr) -> Option<String> {
+    match query_ptr(nameserver, ip, LOOKUP_TIMEOUT) {
+        Ok(name) => name,
+        Err(e) => {
+            debug!("Reverse lookup of {} failed: {}", ip, e);
+            None
+        }
+    }
+}
+
+/// Returns the first name server listed in the configuration of the system resolver.
+///
+/// Only `/etc/resolv.conf` is read, so there is none on systems without it, e.g. Windows.
+pub fn system_nameserver() -> Option<SocketAddr> {
+    let config = fs::read_to_string(RESOLV_CONF).ok()?;
+    parse_nameserver(&config)
+}
+
+/// Parses the first `nameserver` line of a `resolv.conf`, ignoring comments and interface scopes.
+fn parse_nameserver(config: &str) -> Option<SocketAddr> {
+    config.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("nameserver") => {
+                let address = words.next()?.split('%').next()?;
+                let ip = address.parse::<IpAddr>().ok()?;
+                Some(SocketAddr::new(ip, DNS_PORT))
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Sends a PTR query for the IP address to the name server and waits for its answer.
+fn query_ptr(nameserver: SocketAddr, ip: IpAddr, timeout: Duration) -> io::Result<Option<String>> {
+    let local_ip = match nameserver {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind((local_ip, 0))?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.connect(nameserver)?;
+
+    let id = rand::random();
+    socket.send(&build_query(id, &reverse_name(ip)))?;
+
+    let mut reply = [0u8; MAX_MESSAGE_SIZE];
+    let size = socket.recv(&mut reply).map_err(|e| match e.kind() {
+        ErrorKind::WouldBlock => io::Error::from(ErrorKind::TimedOut),
+        _ => e,
+    })?;
+
+    Ok(parse_reply(id, &reply[..size]))
+}
+
+/// Returns the domain name under which the PTR record of an IP address is stored.
+///
+/// IPv4 addresses are reversed by octet below `in-addr.arpa`, IPv6 addresses by nibble below `ip6.arpa`.
+fn reverse_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, d] = ip.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        }
+        IpAddr::V6(ip) => {
+            let mut name = String::new();
+            for byte in ip.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", byte & 0x0F, byte >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        }
+    }
+}
+
+/// Builds a query with a single question for the PTR record of the name.
+fn build_query(id: u16, name: &str) -> Vec<u8> {
+    let mut query = Vec::with_capacity(HEADER_SIZE + name.len() + 6);
+    query.extend_from_slice(&id.to_be_bytes());
+    query.extend_from_slice(&FLAG_RECURSION_DESIRED.to_be_bytes());
+    // One question, and no answer, authority or additional records.
+    query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+
+    query.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    query
+}
+
+/// Extracts the host name from the answer to a PTR query with the given ID.
+///
+/// Returns `None` for answers to other queries, errors like NXDOMAIN and answers without a PTR record.
+fn parse_reply(id: u16, reply: &[u8]) -> Option<String> {
+    let header = reply.get(..HEADER_SIZE)?;
+    let field = |index: usize| u16::from_be_bytes([header[index], header[index + 1]]);
+
+    let flags = field(2);
+    if field(0) != id || flags & FLAG_RESPONSE == 0 || flags & RESPONSE_CODE_MASK != 0 {
+        return None;
+    }
+
+    let mut offset = HEADER_SIZE;
+    for _ in 0..field(4) {
+        // The question repeats the name, followed by its type and class.
+        offset = skip_name(reply, offset)? + 4;
+    }
+
+    for _ in 0..field(6) {
+        offset = skip_name(reply, offset)?;
+        let record = reply.get(offset..offset + 10)?;
+        let record_type = u16::from_be_bytes([record[0], record[1]]);
+        let data_length = u16::from_be_bytes([record[8], record[9]]) as usize;
+        offset += 10;
+
+        if record_type == TYPE_PTR {
+            return read_name(reply, offset);
+        }
+        offset += data_length;
+    }
+
+    None
+}
+
+/// Returns the offset after the name that starts at `offset`, which may end with a pointer.
+fn skip_name(message: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let length = *message.get(offset)?;
+        match length {
+            0 => return Some(offset + 1),
+            _ if length & POINTER_MASK == POINTER_MASK => return Some(offset + 2),
+            _ => offset += 1 + length as usize,
+        }
+    }
+}
+
+/// Reads the dotted name that starts at `offset`, following pointers to compressed labels.
+fn read_name(message: &[u8], mut offset: usize) -> Option<String> {
+    let mut labels = Vec::new();
+    let mut pointers = 0;
+
+    loop {
+        let length = *message.get(offset)?;
+        match length {
+            0 => break,
+            _ if length & POINTER_MASK == POINTER_MASK => {
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    return None;
+                }
+                let low = *message.get(offset + 1)?;
+                offset = u16::from_be_bytes([length & !POINTER_MASK, low]) as usize;
+            }
+            _ => {
+                let label = message.get(offset + 1..offset + 1 + length as usize)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                offset += 1 + length as usize;
+            }
+        }
+    }
+
+    match labels.is_empty() {
+        true => None,
+        false => Some(labels.join(".")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds the answer of a name server to the query, with the name compressed into a pointer.
+    fn reply(query: &[u8], response_code: u8, name: &str) -> Vec<u8> {
+        let mut reply = query.to_vec();
+        reply[2] |= (FLAG_RESPONSE >> 8) as u8;
+        reply[3] |= response_code;
+        if response_code != 0 {
+            return reply;
+        }
+
+        reply[7] = 1;
+        // A pointer to the name of the question, type, class, TTL and data length.
+        reply.extend_from_slice(&[0xC0, HEADER_SIZE as u8, 0, 12, 0, 1, 0, 0, 0x0E, 0x10]);
+        let (host, domain) = name.split_once('.').unwrap();
+        let data_length = 1 + host.len() + 2;
+        reply.extend_from_slice(&(data_length as u16).to_be_bytes());
+        reply.push(host.len() as u8);
+        reply.extend_from_slice(host.as_bytes());
+
+        // The domain of the host name is also stored elsewhere, e.g. in another record.
+        let domain_offset = reply.len() + 2;
+        reply.extend_from_slice(&[0xC0, domain_offset as u8]);
+        reply.push(domain.len() as u8);
+        reply.extend_from_slice(domain.as_bytes());
+        reply.push(0);
+        reply
+    }
+
+    #[test]
+    fn test_reverse_name() {
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2));
+        assert_eq!(reverse_name(ip), "2.1.168.192.in-addr.arpa");
+
+        let ip = "2001:db8::1".parse().unwrap();
+        let name = reverse_name(ip);
+        assert!(name.starts_with("1.0.0.0.0.0.0.0."));
+        assert!(name.ends_with("8.b.d.0.1.0.0.2.ip6.arpa"));
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let query = build_query(0x1234, "1.1.168.192.in-addr.arpa");
+        assert_eq!(&query[HEADER_SIZE..HEADER_SIZE + 2], [1, b'1']);
+
+        let answer = reply(&query, 0, "router.local");
+        assert_eq!(
+            parse_reply(0x1234, &answer).as_deref(),
+            Some("router.local")
+        );
+
+        // Answers to other queries and NXDOMAIN have no name.
+        assert_eq!(parse_reply(0x4321, &answer), None);
+        assert_eq!(parse_reply(0x1234, &reply(&query, 3, "")), None);
+        assert_eq!(parse_reply(0x1234, &answer[..HEADER_SIZE + 4]), None);
+    }
+
+    #[test]
+    fn test_parse_nameserver() {
+        let config = "# Generated\nsearch local\nnameserver fe80::1%eth0\nnameserver 10.0.0.1\n";
+        let nameserver = parse_nameserver(config).unwrap();
+        assert_eq!(
+            nameserver,
+            SocketAddr::new("fe80::1".parse().unwrap(), DNS_PORT)
+        );
+        assert_eq!(parse_nameserver("search local\n"), None);
+    }
+}
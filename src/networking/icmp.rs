@@ -1,101 +1,347 @@
 use super::{
     interface::Interface,
-    osi_layers::{Layer, NetworkLayer, TransportLayer},
+    osi_layers::{Exchange, IcmpEcho, Layer, NetworkLayer, TransportLayer},
+    probe::{build_ipv4_header, has_consistent_ip_header, ProbeOptions, IPV4_HEADER_SIZE},
 };
+use crate::errors::ScannerError;
 use anyhow::Result;
 use pnet::packet::{
     icmp::{
-        self, destination_unreachable::IcmpCodes, echo_request::MutableEchoRequestPacket,
-        IcmpTypes, MutableIcmpPacket,
+        self,
+        echo_request::{IcmpCodes, MutableEchoRequestPacket},
+        IcmpType, IcmpTypes, MutableIcmpPacket,
     },
     ip::IpNextHeaderProtocols,
-    ipv4::{self, Ipv4Flags, MutableIpv4Packet},
 };
 use rand::Rng;
 use std::{
-    net::Ipv4Addr,
+    net::{IpAddr, Ipv4Addr},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-const IPV4_HEADER_SIZE: usize = 20;
 const ICMP_HEADER_SIZE: usize = 8;
 const ICMP_DATA_SIZE: usize = 16;
-const TTL: u8 = 64;
+/// Originate, receive and transmit timestamp of a timestamp request, see RFC 792.
+const TIMESTAMP_DATA_SIZE: usize = 12;
+/// Address mask of an address mask request, see RFC 950.
+const ADDRESS_MASK_DATA_SIZE: usize = 4;
+
+const MILLIS_PER_DAY: u128 = 86_400_000;
+
+/// Kind of ICMP request a ping sends.
+///
+/// Echo requests are blocked most often. Some hosts and routers that drop them still answer
+/// timestamp or address mask requests, which helps to discover them anyway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcmpProbe {
+    Echo,
+    Timestamp,
+    AddressMask,
+}
+
+impl IcmpProbe {
+    /// Returns the ICMP type of replies to this request.
+    pub fn reply_type(self) -> IcmpType {
+        match self {
+            IcmpProbe::Echo => IcmpTypes::EchoReply,
+            IcmpProbe::Timestamp => IcmpTypes::TimestampReply,
+            IcmpProbe::AddressMask => IcmpTypes::AddressMaskReply,
+        }
+    }
+}
 
 pub struct Icmp;
 
 impl Icmp {
-    /// Constructs an IP datagram with an ICMP header.
+    /// Constructs an IP datagram with an ICMP echo request.
+    ///
+    /// The payload carries the time of sending, like the ping utility does.
     pub fn build_icmp_packet(
         src_ip: Ipv4Addr,
         dest_ip: Ipv4Addr,
-    ) -> [u8; IPV4_HEADER_SIZE + ICMP_HEADER_SIZE + ICMP_DATA_SIZE] {
-        let mut rng = rand::thread_rng();
-        let mut ip_packet = [0u8; IPV4_HEADER_SIZE + ICMP_HEADER_SIZE + ICMP_DATA_SIZE];
-
-        let mut ip_header = MutableIpv4Packet::new(&mut ip_packet).unwrap();
-        ip_header.set_version(4);
-        ip_header.set_header_length(5);
-        ip_header.set_source(src_ip);
-        ip_header.set_destination(dest_ip);
-        ip_header.set_total_length((IPV4_HEADER_SIZE + ICMP_HEADER_SIZE + ICMP_DATA_SIZE) as u16);
-        ip_header.set_identification(rng.gen());
-        ip_header.set_flags(Ipv4Flags::DontFragment);
-        ip_header.set_ttl(TTL);
-        ip_header.set_next_level_protocol(IpNextHeaderProtocols::Icmp);
-        let ip_checksum = ipv4::checksum(&ip_header.to_immutable());
-        ip_header.set_checksum(ip_checksum);
-
-        let mut echo_request =
-            MutableEchoRequestPacket::new(&mut ip_packet[IPV4_HEADER_SIZE..]).unwrap();
-        echo_request.set_icmp_type(IcmpTypes::EchoRequest);
-        echo_request.set_icmp_code(IcmpCodes::DestinationNetworkUnreachable);
-        echo_request.set_identifier(1);
-        echo_request.set_identifier(rng.gen());
-
-        let now = SystemTime::now();
-        let duration = now.duration_since(UNIX_EPOCH).unwrap(); // Won't panic
+        identifier: u16,
+        sequence: u16,
+        options: &ProbeOptions,
+    ) -> Result<Vec<u8>> {
+        let duration = SystemTime::now().duration_since(UNIX_EPOCH).unwrap(); // Won't panic
         let secs = duration.as_secs().to_be_bytes();
         let nsecs = duration.subsec_millis().to_be_bytes();
-        let mut timestamp = Vec::with_capacity(secs.len() + nsecs.len());
+        let mut timestamp = Vec::with_capacity(ICMP_DATA_SIZE);
         timestamp.extend_from_slice(&secs);
         timestamp.extend_from_slice(&nsecs);
-        echo_request.set_payload(&timestamp);
+        timestamp.resize(ICMP_DATA_SIZE, 0);
+
+        Icmp::build_request(
+            src_ip,
+            dest_ip,
+            IcmpTypes::EchoRequest,
+            identifier,
+            sequence,
+            &timestamp,
+            options,
+        )
+    }
+
+    /// Constructs an IP datagram with an ICMP timestamp request.
+    ///
+    /// The originate timestamp carries the time of sending in milliseconds since midnight UT.
+    /// The target fills in the receive and transmit timestamps of its reply.
+    pub fn build_timestamp_request(
+        src_ip: Ipv4Addr,
+        dest_ip: Ipv4Addr,
+        identifier: u16,
+        sequence: u16,
+        options: &ProbeOptions,
+    ) -> Result<Vec<u8>> {
+        let duration = SystemTime::now().duration_since(UNIX_EPOCH).unwrap(); // Won't panic
+        let originate = (duration.as_millis() % MILLIS_PER_DAY) as u32;
+        let mut timestamps = [0u8; TIMESTAMP_DATA_SIZE];
+        timestamps[0..4].copy_from_slice(&originate.to_be_bytes());
+
+        Icmp::build_request(
+            src_ip,
+            dest_ip,
+            IcmpTypes::Timestamp,
+            identifier,
+            sequence,
+            &timestamps,
+            options,
+        )
+    }
 
-        let mut icmp_header = MutableIcmpPacket::new(&mut ip_packet[IPV4_HEADER_SIZE..]).unwrap();
+    /// Constructs an IP datagram with an ICMP address mask request.
+    ///
+    /// The address mask is zero, the target fills in the mask of its subnet.
+    pub fn build_address_mask_request(
+        src_ip: Ipv4Addr,
+        dest_ip: Ipv4Addr,
+        identifier: u16,
+        sequence: u16,
+        options: &ProbeOptions,
+    ) -> Result<Vec<u8>> {
+        Icmp::build_request(
+            src_ip,
+            dest_ip,
+            IcmpTypes::AddressMaskRequest,
+            identifier,
+            sequence,
+            &[0u8; ADDRESS_MASK_DATA_SIZE],
+            options,
+        )
+    }
+
+    /// Constructs an IP datagram with an ICMP request of the given type.
+    ///
+    /// Echo, timestamp and address mask requests share the layout of the header,
+    /// an identifier and a sequence number followed by their data.
+    ///
+    /// May fail if the packet can't hold its headers.
+    fn build_request(
+        src_ip: Ipv4Addr,
+        dest_ip: Ipv4Addr,
+        icmp_type: IcmpType,
+        identifier: u16,
+        sequence: u16,
+        data: &[u8],
+        options: &ProbeOptions,
+    ) -> Result<Vec<u8>> {
+        let mut ip_packet = vec![0u8; IPV4_HEADER_SIZE + ICMP_HEADER_SIZE + data.len()];
+
+        build_ipv4_header(
+            &mut ip_packet,
+            src_ip,
+            dest_ip,
+            IpNextHeaderProtocols::Icmp,
+            options,
+        )?;
+
+        let mut request = MutableEchoRequestPacket::new(&mut ip_packet[IPV4_HEADER_SIZE..])
+            .ok_or(ScannerError::CantCreateIcmpPacket)?;
+        request.set_icmp_type(icmp_type);
+        request.set_icmp_code(IcmpCodes::NoCode);
+        request.set_identifier(identifier);
+        request.set_sequence_number(sequence);
+        request.set_payload(data);
+
+        let mut icmp_header = MutableIcmpPacket::new(&mut ip_packet[IPV4_HEADER_SIZE..])
+            .ok_or(ScannerError::CantCreateIcmpPacket)?;
         let icmp_checksum = icmp::checksum(&icmp_header.to_immutable());
         icmp_header.set_checksum(icmp_checksum);
 
-        ip_packet
+        debug_assert!(
+            has_consistent_ip_header(&ip_packet),
+            "Built an inconsistent ICMP packet."
+        );
+
+        Ok(ip_packet)
     }
 
-    /// Sends an ICMP packet.
+    /// Sends an ICMP request of the given kind with a random identifier.
     ///
     /// The packet is handed over to the transport layer.
     pub fn send_icmp_packet(
         interface: Interface,
         src_ip: Ipv4Addr,
         dest_ip: Ipv4Addr,
+        probe: IcmpProbe,
+        options: ProbeOptions,
         timeout: Duration,
-    ) -> Result<(Option<Vec<u8>>, Duration)> {
-        let packet = Icmp::build_icmp_packet(src_ip, dest_ip);
+    ) -> Result<Exchange> {
+        let echo = IcmpEcho {
+            identifier: rand::thread_rng().gen(),
+            sequence: 0,
+        };
+        let build = match probe {
+            IcmpProbe::Echo => Icmp::build_icmp_packet,
+            IcmpProbe::Timestamp => Icmp::build_timestamp_request,
+            IcmpProbe::AddressMask => Icmp::build_address_mask_request,
+        };
+        let packet = build(src_ip, dest_ip, echo.identifier, echo.sequence, &options)?;
 
+        Icmp::send_request(
+            interface,
+            src_ip,
+            packet,
+            Some(dest_ip),
+            echo,
+            options,
+            timeout,
+        )
+    }
+
+    /// Sends an ICMP echo request and accepts responses from any host, e.g. for a traceroute.
+    ///
+    /// Routers on the path answer with ICMP time exceeded errors once the TTL runs out.
+    /// They are attributed to the request by the identifier and sequence number they quote.
+    ///
+    /// The exchange holds the IP address of the responder along with the response.
+    pub fn send_traceroute_probe(
+        interface: Interface,
+        src_ip: Ipv4Addr,
+        dest_ip: Ipv4Addr,
+        echo: IcmpEcho,
+        options: ProbeOptions,
+        timeout: Duration,
+    ) -> Result<Exchange> {
+        let packet =
+            Icmp::build_icmp_packet(src_ip, dest_ip, echo.identifier, echo.sequence, &options)?;
+        Icmp::send_request(interface, src_ip, packet, None, echo, options, timeout)
+    }
+
+    /// Sends an ICMP request and waits for a response from `responder`, or any host if `None`.
+    fn send_request(
+        interface: Interface,
+        src_ip: Ipv4Addr,
+        packet: Vec<u8>,
+        responder: Option<Ipv4Addr>,
+        echo: IcmpEcho,
+        options: ProbeOptions,
+        timeout: Duration,
+    ) -> Result<Exchange> {
         let network_layer = NetworkLayer {
             datalink_layer: None,
-            src_addr: Some(dest_ip.into()),
+            src_addr: responder.map(IpAddr::V4),
             dest_addr: Some(src_ip.into()),
+            protocol: None,
         };
 
         let transport_layer = TransportLayer {
             network_layer: Some(network_layer),
             src_port: None,
             dest_port: None,
+            tcp_sequence: None,
+            icmp_echo: Some(echo),
         };
 
         let layer = Layer::Four(transport_layer);
 
-        let (response, rtt) = NetworkLayer::send_and_receive(interface, &packet, layer, timeout)?;
+        NetworkLayer::send_and_receive(interface, &[packet], layer, &options, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pnet::packet::{
+        icmp::{echo_request::EchoRequestPacket, IcmpPacket},
+        ipv4::Ipv4Packet,
+    };
+
+    #[test]
+    fn test_build_icmp_packet() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+
+        let packet =
+            Icmp::build_icmp_packet(src_ip, dest_ip, 0x1234, 7, &ProbeOptions::default()).unwrap();
+
+        // Verify the IP packet.
+        let ip_packet = Ipv4Packet::new(&packet).unwrap();
+        assert_eq!(
+            ip_packet.get_next_level_protocol(),
+            IpNextHeaderProtocols::Icmp
+        );
+        assert_eq!(ip_packet.get_total_length() as usize, packet.len());
+
+        // Verify the echo request and its checksum.
+        let echo_request = EchoRequestPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        assert_eq!(echo_request.get_icmp_type(), IcmpTypes::EchoRequest);
+        assert_eq!(echo_request.get_icmp_type().0, 8);
+        assert_eq!(echo_request.get_icmp_code(), IcmpCodes::NoCode);
+        assert_eq!(echo_request.get_identifier(), 0x1234);
+        assert_eq!(echo_request.get_sequence_number(), 7);
+
+        let icmp_packet = IcmpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        assert_eq!(icmp_packet.get_checksum(), icmp::checksum(&icmp_packet));
+    }
+
+    #[test]
+    fn test_build_timestamp_request() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+
+        let packet =
+            Icmp::build_timestamp_request(src_ip, dest_ip, 0x1234, 7, &ProbeOptions::default())
+                .unwrap();
+        assert_eq!(
+            packet.len(),
+            IPV4_HEADER_SIZE + ICMP_HEADER_SIZE + TIMESTAMP_DATA_SIZE
+        );
+
+        // Type 13, code 0, followed by the identifier and sequence number.
+        let icmp_bytes = &packet[IPV4_HEADER_SIZE..];
+        assert_eq!(icmp_bytes[0], 13);
+        assert_eq!(icmp_bytes[1], 0);
+        assert_eq!(icmp_bytes[4..8], [0x12, 0x34, 0, 7]);
+
+        // The receive and transmit timestamps are left to the target.
+        assert_eq!(icmp_bytes[12..20], [0; 8]);
+
+        let icmp_packet = IcmpPacket::new(icmp_bytes).unwrap();
+        assert_eq!(icmp_packet.get_checksum(), icmp::checksum(&icmp_packet));
+    }
+
+    #[test]
+    fn test_build_address_mask_request() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+
+        let packet =
+            Icmp::build_address_mask_request(src_ip, dest_ip, 0x1234, 7, &ProbeOptions::default())
+                .unwrap();
+        assert_eq!(
+            packet.len(),
+            IPV4_HEADER_SIZE + ICMP_HEADER_SIZE + ADDRESS_MASK_DATA_SIZE
+        );
+
+        // Type 17, code 0, followed by the identifier, sequence number and an empty mask.
+        let icmp_bytes = &packet[IPV4_HEADER_SIZE..];
+        assert_eq!(icmp_bytes[0], 17);
+        assert_eq!(icmp_bytes[1], 0);
+        assert_eq!(icmp_bytes[4..8], [0x12, 0x34, 0, 7]);
+        assert_eq!(icmp_bytes[8..12], [0; 4]);
 
-        Ok((response, rtt))
+        let icmp_packet = IcmpPacket::new(icmp_bytes).unwrap();
+        assert_eq!(icmp_packet.get_checksum(), icmp::checksum(&icmp_packet));
     }
 }
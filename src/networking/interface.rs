@@ -1,8 +1,13 @@
+use super::ndp::Ndp;
 use crate::errors::ScannerError;
 use anyhow::Result;
-use netdev::{get_default_interface, ip::Ipv4Net, NetworkDevice};
+use log::debug;
+use netdev::{get_default_interface, get_interfaces, ip::Ipv4Net, NetworkDevice};
 use pnet::util::MacAddr;
-use std::net::Ipv4Addr;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, UdpSocket},
+};
 
 // Constants based on the operating system.
 cfg_if::cfg_if! {
@@ -17,6 +22,9 @@ cfg_if::cfg_if! {
 
 const MAX_INTERFACE_NAME_LENTGH: usize = 256;
 
+/// Source address that stands for the source of the route to each target, see `Routes`.
+pub const ROUTED_SOURCE: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
 /// Represents a network interface with an IP address, MAC address, and gateway.
 ///
 /// Retrieving the list of all interfaces in every tokio task to determine which
@@ -34,19 +42,89 @@ pub struct Interface {
     pub index: u32,
     pub name: [u8; MAX_INTERFACE_NAME_LENTGH],
     pub ip: Ipv4Net,
+    /// Link-local IPv6 address, the source of neighbor solicitations, see `Ndp::next_hop_mac`.
+    pub link_local: Option<Ipv6Addr>,
     pub mac: MacAddr,
     pub flags: u32,
     pub gateway: Gateway,
 }
 
 impl Interface {
-    /// Creates a new `Interface` struct.
+    /// Creates a new `Interface` struct for the default interface, see `Interface::pick`.
     ///
-    /// May fail if the default interface, router address, MAC address or gateway can't be found.
+    /// May fail if no interface, router address, MAC address or gateway can be found.
     pub fn new() -> Result<Self> {
-        let interface = get_default_interface()
-            .ok()
+        Interface::pick(None)
+    }
+
+    /// Picks the interface to send packets from.
+    ///
+    /// Prefers the interface that holds `src_ip`, then the interface of the default route,
+    /// then the first interface that is up, no loopback and has an IPv4 address, MAC address
+    /// and gateway. This way multi-homed machines send from the configured address, and
+    /// machines without a default route still find a usable interface.
+    /// A spoofed `src_ip` is held by no interface, so it falls back to the default route.
+    ///
+    /// May fail if none of them exists.
+    pub fn pick(src_ip: Option<IpAddr>) -> Result<Self> {
+        let interface = select_device(get_interfaces(), get_default_interface().ok(), src_ip)
             .ok_or(ScannerError::CantFindInterface)?;
+        Interface::from_device(interface)
+    }
+
+    /// Picks the interface with the given name, bypassing the detection of `Interface::pick`.
+    ///
+    /// May fail if no interface of that name exists or is up, or if it lacks an IPv4 address,
+    /// MAC address or gateway.
+    pub fn by_name(name: &str) -> Result<Self> {
+        let interface =
+            find_device(get_interfaces(), name).ok_or(ScannerError::CantFindInterface)?;
+        Interface::from_device(interface)
+    }
+
+    /// Finds the source IP address and interface the operating system uses to reach `dest_ip`.
+    ///
+    /// The right source depends on the route to the destination, e.g. a VPN or a second network.
+    /// Connecting a UDP socket sends no packets, but makes the operating system look up the
+    /// route in its routing table. The interface is the one that holds the source address.
+    ///
+    /// Loopback destinations are reached from the loopback address itself. The loopback
+    /// interface has no gateway, so the default interface is returned along with it.
+    ///
+    /// May fail if there is no route, or if the interface lacks an IPv4 address, MAC address or gateway.
+    pub fn resolve_source(dest_ip: IpAddr) -> Result<(IpAddr, Self)> {
+        if dest_ip.is_loopback() {
+            return Ok((dest_ip, Interface::new()?));
+        }
+
+        let src_ip = route_source(dest_ip)?;
+
+        let interface = get_interfaces()
+            .into_iter()
+            .find(|interface| holds_ip(interface, src_ip))
+            .ok_or(ScannerError::CantFindInterface)?;
+
+        Ok((src_ip, Interface::from_device(interface)?))
+    }
+
+    /// Returns whether `ip` is assigned to any interface of the machine.
+    ///
+    /// Responses to probes from other, i.e. spoofed, addresses are routed elsewhere.
+    pub fn is_local_ip(ip: IpAddr) -> bool {
+        get_interfaces()
+            .iter()
+            .any(|interface| holds_ip(interface, ip))
+    }
+
+    /// Returns whether `dest_ip` is on the subnet of the interface.
+    ///
+    /// Such hosts are reached directly at the data link layer, all others via the gateway.
+    pub fn is_on_link(&self, dest_ip: Ipv4Addr) -> bool {
+        self.ip.contains(dest_ip)
+    }
+
+    /// Creates a new `Interface` struct from an interface of the netdev crate.
+    fn from_device(interface: netdev::Interface) -> Result<Self> {
         let ip = *interface
             .ipv4
             .first()
@@ -54,12 +132,18 @@ impl Interface {
         let mac = interface
             .mac_addr
             .ok_or(ScannerError::CantFindInterfaceMac)?;
+        let link_local = interface
+            .ipv6
+            .iter()
+            .map(|net| net.addr)
+            .find(|ip| Ndp::is_link_local(*ip));
         let name = Interface::string_to_fixed_bytes(&interface.name);
         let gateway = Gateway::new(interface.gateway)?;
         let iface = Interface {
             index: interface.index,
             name,
             ip,
+            link_local,
             mac: convert_mac_address(mac),
             flags: interface.flags,
             gateway,
@@ -123,6 +207,127 @@ impl Gateway {
     }
 }
 
+/// Sources to send probes to a set of hosts from.
+///
+/// A specified source address is used for every host, along with its interface. An unspecified
+/// one, like `ROUTED_SOURCE`, stands for the source of the route to each host, see
+/// `Interface::resolve_source`. This way hosts behind a VPN, on a second network or of the
+/// other IP version are each reached from the right address.
+#[derive(Debug, Clone)]
+pub struct Routes {
+    interface: Interface,
+    src_ip: IpAddr,
+    resolved: HashMap<IpAddr, (IpAddr, Interface)>,
+}
+
+impl Routes {
+    /// Resolves the sources of the hosts, unless `src_ip` is specified.
+    ///
+    /// Hosts that share a source share its interface, which is only looked up once.
+    /// Hosts without a route are left out, see `source`.
+    pub fn resolve(interface: Interface, src_ip: IpAddr, hosts: &[IpAddr]) -> Routes {
+        let mut resolved = HashMap::new();
+
+        if src_ip.is_unspecified() {
+            let mut interfaces: HashMap<IpAddr, Interface> = HashMap::new();
+            for &host in hosts {
+                if resolved.contains_key(&host) {
+                    continue;
+                }
+                let source = match host.is_loopback() {
+                    true => Ok(host),
+                    false => route_source(host),
+                };
+                let route = source.and_then(|src_ip| match interfaces.get(&src_ip) {
+                    Some(interface) => Ok((src_ip, *interface)),
+                    None => Interface::resolve_source(host),
+                });
+                match route {
+                    Ok((src_ip, interface)) => {
+                        interfaces.insert(src_ip, interface);
+                        resolved.insert(host, (src_ip, interface));
+                    }
+                    Err(e) => debug!("No source found for {}: {}", host, e),
+                }
+            }
+        }
+
+        Routes {
+            interface,
+            src_ip,
+            resolved,
+        }
+    }
+
+    /// Returns the source IP address and interface to reach `dest_ip` from.
+    ///
+    /// May fail if the source is resolved per host, and there is no route to `dest_ip`.
+    pub fn source(&self, dest_ip: IpAddr) -> Result<(IpAddr, Interface)> {
+        if !self.src_ip.is_unspecified() {
+            return Ok((self.src_ip, self.interface));
+        }
+        let route = self
+            .resolved
+            .get(&dest_ip)
+            .ok_or(ScannerError::CantFindRoute(dest_ip))?;
+        Ok(*route)
+    }
+}
+
+/// Applies the fallback chain of `Interface::pick` to the interfaces of the machine.
+fn select_device(
+    devices: Vec<netdev::Interface>,
+    default: Option<netdev::Interface>,
+    src_ip: Option<IpAddr>,
+) -> Option<netdev::Interface> {
+    if let Some(src_ip) = src_ip {
+        if let Some(device) = devices.iter().find(|device| holds_ip(device, src_ip)) {
+            return Some(device.clone());
+        }
+    }
+
+    default.or_else(|| {
+        devices.into_iter().find(|device| {
+            device.is_up()
+                && !device.is_loopback()
+                && !device.ipv4.is_empty()
+                && device.mac_addr.is_some()
+                && device.gateway.is_some()
+        })
+    })
+}
+
+/// Returns the interface with the given name, provided that it is up.
+fn find_device(devices: Vec<netdev::Interface>, name: &str) -> Option<netdev::Interface> {
+    devices
+        .into_iter()
+        .find(|device| device.name == name && device.is_up())
+}
+
+/// Returns whether `ip` is one of the addresses of the interface.
+fn holds_ip(device: &netdev::Interface, ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => device.ipv4.iter().any(|net| net.addr == ip),
+        IpAddr::V6(ip) => device.ipv6.iter().any(|net| net.addr == ip),
+    }
+}
+
+/// Returns the source IP address of the route to `dest_ip`.
+fn route_source(dest_ip: IpAddr) -> Result<IpAddr> {
+    let unspecified = match dest_ip {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind((unspecified, 0))?;
+
+    // Any port will do, since nothing is sent. This is the discard port.
+    socket
+        .connect((dest_ip, 9))
+        .map_err(|_| ScannerError::CantFindRoute(dest_ip))?;
+
+    Ok(socket.local_addr()?.ip())
+}
+
 /// Converts `netdev::mac::MacAddr` to `pnet::util::MacAddr`.
 pub fn convert_mac_address(mac: netdev::mac::MacAddr) -> pnet::util::MacAddr {
     pnet::util::MacAddr(mac.0, mac.1, mac.2, mac.3, mac.4, mac.5)
@@ -139,4 +344,139 @@ mod tests {
         let string = Interface::fixed_bytes_to_string(&bytes);
         assert_eq!(input, string);
     }
+
+    #[test]
+    fn test_is_on_link() {
+        let interface = |prefix_len| Interface {
+            index: 0,
+            name: [0; MAX_INTERFACE_NAME_LENTGH],
+            ip: Ipv4Net::new(Ipv4Addr::new(192, 168, 1, 10), prefix_len),
+            link_local: None,
+            mac: MacAddr::zero(),
+            flags: 0,
+            gateway: Gateway {
+                ip: Ipv4Addr::new(192, 168, 1, 1),
+                mac: MacAddr::zero(),
+            },
+        };
+
+        let neighbor = Ipv4Addr::new(192, 168, 1, 20);
+        let other_subnet = Ipv4Addr::new(192, 168, 2, 20);
+        let remote = Ipv4Addr::new(8, 8, 8, 8);
+
+        let interface_24 = interface(24);
+        assert!(interface_24.is_on_link(neighbor));
+        assert!(!interface_24.is_on_link(other_subnet));
+        assert!(!interface_24.is_on_link(remote));
+
+        // A wider netmask puts more hosts on the link.
+        let interface_16 = interface(16);
+        assert!(interface_16.is_on_link(other_subnet));
+        assert!(!interface_16.is_on_link(remote));
+
+        // Only the interface itself is on the link of a /32.
+        assert!(!interface(32).is_on_link(neighbor));
+    }
+
+    #[test]
+    fn test_routes() -> Result<()> {
+        let interface = Interface::new()?;
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let remote = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+        // A specified source reaches every host, even those it wasn't resolved for.
+        let src_ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10));
+        let routes = Routes::resolve(interface, src_ip, &[]);
+        assert_eq!(routes.source(remote)?.0, src_ip);
+
+        // Otherwise each host is reached from the source of its route.
+        let routes = Routes::resolve(interface, ROUTED_SOURCE, &[localhost, localhost]);
+        assert_eq!(routes.source(localhost)?.0, localhost);
+        let error = routes.source(remote).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ScannerError>(),
+            Some(ScannerError::CantFindRoute(ip)) if *ip == remote
+        ));
+
+        Ok(())
+    }
+
+    /// Creates an interface of the netdev crate that is up and has all addresses.
+    #[cfg(unix)]
+    fn device(name: &str, ip: Ipv4Addr) -> netdev::Interface {
+        let mut device = netdev::Interface::dummy();
+        device.name = name.to_string();
+        device.ipv4 = vec![Ipv4Net::new(ip, 24)];
+        device.mac_addr = Some(netdev::mac::MacAddr::new(0, 1, 2, 3, 4, 5));
+        device.gateway = Some(NetworkDevice::new());
+        device.flags = libc::IFF_UP as u32;
+        device
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_select_device() {
+        let mut loopback = device("lo", Ipv4Addr::LOCALHOST);
+        loopback.flags |= libc::IFF_LOOPBACK as u32;
+        let mut down = device("eth0", Ipv4Addr::new(192, 168, 0, 10));
+        down.flags = 0;
+        let ethernet = device("eth1", Ipv4Addr::new(192, 168, 1, 10));
+        let wifi = device("wlan0", Ipv4Addr::new(10, 0, 0, 10));
+        let devices = vec![loopback, down, ethernet, wifi.clone()];
+
+        let select = |default: Option<&netdev::Interface>, src_ip: Option<[u8; 4]>| {
+            select_device(devices.clone(), default.cloned(), src_ip.map(IpAddr::from))
+                .map(|device| device.name)
+        };
+
+        // The interface that holds the source address wins over the default route.
+        let name = select(Some(&wifi), Some([192, 168, 1, 10]));
+        assert_eq!(name.as_deref(), Some("eth1"));
+
+        // Spoofed source addresses fall back to the default route.
+        let name = select(Some(&wifi), Some([172, 16, 0, 1]));
+        assert_eq!(name.as_deref(), Some("wlan0"));
+
+        // Without a default route, loopback and down interfaces are skipped.
+        assert_eq!(select(None, None).as_deref(), Some("eth1"));
+
+        assert!(select_device(Vec::new(), None, None).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_device() {
+        let mut down = device("eth0", Ipv4Addr::new(192, 168, 0, 10));
+        down.flags = 0;
+        let ethernet = device("eth1", Ipv4Addr::new(192, 168, 1, 10));
+        let devices = vec![down, ethernet];
+
+        let device = find_device(devices.clone(), "eth1");
+        assert_eq!(device.map(|device| device.name).as_deref(), Some("eth1"));
+
+        // Unknown and down interfaces are not found.
+        assert!(find_device(devices.clone(), "wlan0").is_none());
+        assert!(find_device(devices, "eth0").is_none());
+    }
+
+    #[test]
+    fn test_by_name_not_found() {
+        let error = Interface::by_name("no-such-interface").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ScannerError>(),
+            Some(ScannerError::CantFindInterface)
+        ));
+    }
+
+    #[test]
+    fn test_route_source() -> Result<()> {
+        // The loopback interface routes to itself.
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        assert_eq!(route_source(localhost)?, localhost);
+
+        let other_localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+        assert!(route_source(other_localhost)?.is_loopback());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,550 @@
+use super::{
+    osi_layers::{
+        ipv6_upper_layer, quoted_ports, send_with_backoff, Backend, Layer, RECEIVE_POLL_INTERVAL,
+    },
+    sctp::Sctp,
+};
+use anyhow::Result;
+use log::debug;
+use pnet::{
+    datalink::{self, DataLinkSender, NetworkInterface},
+    packet::{
+        ethernet::{EtherTypes, EthernetPacket},
+        icmp::{destination_unreachable::DestinationUnreachablePacket, IcmpPacket, IcmpTypes},
+        icmpv6::{Icmpv6Packet, Icmpv6Types},
+        ip::{IpNextHeaderProtocol, IpNextHeaderProtocols},
+        ipv4::Ipv4Packet,
+        ipv6::Ipv6Packet,
+        tcp::TcpPacket,
+        udp::UdpPacket,
+        Packet,
+    },
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender, SyncSender},
+        Arc, Mutex, OnceLock, RwLock,
+    },
+    thread,
+    time::Instant,
+};
+
+/// Default number of threads that match captured frames against the waiting probes.
+pub const DEFAULT_RECEIVE_WORKERS: usize = 2;
+
+/// Number of matching threads of all matchers, chosen once at startup, see `ResponseMatcher::set_workers`.
+static RECEIVE_WORKERS: OnceLock<usize> = OnceLock::new();
+
+/// Number of captured frames a matching thread may have queued.
+///
+/// Once a queue is full the capture thread waits, so that the kernel drops frames
+/// instead of the queue growing without bound.
+const WORKER_QUEUE_CAPACITY: usize = 1024;
+
+/// Matchers of the interfaces used so far, keyed by interface index. They are never removed.
+static MATCHERS: OnceLock<Mutex<HashMap<u32, Arc<ResponseMatcher>>>> = OnceLock::new();
+
+/// Number of ICMP control messages captured since the start of the program, see `ControlMessage`.
+static CONTROL_MESSAGES: AtomicU64 = AtomicU64::new(0);
+
+/// ICMP messages that routers send to steer traffic, rather than to answer a probe.
+///
+/// They may arrive while scanning, e.g. if a router knows a better route to the target.
+/// Since they say nothing about the state of a port, no probe receives them.
+/// Otherwise a control message from the target would match its probes, like any ICMP message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlMessage {
+    /// ICMP or ICMPv6 redirect, which points to another gateway for the destination.
+    Redirect,
+    /// Deprecated ICMP source quench, which asks the sender to slow down, see RFC 6633.
+    SourceQuench,
+}
+
+impl fmt::Display for ControlMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ControlMessage::Redirect => write!(f, "redirect"),
+            ControlMessage::SourceQuench => write!(f, "source quench"),
+        }
+    }
+}
+
+impl ControlMessage {
+    /// Returns the control message a captured frame carries and the address of its sender.
+    pub fn of_frame(frame: &[u8]) -> Option<(ControlMessage, IpAddr)> {
+        let ethernet_packet = EthernetPacket::new(frame)?;
+        match ethernet_packet.get_ethertype() {
+            EtherTypes::Ipv4 => {
+                let ip = Ipv4Packet::new(ethernet_packet.payload())?;
+                if ip.get_next_level_protocol() != IpNextHeaderProtocols::Icmp {
+                    return None;
+                }
+                let message = match IcmpPacket::new(ip.payload())?.get_icmp_type() {
+                    IcmpTypes::RedirectMessage => ControlMessage::Redirect,
+                    IcmpTypes::SourceQuench => ControlMessage::SourceQuench,
+                    _ => return None,
+                };
+                Some((message, ip.get_source().into()))
+            }
+            EtherTypes::Ipv6 => {
+                let ip = Ipv6Packet::new(ethernet_packet.payload())?;
+                let (protocol, payload) = ipv6_upper_layer(ip.get_next_header(), ip.payload())?;
+                if protocol != IpNextHeaderProtocols::Icmpv6 {
+                    return None;
+                }
+                match Icmpv6Packet::new(payload)?.get_icmpv6_type() {
+                    Icmpv6Types::Redirect => {
+                        Some((ControlMessage::Redirect, ip.get_source().into()))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the number of control messages captured since the start of the program.
+    pub fn captured() -> u64 {
+        CONTROL_MESSAGES.load(Ordering::Relaxed)
+    }
+}
+
+/// Addresses and ports of a frame, from the perspective of the response.
+///
+/// ICMP errors are keyed by the datagram they quote, so they reach the probe that caused them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub src_addr: IpAddr,
+    pub dest_addr: IpAddr,
+    pub src_port: u16,
+    pub dest_port: u16,
+}
+
+impl FlowKey {
+    /// Returns the key of the responses a layer waits for.
+    ///
+    /// Only transport layers with both addresses and both ports have a key.
+    pub fn of_layer(layer: &Layer) -> Option<FlowKey> {
+        let Layer::Four(transport_layer) = layer else {
+            return None;
+        };
+        let network_layer = transport_layer.network_layer?;
+        Some(FlowKey {
+            src_addr: network_layer.src_addr?,
+            dest_addr: network_layer.dest_addr?,
+            src_port: transport_layer.src_port?,
+            dest_port: transport_layer.dest_port?,
+        })
+    }
+
+    /// Returns the key of a captured frame, if it carries a TCP, UDP or SCTP header
+    /// or an ICMP unreachable error that quotes one.
+    pub fn of_frame(frame: &[u8]) -> Option<FlowKey> {
+        let ethernet_packet = EthernetPacket::new(frame)?;
+        let (src_addr, dest_addr, ports) = match ethernet_packet.get_ethertype() {
+            EtherTypes::Ipv4 => {
+                let ip = Ipv4Packet::new(ethernet_packet.payload())?;
+                let ports = response_ports(ip.get_next_level_protocol(), ip.payload());
+                (ip.get_source().into(), ip.get_destination().into(), ports)
+            }
+            EtherTypes::Ipv6 => {
+                let ip = Ipv6Packet::new(ethernet_packet.payload())?;
+                let ports = ipv6_upper_layer(ip.get_next_header(), ip.payload())
+                    .and_then(|(protocol, payload)| response_ports(protocol, payload));
+                (ip.get_source().into(), ip.get_destination().into(), ports)
+            }
+            _ => return None,
+        };
+        let (src_port, dest_port) = ports?;
+
+        Some(FlowKey {
+            src_addr,
+            dest_addr,
+            src_port,
+            dest_port,
+        })
+    }
+}
+
+/// Extracts the ports of a transport header the same way `TransportLayer::match_packet` does.
+///
+/// The ports quoted in ICMP errors are swapped, as they belong to the datagram we sent.
+fn response_ports(protocol: IpNextHeaderProtocol, payload: &[u8]) -> Option<(u16, u16)> {
+    let quoted_ports =
+        |quoted: &[u8]| quoted_ports(quoted).map(|(src_port, dest_port)| (dest_port, src_port));
+
+    match protocol {
+        IpNextHeaderProtocols::Tcp => {
+            TcpPacket::new(payload).map(|tcp| (tcp.get_source(), tcp.get_destination()))
+        }
+        IpNextHeaderProtocols::Udp => {
+            UdpPacket::new(payload).map(|udp| (udp.get_source(), udp.get_destination()))
+        }
+        IpNextHeaderProtocols::Sctp => Sctp::ports(payload),
+        IpNextHeaderProtocols::Icmp => match IcmpPacket::new(payload)?.get_icmp_type() {
+            IcmpTypes::DestinationUnreachable => {
+                quoted_ports(DestinationUnreachablePacket::new(payload)?.payload())
+            }
+            _ => None,
+        },
+        IpNextHeaderProtocols::Icmpv6 => {
+            let icmp = Icmpv6Packet::new(payload)?;
+            match icmp.get_icmpv6_type() {
+                // Skip the unused 4 bytes in front of the quoted datagram.
+                Icmpv6Types::DestinationUnreachable => quoted_ports(icmp.payload().get(4..)?),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// A probe that waits for its response.
+struct Waiter {
+    id: u64,
+    layer: Layer,
+    responses: Sender<Vec<u8>>,
+}
+
+/// Probes that wait for responses, indexed by the key of their responses.
+///
+/// Most probes have a key, so a frame only needs to be matched against the few probes of its
+/// flow. Probes without a key, e.g. ARP requests, are matched against every frame.
+#[derive(Default)]
+pub struct Waiters {
+    next_id: u64,
+    keyed: HashMap<FlowKey, Vec<Waiter>>,
+    unkeyed: Vec<Waiter>,
+}
+
+impl Waiters {
+    /// Registers a probe that waits for a response matching the layer.
+    ///
+    /// Returns the ID of the probe and the receiver its responses are delivered to.
+    pub fn register(&mut self, layer: Layer) -> (u64, Receiver<Vec<u8>>) {
+        let (responses, receiver) = mpsc::channel();
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let waiter = Waiter {
+            id,
+            layer,
+            responses,
+        };
+        match FlowKey::of_layer(&layer) {
+            Some(key) => self.keyed.entry(key).or_default().push(waiter),
+            None => self.unkeyed.push(waiter),
+        }
+
+        (id, receiver)
+    }
+
+    /// Removes a probe, which no longer receives responses.
+    pub fn unregister(&mut self, id: u64, layer: &Layer) {
+        match FlowKey::of_layer(layer) {
+            Some(key) => {
+                if let Some(waiters) = self.keyed.get_mut(&key) {
+                    waiters.retain(|waiter| waiter.id != id);
+                    if waiters.is_empty() {
+                        self.keyed.remove(&key);
+                    }
+                }
+            }
+            None => self.unkeyed.retain(|waiter| waiter.id != id),
+        }
+    }
+
+    /// Delivers a captured frame to every probe whose layer it matches.
+    ///
+    /// Frames without a key, e.g. ICMP echo replies, may still match a keyed probe,
+    /// so they are matched against all probes. ICMP control messages are only counted.
+    pub fn dispatch(&self, frame: &[u8]) {
+        if let Some((message, sender)) = ControlMessage::of_frame(frame) {
+            CONTROL_MESSAGES.fetch_add(1, Ordering::Relaxed);
+            debug!("Ignored an ICMP {} from {}.", message, sender);
+            return;
+        }
+
+        let deliver = |waiter: &Waiter| {
+            if waiter.layer.match_layer(frame) {
+                // The probe may just have timed out and dropped its receiver.
+                let _ = waiter.responses.send(frame.to_vec());
+            }
+        };
+
+        match FlowKey::of_frame(frame) {
+            Some(key) => self.keyed.get(&key).into_iter().flatten().for_each(deliver),
+            None => self.keyed.values().flatten().for_each(deliver),
+        }
+        self.unkeyed.iter().for_each(deliver);
+    }
+}
+
+/// Shared data link channel of an interface, which probes send on and receive from.
+///
+/// Instead of every probe reading all frames of its own channel, a single thread captures the
+/// frames and hands them in turn to a pool of workers, each with its own bounded queue.
+/// The workers match frames side by side under a shared read lock, against the probes that wait
+/// for a response, see `Waiters::dispatch`. Only registering and removing probes takes the write
+/// lock. This way bursts of probes don't outrun the reader, and sending never waits for a read.
+///
+/// A matcher is started on the first use of its interface and lives, like its threads, for the
+/// rest of the process, so that the probes of an interface never wait for a channel to open.
+pub struct ResponseMatcher {
+    sender: Mutex<Box<dyn DataLinkSender>>,
+    waiters: Arc<RwLock<Waiters>>,
+}
+
+impl ResponseMatcher {
+    /// Sets the number of matching threads of all matchers started from now on.
+    ///
+    /// Can only be set once, later calls are ignored.
+    pub fn set_workers(workers: usize) {
+        if RECEIVE_WORKERS.set(workers.max(1)).is_err() {
+            debug!("The number of receive workers was already set.");
+        }
+    }
+
+    /// Returns the matcher of the interface, which is started on first use.
+    ///
+    /// May fail if the channel can't be opened, e.g. without root privileges.
+    pub fn for_interface(interface: &NetworkInterface) -> Result<Arc<ResponseMatcher>> {
+        let mut matchers = MATCHERS.get_or_init(Default::default).lock().unwrap();
+        if let Some(matcher) = matchers.get(&interface.index) {
+            return Ok(matcher.clone());
+        }
+
+        let matcher = Arc::new(ResponseMatcher::start(interface)?);
+        matchers.insert(interface.index, matcher.clone());
+        Ok(matcher)
+    }
+
+    /// Opens a channel to the interface and starts the capture and matching threads.
+    fn start(interface: &NetworkInterface) -> Result<ResponseMatcher> {
+        let config = datalink::Config {
+            read_timeout: Some(RECEIVE_POLL_INTERVAL),
+            ..Default::default()
+        };
+        let (sender, mut receiver) = Backend::current().open_channel(interface, config)?;
+
+        let waiters = Arc::new(RwLock::new(Waiters::default()));
+        let workers = *RECEIVE_WORKERS.get().unwrap_or(&DEFAULT_RECEIVE_WORKERS);
+
+        let queues: Vec<SyncSender<Vec<u8>>> = (0..workers)
+            .map(|_| {
+                let (queue, captured) = mpsc::sync_channel::<Vec<u8>>(WORKER_QUEUE_CAPACITY);
+                let waiters = waiters.clone();
+                thread::spawn(move || {
+                    for frame in captured {
+                        waiters.read().unwrap().dispatch(&frame);
+                    }
+                });
+                queue
+            })
+            .collect();
+
+        // The capture thread only copies frames, so that it keeps up with bursts.
+        thread::spawn(move || {
+            for queue in queues.iter().cycle() {
+                let Ok(frame) = receiver.next() else {
+                    continue;
+                };
+                if queue.send(frame.to_vec()).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(ResponseMatcher {
+            sender: Mutex::new(sender),
+            waiters,
+        })
+    }
+
+    /// Registers a probe that waits for a response matching the layer.
+    ///
+    /// Must be called before the probe is sent, so that no early response is missed.
+    pub fn register(self: &Arc<Self>, layer: Layer) -> Registration {
+        let (id, responses) = self.waiters.write().unwrap().register(layer);
+        Registration {
+            matcher: self.clone(),
+            id,
+            layer,
+            responses,
+        }
+    }
+
+    /// Sends a frame on the channel, retrying transient errors.
+    pub fn send(&self, frame: &[u8]) -> Result<()> {
+        let mut sender = self.sender.lock().unwrap();
+        send_with_backoff(|| sender.send_to(frame, None))
+    }
+}
+
+/// A registered probe, which is removed from the matcher when dropped.
+pub struct Registration {
+    matcher: Arc<ResponseMatcher>,
+    id: u64,
+    layer: Layer,
+    responses: Receiver<Vec<u8>>,
+}
+
+impl Registration {
+    /// Waits until `deadline` for the first matching response.
+    pub fn wait(&self, deadline: Instant) -> Option<Vec<u8>> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        self.responses.recv_timeout(timeout).ok()
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.matcher
+            .waiters
+            .write()
+            .unwrap()
+            .unregister(self.id, &self.layer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::{
+        osi_layers::{DatalinkLayer, NetworkLayer, TransportLayer},
+        probe::{build_ipv4_header, ProbeOptions, IPV4_HEADER_SIZE},
+        tcp::Tcp,
+    };
+    use pnet::{
+        packet::{icmp::MutableIcmpPacket, tcp::TcpFlags},
+        util::MacAddr,
+    };
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_dispatch_by_flow_key() {
+        let target = Ipv4Addr::new(192, 168, 0, 2);
+        let local = Ipv4Addr::new(192, 168, 0, 1);
+        let network_layer = NetworkLayer {
+            datalink_layer: None,
+            src_addr: Some(target.into()),
+            dest_addr: Some(local.into()),
+            protocol: None,
+        };
+        let probe = |src_port| {
+            Layer::Four(TransportLayer {
+                network_layer: Some(network_layer),
+                src_port: Some(80),
+                dest_port: Some(src_port),
+                tcp_sequence: None,
+                icmp_echo: None,
+            })
+        };
+
+        let mut waiters = Waiters::default();
+        let (first_id, first) = waiters.register(probe(40000));
+        let (_, second) = waiters.register(probe(40001));
+        let (_, host) = waiters.register(Layer::Three(network_layer));
+
+        let (response, _) = Tcp::build_tcp_packet(
+            target,
+            80,
+            local,
+            40000,
+            TcpFlags::SYN | TcpFlags::ACK,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let frame = DatalinkLayer::build_ethernet_frame(
+            MacAddr::zero(),
+            MacAddr::zero(),
+            EtherTypes::Ipv4,
+            &response,
+        );
+        assert_eq!(FlowKey::of_frame(&frame), FlowKey::of_layer(&probe(40000)));
+
+        // Only the probe of the flow and the probe without a key receive the response.
+        waiters.dispatch(&frame);
+        assert_eq!(first.try_recv().ok(), Some(frame.clone()));
+        assert!(second.try_recv().is_err());
+        assert_eq!(host.try_recv().ok(), Some(frame.clone()));
+
+        // Removed probes receive nothing.
+        waiters.unregister(first_id, &probe(40000));
+        waiters.dispatch(&frame);
+        assert!(first.try_recv().is_err());
+        assert!(host.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_dispatch_ignores_control_messages() {
+        let target = Ipv4Addr::new(192, 168, 0, 2);
+        let local = Ipv4Addr::new(192, 168, 0, 1);
+        let network_layer = NetworkLayer {
+            datalink_layer: None,
+            src_addr: Some(target.into()),
+            dest_addr: Some(local.into()),
+            protocol: None,
+        };
+        let probe = Layer::Four(TransportLayer {
+            network_layer: Some(network_layer),
+            src_port: Some(80),
+            dest_port: Some(40000),
+            tcp_sequence: None,
+            icmp_echo: None,
+        });
+
+        // A redirect from the target, which would match its probes like any other ICMP message.
+        let mut packet = vec![0u8; IPV4_HEADER_SIZE + 8];
+        build_ipv4_header(
+            &mut packet,
+            target,
+            local,
+            IpNextHeaderProtocols::Icmp,
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let mut icmp_packet = MutableIcmpPacket::new(&mut packet[IPV4_HEADER_SIZE..]).unwrap();
+        icmp_packet.set_icmp_type(IcmpTypes::RedirectMessage);
+        let frame = DatalinkLayer::build_ethernet_frame(
+            MacAddr::zero(),
+            MacAddr::zero(),
+            EtherTypes::Ipv4,
+            &packet,
+        );
+        assert!(probe.match_layer(&frame));
+        assert_eq!(
+            ControlMessage::of_frame(&frame),
+            Some((ControlMessage::Redirect, target.into()))
+        );
+
+        let mut waiters = Waiters::default();
+        let (_, responses) = waiters.register(probe);
+        let (_, host) = waiters.register(Layer::Three(network_layer));
+
+        let captured = ControlMessage::captured();
+        waiters.dispatch(&frame);
+        assert!(responses.try_recv().is_err());
+        assert!(host.try_recv().is_err());
+        assert!(ControlMessage::captured() > captured);
+
+        // Other ICMP messages are still delivered.
+        let mut icmp_packet = MutableIcmpPacket::new(&mut packet[IPV4_HEADER_SIZE..]).unwrap();
+        icmp_packet.set_icmp_type(IcmpTypes::TimeExceeded);
+        let frame = DatalinkLayer::build_ethernet_frame(
+            MacAddr::zero(),
+            MacAddr::zero(),
+            EtherTypes::Ipv4,
+            &packet,
+        );
+        assert_eq!(ControlMessage::of_frame(&frame), None);
+        waiters.dispatch(&frame);
+        assert!(host.try_recv().is_ok());
+    }
+}
@@ -1,7 +1,14 @@
 pub mod arp;
+pub mod dns;
 pub mod icmp;
 pub mod interface;
+pub mod matcher;
+pub mod ndp;
 pub mod osi_layers;
+pub mod packet_log;
+pub mod probe;
+pub mod sctp;
 pub mod socket_iterator;
+pub mod socks;
 pub mod tcp;
 pub mod udp;
@@ -0,0 +1,331 @@
+use super::{
+    interface::Interface,
+    osi_layers::{DatalinkLayer, Layer, NetworkLayer},
+    probe::{build_ipv6_header, ProbeOptions, IPV6_HEADER_SIZE},
+};
+use crate::errors::ScannerError;
+use anyhow::Result;
+use pnet::{
+    packet::{
+        ethernet::{EtherTypes, EthernetPacket},
+        icmpv6::{
+            self,
+            ndp::{MutableNeighborSolicitPacket, NdpOption, NdpOptionTypes, NeighborAdvertPacket},
+            Icmpv6Code, Icmpv6Packet, Icmpv6Types,
+        },
+        ip::IpNextHeaderProtocols,
+        ipv6::Ipv6Packet,
+        Packet,
+    },
+    util::MacAddr,
+};
+use std::{
+    collections::HashMap,
+    net::Ipv6Addr,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+/// Hop limit of all neighbor discovery messages.
+/// Receivers drop messages with any other hop limit, since a router must have forwarded them.
+const NDP_HOP_LIMIT: u8 = 255;
+
+/// Size of a neighbor solicitation without options.
+const NEIGHBOR_SOLICIT_SIZE: usize = 24;
+
+/// Size of an option that carries an Ethernet address. Its length field counts units of 8 bytes.
+const LINK_LAYER_OPTION_SIZE: usize = 8;
+
+/// MAC addresses resolved so far, shared by all probes, like the ARP cache of `Arp::next_hop_mac`.
+static NEIGHBORS: OnceLock<Mutex<HashMap<Ipv6Addr, MacAddr>>> = OnceLock::new();
+
+/// Neighbor Discovery Protocol of RFC 4861, the IPv6 counterpart to ARP.
+pub struct Ndp;
+
+impl Ndp {
+    /// Returns the solicited-node multicast address of `target`, e.g. `ff02::1:ff00:1` for `fe80::1`.
+    ///
+    /// It consists of the prefix `ff02::1:ff00:0/104` and the lower 24 bits of the target.
+    /// Only hosts whose addresses end in these bits listen on it, so solicitations don't reach
+    /// every host on the link like ARP broadcasts do.
+    pub fn solicited_node_multicast(target: Ipv6Addr) -> Ipv6Addr {
+        let octets = target.octets();
+        Ipv6Addr::new(
+            0xff02,
+            0,
+            0,
+            0,
+            0,
+            1,
+            0xff00 | octets[13] as u16,
+            u16::from_be_bytes([octets[14], octets[15]]),
+        )
+    }
+
+    /// Returns the Ethernet address of an IPv6 multicast address, `33:33` followed by its lower
+    /// 32 bits, see RFC 2464.
+    pub fn multicast_mac(ip: Ipv6Addr) -> MacAddr {
+        let octets = ip.octets();
+        MacAddr::new(0x33, 0x33, octets[12], octets[13], octets[14], octets[15])
+    }
+
+    /// Returns whether `ip` is a link-local unicast address in `fe80::/10`.
+    ///
+    /// Such addresses are only valid on the link, so their hosts are always reached directly.
+    pub fn is_link_local(ip: Ipv6Addr) -> bool {
+        ip.segments()[0] & 0xffc0 == 0xfe80
+    }
+
+    /// Constructs an IPv6 packet with a neighbor solicitation for the MAC address of `target`.
+    ///
+    /// The solicitation is sent to the solicited-node multicast address of the target and
+    /// carries our MAC address, so that the target can answer right away.
+    pub fn build_neighbor_solicitation(
+        src_mac: MacAddr,
+        src_ip: Ipv6Addr,
+        target: Ipv6Addr,
+    ) -> Result<Vec<u8>> {
+        let dest_ip = Ndp::solicited_node_multicast(target);
+
+        let mut packet =
+            vec![0u8; IPV6_HEADER_SIZE + NEIGHBOR_SOLICIT_SIZE + LINK_LAYER_OPTION_SIZE];
+
+        let options = ProbeOptions {
+            ttl: NDP_HOP_LIMIT,
+            ..Default::default()
+        };
+        build_ipv6_header(
+            &mut packet,
+            src_ip,
+            dest_ip,
+            IpNextHeaderProtocols::Icmpv6,
+            &options,
+        )?;
+
+        let mut solicit = MutableNeighborSolicitPacket::new(&mut packet[IPV6_HEADER_SIZE..])
+            .ok_or(ScannerError::CantCreateIcmpPacket)?;
+        solicit.set_icmpv6_type(Icmpv6Types::NeighborSolicit);
+        solicit.set_icmpv6_code(Icmpv6Code(0));
+        solicit.set_target_addr(target);
+        solicit.set_options(&[NdpOption {
+            option_type: NdpOptionTypes::SourceLLAddr,
+            length: (LINK_LAYER_OPTION_SIZE / 8) as u8,
+            data: src_mac.octets().to_vec(),
+        }]);
+
+        let icmp_packet = Icmpv6Packet::new(&packet[IPV6_HEADER_SIZE..])
+            .ok_or(ScannerError::CantCreateIcmpPacket)?;
+        let checksum = icmpv6::checksum(&icmp_packet, &src_ip, &dest_ip);
+        MutableNeighborSolicitPacket::new(&mut packet[IPV6_HEADER_SIZE..])
+            .ok_or(ScannerError::CantCreateIcmpPacket)?
+            .set_checksum(checksum);
+
+        Ok(packet)
+    }
+
+    /// Sends a neighbor solicitation to retrieve the MAC address of `target`.
+    ///
+    /// The packet is handed over to the data link layer, addressed to the multicast MAC address
+    /// of the solicited-node multicast address.
+    pub fn send_solicitation(
+        interface: Interface,
+        src_ip: Ipv6Addr,
+        target: Ipv6Addr,
+        timeout: Duration,
+    ) -> Result<(Option<MacAddr>, Duration)> {
+        let iface = interface.convert_interface()?;
+
+        let packet = Ndp::build_neighbor_solicitation(interface.mac, src_ip, target)?;
+
+        let ethernet_type = EtherTypes::Ipv6;
+
+        let data_link_layer = DatalinkLayer {
+            src_mac: None,
+            dest_mac: None,
+            ethernet_type: Some(ethernet_type),
+        };
+
+        let network_layer = NetworkLayer {
+            datalink_layer: Some(data_link_layer),
+            src_addr: Some(target.into()),
+            dest_addr: Some(src_ip.into()),
+            protocol: None,
+        };
+
+        let layer = Layer::Three(network_layer);
+
+        let dest_mac = Ndp::multicast_mac(Ndp::solicited_node_multicast(target));
+
+        let (response, rtt) = DatalinkLayer::send_and_receive(
+            &iface,
+            dest_mac,
+            ethernet_type,
+            &[packet],
+            layer,
+            timeout,
+        )?;
+
+        match response {
+            Some(packet) => Ok((Ndp::get_mac_address(&packet, target), rtt)),
+            None => Ok((None, rtt)),
+        }
+    }
+
+    /// Returns the MAC address that frames to `dest_ip` must be sent to.
+    ///
+    /// Link-local hosts are addressed directly, so their MAC address is resolved via neighbor
+    /// discovery. Frames to all other hosts go to the gateway, which routes them further.
+    ///
+    /// May fail if the interface has no link-local address, if the host does not answer,
+    /// or if the MAC address of the gateway is unknown.
+    pub fn next_hop_mac(
+        interface: Interface,
+        dest_ip: Ipv6Addr,
+        timeout: Duration,
+    ) -> Result<MacAddr> {
+        // Frames to the zero MAC address would be sent, but never reach a router.
+        if !Ndp::is_link_local(dest_ip) {
+            return match interface.gateway.mac {
+                mac if mac == MacAddr::zero() => Err(ScannerError::CantResolveMac(dest_ip.into()))?,
+                mac => Ok(mac),
+            };
+        }
+
+        let neighbors = NEIGHBORS.get_or_init(Default::default);
+        if let Some(mac) = neighbors.lock().unwrap().get(&dest_ip) {
+            return Ok(*mac);
+        }
+
+        let src_ip = interface
+            .link_local
+            .ok_or(ScannerError::CantFindInterfaceIp)?;
+        let (mac, _) = Ndp::send_solicitation(interface, src_ip, dest_ip, timeout)?;
+        let mac = mac.ok_or(ScannerError::CantResolveMac(dest_ip.into()))?;
+
+        neighbors.lock().unwrap().insert(dest_ip, mac);
+
+        Ok(mac)
+    }
+
+    /// Extracts the MAC address of `target` from a neighbor advertisement.
+    ///
+    /// Prefers the target link-layer address option, which advertisements in response
+    /// to multicast solicitations must carry. Otherwise falls back to the sender of the frame.
+    pub fn get_mac_address(packet: &[u8], target: Ipv6Addr) -> Option<MacAddr> {
+        let ethernet_packet = EthernetPacket::new(packet)?;
+        if ethernet_packet.get_ethertype() != EtherTypes::Ipv6 {
+            return None;
+        }
+
+        let ipv6_packet = Ipv6Packet::new(ethernet_packet.payload())?;
+        if ipv6_packet.get_next_header() != IpNextHeaderProtocols::Icmpv6 {
+            return None;
+        }
+
+        let advert = NeighborAdvertPacket::new(ipv6_packet.payload())?;
+        if advert.get_icmpv6_type() != Icmpv6Types::NeighborAdvert
+            || advert.get_target_addr() != target
+        {
+            return None;
+        }
+
+        let advertised_mac = advert
+            .get_options()
+            .into_iter()
+            .find(|option| option.option_type == NdpOptionTypes::TargetLLAddr)
+            .and_then(|option| <[u8; 6]>::try_from(option.data.get(..6)?).ok())
+            .map(MacAddr::from);
+
+        Some(advertised_mac.unwrap_or(ethernet_packet.get_source()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pnet::packet::icmpv6::ndp::{MutableNeighborAdvertPacket, NeighborSolicitPacket};
+
+    #[test]
+    fn test_solicited_node_multicast() {
+        let target: Ipv6Addr = "fe80::1".parse().unwrap();
+        assert_eq!(
+            Ndp::solicited_node_multicast(target),
+            "ff02::1:ff00:1".parse::<Ipv6Addr>().unwrap()
+        );
+
+        // Only the lower 24 bits of the target are kept.
+        let target: Ipv6Addr = "2001:db8::2aa:ff:fe28:9c5a".parse().unwrap();
+        let multicast = Ndp::solicited_node_multicast(target);
+        assert_eq!(multicast, "ff02::1:ff28:9c5a".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(
+            Ndp::multicast_mac(multicast),
+            MacAddr::new(0x33, 0x33, 0xff, 0x28, 0x9c, 0x5a)
+        );
+
+        assert!(!Ndp::is_link_local(target));
+        assert!(Ndp::is_link_local(
+            "fe80::2aa:ff:fe28:9c5a".parse().unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_get_mac_address() -> Result<()> {
+        let src_mac = MacAddr::new(0, 1, 2, 3, 4, 5);
+        let target_mac = MacAddr::new(6, 7, 8, 9, 10, 11);
+        let src_ip: Ipv6Addr = "fe80::1".parse()?;
+        let target: Ipv6Addr = "fe80::2".parse()?;
+
+        let solicitation = Ndp::build_neighbor_solicitation(src_mac, src_ip, target)?;
+        let ipv6_packet = Ipv6Packet::new(&solicitation).unwrap();
+        assert_eq!(ipv6_packet.get_hop_limit(), NDP_HOP_LIMIT);
+        assert_eq!(
+            ipv6_packet.get_destination(),
+            Ndp::solicited_node_multicast(target)
+        );
+        let solicit = NeighborSolicitPacket::new(ipv6_packet.payload()).unwrap();
+        assert_eq!(solicit.get_target_addr(), target);
+        assert_eq!(solicit.get_options()[0].data, src_mac.octets());
+
+        // The advertisement of the target carries its MAC address as an option.
+        let mut advert = solicitation.clone();
+        let mut advert_packet = MutableNeighborAdvertPacket::new(&mut advert[IPV6_HEADER_SIZE..])
+            .ok_or(ScannerError::CantCreateIcmpPacket)?;
+        advert_packet.set_icmpv6_type(Icmpv6Types::NeighborAdvert);
+        advert_packet.set_options(&[NdpOption {
+            option_type: NdpOptionTypes::TargetLLAddr,
+            length: 1,
+            data: target_mac.octets().to_vec(),
+        }]);
+        let frame = |packet: &[u8]| {
+            DatalinkLayer::build_ethernet_frame(MacAddr::zero(), src_mac, EtherTypes::Ipv6, packet)
+        };
+        assert_eq!(
+            Ndp::get_mac_address(&frame(&advert), target),
+            Some(target_mac)
+        );
+
+        // Our own solicitation and advertisements for other targets don't count.
+        assert_eq!(Ndp::get_mac_address(&frame(&solicitation), target), None);
+        assert_eq!(Ndp::get_mac_address(&frame(&advert), src_ip), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_hop_mac_of_unknown_gateway() -> Result<()> {
+        let mut interface = Interface::new()?;
+        let dest_ip: Ipv6Addr = "2001:db8::1".parse()?;
+        let timeout = Duration::from_millis(1);
+
+        interface.gateway.mac = MacAddr::new(0, 1, 2, 3, 4, 5);
+        assert_eq!(
+            Ndp::next_hop_mac(interface, dest_ip, timeout)?,
+            interface.gateway.mac
+        );
+
+        interface.gateway.mac = MacAddr::zero();
+        assert!(Ndp::next_hop_mac(interface, dest_ip, timeout).is_err());
+
+        Ok(())
+    }
+}
@@ -1,27 +1,149 @@
-use super::interface::Interface;
+use super::{
+    arp::Arp,
+    interface::Interface,
+    matcher::ResponseMatcher,
+    ndp::Ndp,
+    packet_log::log_frame,
+    probe::{fragment_ipv4_packet, ProbeOptions, IPV6_HEADER_SIZE},
+    sctp::Sctp,
+};
 use crate::errors::{ChannelError, ScannerError};
 use anyhow::Result;
+use log::debug;
 use pnet::{
-    datalink::{self, Channel, NetworkInterface},
+    datalink::{self, Channel, DataLinkReceiver, DataLinkSender, NetworkInterface},
     packet::{
         arp::ArpPacket,
         ethernet::{EtherType, EtherTypes, EthernetPacket, MutableEthernetPacket},
-        ip::IpNextHeaderProtocols,
-        ipv4::Ipv4Packet,
-        tcp::TcpPacket,
+        icmp::{
+            destination_unreachable::DestinationUnreachablePacket, echo_reply::EchoReplyPacket,
+            echo_request::EchoRequestPacket, IcmpPacket, IcmpTypes,
+        },
+        icmpv6::{Icmpv6Packet, Icmpv6Types},
+        ip::{IpNextHeaderProtocol, IpNextHeaderProtocols},
+        ipv4::{self, Ipv4Packet},
+        ipv6::Ipv6Packet,
+        tcp::{self, TcpFlags, TcpPacket},
         udp::UdpPacket,
         Packet,
     },
     util::MacAddr,
 };
 use std::{
+    io::{self, ErrorKind},
     net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    thread,
     time::{Duration, Instant},
 };
 
 const ETHERNET_HEADER_SIZE: usize = 14;
 
+/// Longest time a read on the data link channel blocks before the timeout is checked again.
+/// Without a read timeout, the channel would block forever on a quiet network.
+pub const RECEIVE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Number of times a send on the data link channel is retried after a transient error.
+const SEND_RETRIES: u32 = 5;
+
+/// Time to wait before the first retry of a send. Doubles with each further retry.
+const INITIAL_SEND_BACKOFF: Duration = Duration::from_millis(1);
+
+/// Frames and bytes sent on all data link channels, shared by all probes, see `DatalinkLayer::traffic`.
+static SENT_PACKETS: AtomicU64 = AtomicU64::new(0);
+static SENT_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Backend of all data link channels, chosen once at startup, see `Backend::select`.
+static BACKEND: OnceLock<Backend> = OnceLock::new();
+
+/// Mechanism to send and capture Ethernet frames with.
+///
+/// All backends hand the same frames to the same matching logic, see `Layer::match_layer`,
+/// so they only differ in how the channel to the interface is opened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Raw sockets of the operating system, e.g. `AF_PACKET` on Linux.
+    #[default]
+    PnetRaw,
+    /// Injection and capture with libpcap, for platforms that restrict raw sockets.
+    #[cfg(feature = "pcap")]
+    Pcap,
+}
+
+impl Backend {
+    /// Selects the backend of all channels opened from now on.
+    ///
+    /// Can only be selected once, later calls are ignored.
+    #[cfg(feature = "pcap")]
+    pub fn select(self) {
+        if BACKEND.set(self).is_err() {
+            debug!("The data link backend was already selected.");
+        }
+    }
+
+    /// Returns the selected backend, or raw sockets if none was selected.
+    pub fn current() -> Backend {
+        BACKEND.get().copied().unwrap_or_default()
+    }
+
+    /// Opens an Ethernet channel to the interface.
+    pub fn open_channel(
+        self,
+        interface: &NetworkInterface,
+        config: datalink::Config,
+    ) -> Result<(Box<dyn DataLinkSender>, Box<dyn DataLinkReceiver>)> {
+        let channel = match self {
+            Backend::PnetRaw => datalink::channel(interface, config).map_err(raw_socket_error)?,
+            #[cfg(feature = "pcap")]
+            Backend::Pcap => datalink::pcap::channel(interface, (&config).into())
+                .map_err(|e| ScannerError::PcapFailed(e.to_string()))?,
+        };
+
+        match channel {
+            Channel::Ethernet(tx, rx) => Ok((tx, rx)),
+            _ => Err(ChannelError::UnexpectedChannelType.into()),
+        }
+    }
+}
+
+/// Amount of traffic sent on the data link layer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Traffic {
+    /// Number of frames sent.
+    pub packets: u64,
+    /// Number of bytes sent, counting each frame with its Ethernet header.
+    pub bytes: u64,
+}
+
+impl Traffic {
+    /// Returns the traffic sent after `earlier`, e.g. by a single scan.
+    pub fn since(self, earlier: Traffic) -> Traffic {
+        Traffic {
+            packets: self.packets.saturating_sub(earlier.packets),
+            bytes: self.bytes.saturating_sub(earlier.bytes),
+        }
+    }
+}
+
+/// Outcome of handing packets over to the network layer, see `NetworkLayer::send_and_receive`.
+#[derive(Debug)]
+pub enum Exchange {
+    /// The packets were sent. Holds the response, its source IP address and the round-trip time.
+    Sent(Option<Vec<u8>>, Option<IpAddr>, Duration),
+    /// A dry run built the packets and the frames that would go on the wire, but sent nothing.
+    /// Scans only report the probe as not sent, since the frames are logged already.
+    #[allow(dead_code)]
+    Built {
+        packets: Vec<Vec<u8>>,
+        frames: Vec<Vec<u8>>,
+    },
+}
+
 /// Represents the different layers of the OSI model.
+#[derive(Debug, Clone, Copy)]
 #[allow(dead_code)]
 pub enum Layer {
     Two(DatalinkLayer),
@@ -43,6 +165,9 @@ pub struct NetworkLayer {
     pub datalink_layer: Option<DatalinkLayer>,
     pub src_addr: Option<IpAddr>,
     pub dest_addr: Option<IpAddr>,
+    /// Protocol of the probe a response must answer: a packet of that protocol, or an ICMP
+    /// error that quotes a datagram of it. Only checked for IPv4 packets.
+    pub protocol: Option<IpNextHeaderProtocol>,
 }
 
 /// Represents the transport layer of the OSI model.
@@ -51,6 +176,75 @@ pub struct TransportLayer {
     pub network_layer: Option<NetworkLayer>,
     pub src_port: Option<u16>,
     pub dest_port: Option<u16>,
+    pub tcp_sequence: Option<TcpSequence>,
+    pub icmp_echo: Option<IcmpEcho>,
+}
+
+/// Sequence numbers and flags of a sent TCP segment.
+///
+/// Used to attribute a response to exactly that segment, since ports alone can be ambiguous.
+#[derive(Debug, Clone, Copy)]
+pub struct TcpSequence {
+    pub sequence: u32,
+    pub acknowledgement: u32,
+    pub flags: u8,
+    pub payload_length: u32,
+}
+
+impl TcpSequence {
+    /// Checks if the TCP segment is a response to the sent segment.
+    ///
+    /// As of RFC 793, a response with the ACK flag acknowledges our sequence number
+    /// plus the payload and the SYN and FIN flags, which occupy one sequence number each.
+    /// A RST without the ACK flag answers a segment with the ACK flag and takes its
+    /// acknowledgement number.
+    pub fn match_response(&self, response: &TcpPacket) -> bool {
+        if response.get_flags() & TcpFlags::ACK != 0 {
+            let length = self
+                .payload_length
+                .wrapping_add(u32::from(self.flags & TcpFlags::SYN != 0))
+                .wrapping_add(u32::from(self.flags & TcpFlags::FIN != 0));
+            response.get_acknowledgement() == self.sequence.wrapping_add(length)
+        } else {
+            self.flags & TcpFlags::ACK != 0 && response.get_sequence() == self.acknowledgement
+        }
+    }
+}
+
+/// Identifier and sequence number of a sent ICMP echo request.
+///
+/// Used to attribute echo replies and ICMP errors to exactly that request,
+/// since ICMP has no ports and errors come from routers on the path.
+#[derive(Debug, Clone, Copy)]
+pub struct IcmpEcho {
+    pub identifier: u16,
+    pub sequence: u16,
+}
+
+impl IcmpEcho {
+    /// Checks if the ICMP packet is a reply to the sent request, or an error quoting it.
+    ///
+    /// Timestamp and address mask replies carry the identifier and sequence number
+    /// at the same offsets as echo replies.
+    pub fn match_response(&self, response: &IcmpPacket) -> bool {
+        let echo = match response.get_icmp_type() {
+            IcmpTypes::EchoReply | IcmpTypes::TimestampReply | IcmpTypes::AddressMaskReply => {
+                EchoReplyPacket::new(response.packet())
+                    .map(|reply| (reply.get_identifier(), reply.get_sequence_number()))
+            }
+            // Skip the unused 4 bytes in front of the quoted datagram.
+            IcmpTypes::TimeExceeded | IcmpTypes::DestinationUnreachable => response
+                .payload()
+                .get(4..)
+                .and_then(Ipv4Packet::new)
+                .and_then(|quoted| {
+                    EchoRequestPacket::new(quoted.payload())
+                        .map(|request| (request.get_identifier(), request.get_sequence_number()))
+                }),
+            _ => None,
+        };
+        echo == Some((self.identifier, self.sequence))
+    }
 }
 
 impl Layer {
@@ -129,6 +323,29 @@ impl MatchLayer for NetworkLayer {
                     _ => false,
                 });
 
+                let match_protocol = self
+                    .protocol
+                    .map_or(true, |protocol| answers_protocol(&ipv4_packet, protocol));
+
+                // Only verify the checksum of a matching packet, which is rather expensive.
+                match_src && match_dest && match_protocol && has_valid_ipv4_checksum(&ipv4_packet)
+            }
+            EtherTypes::Ipv6 => {
+                let ipv6_packet = match Ipv6Packet::new(ethernet_packet.payload()) {
+                    Some(packet) => packet,
+                    None => return false,
+                };
+
+                let match_src = self.src_addr.map_or(true, |src| match src {
+                    IpAddr::V6(src_ip) => ipv6_packet.get_source() == src_ip,
+                    _ => false,
+                });
+
+                let match_dest = self.dest_addr.map_or(true, |dest| match dest {
+                    IpAddr::V6(dest_ip) => ipv6_packet.get_destination() == dest_ip,
+                    _ => false,
+                });
+
                 match_src && match_dest
             }
             EtherTypes::Arp => {
@@ -149,7 +366,7 @@ impl MatchLayer for NetworkLayer {
 
                 match_src && match_dest
             }
-            _ => false, // IPv6, etc.
+            _ => false,
         }
     }
 }
@@ -181,10 +398,58 @@ impl MatchLayer for TransportLayer {
 
                 match ipv4_packet.get_next_level_protocol() {
                     IpNextHeaderProtocols::Tcp => TcpPacket::new(ipv4_packet.payload())
+                        .filter(|tcp| self.match_tcp_sequence(tcp))
                         .map(|tcp| (tcp.get_source(), tcp.get_destination())),
                     IpNextHeaderProtocols::Udp => UdpPacket::new(ipv4_packet.payload())
                         .map(|udp| (udp.get_source(), udp.get_destination())),
-                    IpNextHeaderProtocols::Icmp => return true,
+                    IpNextHeaderProtocols::Sctp => Sctp::ports(ipv4_packet.payload()),
+                    IpNextHeaderProtocols::Icmp => match IcmpPacket::new(ipv4_packet.payload()) {
+                        Some(icmp) if !self.match_icmp_echo(&icmp) => return false,
+                        // ICMP errors quote the datagram we sent, which carries the ports.
+                        // They are swapped because we match from the perspective of the response.
+                        Some(icmp) if icmp.get_icmp_type() == IcmpTypes::DestinationUnreachable => {
+                            DestinationUnreachablePacket::new(ipv4_packet.payload())
+                                .and_then(|unreachable| quoted_ports(unreachable.payload()))
+                                .map(|(src_port, dest_port)| (dest_port, src_port))
+                        }
+                        _ => return true,
+                    },
+                    _ => None,
+                }
+            }
+            EtherTypes::Ipv6 => {
+                let ipv6_packet = match Ipv6Packet::new(ethernet_packet.payload()) {
+                    Some(packet) => packet,
+                    None => return false,
+                };
+                let (next_header, payload) =
+                    match ipv6_upper_layer(ipv6_packet.get_next_header(), ipv6_packet.payload()) {
+                        Some(upper_layer) => upper_layer,
+                        None => return false,
+                    };
+
+                match next_header {
+                    IpNextHeaderProtocols::Tcp => TcpPacket::new(payload)
+                        .filter(|tcp| self.match_tcp_sequence(tcp))
+                        .map(|tcp| (tcp.get_source(), tcp.get_destination())),
+                    IpNextHeaderProtocols::Udp => {
+                        UdpPacket::new(payload).map(|udp| (udp.get_source(), udp.get_destination()))
+                    }
+                    IpNextHeaderProtocols::Icmpv6 => {
+                        match Icmpv6Packet::new(payload) {
+                            // Skip the unused 4 bytes in front of the quoted datagram.
+                            Some(icmp)
+                                if icmp.get_icmpv6_type()
+                                    == Icmpv6Types::DestinationUnreachable =>
+                            {
+                                icmp.payload()
+                                    .get(4..)
+                                    .and_then(quoted_ports)
+                                    .map(|(src_port, dest_port)| (dest_port, src_port))
+                            }
+                            _ => return true,
+                        }
+                    }
                     _ => None,
                 }
             }
@@ -200,7 +465,137 @@ impl MatchLayer for TransportLayer {
 
         let match_dest_port = self.dest_port.map_or(true, |port| port == dest_port);
 
-        match_src_port && match_dest_port
+        // Only verify the checksum of a matching packet, which is rather expensive.
+        match_src_port && match_dest_port && has_valid_tcp_checksum(&ethernet_packet)
+    }
+}
+
+impl TransportLayer {
+    /// Matches the sequence numbers of a TCP segment, if a sent segment is given.
+    fn match_tcp_sequence(&self, tcp_packet: &TcpPacket) -> bool {
+        self.tcp_sequence
+            .map_or(true, |sequence| sequence.match_response(tcp_packet))
+    }
+
+    /// Matches the identifier and sequence number of an ICMP packet, if a sent echo request is given.
+    fn match_icmp_echo(&self, icmp_packet: &IcmpPacket) -> bool {
+        self.icmp_echo
+            .map_or(true, |echo| echo.match_response(icmp_packet))
+    }
+}
+
+/// Verifies the header checksum of an IPv4 packet.
+///
+/// Corrupted or forged packets are discarded, so they can't be mistaken for a response.
+fn has_valid_ipv4_checksum(packet: &Ipv4Packet) -> bool {
+    let is_valid = packet.get_checksum() == ipv4::checksum(packet);
+    if !is_valid {
+        debug!(
+            "Discarded IPv4 packet from {} with an invalid checksum.",
+            packet.get_source()
+        );
+    }
+    is_valid
+}
+
+/// Verifies the checksum of a TCP segment, which also covers the IP addresses.
+///
+/// Packets of other protocols are always considered valid.
+fn has_valid_tcp_checksum(ethernet_packet: &EthernetPacket) -> bool {
+    let is_valid = match ethernet_packet.get_ethertype() {
+        EtherTypes::Ipv4 => match Ipv4Packet::new(ethernet_packet.payload()) {
+            Some(ip) if ip.get_next_level_protocol() == IpNextHeaderProtocols::Tcp => {
+                TcpPacket::new(ip.payload()).is_some_and(|segment| {
+                    segment.get_checksum()
+                        == tcp::ipv4_checksum(&segment, &ip.get_source(), &ip.get_destination())
+                })
+            }
+            _ => true,
+        },
+        EtherTypes::Ipv6 => match Ipv6Packet::new(ethernet_packet.payload()) {
+            Some(ip) => match ipv6_upper_layer(ip.get_next_header(), ip.payload()) {
+                Some((IpNextHeaderProtocols::Tcp, payload)) => {
+                    TcpPacket::new(payload).is_some_and(|segment| {
+                        segment.get_checksum()
+                            == tcp::ipv6_checksum(&segment, &ip.get_source(), &ip.get_destination())
+                    })
+                }
+                _ => true,
+            },
+            None => true,
+        },
+        _ => true,
+    };
+    if !is_valid {
+        debug!("Discarded TCP segment with an invalid checksum.");
+    }
+    is_valid
+}
+
+/// Returns whether the packet is of the protocol, or an ICMP error that quotes a datagram of it.
+fn answers_protocol(packet: &Ipv4Packet, protocol: IpNextHeaderProtocol) -> bool {
+    if packet.get_next_level_protocol() == protocol {
+        return true;
+    }
+    if packet.get_next_level_protocol() != IpNextHeaderProtocols::Icmp {
+        return false;
+    }
+    DestinationUnreachablePacket::new(packet.payload())
+        .filter(|icmp| icmp.get_icmp_type() == IcmpTypes::DestinationUnreachable)
+        .and_then(|icmp| {
+            Ipv4Packet::new(icmp.payload()).map(|quoted| quoted.get_next_level_protocol())
+        })
+        .is_some_and(|quoted| quoted == protocol)
+}
+
+/// Extracts the source and destination port of the IP datagram quoted in an ICMP error.
+///
+/// ICMP errors contain the IP header and at least the first 8 bytes of the original datagram,
+/// which is enough to read the ports of a TCP or UDP header.
+/// IPv6 extension headers are not supported, since our probes don't carry any.
+pub fn quoted_ports(payload: &[u8]) -> Option<(u16, u16)> {
+    let offset = match payload.first()? >> 4 {
+        4 => Ipv4Packet::new(payload)?.get_header_length() as usize * 4,
+        6 => IPV6_HEADER_SIZE,
+        _ => return None,
+    };
+    let ports = payload.get(offset..offset + 4)?;
+    let src_port = u16::from_be_bytes([ports[0], ports[1]]);
+    let dest_port = u16::from_be_bytes([ports[2], ports[3]]);
+    Some((src_port, dest_port))
+}
+
+/// Walks the extension headers of an IPv6 packet to the header of the upper layer, e.g. TCP.
+///
+/// Unlike our probes, responses may carry hop-by-hop, routing, fragment or destination options
+/// headers in front of the transport header, so it isn't at a fixed offset. Each extension header
+/// names the header that follows it and is a multiple of 8 bytes long, like IPv4 options.
+///
+/// Returns the protocol of the upper layer and its header with the payload. Returns `None` if the
+/// chain is truncated, or if the packet is a later fragment, which has no upper layer header.
+pub fn ipv6_upper_layer(
+    next_header: IpNextHeaderProtocol,
+    payload: &[u8],
+) -> Option<(IpNextHeaderProtocol, &[u8])> {
+    let mut next_header = next_header;
+    let mut payload = payload;
+    loop {
+        let length = match next_header {
+            IpNextHeaderProtocols::Hopopt
+            | IpNextHeaderProtocols::Ipv6Route
+            | IpNextHeaderProtocols::Ipv6Opts => (*payload.get(1)? as usize + 1) * 8,
+            IpNextHeaderProtocols::Ipv6Frag => {
+                // The upper 13 bits of the third and fourth byte hold the fragment offset.
+                let offset = u16::from_be_bytes([*payload.get(2)?, *payload.get(3)?]) >> 3;
+                if offset != 0 {
+                    return None;
+                }
+                8
+            }
+            _ => return Some((next_header, payload)),
+        };
+        next_header = IpNextHeaderProtocol(*payload.first()?);
+        payload = payload.get(length..)?;
     }
 }
 
@@ -221,51 +616,78 @@ impl DatalinkLayer {
         ethernet_packet.set_payload(payload);
     }
 
-    /// Sends a packet over a data link channel and waits `timeout` for a response.
+    /// Returns the EtherType of an IP packet, derived from its version field.
+    /// Anything but IPv6 is taken as IPv4.
+    pub fn ip_ethertype(ip_packet: &[u8]) -> EtherType {
+        match ip_packet.first() {
+            Some(byte) if byte >> 4 == 6 => EtherTypes::Ipv6,
+            _ => EtherTypes::Ipv4,
+        }
+    }
+
+    /// Assembles the Ethernet frame that carries an IP packet on the wire, e.g. to debug MAC
+    /// resolution. The EtherType is derived from the packet, see `ip_ethertype`.
+    pub fn assemble_frame(src_mac: MacAddr, dest_mac: MacAddr, ip_packet: &[u8]) -> Vec<u8> {
+        let ethertype = DatalinkLayer::ip_ethertype(ip_packet);
+        DatalinkLayer::build_ethernet_frame(src_mac, dest_mac, ethertype, ip_packet)
+    }
+
+    /// Encapsulates a payload, e.g. an IP packet, in a new Ethernet frame.
+    pub fn build_ethernet_frame(
+        src_mac: MacAddr,
+        dest_mac: MacAddr,
+        ethertype: EtherType,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut frame = vec![0u8; ETHERNET_HEADER_SIZE + payload.len()];
+        Self::build_ethernet_packet(src_mac, dest_mac, ethertype, payload, &mut frame);
+        frame
+    }
+
+    /// Sends packets over a data link channel and waits `timeout` for a response.
     ///
-    /// Processes the ethernet frames in the channel and matches them against the provided layer data.
+    /// The payloads are sent in sequence, e.g. the fragments of an IP datagram.
+    /// Each payload is encapsulated in an Ethernet frame addressed to `dest_mac`.
+    /// Frames are sent on the shared channel of the interface, whose workers match the captured
+    /// frames against the provided layer data, see `ResponseMatcher`.
+    /// The channel is opened with the selected backend, see `Backend::current`.
     ///
     /// Returns a matching response and the round-trip time.
+    /// Returns no response if none arrives within the timeout, e.g. for filtered ports.
     pub fn send_and_receive(
         interface: &NetworkInterface,
         dest_mac: MacAddr,
         ethertype: EtherType,
-        payload: &[u8],
+        payloads: &[Vec<u8>],
         layers: Layer,
         timeout: Duration,
     ) -> Result<(Option<Vec<u8>>, Duration)> {
-        let (mut sender, mut receiver) = match datalink::channel(interface, Default::default())? {
-            Channel::Ethernet(tx, rx) => (tx, rx),
-            _ => return Err(ChannelError::UnexpectedChannelType.into()),
-        };
+        let matcher = ResponseMatcher::for_interface(interface)?;
 
         let src_mac = interface.mac.ok_or(ScannerError::CantFindInterfaceMac)?;
 
-        let mut build_packet_fn = |packet: &mut [u8]| {
-            Self::build_ethernet_packet(src_mac, dest_mac, ethertype, payload, packet);
-        };
+        // Register before sending, so that even the fastest response is matched.
+        let registration = matcher.register(layers);
 
         let send_time = Instant::now();
 
-        sender
-            .build_and_send(
-                1,
-                ETHERNET_HEADER_SIZE + payload.len(),
-                &mut build_packet_fn,
-            )
-            .ok_or(ChannelError::SendError)??;
+        let frames = payloads
+            .iter()
+            .map(|payload| Self::build_ethernet_frame(src_mac, dest_mac, ethertype, payload))
+            .collect::<Vec<_>>();
 
-        let deadline = send_time + timeout;
+        for frame in &frames {
+            log_frame("Sent", frame);
+            matcher.send(frame)?;
+            record_sent(1, frame.len());
+        }
 
-        while Instant::now() < deadline {
-            if let Ok(response) = receiver.next() {
-                if layers.match_layer(response) {
-                    return Ok((Some(response.to_vec()), send_time.elapsed()));
-                }
-            }
+        let response = registration.wait(send_time + timeout);
+        if let Some(response) = &response {
+            log_frame("Received", response);
         }
 
-        Ok((None, send_time.elapsed()))
+        Ok((response, send_time.elapsed()))
     }
 
     /// Sends a packet over a data link channel.
@@ -278,10 +700,8 @@ impl DatalinkLayer {
         dest_mac: MacAddr,
         ethertype: EtherType,
     ) -> Result<()> {
-        let (mut sender, mut _receiver) = match datalink::channel(&interface, Default::default())? {
-            Channel::Ethernet(tx, rx) => (tx, rx),
-            _ => return Err(ChannelError::UnexpectedChannelType.into()),
-        };
+        let (mut sender, _receiver) =
+            Backend::current().open_channel(&interface, Default::default())?;
 
         let src_mac = interface.mac.ok_or(ScannerError::CantFindInterfaceMac)?;
 
@@ -296,64 +716,174 @@ impl DatalinkLayer {
                 &mut build_packet_fn,
             )
             .ok_or(ChannelError::SendError)??;
+        record_sent(number_of_packets, ETHERNET_HEADER_SIZE + payload.len());
 
         Ok(())
     }
+
+    /// Returns the frames and bytes sent since the start of the program.
+    ///
+    /// Covers every frame sent by this crate, e.g. probes, ARP requests and floods, but not the
+    /// packets the operating system sends for connect scans.
+    pub fn traffic() -> Traffic {
+        Traffic {
+            packets: SENT_PACKETS.load(Ordering::Relaxed),
+            bytes: SENT_BYTES.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Adds sent frames of the given size to the traffic.
+fn record_sent(packets: usize, frame_size: usize) {
+    SENT_PACKETS.fetch_add(packets as u64, Ordering::Relaxed);
+    SENT_BYTES.fetch_add((packets * frame_size) as u64, Ordering::Relaxed);
+}
+
+/// Calls `send` until it succeeds, retrying transient errors with exponential backoff.
+///
+/// The socket buffer may be full for a moment when many probes are sent at the same time,
+/// which the operating system reports as `EAGAIN` or `ENOBUFS`. Such a send is worth retrying,
+/// unlike e.g. missing privileges. This is unrelated to probes that receive no response.
+///
+/// May fail with `RawSocketPermissionDenied` or `RawSocketSendFailed` if the error persists.
+pub fn send_with_backoff(mut send: impl FnMut() -> Option<io::Result<()>>) -> Result<()> {
+    let mut backoff = INITIAL_SEND_BACKOFF;
+    let mut retries = 0;
+
+    loop {
+        match send().ok_or(ChannelError::SendError)? {
+            Ok(()) => return Ok(()),
+            Err(e) if is_transient(&e) && retries < SEND_RETRIES => {
+                debug!(
+                    "Retrying send in {:?} after a transient error: {}",
+                    backoff, e
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+                retries += 1;
+            }
+            Err(e) => return Err(raw_socket_error(e).into()),
+        }
+    }
+}
+
+/// Returns whether a send error is temporary, e.g. because the socket buffer is full.
+fn is_transient(error: &io::Error) -> bool {
+    matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted)
+        || error.raw_os_error() == Some(libc::ENOBUFS)
+}
+
+/// Maps an error of a raw socket to a `ScannerError`.
+fn raw_socket_error(error: io::Error) -> ScannerError {
+    match error.kind() {
+        ErrorKind::PermissionDenied => ScannerError::RawSocketPermissionDenied,
+        _ => ScannerError::RawSocketSendFailed(error.to_string()),
+    }
 }
 
 impl NetworkLayer {
-    /// Hands over the packet to the data link layer.
+    /// Hands over the packets to the data link layer, which sends them in sequence.
     ///
     /// Converts the interface to a `pnet::datalink::NetworkInterface`.
+    /// The EtherType is derived from the version field of the first IP packet.
+    /// IPv4 packets are sent to the MAC address of the next hop, see `Arp::next_hop_mac`.
+    /// IPv6 packets to link-local hosts are sent to the MAC address resolved via neighbor
+    /// discovery, all others go to the gateway, see `Ndp::next_hop_mac`.
+    ///
+    /// If an MTU is given, IPv4 packets that exceed it are sent as fragments.
+    ///
+    /// Returns the response, its source IP address and the round-trip time.
+    /// The source may differ from the destination of the packets, e.g. for ICMP errors of routers.
     ///
-    /// Returns the response and the round-trip time.
+    /// In a dry run the packets are logged instead, without resolving MAC addresses or opening a
+    /// channel. Returns the packets and their frames then, as no response was awaited.
+    /// The frames are addressed to the gateway, since no MAC address is resolved.
     pub fn send_and_receive(
         interface: Interface,
-        packet: &[u8],
+        packets: &[Vec<u8>],
         layers: Layer,
+        options: &ProbeOptions,
         timeout: Duration,
-    ) -> Result<(Option<Vec<u8>>, Duration)> {
-        let dest_mac = interface.gateway.mac;
-
+    ) -> Result<Exchange> {
         let iface = interface.convert_interface()?;
 
+        let ethertype = packets.first().map_or(EtherTypes::Ipv4, |packet| {
+            DatalinkLayer::ip_ethertype(packet)
+        });
+
+        let fragments = match (options.mtu, ethertype) {
+            (Some(mtu), EtherTypes::Ipv4) => packets
+                .iter()
+                .flat_map(|packet| fragment_ipv4_packet(packet, mtu))
+                .collect(),
+            _ => packets.to_vec(),
+        };
+
+        if options.dry_run {
+            let src_mac = iface.mac.unwrap_or(MacAddr::zero());
+            let frames = fragments
+                .iter()
+                .map(|fragment| {
+                    DatalinkLayer::assemble_frame(src_mac, interface.gateway.mac, fragment)
+                })
+                .collect::<Vec<_>>();
+            for frame in &frames {
+                log_frame("Built", frame);
+            }
+            return Ok(Exchange::Built {
+                packets: fragments,
+                frames,
+            });
+        }
+
+        let dest_ip = packets.first().and_then(|packet| match ethertype {
+            EtherTypes::Ipv6 => Ipv6Packet::new(packet).map(|ip| IpAddr::V6(ip.get_destination())),
+            _ => Ipv4Packet::new(packet).map(|ip| IpAddr::V4(ip.get_destination())),
+        });
+
+        let dest_mac = match dest_ip {
+            Some(IpAddr::V4(dest_ip)) => Arp::next_hop_mac(interface, dest_ip, timeout)?,
+            Some(IpAddr::V6(dest_ip)) => Ndp::next_hop_mac(interface, dest_ip, timeout)?,
+            None => interface.gateway.mac,
+        };
+
         let (response, rtt) = DatalinkLayer::send_and_receive(
-            &iface,
-            dest_mac,
-            EtherTypes::Ipv4,
-            packet,
-            layers,
-            timeout,
+            &iface, dest_mac, ethertype, &fragments, layers, timeout,
         )?;
 
-        Ok((response, rtt))
+        let responder = response.as_deref().and_then(response_source);
+
+        Ok(Exchange::Sent(response, responder, rtt))
+    }
+}
+
+/// Extracts the source IP address of an Ethernet frame carrying an IPv4 or IPv6 packet.
+fn response_source(frame: &[u8]) -> Option<IpAddr> {
+    let ethernet_packet = EthernetPacket::new(frame)?;
+    match ethernet_packet.get_ethertype() {
+        EtherTypes::Ipv4 => Ipv4Packet::new(ethernet_packet.payload())
+            .map(|ip_packet| IpAddr::V4(ip_packet.get_source())),
+        EtherTypes::Ipv6 => Ipv6Packet::new(ethernet_packet.payload())
+            .map(|ip_packet| IpAddr::V6(ip_packet.get_source())),
+        _ => None,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::networking::tcp::Tcp;
-    use pnet::packet::tcp::TcpFlags;
-    use std::net::Ipv4Addr;
-
-    /// Creates an Ethernet packet.
-    fn build_ethernet_packet(
-        src_mac: MacAddr,
-        dest_mac: MacAddr,
-        ethertype: EtherType,
-        payload: &[u8],
-    ) -> Vec<u8> {
-        let mut ethernet_buffer = [0u8; 4096];
-        let mut ethernet_packet = MutableEthernetPacket::new(&mut ethernet_buffer).unwrap();
-
-        ethernet_packet.set_source(src_mac);
-        ethernet_packet.set_destination(dest_mac);
-        ethernet_packet.set_ethertype(ethertype);
-        ethernet_packet.set_payload(payload);
-
-        ethernet_buffer[..(ETHERNET_HEADER_SIZE + payload.len())].to_vec()
-    }
+    use crate::networking::{
+        icmp::Icmp,
+        probe::{build_ipv4_header, build_protocol_probe, ProbeOptions, IPV4_HEADER_SIZE},
+        tcp::Tcp,
+        udp::Udp,
+    };
+    use pnet::packet::{
+        icmp::{destination_unreachable::IcmpCodes, MutableIcmpPacket},
+        ipv4::MutableIpv4Packet,
+        tcp::{MutableTcpPacket, TcpFlags},
+    };
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     #[test]
     fn test_layers_match() {
@@ -378,6 +908,7 @@ mod tests {
             datalink_layer: Some(datalink_layer),
             src_addr: Some(IpAddr::V4(src_ip)),
             dest_addr: Some(IpAddr::V4(dest_ip)),
+            protocol: None,
         };
 
         // Create the transport layer.
@@ -385,23 +916,480 @@ mod tests {
             network_layer: Some(network_layer),
             src_port: Some(src_port),
             dest_port: Some(dest_port),
+            tcp_sequence: None,
+            icmp_echo: None,
         };
 
         // The packet should match all layers.
-        let tcp_packet_1 =
-            Tcp::build_tcp_packet(src_ip, src_port, dest_ip, dest_port, TcpFlags::SYN);
-        let ethernet_packet_1 = build_ethernet_packet(src_mac, dest_mac, ethertype, &tcp_packet_1);
+        let (tcp_packet_1, _) = Tcp::build_tcp_packet(
+            src_ip,
+            src_port,
+            dest_ip,
+            dest_port,
+            TcpFlags::SYN,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let ethernet_packet_1 =
+            DatalinkLayer::build_ethernet_frame(src_mac, dest_mac, ethertype, &tcp_packet_1);
         assert!(transport_layer.match_packet(&ethernet_packet_1));
 
         // The packet should not match anymore since src_ip and dest_port are different.
-        let tcp_packet_2 = Tcp::build_tcp_packet(
+        let (tcp_packet_2, _) = Tcp::build_tcp_packet(
             Ipv4Addr::new(10, 0, 0, 1),
             src_port,
             dest_ip,
             443,
             TcpFlags::SYN,
-        );
-        let ethernet_packet_2 = build_ethernet_packet(src_mac, dest_mac, ethertype, &tcp_packet_2);
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let ethernet_packet_2 =
+            DatalinkLayer::build_ethernet_frame(src_mac, dest_mac, ethertype, &tcp_packet_2);
         assert!(!transport_layer.match_packet(&ethernet_packet_2));
     }
+
+    #[test]
+    fn test_match_tcp_sequence() {
+        let match_segment = |sent: TcpSequence, flags: u8, sequence: u32, acknowledgement: u32| {
+            let mut segment = [0u8; 20];
+            let mut tcp_header = MutableTcpPacket::new(&mut segment).unwrap();
+            tcp_header.set_flags(flags);
+            tcp_header.set_sequence(sequence);
+            tcp_header.set_acknowledgement(acknowledgement);
+            sent.match_response(&TcpPacket::new(&segment).unwrap())
+        };
+
+        // Responses to a SYN acknowledge it, since the SYN flag occupies one sequence number.
+        let syn = TcpSequence {
+            sequence: 1000,
+            acknowledgement: 0,
+            flags: TcpFlags::SYN,
+            payload_length: 0,
+        };
+        assert!(match_segment(syn, TcpFlags::SYN | TcpFlags::ACK, 5, 1001));
+        assert!(match_segment(syn, TcpFlags::RST | TcpFlags::ACK, 0, 1001));
+        assert!(!match_segment(syn, TcpFlags::SYN | TcpFlags::ACK, 5, 1000));
+        assert!(!match_segment(syn, TcpFlags::RST, 0, 1001));
+
+        // A RST answers a segment with the ACK flag with its acknowledgement number.
+        let ack = TcpSequence {
+            sequence: 1000,
+            acknowledgement: 2000,
+            flags: TcpFlags::ACK,
+            payload_length: 0,
+        };
+        assert!(match_segment(ack, TcpFlags::RST, 2000, 0));
+        assert!(!match_segment(ack, TcpFlags::RST, 2001, 0));
+
+        // The FIN flag occupies a sequence number, too. Sequence numbers wrap around.
+        let xmas = TcpSequence {
+            sequence: u32::MAX,
+            acknowledgement: 0,
+            flags: TcpFlags::FIN | TcpFlags::PSH | TcpFlags::URG,
+            payload_length: 0,
+        };
+        assert!(match_segment(xmas, TcpFlags::RST | TcpFlags::ACK, 0, 0));
+        assert!(!match_segment(
+            xmas,
+            TcpFlags::RST | TcpFlags::ACK,
+            0,
+            u32::MAX
+        ));
+
+        // Closed ports acknowledge the payload of a probe as well.
+        let payload = TcpSequence {
+            sequence: 1000,
+            acknowledgement: 0,
+            flags: TcpFlags::SYN,
+            payload_length: 5,
+        };
+        assert!(match_segment(
+            payload,
+            TcpFlags::RST | TcpFlags::ACK,
+            0,
+            1006
+        ));
+        assert!(!match_segment(
+            payload,
+            TcpFlags::RST | TcpFlags::ACK,
+            0,
+            1001
+        ));
+        let fin = TcpSequence {
+            flags: TcpFlags::FIN,
+            ..payload
+        };
+        assert!(match_segment(fin, TcpFlags::RST | TcpFlags::ACK, 0, 1006));
+    }
+
+    #[test]
+    fn test_invalid_checksums_do_not_match() {
+        let mac = MacAddr::zero();
+        let src_ip = Ipv4Addr::new(192, 168, 0, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 0, 2);
+
+        let transport_layer = TransportLayer {
+            network_layer: Some(NetworkLayer {
+                datalink_layer: None,
+                src_addr: Some(IpAddr::V4(src_ip)),
+                dest_addr: Some(IpAddr::V4(dest_ip)),
+                protocol: None,
+            }),
+            src_port: Some(12345),
+            dest_port: Some(80),
+            tcp_sequence: None,
+            icmp_echo: None,
+        };
+
+        let (tcp_packet, _) = Tcp::build_tcp_packet(
+            src_ip,
+            12345,
+            dest_ip,
+            80,
+            TcpFlags::SYN | TcpFlags::ACK,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let ethernet_packet =
+            DatalinkLayer::build_ethernet_frame(mac, mac, EtherTypes::Ipv4, &tcp_packet);
+        assert!(transport_layer.match_packet(&ethernet_packet));
+
+        // Corrupt the checksum of the IPv4 header.
+        let mut corrupted = ethernet_packet.clone();
+        corrupted[ETHERNET_HEADER_SIZE + 10] ^= 0xff;
+        assert!(!transport_layer.match_packet(&corrupted));
+
+        // Corrupt the flags of the TCP header, which are covered by the TCP checksum.
+        let mut corrupted = ethernet_packet.clone();
+        corrupted[ETHERNET_HEADER_SIZE + 20 + 13] ^= TcpFlags::RST;
+        assert!(!transport_layer.match_packet(&corrupted));
+    }
+
+    #[test]
+    fn test_icmp_unreachable_match() {
+        let target_ip = Ipv4Addr::new(192, 168, 0, 2);
+        let local_ip = Ipv4Addr::new(192, 168, 0, 1);
+        let target_port = 53;
+        let local_port = 12345;
+
+        // The UDP probe we sent to the target.
+        let probe = Udp::build_udp_packet(
+            local_ip,
+            local_port,
+            target_ip,
+            target_port,
+            &[0; 4],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+
+        // The target answers with an ICMP port unreachable error quoting the probe.
+        let mut response = vec![0u8; 20 + 8 + probe.len()];
+        let mut ip_header = MutableIpv4Packet::new(&mut response).unwrap();
+        ip_header.set_version(4);
+        ip_header.set_header_length(5);
+        ip_header.set_total_length((20 + 8 + probe.len()) as u16);
+        ip_header.set_source(target_ip);
+        ip_header.set_destination(local_ip);
+        ip_header.set_next_level_protocol(IpNextHeaderProtocols::Icmp);
+        let mut icmp_header = MutableIcmpPacket::new(&mut response[20..]).unwrap();
+        icmp_header.set_icmp_type(IcmpTypes::DestinationUnreachable);
+        icmp_header.set_icmp_code(IcmpCodes::DestinationPortUnreachable);
+        response[28..].copy_from_slice(&probe);
+
+        let mac = MacAddr::zero();
+        let ethernet_packet =
+            DatalinkLayer::build_ethernet_frame(mac, mac, EtherTypes::Ipv4, &response);
+
+        // Match from the perspective of the response.
+        let matching_layer = TransportLayer {
+            network_layer: None,
+            src_port: Some(target_port),
+            dest_port: Some(local_port),
+            tcp_sequence: None,
+            icmp_echo: None,
+        };
+        assert!(matching_layer.match_packet(&ethernet_packet));
+
+        // An error quoting a different probe must not match.
+        let other_layer = TransportLayer {
+            network_layer: None,
+            src_port: Some(target_port),
+            dest_port: Some(local_port + 1),
+            tcp_sequence: None,
+            icmp_echo: None,
+        };
+        assert!(!other_layer.match_packet(&ethernet_packet));
+    }
+
+    #[test]
+    fn test_protocol_match() {
+        let target_ip = Ipv4Addr::new(192, 168, 0, 2);
+        let local_ip = Ipv4Addr::new(192, 168, 0, 1);
+        let gre = IpNextHeaderProtocol(47);
+
+        // The target answers the GRE probe with an ICMP protocol unreachable error quoting it.
+        let probe =
+            build_protocol_probe(local_ip, target_ip, gre.0, &ProbeOptions::default()).unwrap();
+        let mut response = vec![0u8; IPV4_HEADER_SIZE + 8 + probe.len()];
+        let mut icmp_header = MutableIcmpPacket::new(&mut response[IPV4_HEADER_SIZE..]).unwrap();
+        icmp_header.set_icmp_type(IcmpTypes::DestinationUnreachable);
+        icmp_header.set_icmp_code(IcmpCodes::DestinationProtocolUnreachable);
+        response[IPV4_HEADER_SIZE + 8..].copy_from_slice(&probe);
+        build_ipv4_header(
+            &mut response,
+            target_ip,
+            local_ip,
+            IpNextHeaderProtocols::Icmp,
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+
+        let mac = MacAddr::zero();
+        let frame = DatalinkLayer::build_ethernet_frame(mac, mac, EtherTypes::Ipv4, &response);
+        let layer = |protocol| NetworkLayer {
+            datalink_layer: None,
+            src_addr: Some(target_ip.into()),
+            dest_addr: Some(local_ip.into()),
+            protocol: Some(protocol),
+        };
+        assert!(layer(gre).match_packet(&frame));
+
+        // Errors about probes of other protocols must not match.
+        assert!(!layer(IpNextHeaderProtocols::Esp).match_packet(&frame));
+
+        // Packets of the probed protocol itself match as well, but only for that protocol.
+        let packet =
+            build_protocol_probe(target_ip, local_ip, gre.0, &ProbeOptions::default()).unwrap();
+        let frame = DatalinkLayer::build_ethernet_frame(mac, mac, EtherTypes::Ipv4, &packet);
+        assert!(layer(gre).match_packet(&frame));
+        assert!(!layer(IpNextHeaderProtocols::Esp).match_packet(&frame));
+    }
+
+    #[test]
+    fn test_ipv6_layers_match() {
+        let src_ip: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let dest_ip: Ipv6Addr = "2001:db8::2".parse().unwrap();
+        let src_port = 12345;
+        let dest_port = 80;
+
+        let transport_layer = TransportLayer {
+            network_layer: Some(NetworkLayer {
+                datalink_layer: None,
+                src_addr: Some(IpAddr::V6(src_ip)),
+                dest_addr: Some(IpAddr::V6(dest_ip)),
+                protocol: None,
+            }),
+            src_port: Some(src_port),
+            dest_port: Some(dest_port),
+            tcp_sequence: None,
+            icmp_echo: None,
+        };
+
+        let mac = MacAddr::zero();
+
+        // The packet should match all layers.
+        let (tcp_packet, _) = Tcp::build_tcp_packet_v6(
+            src_ip,
+            src_port,
+            dest_ip,
+            dest_port,
+            TcpFlags::SYN,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let ethernet_packet =
+            DatalinkLayer::build_ethernet_frame(mac, mac, EtherTypes::Ipv6, &tcp_packet);
+        assert!(transport_layer.match_packet(&ethernet_packet));
+
+        // An IPv4 address never matches an IPv6 packet.
+        let ipv4_layer = NetworkLayer {
+            datalink_layer: None,
+            src_addr: Some(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1))),
+            dest_addr: None,
+            protocol: None,
+        };
+        assert!(!ipv4_layer.match_packet(&ethernet_packet));
+    }
+
+    #[test]
+    fn icmp_echo_match() {
+        let local_ip = Ipv4Addr::new(192, 168, 0, 1);
+        let target_ip = Ipv4Addr::new(192, 168, 0, 2);
+        let router_ip = Ipv4Addr::new(10, 0, 0, 1);
+        let echo = IcmpEcho {
+            identifier: 0x1234,
+            sequence: 3,
+        };
+
+        // A router quotes the expired echo request in a time exceeded error.
+        let probe =
+            Icmp::build_icmp_packet(local_ip, target_ip, 0x1234, 3, &ProbeOptions::default())
+                .unwrap();
+        let mut response = vec![0u8; 20 + 8 + probe.len()];
+        let mut ip_header = MutableIpv4Packet::new(&mut response).unwrap();
+        ip_header.set_version(4);
+        ip_header.set_header_length(5);
+        ip_header.set_total_length((20 + 8 + probe.len()) as u16);
+        ip_header.set_source(router_ip);
+        ip_header.set_destination(local_ip);
+        ip_header.set_next_level_protocol(IpNextHeaderProtocols::Icmp);
+        let mut icmp_header = MutableIcmpPacket::new(&mut response[20..]).unwrap();
+        icmp_header.set_icmp_type(IcmpTypes::TimeExceeded);
+        response[28..].copy_from_slice(&probe);
+
+        let mac = MacAddr::zero();
+        let ethernet_packet =
+            DatalinkLayer::build_ethernet_frame(mac, mac, EtherTypes::Ipv4, &response);
+
+        let layer = |echo| TransportLayer {
+            network_layer: None,
+            src_port: None,
+            dest_port: None,
+            tcp_sequence: None,
+            icmp_echo: Some(echo),
+        };
+        assert!(layer(echo).match_packet(&ethernet_packet));
+
+        // The error is attributed to the router, not the target of the probe.
+        assert_eq!(
+            response_source(&ethernet_packet),
+            Some(IpAddr::V4(router_ip))
+        );
+
+        // The error of a probe with another TTL must not match.
+        let other_echo = IcmpEcho {
+            sequence: 4,
+            ..echo
+        };
+        assert!(!layer(other_echo).match_packet(&ethernet_packet));
+    }
+
+    #[test]
+    fn test_ipv6_upper_layer() {
+        let tcp = [0xaa; 20];
+
+        // No extension headers.
+        let upper_layer = ipv6_upper_layer(IpNextHeaderProtocols::Tcp, &tcp);
+        assert_eq!(upper_layer, Some((IpNextHeaderProtocols::Tcp, &tcp[..])));
+
+        // A 16-byte destination options header, followed by a first fragment.
+        let mut payload = vec![IpNextHeaderProtocols::Ipv6Frag.0, 1];
+        payload.extend_from_slice(&[0; 14]);
+        payload.extend_from_slice(&[IpNextHeaderProtocols::Tcp.0, 0, 0, 1, 0, 0, 0, 7]);
+        payload.extend_from_slice(&tcp);
+        let upper_layer = ipv6_upper_layer(IpNextHeaderProtocols::Ipv6Opts, &payload);
+        assert_eq!(upper_layer, Some((IpNextHeaderProtocols::Tcp, &tcp[..])));
+
+        // Later fragments and truncated headers hold no upper layer header.
+        payload[18] = 0x01;
+        assert_eq!(
+            ipv6_upper_layer(IpNextHeaderProtocols::Ipv6Opts, &payload),
+            None
+        );
+        assert_eq!(
+            ipv6_upper_layer(IpNextHeaderProtocols::Ipv6Opts, &payload[..10]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_build_ethernet_frame() {
+        let src_mac = MacAddr::new(0, 1, 2, 3, 4, 5);
+        let dest_mac = MacAddr::new(6, 7, 8, 9, 10, 11);
+        let payload = [0x45, 0, 0, 20];
+
+        let frame =
+            DatalinkLayer::build_ethernet_frame(src_mac, dest_mac, EtherTypes::Ipv4, &payload);
+        assert_eq!(frame.len(), ETHERNET_HEADER_SIZE + payload.len());
+
+        let ethernet_packet = EthernetPacket::new(&frame).unwrap();
+        assert_eq!(ethernet_packet.get_source(), src_mac);
+        assert_eq!(ethernet_packet.get_destination(), dest_mac);
+        assert_eq!(ethernet_packet.get_ethertype(), EtherTypes::Ipv4);
+        assert_eq!(ethernet_packet.payload(), payload);
+    }
+
+    #[test]
+    fn test_assemble_frame() -> Result<()> {
+        let src_mac = MacAddr::new(0, 1, 2, 3, 4, 5);
+        let dest_mac = MacAddr::new(6, 7, 8, 9, 10, 11);
+
+        let (ipv4_packet, _) = Tcp::build_tcp_packet(
+            Ipv4Addr::new(192, 168, 1, 1),
+            12345,
+            Ipv4Addr::new(192, 168, 1, 2),
+            80,
+            TcpFlags::SYN,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )?;
+        let frame = DatalinkLayer::assemble_frame(src_mac, dest_mac, &ipv4_packet);
+        assert_eq!(&frame[12..14], [0x08, 0x00]);
+        let ethernet_packet = EthernetPacket::new(&frame).unwrap();
+        assert_eq!(ethernet_packet.get_source(), src_mac);
+        assert_eq!(ethernet_packet.get_destination(), dest_mac);
+        assert_eq!(ethernet_packet.payload(), ipv4_packet);
+
+        let (ipv6_packet, _) = Tcp::build_tcp_packet_v6(
+            "2001:db8::1".parse()?,
+            12345,
+            "2001:db8::2".parse()?,
+            80,
+            TcpFlags::SYN,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )?;
+        let frame = DatalinkLayer::assemble_frame(src_mac, dest_mac, &ipv6_packet);
+        assert_eq!(&frame[12..14], [0x86, 0xdd]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backoff_on_transient_errors() {
+        // Transient errors are retried until the send succeeds.
+        let mut attempts = 0;
+        let result = send_with_backoff(|| {
+            attempts += 1;
+            match attempts {
+                1 => Some(Err(io::Error::from(ErrorKind::WouldBlock))),
+                2 => Some(Err(io::Error::from_raw_os_error(libc::ENOBUFS))),
+                _ => Some(Ok(())),
+            }
+        });
+        assert!(result.is_ok());
+        assert_eq!(attempts, 3);
+
+        // Transient errors that persist give up after the retries.
+        let mut attempts = 0;
+        let result = send_with_backoff(|| {
+            attempts += 1;
+            Some(Err(io::Error::from(ErrorKind::WouldBlock)))
+        });
+        let error = result.unwrap_err().downcast::<ScannerError>().unwrap();
+        assert!(matches!(error, ScannerError::RawSocketSendFailed(_)));
+        assert_eq!(attempts, SEND_RETRIES + 1);
+
+        // Permanent errors are not retried.
+        let mut attempts = 0;
+        let result = send_with_backoff(|| {
+            attempts += 1;
+            Some(Err(io::Error::from(ErrorKind::PermissionDenied)))
+        });
+        let error = result.unwrap_err().downcast::<ScannerError>().unwrap();
+        assert!(matches!(error, ScannerError::RawSocketPermissionDenied));
+        assert_eq!(attempts, 1);
+    }
 }
@@ -0,0 +1,232 @@
+use super::osi_layers::ipv6_upper_layer;
+use log::{log_enabled, trace, Level};
+use pnet::packet::{
+    arp::ArpPacket,
+    ethernet::{EtherTypes, EthernetPacket},
+    icmp::IcmpPacket,
+    icmpv6::Icmpv6Packet,
+    ip::{IpNextHeaderProtocol, IpNextHeaderProtocols},
+    ipv4::Ipv4Packet,
+    ipv6::Ipv6Packet,
+    tcp::{TcpFlags, TcpPacket},
+    udp::UdpPacket,
+    Packet,
+};
+use std::{fmt::Write, net::IpAddr};
+
+/// Log target of the hex dumps of sent and received frames.
+///
+/// Dumps are logged at trace level and only if this target is enabled, e.g. with `--hexdump`.
+pub const PACKET_LOG_TARGET: &str = "packets";
+
+/// Number of bytes per line of a hex dump.
+const BYTES_PER_LINE: usize = 16;
+
+/// Names of the TCP flags in the order of the header, from least significant bit.
+const TCP_FLAG_NAMES: [(u8, &str); 8] = [
+    (TcpFlags::FIN, "FIN"),
+    (TcpFlags::SYN, "SYN"),
+    (TcpFlags::RST, "RST"),
+    (TcpFlags::PSH, "PSH"),
+    (TcpFlags::ACK, "ACK"),
+    (TcpFlags::URG, "URG"),
+    (TcpFlags::ECE, "ECE"),
+    (TcpFlags::CWR, "CWR"),
+];
+
+/// Logs a summary and a hex dump of an Ethernet frame, e.g. to debug checksums or flags.
+///
+/// Does nothing unless tracing of `PACKET_LOG_TARGET` is enabled, so frames are only
+/// decoded and formatted when needed.
+pub fn log_frame(direction: &str, frame: &[u8]) {
+    if log_enabled!(target: PACKET_LOG_TARGET, Level::Trace) {
+        trace!(
+            target: PACKET_LOG_TARGET,
+            "{} {}\n{}",
+            direction,
+            summary(frame),
+            hexdump(frame)
+        );
+    }
+}
+
+/// Formats bytes like `hexdump -C`, with the offset, 16 bytes in hex and their printable ASCII.
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let _ = write!(dump, "{:04x}  ", line * BYTES_PER_LINE);
+        for index in 0..BYTES_PER_LINE {
+            match chunk.get(index) {
+                Some(byte) => {
+                    let _ = write!(dump, "{:02x} ", byte);
+                }
+                None => dump.push_str("   "),
+            }
+            if index == BYTES_PER_LINE / 2 - 1 {
+                dump.push(' ');
+            }
+        }
+        dump.push(' ');
+        dump.extend(chunk.iter().map(|byte| match byte {
+            0x20..=0x7e => *byte as char,
+            _ => '.',
+        }));
+        dump.push('\n');
+    }
+    dump.pop();
+    dump
+}
+
+/// Describes an Ethernet frame in one line, like `58 bytes, 10.0.0.1 -> 10.0.0.2 TCP 40000 -> 80 [SYN] ...`.
+///
+/// Parts that can't be decoded are left out.
+fn summary(frame: &[u8]) -> String {
+    let mut summary = format!("{} bytes", frame.len());
+    let Some(ethernet_packet) = EthernetPacket::new(frame) else {
+        return summary;
+    };
+
+    let (src_ip, dest_ip, protocol, payload) = match ethernet_packet.get_ethertype() {
+        EtherTypes::Ipv4 => match Ipv4Packet::new(ethernet_packet.payload()) {
+            Some(ip) => (
+                IpAddr::V4(ip.get_source()),
+                IpAddr::V4(ip.get_destination()),
+                ip.get_next_level_protocol(),
+                ip.payload().to_vec(),
+            ),
+            None => return summary,
+        },
+        EtherTypes::Ipv6 => match Ipv6Packet::new(ethernet_packet.payload()) {
+            Some(ip) => {
+                let (protocol, payload) = ipv6_upper_layer(ip.get_next_header(), ip.payload())
+                    .unwrap_or((ip.get_next_header(), ip.payload()));
+                (
+                    IpAddr::V6(ip.get_source()),
+                    IpAddr::V6(ip.get_destination()),
+                    protocol,
+                    payload.to_vec(),
+                )
+            }
+            None => return summary,
+        },
+        EtherTypes::Arp => {
+            if let Some(arp) = ArpPacket::new(ethernet_packet.payload()) {
+                let _ = write!(
+                    summary,
+                    ", ARP {} -> {}",
+                    arp.get_sender_proto_addr(),
+                    arp.get_target_proto_addr()
+                );
+            }
+            return summary;
+        }
+        ethertype => {
+            let _ = write!(summary, ", {}", ethertype);
+            return summary;
+        }
+    };
+
+    let _ = write!(summary, ", {} -> {}", src_ip, dest_ip);
+    let _ = write!(summary, " {}", transport_summary(protocol, &payload));
+    summary
+}
+
+/// Describes the transport header of an IP payload, e.g. its ports and TCP flags.
+fn transport_summary(protocol: IpNextHeaderProtocol, payload: &[u8]) -> String {
+    match protocol {
+        IpNextHeaderProtocols::Tcp => match TcpPacket::new(payload) {
+            Some(tcp) => {
+                let flags = TCP_FLAG_NAMES
+                    .iter()
+                    .filter(|(flag, _)| tcp.get_flags() & flag != 0)
+                    .map(|(_, name)| *name)
+                    .collect::<Vec<_>>()
+                    .join("|");
+                format!(
+                    "TCP {} -> {} [{}] seq {} ack {} win {} checksum 0x{:04x}",
+                    tcp.get_source(),
+                    tcp.get_destination(),
+                    flags,
+                    tcp.get_sequence(),
+                    tcp.get_acknowledgement(),
+                    tcp.get_window(),
+                    tcp.get_checksum()
+                )
+            }
+            None => "TCP".to_string(),
+        },
+        IpNextHeaderProtocols::Udp => match UdpPacket::new(payload) {
+            Some(udp) => format!(
+                "UDP {} -> {} checksum 0x{:04x}",
+                udp.get_source(),
+                udp.get_destination(),
+                udp.get_checksum()
+            ),
+            None => "UDP".to_string(),
+        },
+        IpNextHeaderProtocols::Icmp => match IcmpPacket::new(payload) {
+            Some(icmp) => format!(
+                "ICMP type {} code {}",
+                icmp.get_icmp_type().0,
+                icmp.get_icmp_code().0
+            ),
+            None => "ICMP".to_string(),
+        },
+        IpNextHeaderProtocols::Icmpv6 => match Icmpv6Packet::new(payload) {
+            Some(icmp) => format!(
+                "ICMPv6 type {} code {}",
+                icmp.get_icmpv6_type().0,
+                icmp.get_icmpv6_code().0
+            ),
+            None => "ICMPv6".to_string(),
+        },
+        protocol => protocol.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::{osi_layers::DatalinkLayer, probe::ProbeOptions, tcp::Tcp};
+    use pnet::util::MacAddr;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_hexdump() {
+        let bytes = (0x41..0x41 + 18).collect::<Vec<u8>>();
+        assert_eq!(
+            hexdump(&bytes),
+            "0000  41 42 43 44 45 46 47 48  49 4a 4b 4c 4d 4e 4f 50  ABCDEFGHIJKLMNOP\n\
+             0010  51 52                                             QR"
+        );
+        assert!(hexdump(&[0x00, 0x7f]).ends_with("  .."));
+        assert_eq!(hexdump(&[]), "");
+    }
+
+    #[test]
+    fn test_summary() {
+        let (packet, _) = Tcp::build_tcp_packet(
+            Ipv4Addr::new(192, 168, 0, 1),
+            40000,
+            Ipv4Addr::new(192, 168, 0, 2),
+            80,
+            TcpFlags::SYN | TcpFlags::ACK,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let frame = DatalinkLayer::build_ethernet_frame(
+            MacAddr::zero(),
+            MacAddr::zero(),
+            EtherTypes::Ipv4,
+            &packet,
+        );
+
+        let summary = summary(&frame);
+        assert!(summary.starts_with(&format!(
+            "{} bytes, 192.168.0.1 -> 192.168.0.2 TCP 40000 -> 80 [SYN|ACK]",
+            frame.len()
+        )));
+    }
+}
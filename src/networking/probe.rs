@@ -0,0 +1,504 @@
+use crate::errors::ScannerError;
+use anyhow::Result;
+use pnet::packet::{
+    ip::IpNextHeaderProtocol,
+    ipv4::{self, Ipv4Flags, Ipv4Packet, MutableIpv4Packet},
+    ipv6::{Ipv6Packet, MutableIpv6Packet},
+};
+use rand::Rng;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+pub const IPV4_HEADER_SIZE: usize = 20;
+pub const IPV6_HEADER_SIZE: usize = 40;
+
+/// Maximum number of decoys. Stored in a fixed-size array, so that the options can be copied.
+pub const MAX_DECOYS: usize = 16;
+
+const DEFAULT_TTL: u8 = 64;
+const DEFAULT_TOS: u8 = 0;
+const DEFAULT_WINDOW: u16 = 1024;
+const DEFAULT_URGENT_POINTER: u16 = 0;
+const DEFAULT_RETRIES: u8 = 0;
+const DEFAULT_TCP_OPTIONS: bool = false;
+const DEFAULT_MTU: Option<usize> = None;
+const DEFAULT_DONT_FRAGMENT: bool = true;
+const DEFAULT_BAD_CHECKSUM: bool = false;
+const DEFAULT_RESET_CONNECTIONS: bool = false;
+const DEFAULT_DRY_RUN: bool = false;
+
+/// Header fields of crafted probe packets and how they are sent, as chosen by the caller.
+///
+/// The defaults mimic the values most operating systems use.
+///
+/// Every probe gets its own clone, which shares the payload rather than copying it.
+#[derive(Debug, Clone)]
+pub struct ProbeOptions {
+    /// Time-to-live of the IPv4 header, or the hop limit of the IPv6 header.
+    pub ttl: u8,
+    /// Type-of-service byte of the IPv4 header, or the traffic class of the IPv6 header.
+    /// Holds the DSCP in the upper six bits and the ECN in the lower two bits.
+    pub tos: u8,
+    /// Window size advertised in the TCP header.
+    pub window: u16,
+    /// Urgent pointer of the TCP header. Real stacks only set it along with the URG flag.
+    pub urgent_pointer: u16,
+    /// Number of times a TCP probe is resent if no response arrives within the timeout.
+    pub retries: u8,
+    /// Whether TCP SYN probes carry the options of a real operating system, e.g. the MSS.
+    pub tcp_options: bool,
+    /// Maximum segment size announced by TCP SYN probes, e.g. 1460 as on Ethernet.
+    /// Replaces the MSS of `tcp_options`, or is the only option without them.
+    pub mss: Option<u16>,
+    /// Maximum size of sent IPv4 packets. Larger packets are fragmented if given.
+    pub mtu: Option<usize>,
+    /// Whether IPv4 headers set the DontFragment flag. Fragments never do, see `fragment_ipv4_packet`.
+    pub dont_fragment: bool,
+    /// Forged source addresses of TCP probes that are sent along with each real probe.
+    /// Requires privileges for raw sockets, like all crafted packets.
+    pub decoys: [Option<IpAddr>; MAX_DECOYS],
+    /// Whether TCP probes carry an invalid checksum. Hosts drop such packets, so any response
+    /// comes from a firewall or IDS that doesn't verify checksums.
+    pub bad_checksum: bool,
+    /// Data carried by TCP probes after their header, e.g. to trigger an application that
+    /// ignores empty segments. Probes carry no data if it's empty.
+    pub tcp_payload: Arc<[u8]>,
+    /// First and last port, both included, that random source ports are picked from, e.g. to pass
+    /// firewall rules. Ephemeral ports are picked if `None`, see `Tcp::random_source_port`.
+    pub source_port_range: Option<(u16, u16)>,
+    /// Shortest and longest random delay, both included, between two probes of a port scan,
+    /// so that they are not perfectly regular. Adds to the rate, see `RateLimiter::with_delay`.
+    pub delay: Option<(Duration, Duration)>,
+    /// Idle host and port whose IP identification the idle scan observes, see `tcp_idle_scan`.
+    pub zombie: Option<SocketAddr>,
+    /// SOCKS5 proxy through which the connect scan opens connections, see `Socks5`.
+    pub proxy: Option<SocketAddr>,
+    /// Whether the connect scan aborts connections with a RST instead of closing them with a FIN,
+    /// see `Tcp::reset_on_close`.
+    pub reset_connections: bool,
+    /// Whether probes are only built and logged, but never sent, e.g. to check a configuration.
+    /// Needs neither privileges nor network access, see `NetworkLayer::send_and_receive`.
+    pub dry_run: bool,
+}
+
+impl Default for ProbeOptions {
+    fn default() -> Self {
+        ProbeOptions {
+            ttl: DEFAULT_TTL,
+            tos: DEFAULT_TOS,
+            window: DEFAULT_WINDOW,
+            urgent_pointer: DEFAULT_URGENT_POINTER,
+            retries: DEFAULT_RETRIES,
+            tcp_options: DEFAULT_TCP_OPTIONS,
+            mss: None,
+            mtu: DEFAULT_MTU,
+            dont_fragment: DEFAULT_DONT_FRAGMENT,
+            decoys: [None; MAX_DECOYS],
+            bad_checksum: DEFAULT_BAD_CHECKSUM,
+            tcp_payload: Arc::from([]),
+            source_port_range: None,
+            delay: None,
+            zombie: None,
+            proxy: None,
+            reset_connections: DEFAULT_RESET_CONNECTIONS,
+            dry_run: DEFAULT_DRY_RUN,
+        }
+    }
+}
+
+/// Writes an IPv4 header to the start of `packet`, which must hold the whole datagram.
+///
+/// The checksum is computed last, so that it covers all fields set from the probe options.
+///
+/// May fail if `packet` is too small for the header.
+pub fn build_ipv4_header(
+    packet: &mut [u8],
+    src_ip: Ipv4Addr,
+    dest_ip: Ipv4Addr,
+    protocol: IpNextHeaderProtocol,
+    options: &ProbeOptions,
+) -> Result<()> {
+    build_ipv4_header_with_rng(
+        packet,
+        src_ip,
+        dest_ip,
+        protocol,
+        options,
+        &mut rand::thread_rng(),
+    )
+}
+
+/// Like `build_ipv4_header`, but draws the identification field from the given RNG.
+///
+/// Tests pass a seeded RNG to get deterministic packets.
+pub fn build_ipv4_header_with_rng(
+    packet: &mut [u8],
+    src_ip: Ipv4Addr,
+    dest_ip: Ipv4Addr,
+    protocol: IpNextHeaderProtocol,
+    options: &ProbeOptions,
+    rng: &mut impl Rng,
+) -> Result<()> {
+    let total_length = packet.len() as u16;
+
+    let mut ip_header = MutableIpv4Packet::new(packet).ok_or(ScannerError::CantCreateIpv4Packet)?;
+    ip_header.set_version(4);
+    ip_header.set_header_length((IPV4_HEADER_SIZE / 4) as u8);
+    ip_header.set_source(src_ip);
+    ip_header.set_destination(dest_ip);
+    ip_header.set_total_length(total_length);
+    ip_header.set_dscp(options.tos >> 2);
+    ip_header.set_ecn(options.tos & 0b11);
+    ip_header.set_identification(rng.gen());
+    ip_header.set_flags(match options.dont_fragment {
+        true => Ipv4Flags::DontFragment,
+        false => 0,
+    });
+    ip_header.set_ttl(options.ttl);
+    ip_header.set_next_level_protocol(protocol);
+    let ip_checksum = ipv4::checksum(&ip_header.to_immutable());
+    ip_header.set_checksum(ip_checksum);
+
+    Ok(())
+}
+
+/// Constructs a bare IP datagram of the given protocol, without any header of that protocol.
+///
+/// Probes which IP protocols a host supports. Hosts answer protocols they don't implement
+/// with an ICMP protocol unreachable error, but typically drop malformed packets of the others.
+///
+/// May fail if the packet can't hold its header.
+pub fn build_protocol_probe(
+    src_ip: Ipv4Addr,
+    dest_ip: Ipv4Addr,
+    protocol: u8,
+    options: &ProbeOptions,
+) -> Result<Vec<u8>> {
+    let mut packet = vec![0u8; IPV4_HEADER_SIZE];
+
+    build_ipv4_header(
+        &mut packet,
+        src_ip,
+        dest_ip,
+        IpNextHeaderProtocol(protocol),
+        options,
+    )?;
+
+    debug_assert!(
+        has_consistent_ip_header(&packet),
+        "Built an inconsistent protocol probe."
+    );
+
+    Ok(packet)
+}
+
+/// Splits an IPv4 datagram into fragments that fit into the MTU.
+///
+/// Fragmented probes may slip through firewalls and IDS that don't reassemble packets.
+/// Each fragment carries a copy of the IP header, but with its own length, fragment offset
+/// and checksum. All but the last fragment set the MoreFragments flag instead of DontFragment.
+///
+/// Returns the datagram unchanged if it fits into the MTU.
+pub fn fragment_ipv4_packet(packet: &[u8], mtu: usize) -> Vec<Vec<u8>> {
+    let header_length = match Ipv4Packet::new(packet) {
+        Some(ip_packet) => ip_packet.get_header_length() as usize * 4,
+        None => return vec![packet.to_vec()],
+    };
+
+    if packet.len() <= mtu || header_length > packet.len() {
+        return vec![packet.to_vec()];
+    }
+
+    // Fragment offsets are counted in units of 8 bytes, so all but the last fragment need
+    // a multiple of 8 bytes of payload. Fragments can't be smaller than 8 bytes.
+    let fragment_size = (mtu.saturating_sub(header_length) / 8 * 8).max(8);
+
+    let (header, payload) = packet.split_at(header_length);
+    let number_of_fragments = payload.len().div_ceil(fragment_size);
+
+    payload
+        .chunks(fragment_size)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut fragment = [header, chunk].concat();
+            let total_length = fragment.len() as u16;
+            let is_last = index + 1 == number_of_fragments;
+
+            let mut ip_header = MutableIpv4Packet::new(&mut fragment).unwrap();
+            ip_header.set_total_length(total_length);
+            ip_header.set_fragment_offset((index * fragment_size / 8) as u16);
+            ip_header.set_flags(if is_last { 0 } else { Ipv4Flags::MoreFragments });
+            let ip_checksum = ipv4::checksum(&ip_header.to_immutable());
+            ip_header.set_checksum(ip_checksum);
+
+            fragment
+        })
+        .collect()
+}
+
+/// Writes an IPv6 header to the start of `packet`, which must hold the whole datagram.
+///
+/// Unlike IPv4, the IPv6 header has no checksum of its own.
+pub fn build_ipv6_header(
+    packet: &mut [u8],
+    src_ip: Ipv6Addr,
+    dest_ip: Ipv6Addr,
+    next_header: IpNextHeaderProtocol,
+    options: &ProbeOptions,
+) -> Result<()> {
+    let payload_length = packet.len().saturating_sub(IPV6_HEADER_SIZE) as u16;
+
+    let mut ip_header = MutableIpv6Packet::new(packet).ok_or(ScannerError::CantCreateIpv6Packet)?;
+    ip_header.set_version(6);
+    ip_header.set_traffic_class(options.tos);
+    ip_header.set_flow_label(0);
+    ip_header.set_payload_length(payload_length);
+    ip_header.set_next_header(next_header);
+    ip_header.set_hop_limit(options.ttl);
+    ip_header.set_source(src_ip);
+    ip_header.set_destination(dest_ip);
+
+    Ok(())
+}
+
+/// Checks that the IP header of a built datagram agrees with the datagram.
+///
+/// The length fields must match the size of the datagram, and the IPv4 checksum must be valid.
+/// Builders assert this in debug builds before a packet is sent.
+pub fn has_consistent_ip_header(packet: &[u8]) -> bool {
+    match packet.first().map(|byte| byte >> 4) {
+        Some(4) => Ipv4Packet::new(packet).is_some_and(|ip_packet| {
+            ip_packet.get_total_length() as usize == packet.len()
+                && ip_packet.get_checksum() == ipv4::checksum(&ip_packet)
+        }),
+        Some(6) => Ipv6Packet::new(packet).is_some_and(|ip_packet| {
+            ip_packet.get_payload_length() as usize + IPV6_HEADER_SIZE == packet.len()
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pnet::packet::{ip::IpNextHeaderProtocols, ipv4::Ipv4Packet, ipv6::Ipv6Packet};
+
+    #[test]
+    fn test_build_ipv4_header_ttl() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+        let options = ProbeOptions {
+            ttl: 3,
+            ..Default::default()
+        };
+
+        let mut packet = [0u8; IPV4_HEADER_SIZE];
+        build_ipv4_header(
+            &mut packet,
+            src_ip,
+            dest_ip,
+            IpNextHeaderProtocols::Tcp,
+            &options,
+        )
+        .unwrap();
+
+        // The TTL is written and covered by the checksum.
+        let ip_packet = Ipv4Packet::new(&packet).unwrap();
+        assert_eq!(ip_packet.get_ttl(), 3);
+        assert_eq!(ip_packet.get_checksum(), ipv4::checksum(&ip_packet));
+    }
+
+    #[test]
+    fn test_build_ipv4_header_dont_fragment() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+
+        for dont_fragment in [true, false] {
+            let options = ProbeOptions {
+                dont_fragment,
+                ..Default::default()
+            };
+
+            let mut packet = [0u8; IPV4_HEADER_SIZE];
+            build_ipv4_header(
+                &mut packet,
+                src_ip,
+                dest_ip,
+                IpNextHeaderProtocols::Tcp,
+                &options,
+            )
+            .unwrap();
+
+            // The flag is written and covered by the checksum.
+            let ip_packet = Ipv4Packet::new(&packet).unwrap();
+            let flag = ip_packet.get_flags() & Ipv4Flags::DontFragment != 0;
+            assert_eq!(flag, dont_fragment);
+            assert_eq!(ip_packet.get_checksum(), ipv4::checksum(&ip_packet));
+        }
+    }
+
+    #[test]
+    fn test_build_ipv4_header_tos() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+
+        // Expedited forwarding (DSCP 46) with ECN capable transport (ECN 2).
+        let options = ProbeOptions {
+            tos: 46 << 2 | 2,
+            ..Default::default()
+        };
+
+        let mut packet = [0u8; IPV4_HEADER_SIZE];
+        build_ipv4_header(
+            &mut packet,
+            src_ip,
+            dest_ip,
+            IpNextHeaderProtocols::Tcp,
+            &options,
+        )
+        .unwrap();
+
+        let ip_packet = Ipv4Packet::new(&packet).unwrap();
+        assert_eq!(ip_packet.get_dscp(), 46);
+        assert_eq!(ip_packet.get_ecn(), 2);
+        assert_eq!(packet[1], options.tos);
+        assert_eq!(ip_packet.get_checksum(), ipv4::checksum(&ip_packet));
+
+        // The type of service is 0 by default.
+        build_ipv4_header(
+            &mut packet,
+            src_ip,
+            dest_ip,
+            IpNextHeaderProtocols::Tcp,
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(packet[1], 0);
+    }
+
+    #[test]
+    fn test_build_ipv6_header() {
+        let src_ip = "2001:db8::1".parse().unwrap();
+        let dest_ip = "2001:db8::2".parse().unwrap();
+        let options = ProbeOptions {
+            ttl: 3,
+            ..Default::default()
+        };
+
+        let mut packet = [0u8; IPV6_HEADER_SIZE + 8];
+        build_ipv6_header(
+            &mut packet,
+            src_ip,
+            dest_ip,
+            IpNextHeaderProtocols::Tcp,
+            &options,
+        )
+        .unwrap();
+
+        let ip_packet = Ipv6Packet::new(&packet).unwrap();
+        assert_eq!(ip_packet.get_version(), 6);
+        assert_eq!(ip_packet.get_payload_length(), 8);
+        assert_eq!(ip_packet.get_next_header(), IpNextHeaderProtocols::Tcp);
+        assert_eq!(ip_packet.get_hop_limit(), 3);
+        assert_eq!(ip_packet.get_source(), src_ip);
+        assert_eq!(ip_packet.get_destination(), dest_ip);
+    }
+
+    #[test]
+    fn test_has_consistent_ip_header() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+
+        // Buffers that can't hold the header are an error instead of a panic.
+        let mut packet = [0u8; IPV4_HEADER_SIZE - 1];
+        assert!(build_ipv4_header(
+            &mut packet,
+            src_ip,
+            dest_ip,
+            IpNextHeaderProtocols::Tcp,
+            &ProbeOptions::default(),
+        )
+        .is_err());
+        assert!(!has_consistent_ip_header(&packet));
+
+        let mut packet = [0u8; IPV4_HEADER_SIZE + 8];
+        build_ipv4_header(
+            &mut packet,
+            src_ip,
+            dest_ip,
+            IpNextHeaderProtocols::Tcp,
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        assert!(has_consistent_ip_header(&packet));
+
+        // A modified header no longer matches its checksum.
+        packet[8] ^= 0xFF;
+        assert!(!has_consistent_ip_header(&packet));
+    }
+
+    #[test]
+    fn test_build_protocol_probe() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+
+        let packet = build_protocol_probe(src_ip, dest_ip, 47, &ProbeOptions::default()).unwrap();
+        assert_eq!(packet.len(), IPV4_HEADER_SIZE);
+
+        let ip_packet = Ipv4Packet::new(&packet).unwrap();
+        assert_eq!(
+            ip_packet.get_next_level_protocol(),
+            IpNextHeaderProtocol(47)
+        );
+        assert_eq!(ip_packet.get_destination(), dest_ip);
+    }
+
+    #[test]
+    fn test_fragment_ipv4_packet() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+
+        // A datagram with 20 bytes of payload, e.g. a TCP header.
+        let mut packet = [0u8; IPV4_HEADER_SIZE + 20];
+        for (index, byte) in packet[IPV4_HEADER_SIZE..].iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        build_ipv4_header(
+            &mut packet,
+            src_ip,
+            dest_ip,
+            IpNextHeaderProtocols::Tcp,
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let identification = Ipv4Packet::new(&packet).unwrap().get_identification();
+
+        // Packets that fit into the MTU are not fragmented.
+        assert_eq!(fragment_ipv4_packet(&packet, 1500), vec![packet.to_vec()]);
+
+        // Fragments carry 8 bytes of payload, except for the last one.
+        let fragments = fragment_ipv4_packet(&packet, 28);
+        assert_eq!(fragments.len(), 3);
+
+        let mut payload = vec![0u8; 20];
+        for (index, fragment) in fragments.iter().enumerate() {
+            let ip_packet = Ipv4Packet::new(fragment).unwrap();
+            assert_eq!(ip_packet.get_total_length() as usize, fragment.len());
+            assert_eq!(ip_packet.get_identification(), identification);
+            assert_eq!(ip_packet.get_checksum(), ipv4::checksum(&ip_packet));
+
+            let more_fragments = ip_packet.get_flags() & Ipv4Flags::MoreFragments != 0;
+            assert_eq!(more_fragments, index < 2);
+            assert_eq!(ip_packet.get_flags() & Ipv4Flags::DontFragment, 0);
+
+            // Reassemble the payload at the fragment offsets.
+            let offset = ip_packet.get_fragment_offset() as usize * 8;
+            let data = &fragment[IPV4_HEADER_SIZE..];
+            payload[offset..offset + data.len()].copy_from_slice(data);
+        }
+        assert_eq!(payload, packet[IPV4_HEADER_SIZE..]);
+    }
+}
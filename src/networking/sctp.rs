@@ -0,0 +1,205 @@
+use super::{
+    interface::Interface,
+    osi_layers::{Exchange, Layer, NetworkLayer, TransportLayer},
+    probe::{build_ipv4_header, has_consistent_ip_header, ProbeOptions, IPV4_HEADER_SIZE},
+};
+use anyhow::Result;
+use pnet::packet::ip::IpNextHeaderProtocols;
+use rand::Rng;
+use std::{net::Ipv4Addr, time::Duration};
+
+/// Size of the SCTP common header: ports, verification tag and checksum.
+pub const SCTP_HEADER_SIZE: usize = 12;
+const INIT_CHUNK_SIZE: usize = 20;
+
+/// Chunk types of RFC 4960 that are sent or expected in response.
+pub const CHUNK_INIT: u8 = 1;
+pub const CHUNK_INIT_ACK: u8 = 2;
+pub const CHUNK_ABORT: u8 = 6;
+
+/// Receiver window announced in INIT chunks, as used by Linux.
+const INIT_RECEIVER_WINDOW: u32 = 106_496;
+/// Number of streams announced in INIT chunks in each direction.
+const INIT_STREAMS: u16 = 10;
+
+/// Reversed polynomial of CRC32c (Castagnoli), as used by SCTP.
+const CRC32C_POLYNOMIAL: u32 = 0x82F6_3B78;
+
+pub struct Sctp;
+
+impl Sctp {
+    /// Constructs an IP datagram with an SCTP common header and an INIT chunk.
+    ///
+    /// The INIT chunk opens an association, like a TCP SYN opens a connection.
+    /// Its verification tag is zero, since the target has not chosen a tag yet.
+    /// `initiate_tag` is the tag the target must use in its response and should be random.
+    ///
+    /// May fail if the packet can't hold its headers.
+    pub fn build_init_packet(
+        src_ip: Ipv4Addr,
+        src_port: u16,
+        dest_ip: Ipv4Addr,
+        dest_port: u16,
+        initiate_tag: u32,
+        options: &ProbeOptions,
+    ) -> Result<Vec<u8>> {
+        let mut ip_packet = vec![0u8; IPV4_HEADER_SIZE + SCTP_HEADER_SIZE + INIT_CHUNK_SIZE];
+
+        build_ipv4_header(
+            &mut ip_packet,
+            src_ip,
+            dest_ip,
+            IpNextHeaderProtocols::Sctp,
+            options,
+        )?;
+
+        let initial_tsn = rand::thread_rng().gen::<u32>();
+
+        let sctp_packet = &mut ip_packet[IPV4_HEADER_SIZE..];
+        sctp_packet[0..2].copy_from_slice(&src_port.to_be_bytes());
+        sctp_packet[2..4].copy_from_slice(&dest_port.to_be_bytes());
+
+        let chunk = &mut sctp_packet[SCTP_HEADER_SIZE..];
+        chunk[0] = CHUNK_INIT;
+        chunk[2..4].copy_from_slice(&(INIT_CHUNK_SIZE as u16).to_be_bytes());
+        chunk[4..8].copy_from_slice(&initiate_tag.to_be_bytes());
+        chunk[8..12].copy_from_slice(&INIT_RECEIVER_WINDOW.to_be_bytes());
+        chunk[12..14].copy_from_slice(&INIT_STREAMS.to_be_bytes());
+        chunk[14..16].copy_from_slice(&INIT_STREAMS.to_be_bytes());
+        chunk[16..20].copy_from_slice(&initial_tsn.to_be_bytes());
+
+        // The checksum covers the whole packet with a zeroed checksum field. Unlike all other
+        // fields, it is written in little-endian byte order, see RFC 4960 appendix B.
+        let checksum = crc32c(sctp_packet);
+        sctp_packet[8..12].copy_from_slice(&checksum.to_le_bytes());
+
+        debug_assert!(
+            has_consistent_ip_header(&ip_packet),
+            "Built an inconsistent SCTP packet."
+        );
+
+        Ok(ip_packet)
+    }
+
+    /// Sends an SCTP INIT chunk from a random initiate tag.
+    ///
+    /// The packet is handed over to the network layer. Matches either an SCTP response
+    /// or an ICMP error that quotes the sent datagram.
+    pub fn send_init_packet(
+        interface: Interface,
+        src_ip: Ipv4Addr,
+        src_port: u16,
+        dest_ip: Ipv4Addr,
+        dest_port: u16,
+        options: ProbeOptions,
+        timeout: Duration,
+    ) -> Result<Exchange> {
+        // A zero initiate tag is invalid and gets aborted by the target.
+        let initiate_tag = rand::thread_rng().gen_range(1..=u32::MAX);
+        let packet =
+            Sctp::build_init_packet(src_ip, src_port, dest_ip, dest_port, initiate_tag, &options)?;
+
+        let network_layer = NetworkLayer {
+            datalink_layer: None,
+            src_addr: Some(dest_ip.into()),
+            dest_addr: Some(src_ip.into()),
+            protocol: None,
+        };
+
+        let transport_layer = TransportLayer {
+            network_layer: Some(network_layer),
+            src_port: Some(dest_port),
+            dest_port: Some(src_port),
+            tcp_sequence: None,
+            icmp_echo: None,
+        };
+
+        let layers = Layer::Four(transport_layer);
+
+        NetworkLayer::send_and_receive(interface, &[packet], layers, &options, timeout)
+    }
+
+    /// Returns the source and destination port of an SCTP packet.
+    pub fn ports(sctp_packet: &[u8]) -> Option<(u16, u16)> {
+        let ports = sctp_packet.get(0..4)?;
+        Some((
+            u16::from_be_bytes([ports[0], ports[1]]),
+            u16::from_be_bytes([ports[2], ports[3]]),
+        ))
+    }
+
+    /// Returns the type of the first chunk of an SCTP packet.
+    pub fn chunk_type(sctp_packet: &[u8]) -> Option<u8> {
+        sctp_packet.get(SCTP_HEADER_SIZE).copied()
+    }
+}
+
+/// Computes the CRC32c checksum of `data`, bit by bit to spare a lookup table.
+///
+/// Probes are small, so the slower computation doesn't matter.
+pub fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ CRC32C_POLYNOMIAL,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pnet::packet::ipv4::Ipv4Packet;
+
+    #[test]
+    fn test_crc32c() {
+        // Check value of the CRC catalogue and test vectors of RFC 3720 appendix B.4.
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+        assert_eq!(crc32c(&[0x00; 32]), 0x8A91_36AA);
+        assert_eq!(crc32c(&[0xFF; 32]), 0x62A8_AB43);
+        let ascending = (0..32).collect::<Vec<u8>>();
+        assert_eq!(crc32c(&ascending), 0x46DD_794E);
+    }
+
+    #[test]
+    fn test_build_init_packet() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+
+        let packet = Sctp::build_init_packet(
+            src_ip,
+            12345,
+            dest_ip,
+            3868,
+            0xDEAD_BEEF,
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+
+        // Verify the IP packet.
+        let ip_packet = Ipv4Packet::new(&packet).unwrap();
+        assert_eq!(
+            ip_packet.get_next_level_protocol(),
+            IpNextHeaderProtocols::Sctp
+        );
+        assert_eq!(ip_packet.get_total_length() as usize, packet.len());
+
+        // Verify the common header and the INIT chunk.
+        let sctp_packet = &packet[IPV4_HEADER_SIZE..];
+        assert_eq!(Sctp::ports(sctp_packet), Some((12345, 3868)));
+        assert_eq!(sctp_packet[4..8], [0; 4]);
+        assert_eq!(Sctp::chunk_type(sctp_packet), Some(CHUNK_INIT));
+        assert_eq!(sctp_packet[14..16], (INIT_CHUNK_SIZE as u16).to_be_bytes());
+        assert_eq!(sctp_packet[16..20], 0xDEAD_BEEFu32.to_be_bytes());
+
+        // The checksum covers the packet with a zeroed checksum field.
+        let mut unchecked = sctp_packet.to_vec();
+        unchecked[8..12].fill(0);
+        assert_eq!(sctp_packet[8..12], crc32c(&unchecked).to_le_bytes());
+    }
+}
@@ -0,0 +1,182 @@
+use crate::errors::ScannerError;
+use anyhow::Result;
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream},
+    time::{Duration, Instant},
+};
+
+/// Version number of SOCKS5 at the start of every message, see RFC 1928.
+const SOCKS_VERSION: u8 = 5;
+/// Authentication method that requires no authentication.
+const NO_AUTHENTICATION: u8 = 0;
+/// Command that asks the proxy to open a TCP connection.
+const COMMAND_CONNECT: u8 = 1;
+
+/// Address types of SOCKS5 requests and replies.
+const ADDRESS_IPV4: u8 = 1;
+const ADDRESS_DOMAIN: u8 = 3;
+const ADDRESS_IPV6: u8 = 4;
+
+/// Reply codes of SOCKS5 that describe the connection to the target.
+const REPLY_SUCCEEDED: u8 = 0;
+const REPLY_NETWORK_UNREACHABLE: u8 = 3;
+const REPLY_HOST_UNREACHABLE: u8 = 4;
+const REPLY_CONNECTION_REFUSED: u8 = 5;
+const REPLY_TTL_EXPIRED: u8 = 6;
+
+pub struct Socks5;
+
+impl Socks5 {
+    /// Opens a TCP connection to the given socket through a SOCKS5 proxy.
+    ///
+    /// Performs the handshake of RFC 1928 without authentication, then asks the proxy to
+    /// connect to the target. The target only sees connections from the proxy.
+    ///
+    /// Returns the outcome of the connection attempt of the proxy and the round-trip time,
+    /// like `Tcp::connect`. A target that refuses the connection returns a `ConnectionRefused`
+    /// error. May fail if the proxy can't be reached or refuses to connect to the target.
+    pub fn connect(
+        proxy: SocketAddr,
+        dest_ip: IpAddr,
+        dest_port: u16,
+        timeout: Duration,
+    ) -> Result<(io::Result<TcpStream>, Duration)> {
+        let mut stream = TcpStream::connect_timeout(&proxy, timeout)
+            .map_err(|_| ScannerError::ProxyUnreachable(proxy))?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        // Offer no authentication, which is the only method supported.
+        stream.write_all(&[SOCKS_VERSION, 1, NO_AUTHENTICATION])?;
+        let mut method = [0u8; 2];
+        stream
+            .read_exact(&mut method)
+            .map_err(|_| ScannerError::ProxyUnreachable(proxy))?;
+        if method != [SOCKS_VERSION, NO_AUTHENTICATION] {
+            Err(ScannerError::ProxyAuthentication(proxy))?;
+        }
+
+        let start_time = Instant::now();
+        stream.write_all(&connect_request(dest_ip, dest_port))?;
+
+        // The proxy replies once its connection attempt is done, which is the round-trip time.
+        let outcome = read_reply(&mut stream).map(|_| stream);
+        let rtt = start_time.elapsed();
+
+        match outcome {
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                Err(ScannerError::ProxyRefused(proxy))?
+            }
+            outcome => Ok((outcome, rtt)),
+        }
+    }
+}
+
+/// Builds the request that asks the proxy to connect to the given socket.
+fn connect_request(dest_ip: IpAddr, dest_port: u16) -> Vec<u8> {
+    let mut request = vec![SOCKS_VERSION, COMMAND_CONNECT, 0];
+    match dest_ip {
+        IpAddr::V4(ip) => {
+            request.push(ADDRESS_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            request.push(ADDRESS_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+    }
+    request.extend_from_slice(&dest_port.to_be_bytes());
+    request
+}
+
+/// Reads the reply to a connect request and maps its reply code to the outcome of a connect call.
+///
+/// Refused connections -> `ConnectionRefused`. Unreachable targets -> other errors.
+/// Rejected requests, e.g. by the rules of the proxy -> `PermissionDenied`.
+/// A proxy that doesn't reply within the timeout also gave up on the target -> `TimedOut`.
+fn read_reply(stream: &mut impl Read) -> io::Result<()> {
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).map_err(|e| match e.kind() {
+        ErrorKind::WouldBlock => io::Error::from(ErrorKind::TimedOut),
+        _ => e,
+    })?;
+
+    match header[1] {
+        REPLY_SUCCEEDED => {}
+        REPLY_CONNECTION_REFUSED => return Err(ErrorKind::ConnectionRefused.into()),
+        REPLY_NETWORK_UNREACHABLE | REPLY_HOST_UNREACHABLE | REPLY_TTL_EXPIRED => {
+            return Err(io::Error::other("host unreachable"))
+        }
+        _ => return Err(ErrorKind::PermissionDenied.into()),
+    }
+
+    // Skip the address the proxy bound for the connection.
+    let address_size = match header[3] {
+        ADDRESS_IPV4 => 4,
+        ADDRESS_IPV6 => 16,
+        ADDRESS_DOMAIN => {
+            let mut size = [0u8; 1];
+            stream.read_exact(&mut size)?;
+            size[0] as usize
+        }
+        _ => return Err(ErrorKind::InvalidData.into()),
+    };
+    let mut bound_address = vec![0u8; address_size + 2];
+    stream.read_exact(&mut bound_address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::Ipv4Addr, net::TcpListener, thread};
+
+    #[test]
+    fn test_connect_request() {
+        let request = connect_request(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)), 443);
+        assert_eq!(request, [5, 1, 0, 1, 192, 168, 1, 2, 0x01, 0xBB]);
+
+        let request = connect_request("::1".parse().unwrap(), 22);
+        assert_eq!(request.len(), 4 + 16 + 2);
+        assert_eq!(request[3], ADDRESS_IPV6);
+    }
+
+    #[test]
+    fn test_read_reply() {
+        let reply = |code: u8| [5, code, 0, 1, 10, 0, 0, 1, 0x1F, 0x90];
+
+        assert!(read_reply(&mut &reply(REPLY_SUCCEEDED)[..]).is_ok());
+        let error = read_reply(&mut &reply(REPLY_CONNECTION_REFUSED)[..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ConnectionRefused);
+        let error = read_reply(&mut &reply(REPLY_HOST_UNREACHABLE)[..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Other);
+        let error = read_reply(&mut &reply(2)[..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn test_connect_through_proxy() -> Result<()> {
+        // A proxy that accepts the handshake, but reports that the target refused the connection.
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let proxy = listener.local_addr()?;
+        let server = thread::spawn(move || -> io::Result<()> {
+            let (mut stream, _) = listener.accept()?;
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting)?;
+            stream.write_all(&[SOCKS_VERSION, NO_AUTHENTICATION])?;
+            let mut request = [0u8; 10];
+            stream.read_exact(&mut request)?;
+            stream.write_all(&[5, REPLY_CONNECTION_REFUSED, 0, 1, 0, 0, 0, 0, 0, 0])
+        });
+
+        let dest_ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2));
+        let (outcome, _rtt) = Socks5::connect(proxy, dest_ip, 80, Duration::from_secs(1))?;
+        assert_eq!(outcome.unwrap_err().kind(), ErrorKind::ConnectionRefused);
+        server.join().unwrap()?;
+
+        // Nothing listens on the port of the proxy anymore.
+        assert!(Socks5::connect(proxy, dest_ip, 80, Duration::from_secs(1)).is_err());
+
+        Ok(())
+    }
+}
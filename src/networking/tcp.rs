@@ -1,107 +1,598 @@
 use super::{
     interface::Interface,
-    osi_layers::{Layer, NetworkLayer, TransportLayer},
+    osi_layers::{Exchange, Layer, NetworkLayer, TcpSequence, TransportLayer},
+    probe::{
+        build_ipv4_header_with_rng, build_ipv6_header, has_consistent_ip_header, ProbeOptions,
+        IPV4_HEADER_SIZE, IPV6_HEADER_SIZE,
+    },
 };
+use crate::errors::ScannerError;
 use anyhow::Result;
 use pnet::packet::{
     self,
     ip::IpNextHeaderProtocols,
-    ipv4::{self, Ipv4Flags, MutableIpv4Packet},
-    tcp::MutableTcpPacket,
+    ipv4::Ipv4Packet,
+    ipv6::Ipv6Packet,
+    tcp::{MutableTcpPacket, TcpFlags, TcpOption, TcpOptionPacket, TcpPacket},
+    Packet,
 };
 use rand::Rng;
-use std::{net::Ipv4Addr, time::Duration};
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream},
+    ops::RangeInclusive,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-const IPV4_HEADER_SIZE: usize = 20;
 const TCP_HEADER_SIZE: usize = 20;
-const TCP_DATA_SIZE: usize = 0;
-const TTL: u8 = 64;
+
+/// Maximum segment size announced in SYN packets, as usual for Ethernet.
+const SYN_MSS: u16 = 1460;
+/// Window scale announced in SYN packets, as used by Linux.
+const SYN_WINDOW_SCALE: u8 = 7;
+
+/// Maximum number of bytes read from a service banner.
+const BANNER_SIZE: usize = 1024;
+
+/// Bits flipped in the checksum of probes with a bad checksum.
+const BAD_CHECKSUM_MASK: u16 = 0x5555;
+
+/// Dynamic port range as assigned by IANA.
+const EPHEMERAL_PORTS: RangeInclusive<u16> = 49152..=65535;
 
 pub struct Tcp;
 
 impl Tcp {
     /// Constructs an IP datagram with a TCP header.
+    ///
+    /// `flags` is a combination of the `TcpFlags` constants, like `TcpFlags::SYN | TcpFlags::ACK`.
+    ///
+    /// The TCP options are appended to the header, which grows accordingly, and the payload
+    /// follows the header. Both are covered by the lengths and checksums.
+    ///
+    /// Returns the packet and its random sequence numbers to match the response.
+    /// May fail if the packet can't hold its headers.
+    #[allow(clippy::too_many_arguments)]
     pub fn build_tcp_packet(
         src_ip: Ipv4Addr,
         src_port: u16,
         dest_ip: Ipv4Addr,
         dest_port: u16,
         flags: u8,
-    ) -> [u8; IPV4_HEADER_SIZE + TCP_HEADER_SIZE + TCP_DATA_SIZE] {
-        let mut rng = rand::thread_rng();
-        let mut ip_packet = [0u8; IPV4_HEADER_SIZE + TCP_HEADER_SIZE + TCP_DATA_SIZE];
-
-        let mut ip_header = MutableIpv4Packet::new(&mut ip_packet).unwrap();
-        ip_header.set_version(4);
-        ip_header.set_header_length(5);
-        ip_header.set_source(src_ip);
-        ip_header.set_destination(dest_ip);
-        ip_header.set_total_length((IPV4_HEADER_SIZE + TCP_HEADER_SIZE + TCP_DATA_SIZE) as u16);
-        ip_header.set_identification(rng.gen());
-        ip_header.set_flags(Ipv4Flags::DontFragment);
-        ip_header.set_ttl(TTL);
-        ip_header.set_next_level_protocol(IpNextHeaderProtocols::Tcp);
-        let ip_checksum = ipv4::checksum(&ip_header.to_immutable());
-        ip_header.set_checksum(ip_checksum);
-
-        let mut tcp_header = MutableTcpPacket::new(&mut ip_packet[IPV4_HEADER_SIZE..]).unwrap();
+        tcp_options: &[TcpOption],
+        payload: &[u8],
+        options: &ProbeOptions,
+    ) -> Result<(Vec<u8>, TcpSequence)> {
+        Tcp::build_tcp_packet_with_rng(
+            src_ip,
+            src_port,
+            dest_ip,
+            dest_port,
+            flags,
+            tcp_options,
+            payload,
+            options,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    /// Like `build_tcp_packet`, but draws the IP identification, the sequence number and,
+    /// with the ACK flag, the acknowledgement number from the given RNG, in this order.
+    ///
+    /// Tests pass a seeded RNG to get deterministic packets.
+    #[allow(clippy::too_many_arguments)]
+    pub fn build_tcp_packet_with_rng(
+        src_ip: Ipv4Addr,
+        src_port: u16,
+        dest_ip: Ipv4Addr,
+        dest_port: u16,
+        flags: u8,
+        tcp_options: &[TcpOption],
+        payload: &[u8],
+        options: &ProbeOptions,
+        rng: &mut impl Rng,
+    ) -> Result<(Vec<u8>, TcpSequence)> {
+        let tcp_header_size = Tcp::tcp_header_size(tcp_options);
+        let mut ip_packet = vec![0u8; IPV4_HEADER_SIZE + tcp_header_size + payload.len()];
+
+        build_ipv4_header_with_rng(
+            &mut ip_packet,
+            src_ip,
+            dest_ip,
+            IpNextHeaderProtocols::Tcp,
+            options,
+            rng,
+        )?;
+
+        let mut tcp_header = MutableTcpPacket::new(&mut ip_packet[IPV4_HEADER_SIZE..])
+            .ok_or(ScannerError::CantCreateTcpPacket)?;
+        let sequence = Tcp::set_tcp_header(
+            &mut tcp_header,
+            src_port,
+            dest_port,
+            flags,
+            tcp_options,
+            payload,
+            options,
+            rng,
+        );
+        let tcp_checksum =
+            packet::tcp::ipv4_checksum(&tcp_header.to_immutable(), &src_ip, &dest_ip);
+        tcp_header.set_checksum(Tcp::probe_checksum(tcp_checksum, options));
+
+        Ok((ip_packet, sequence))
+    }
+
+    /// Constructs an IPv6 datagram with a TCP header.
+    ///
+    /// `flags` is a combination of the `TcpFlags` constants, like `TcpFlags::SYN | TcpFlags::ACK`.
+    ///
+    /// The TCP options are appended to the header, which grows accordingly, and the payload
+    /// follows the header. Both are covered by the lengths and checksums.
+    ///
+    /// Returns the packet and its random sequence numbers to match the response.
+    /// May fail if the packet can't hold its headers.
+    #[allow(clippy::too_many_arguments)]
+    pub fn build_tcp_packet_v6(
+        src_ip: Ipv6Addr,
+        src_port: u16,
+        dest_ip: Ipv6Addr,
+        dest_port: u16,
+        flags: u8,
+        tcp_options: &[TcpOption],
+        payload: &[u8],
+        options: &ProbeOptions,
+    ) -> Result<(Vec<u8>, TcpSequence)> {
+        let tcp_header_size = Tcp::tcp_header_size(tcp_options);
+        let mut ip_packet = vec![0u8; IPV6_HEADER_SIZE + tcp_header_size + payload.len()];
+
+        build_ipv6_header(
+            &mut ip_packet,
+            src_ip,
+            dest_ip,
+            IpNextHeaderProtocols::Tcp,
+            options,
+        )?;
+
+        let mut tcp_header = MutableTcpPacket::new(&mut ip_packet[IPV6_HEADER_SIZE..])
+            .ok_or(ScannerError::CantCreateTcpPacket)?;
+        let sequence = Tcp::set_tcp_header(
+            &mut tcp_header,
+            src_port,
+            dest_port,
+            flags,
+            tcp_options,
+            payload,
+            options,
+            &mut rand::thread_rng(),
+        );
+        let tcp_checksum =
+            packet::tcp::ipv6_checksum(&tcp_header.to_immutable(), &src_ip, &dest_ip);
+        tcp_header.set_checksum(Tcp::probe_checksum(tcp_checksum, options));
+
+        Ok((ip_packet, sequence))
+    }
+
+    /// Checks that the lengths and checksums of a built IPv4 or IPv6 datagram with a TCP header
+    /// agree with its content. A bad checksum is only consistent if the probe options ask for it.
+    fn is_consistent(packet: &[u8], options: &ProbeOptions) -> bool {
+        if !has_consistent_ip_header(packet) {
+            return false;
+        }
+        let checksum = match packet[0] >> 4 {
+            4 => Ipv4Packet::new(packet).and_then(|ip| {
+                TcpPacket::new(ip.payload()).map(|segment| {
+                    (
+                        segment.get_checksum(),
+                        packet::tcp::ipv4_checksum(
+                            &segment,
+                            &ip.get_source(),
+                            &ip.get_destination(),
+                        ),
+                    )
+                })
+            }),
+            _ => Ipv6Packet::new(packet).and_then(|ip| {
+                TcpPacket::new(ip.payload()).map(|segment| {
+                    (
+                        segment.get_checksum(),
+                        packet::tcp::ipv6_checksum(
+                            &segment,
+                            &ip.get_source(),
+                            &ip.get_destination(),
+                        ),
+                    )
+                })
+            }),
+        };
+        checksum.is_some_and(|(actual, expected)| actual == Tcp::probe_checksum(expected, options))
+    }
+
+    /// Returns the checksum to write into the TCP header, which is deliberately wrong if the
+    /// probe options ask for a bad checksum.
+    ///
+    /// Flipping bits keeps the checksum wrong even in one's complement arithmetic, where 0x0000
+    /// and 0xFFFF are the same value and a simple increment could yield a valid checksum.
+    fn probe_checksum(checksum: u16, options: &ProbeOptions) -> u16 {
+        match options.bad_checksum {
+            true => checksum ^ BAD_CHECKSUM_MASK,
+            false => checksum,
+        }
+    }
+
+    /// Sets all fields of the TCP header and the payload, except the checksum, which depends
+    /// on the IP version.
+    ///
+    /// Returns the random sequence numbers that were set, along with the length of the payload.
+    #[allow(clippy::too_many_arguments)]
+    fn set_tcp_header(
+        tcp_header: &mut MutableTcpPacket,
+        src_port: u16,
+        dest_port: u16,
+        flags: u8,
+        tcp_options: &[TcpOption],
+        payload: &[u8],
+        options: &ProbeOptions,
+        rng: &mut impl Rng,
+    ) -> TcpSequence {
+        let sequence_number = rng.gen();
+
+        // The acknowledgement number is only valid with the ACK flag. Real stacks set it to zero
+        // otherwise, so a random number would give away the probe as crafted.
+        let acknowledgement = match flags & TcpFlags::ACK != 0 {
+            true => rng.gen(),
+            false => 0,
+        };
+
+        let sequence = TcpSequence {
+            sequence: sequence_number,
+            acknowledgement,
+            flags,
+            payload_length: payload.len() as u32,
+        };
+
         tcp_header.set_source(src_port);
         tcp_header.set_destination(dest_port);
-        tcp_header.set_sequence(rng.gen());
-        tcp_header.set_acknowledgement(rng.gen());
+        tcp_header.set_sequence(sequence.sequence);
+        tcp_header.set_acknowledgement(sequence.acknowledgement);
         tcp_header.set_reserved(0);
         tcp_header.set_flags(flags);
-        tcp_header.set_urgent_ptr(0);
-        tcp_header.set_window(1024);
-        tcp_header.set_data_offset(5);
-        let tcp_checksum =
-            packet::tcp::ipv4_checksum(&tcp_header.to_immutable(), &src_ip, &dest_ip);
-        tcp_header.set_checksum(tcp_checksum);
+        tcp_header.set_urgent_ptr(options.urgent_pointer);
+        tcp_header.set_window(options.window);
+        tcp_header.set_data_offset((Tcp::tcp_header_size(tcp_options) / 4) as u8);
+        tcp_header.set_options(tcp_options);
+        tcp_header.set_payload(payload);
+
+        sequence
+    }
+
+    /// Returns the size of a TCP header with the given options.
+    ///
+    /// Options are padded with zeros, i.e. end of option list, to a multiple of 4 bytes.
+    fn tcp_header_size(tcp_options: &[TcpOption]) -> usize {
+        let options_size: usize = tcp_options.iter().map(TcpOptionPacket::packet_size).sum();
+        TCP_HEADER_SIZE + options_size.div_ceil(4) * 4
+    }
+
+    /// Returns the TCP options of a SYN packet sent by Linux.
+    ///
+    /// Without them, SYN probes are easily fingerprinted as crafted by a scanner.
+    pub fn syn_options() -> Vec<TcpOption> {
+        // Timestamps are not bound to the system clock, but usually increase in milliseconds.
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis() as u32);
+
+        vec![
+            TcpOption::mss(SYN_MSS),
+            TcpOption::sack_perm(),
+            TcpOption::timestamp(timestamp, 0),
+            TcpOption::nop(),
+            TcpOption::wscale(SYN_WINDOW_SCALE),
+        ]
+    }
+
+    /// Returns the TCP options of a probe with the given flags, as chosen by the probe options.
+    fn probe_tcp_options(flags: u8, options: &ProbeOptions) -> Vec<TcpOption> {
+        // Only SYN packets negotiate options like the MSS.
+        if flags & TcpFlags::SYN == 0 {
+            return Vec::new();
+        }
 
-        ip_packet
+        match (options.tcp_options, options.mss) {
+            (true, mss) => {
+                let mut tcp_options = Tcp::syn_options();
+                // The MSS comes first, like in the SYN packets of real stacks.
+                if let Some(mss) = mss {
+                    tcp_options[0] = TcpOption::mss(mss);
+                }
+                tcp_options
+            }
+            (false, Some(mss)) => vec![TcpOption::mss(mss)],
+            (false, None) => Vec::new(),
+        }
     }
 
-    /// Sends a TCP packet with the given `TcpFlags`.
-    /// 
+    /// Sends a TCP packet with the given `TcpFlags`, which are combined into `flags` bitwise.
+    ///
+    /// Builds an IPv4 or IPv6 datagram depending on the addresses, which must be of the same version.
     /// The packet is handed over to the network layer.
+    ///
+    /// Resends the packet up to `options.retries` times if no response arrives within the timeout.
+    /// Every attempt builds a new packet, so that the IP identification field differs.
+    /// Each packet carries `options.tcp_payload` after its header.
+    ///
+    /// Each attempt is hidden among packets from `options.decoys`, which are sent along in random order.
+    /// Responses to the decoys are sent to their forged address and will never be received.
+    ///
+    /// Returns the exchange of the last attempt and the number of attempts.
+    /// A dry run builds the packets once and makes a single attempt.
+    #[allow(clippy::too_many_arguments)]
     pub fn send_tcp_packet(
         interface: Interface,
-        src_ip: Ipv4Addr,
+        src_ip: IpAddr,
         src_port: u16,
-        dest_ip: Ipv4Addr,
+        dest_ip: IpAddr,
         dest_port: u16,
         flags: u8,
+        options: ProbeOptions,
         timeout: Duration,
-    ) -> Result<(Option<Vec<u8>>, Duration)> {
-        let packet = Tcp::build_tcp_packet(src_ip, src_port, dest_ip, dest_port, flags);
+    ) -> Result<(Exchange, u16)> {
+        let build_packet = |src_ip: IpAddr| {
+            let tcp_options = Tcp::probe_tcp_options(flags, &options);
+
+            let (packet, sequence) = match (src_ip, dest_ip) {
+                (IpAddr::V4(src_ip), IpAddr::V4(dest_ip)) => Tcp::build_tcp_packet(
+                    src_ip,
+                    src_port,
+                    dest_ip,
+                    dest_port,
+                    flags,
+                    &tcp_options,
+                    &options.tcp_payload,
+                    &options,
+                )?,
+                (IpAddr::V6(src_ip), IpAddr::V6(dest_ip)) => Tcp::build_tcp_packet_v6(
+                    src_ip,
+                    src_port,
+                    dest_ip,
+                    dest_port,
+                    flags,
+                    &tcp_options,
+                    &options.tcp_payload,
+                    &options,
+                )?,
+                _ => Err(ScannerError::UnsupportedIpVersion)?,
+            };
+            debug_assert!(
+                Tcp::is_consistent(&packet, &options),
+                "Built an inconsistent TCP packet."
+            );
+            Ok::<_, anyhow::Error>((packet, sequence))
+        };
 
         let network_layer = NetworkLayer {
             datalink_layer: None,
-            src_addr: Some(dest_ip.into()),
-            dest_addr: Some(src_ip.into()),
+            src_addr: Some(dest_ip),
+            dest_addr: Some(src_ip),
+            protocol: None,
         };
 
-        let transport_layer = TransportLayer {
-            network_layer: Some(network_layer),
-            src_port: Some(dest_port),
-            dest_port: Some(src_port),
-        };
+        let max_attempts = u16::from(options.retries) + 1;
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            // Each attempt has new sequence numbers, so only its own response can match.
+            let (packet, sequence) = build_packet(src_ip)?;
+
+            let mut packets = options
+                .decoys
+                .iter()
+                .flatten()
+                .map(|decoy| build_packet(*decoy).map(|(packet, _)| packet))
+                .collect::<Result<Vec<_>, _>>()?;
+            let position = rand::thread_rng().gen_range(0..=packets.len());
+            packets.insert(position, packet);
+
+            let transport_layer = TransportLayer {
+                network_layer: Some(network_layer),
+                src_port: Some(dest_port),
+                dest_port: Some(src_port),
+                tcp_sequence: Some(sequence),
+                icmp_echo: None,
+            };
+
+            let layer = Layer::Four(transport_layer);
+
+            match NetworkLayer::send_and_receive(interface, &packets, layer, &options, timeout)? {
+                Exchange::Sent(None, ..) if attempts < max_attempts => {}
+                exchange => return Ok((exchange, attempts)),
+            }
+        }
+    }
 
-        let layer = Layer::Four(transport_layer);
+    /// Sends a SYN packet like `send_tcp_packet`, but returns a future instead of blocking,
+    /// e.g. to await many probes with `tokio::join!` or a `JoinSet`.
+    ///
+    /// pnet only offers blocking I/O, so the probe runs in a blocking thread via `spawn_blocking`.
+    /// May also fail if that thread panics.
+    #[cfg(feature = "async")]
+    #[allow(dead_code)]
+    pub async fn send_syn_packet_async(
+        interface: Interface,
+        src_ip: IpAddr,
+        src_port: u16,
+        dest_ip: IpAddr,
+        dest_port: u16,
+        options: ProbeOptions,
+        timeout: Duration,
+    ) -> Result<(Exchange, u16)> {
+        tokio::task::spawn_blocking(move || {
+            Tcp::send_tcp_packet(
+                interface,
+                src_ip,
+                src_port,
+                dest_ip,
+                dest_port,
+                TcpFlags::SYN,
+                options,
+                timeout,
+            )
+        })
+        .await?
+    }
 
-        let (response, rtt) = NetworkLayer::send_and_receive(interface, &packet, layer, timeout)?;
+    /// Picks a random source port from the given range, or from the ephemeral port range if none
+    /// is given. The range must not be empty, see `ProbeOptions::source_port_range`.
+    ///
+    /// Real clients use ephemeral ports, so a fixed source port is easily flagged by an IDS.
+    pub fn random_source_port(range: Option<(u16, u16)>) -> u16 {
+        let range = range.map_or(EPHEMERAL_PORTS, |(first, last)| first..=last);
+        rand::thread_rng().gen_range(range)
+    }
 
-        Ok((response, rtt))
+    /// Opens a TCP connection to the given socket with the connect system call.
+    ///
+    /// The operating system completes the three-way handshake, so no raw packets
+    /// and therefore no root privileges are required.
+    ///
+    /// Returns the outcome of the connection attempt and the round-trip time.
+    pub fn connect(
+        dest_ip: IpAddr,
+        dest_port: u16,
+        timeout: Duration,
+    ) -> (io::Result<TcpStream>, Duration) {
+        let socket = SocketAddr::new(dest_ip, dest_port);
+        let start_time = Instant::now();
+        let stream = TcpStream::connect_timeout(&socket, timeout);
+        (stream, start_time.elapsed())
+    }
+
+    /// Makes the operating system abort the connection with a RST once the stream is dropped.
+    ///
+    /// Enabling `SO_LINGER` with a linger time of zero skips the usual FIN handshake on close.
+    /// Unsent data is discarded, a RST is sent and the socket is freed at once, instead of
+    /// lingering in the `TIME_WAIT` state. The target never sees an orderly shutdown, so
+    /// applications that only log completed sessions don't log the connection.
+    ///
+    /// Windows lacks the `libc` bindings, so connections are closed as usual there.
+    pub fn reset_on_close(stream: &TcpStream) -> io::Result<()> {
+        cfg_if::cfg_if! {
+            if #[cfg(unix)] {
+                use std::os::fd::AsRawFd;
+
+                let linger = libc::linger {
+                    l_onoff: 1,
+                    l_linger: 0,
+                };
+                // SAFETY: The descriptor belongs to the open stream and `linger` outlives the call.
+                let result = unsafe {
+                    libc::setsockopt(
+                        stream.as_raw_fd(),
+                        libc::SOL_SOCKET,
+                        libc::SO_LINGER,
+                        &linger as *const libc::linger as *const libc::c_void,
+                        std::mem::size_of::<libc::linger>() as libc::socklen_t,
+                    )
+                };
+                if result != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            } else {
+                let _ = stream;
+            }
+        }
+        Ok(())
+    }
+
+    /// Connects to an open port and reads the banner the service sends, like `SSH-2.0-OpenSSH`.
+    ///
+    /// Some services only talk after a request, so `probe` is sent first if given,
+    /// e.g. `GET / HTTP/1.0\r\n\r\n` for HTTP. See `read_response` for how long it reads.
+    ///
+    /// Returns the banner, which is empty if the service sent nothing or closed immediately.
+    /// Invalid UTF-8 is replaced, since banners may contain binary data.
+    pub fn grab_banner(
+        dest_ip: IpAddr,
+        dest_port: u16,
+        timeout: Duration,
+        probe: Option<&[u8]>,
+    ) -> Result<String> {
+        let banner = Tcp::read_response(dest_ip, dest_port, timeout, probe)?;
+        Ok(String::from_utf8_lossy(&banner).into_owned())
+    }
+
+    /// Connects to an open port, sends `probe` if given and returns the raw bytes the service
+    /// answers with. Reads up to `BANNER_SIZE` bytes until the service closes the connection
+    /// or stays silent for `timeout`.
+    pub fn read_response(
+        dest_ip: IpAddr,
+        dest_port: u16,
+        timeout: Duration,
+        probe: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        let (stream, _rtt) = Tcp::connect(dest_ip, dest_port, timeout);
+        let mut stream = stream?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        if let Some(probe) = probe {
+            match stream.write_all(probe) {
+                Ok(()) => {}
+                Err(e) if is_closed(&e) => return Ok(Vec::new()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let mut response = Vec::with_capacity(BANNER_SIZE);
+        let mut buffer = [0u8; BANNER_SIZE];
+
+        while response.len() < BANNER_SIZE {
+            match stream.read(&mut buffer[..BANNER_SIZE - response.len()]) {
+                Ok(0) => break,
+                Ok(length) => response.extend_from_slice(&buffer[..length]),
+                // The service has nothing more to say or has already gone away.
+                Err(e) if is_closed(&e) || is_silent(&e) => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(response)
     }
 }
 
+/// Whether the peer closed the connection.
+fn is_closed(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+    )
+}
+
+/// Whether a read ran into the timeout, which is reported as either kind depending on the platform.
+fn is_silent(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use anyhow::Result;
-    use ipv4::Ipv4Packet;
     use packet::tcp::TcpFlags;
-    use pnet::packet::tcp::TcpPacket;
+    use pnet::packet::ipv4::{self, Ipv4Packet};
+    use pnet::packet::ipv6::Ipv6Packet;
+    use pnet::packet::tcp::{TcpOptionNumbers, TcpPacket};
+    use pnet::packet::Packet;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::net::TcpListener;
 
     #[test]
     fn test_build_syn_packet() {
@@ -111,7 +602,17 @@ mod tests {
         let dest_port = 80;
 
         // Build a SYN packet.
-        let packet = Tcp::build_tcp_packet(src_ip, src_port, dest_ip, dest_port, TcpFlags::SYN);
+        let (packet, _) = Tcp::build_tcp_packet(
+            src_ip,
+            src_port,
+            dest_ip,
+            dest_port,
+            TcpFlags::SYN,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
 
         // Create the IP packet.
         let ip_packet = Ipv4Packet::new(&packet).unwrap();
@@ -132,6 +633,438 @@ mod tests {
         assert_eq!(tcp_packet.get_source(), src_port);
         assert_eq!(tcp_packet.get_destination(), dest_port);
         assert_eq!(tcp_packet.get_flags(), TcpFlags::SYN);
+        assert_eq!(tcp_packet.get_acknowledgement(), 0);
+    }
+
+    #[test]
+    fn test_build_fin_packet() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let src_port = 12345;
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+        let dest_port = 80;
+
+        // Build a FIN packet.
+        let (packet, _) = Tcp::build_tcp_packet(
+            src_ip,
+            src_port,
+            dest_ip,
+            dest_port,
+            TcpFlags::FIN,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+
+        // Create the IP packet.
+        let ip_packet = Ipv4Packet::new(&packet).unwrap();
+
+        // Verify the IP packet.
+        assert_eq!(ip_packet.get_source(), src_ip);
+        assert_eq!(ip_packet.get_destination(), dest_ip);
+
+        // Create the TCP packet.
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+
+        // Verify the TCP packet has only the FIN flag set.
+        assert_eq!(tcp_packet.get_source(), src_port);
+        assert_eq!(tcp_packet.get_destination(), dest_port);
+        assert_eq!(tcp_packet.get_flags(), TcpFlags::FIN);
+    }
+
+    #[test]
+    fn test_build_null_and_xmas_packets() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+
+        // Build a NULL packet without any flags.
+        let (packet, _) = Tcp::build_tcp_packet(
+            src_ip,
+            12345,
+            dest_ip,
+            80,
+            0,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        assert_eq!(tcp_packet.get_flags(), 0b0000_0000);
+
+        // Build a XMAS packet with FIN, PSH and URG flags set.
+        let flags = TcpFlags::FIN | TcpFlags::PSH | TcpFlags::URG;
+        let (packet, _) = Tcp::build_tcp_packet(
+            src_ip,
+            12345,
+            dest_ip,
+            80,
+            flags,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        assert_eq!(tcp_packet.get_flags(), 0b0010_1001);
+    }
+
+    #[test]
+    fn test_build_syn_packet_with_options() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+        let tcp_options = Tcp::syn_options();
+
+        let (packet, _) = Tcp::build_tcp_packet(
+            src_ip,
+            12345,
+            dest_ip,
+            80,
+            TcpFlags::SYN,
+            &tcp_options,
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+
+        // The options grow the header by 20 bytes, which is reflected in the lengths.
+        assert_eq!(packet.len(), IPV4_HEADER_SIZE + TCP_HEADER_SIZE + 20);
+        let ip_packet = Ipv4Packet::new(&packet).unwrap();
+        assert_eq!(ip_packet.get_total_length() as usize, packet.len());
+        assert_eq!(ip_packet.get_checksum(), ipv4::checksum(&ip_packet));
+
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        assert_eq!(tcp_packet.get_data_offset(), 10);
+        assert!(tcp_packet.payload().is_empty());
+        assert_eq!(
+            tcp_packet.get_checksum(),
+            packet::tcp::ipv4_checksum(&tcp_packet, &src_ip, &dest_ip)
+        );
+
+        // Parse back the options.
+        let numbers: Vec<_> = tcp_packet
+            .get_options_iter()
+            .map(|option| option.get_number())
+            .collect();
+        assert_eq!(
+            numbers,
+            [
+                TcpOptionNumbers::MSS,
+                TcpOptionNumbers::SACK_PERMITTED,
+                TcpOptionNumbers::TIMESTAMPS,
+                TcpOptionNumbers::NOP,
+                TcpOptionNumbers::WSCALE,
+            ]
+        );
+        let mss = tcp_packet.get_options_iter().next().unwrap();
+        assert_eq!(mss.payload(), SYN_MSS.to_be_bytes());
+
+        // Options that don't end on a 4 byte boundary are padded.
+        let (packet, _) = Tcp::build_tcp_packet(
+            src_ip,
+            12345,
+            dest_ip,
+            80,
+            TcpFlags::SYN,
+            &[TcpOption::wscale(SYN_WINDOW_SCALE)],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        assert_eq!(tcp_packet.get_data_offset(), 6);
+    }
+
+    #[test]
+    fn test_build_syn_packet_with_mss() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+        let options = ProbeOptions {
+            mss: Some(1400),
+            ..ProbeOptions::default()
+        };
+
+        // The MSS alone is the only option, and only SYN packets carry it.
+        let tcp_options = Tcp::probe_tcp_options(TcpFlags::SYN, &options);
+        assert!(Tcp::probe_tcp_options(TcpFlags::ACK, &options).is_empty());
+        let (packet, _) = Tcp::build_tcp_packet(
+            src_ip,
+            12345,
+            dest_ip,
+            80,
+            TcpFlags::SYN,
+            &tcp_options,
+            &[],
+            &options,
+        )
+        .unwrap();
+
+        // Kind 2 and length 4 are followed by the MSS, which fills the 4 option bytes.
+        assert_eq!(packet.len(), IPV4_HEADER_SIZE + TCP_HEADER_SIZE + 4);
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        assert_eq!(tcp_packet.get_data_offset(), 6);
+        assert_eq!(
+            &packet[IPV4_HEADER_SIZE + TCP_HEADER_SIZE..],
+            [2, 4, 0x05, 0x78]
+        );
+        assert_eq!(
+            tcp_packet.get_checksum(),
+            packet::tcp::ipv4_checksum(&tcp_packet, &src_ip, &dest_ip)
+        );
+
+        // Along with the options of a real stack, it replaces their MSS.
+        let tcp_options = Tcp::probe_tcp_options(
+            TcpFlags::SYN,
+            &ProbeOptions {
+                tcp_options: true,
+                ..options.clone()
+            },
+        );
+        assert_eq!(tcp_options.len(), Tcp::syn_options().len());
+        let (packet, _) = Tcp::build_tcp_packet(
+            src_ip,
+            12345,
+            dest_ip,
+            80,
+            TcpFlags::SYN,
+            &tcp_options,
+            &[],
+            &options,
+        )
+        .unwrap();
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        assert_eq!(tcp_packet.get_data_offset(), 10);
+        let mss = tcp_packet.get_options_iter().next().unwrap();
+        assert_eq!(mss.get_number(), TcpOptionNumbers::MSS);
+        assert_eq!(mss.payload(), 1400u16.to_be_bytes());
+
+        assert!(Tcp::probe_tcp_options(TcpFlags::SYN, &ProbeOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_build_maimon_packet() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+
+        // Build a Maimon packet with FIN and ACK flags set.
+        let flags = TcpFlags::FIN | TcpFlags::ACK;
+        let (packet, sequence) = Tcp::build_tcp_packet(
+            src_ip,
+            12345,
+            dest_ip,
+            80,
+            flags,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        assert_eq!(tcp_packet.get_flags(), 0b0001_0001);
+        assert_eq!(sequence.flags, flags);
+        assert_eq!(tcp_packet.get_sequence(), sequence.sequence);
+        assert_eq!(tcp_packet.get_acknowledgement(), sequence.acknowledgement);
+    }
+
+    #[test]
+    fn test_build_ack_packet() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+        let options = ProbeOptions::default();
+
+        // Build an ACK packet.
+        let (packet, _) = Tcp::build_tcp_packet(
+            src_ip,
+            12345,
+            dest_ip,
+            80,
+            TcpFlags::ACK,
+            &[],
+            &[],
+            &options,
+        )
+        .unwrap();
+
+        // Verify the TCP packet has only the ACK flag set.
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        assert_eq!(tcp_packet.get_flags(), TcpFlags::ACK);
+    }
+
+    #[test]
+    fn test_build_packet_with_urgent_pointer() {
+        let build = |flags, options: &ProbeOptions| {
+            Tcp::build_tcp_packet_with_rng(
+                Ipv4Addr::new(192, 168, 1, 1),
+                12345,
+                Ipv4Addr::new(192, 168, 1, 2),
+                80,
+                flags,
+                &[],
+                &[],
+                options,
+                &mut StdRng::seed_from_u64(7),
+            )
+            .unwrap()
+            .0
+        };
+
+        // An urgent pointer of 0 yields the same packet as the defaults.
+        let options = ProbeOptions {
+            urgent_pointer: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            build(TcpFlags::SYN, &options),
+            build(TcpFlags::SYN, &ProbeOptions::default())
+        );
+
+        // The urgent pointer is written along with any combination of flags.
+        let options = ProbeOptions {
+            urgent_pointer: 42,
+            ..Default::default()
+        };
+        let flags = TcpFlags::PSH | TcpFlags::URG | TcpFlags::ACK;
+        let packet = build(flags, &options);
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        assert_eq!(tcp_packet.get_flags(), flags);
+        assert_eq!(tcp_packet.get_urgent_ptr(), 42);
+    }
+
+    #[test]
+    fn test_build_packet_with_window() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+
+        // The default window size is kept if none is given.
+        let options = ProbeOptions::default();
+        let (packet, _) = Tcp::build_tcp_packet(
+            src_ip,
+            12345,
+            dest_ip,
+            80,
+            TcpFlags::SYN,
+            &[],
+            &[],
+            &options,
+        )
+        .unwrap();
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        assert_eq!(tcp_packet.get_window(), 1024);
+
+        // A custom window size is written into the TCP header.
+        let options = ProbeOptions {
+            window: 65535,
+            ..Default::default()
+        };
+        let (packet, _) = Tcp::build_tcp_packet(
+            src_ip,
+            12345,
+            dest_ip,
+            80,
+            TcpFlags::SYN,
+            &[],
+            &[],
+            &options,
+        )
+        .unwrap();
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        assert_eq!(tcp_packet.get_window(), 65535);
+        assert_eq!(
+            tcp_packet.get_checksum(),
+            packet::tcp::ipv4_checksum(&tcp_packet, &src_ip, &dest_ip)
+        );
+    }
+
+    #[test]
+    fn test_build_packet_with_payload() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+        let payload = b"GET / HTTP/1.0\r\n\r\n";
+        let options = ProbeOptions::default();
+
+        // The payload follows the header including its options.
+        let tcp_options = Tcp::syn_options();
+        let (packet, sequence) = Tcp::build_tcp_packet(
+            src_ip,
+            12345,
+            dest_ip,
+            80,
+            TcpFlags::SYN,
+            &tcp_options,
+            payload,
+            &options,
+        )
+        .unwrap();
+        let ip_packet = Ipv4Packet::new(&packet).unwrap();
+        let header_size = Tcp::tcp_header_size(&tcp_options);
+        assert_eq!(sequence.payload_length as usize, payload.len());
+        assert_eq!(
+            ip_packet.get_total_length() as usize,
+            IPV4_HEADER_SIZE + header_size + payload.len()
+        );
+        assert_eq!(ip_packet.get_checksum(), packet::ipv4::checksum(&ip_packet));
+        let tcp_packet = TcpPacket::new(ip_packet.payload()).unwrap();
+        assert_eq!(tcp_packet.payload(), payload);
+        assert_eq!(&packet[IPV4_HEADER_SIZE + header_size..], payload);
+        assert_eq!(
+            tcp_packet.get_checksum(),
+            packet::tcp::ipv4_checksum(&tcp_packet, &src_ip, &dest_ip)
+        );
+        assert!(Tcp::is_consistent(&packet, &options));
+
+        // IPv6 packets count the payload in the payload length.
+        let (packet, _) = Tcp::build_tcp_packet_v6(
+            "2001:db8::1".parse().unwrap(),
+            12345,
+            "2001:db8::2".parse().unwrap(),
+            80,
+            TcpFlags::SYN,
+            &[],
+            payload,
+            &options,
+        )
+        .unwrap();
+        let ip_packet = Ipv6Packet::new(&packet).unwrap();
+        assert_eq!(
+            ip_packet.get_payload_length() as usize,
+            TCP_HEADER_SIZE + payload.len()
+        );
+        assert_eq!(&packet[IPV6_HEADER_SIZE + TCP_HEADER_SIZE..], payload);
+        assert!(Tcp::is_consistent(&packet, &options));
+    }
+
+    #[test]
+    fn test_build_syn_packet_v6() {
+        let src_ip: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let dest_ip: Ipv6Addr = "2001:db8::2".parse().unwrap();
+
+        let (packet, _) = Tcp::build_tcp_packet_v6(
+            src_ip,
+            12345,
+            dest_ip,
+            80,
+            TcpFlags::SYN,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+
+        // Verify the IP packet.
+        let ip_packet = Ipv6Packet::new(&packet).unwrap();
+        assert_eq!(ip_packet.get_version(), 6);
+        assert_eq!(ip_packet.get_next_header(), IpNextHeaderProtocols::Tcp);
+        assert_eq!(ip_packet.get_payload_length() as usize, TCP_HEADER_SIZE);
+
+        // Verify the TCP packet and its checksum over the IPv6 pseudo header.
+        let tcp_packet = TcpPacket::new(&packet[IPV6_HEADER_SIZE..]).unwrap();
+        assert_eq!(tcp_packet.get_source(), 12345);
+        assert_eq!(tcp_packet.get_destination(), 80);
+        assert_eq!(tcp_packet.get_flags(), TcpFlags::SYN);
+        assert_eq!(
+            tcp_packet.get_checksum(),
+            packet::tcp::ipv6_checksum(&tcp_packet, &src_ip, &dest_ip)
+        );
     }
 
     #[test]
@@ -148,19 +1081,263 @@ mod tests {
         let timeout = Duration::from_secs(5);
 
         // Send a SYN packet. Calls subsequently the network and data link layer.
-        let (packet, _rtt) = Tcp::send_tcp_packet(
+        let (exchange, _attempts) = Tcp::send_tcp_packet(
             iface,
-            src_ip,
+            src_ip.into(),
             src_port,
-            dest_ip,
+            dest_ip.into(),
             dest_port,
             TcpFlags::SYN,
+            ProbeOptions::default(),
             timeout,
         )?;
 
         // Ensure we have received a response packet.
-        assert!(packet.is_some());
+        assert!(matches!(exchange, Exchange::Sent(Some(_), ..)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_syn_packet_retries() -> Result<()> {
+        let iface = Interface::new()?;
+        let src_ip = IpAddr::V4(iface.ip.addr);
+
+        // Reserved for documentation and not routable. With a TTL of 1 the probe is
+        // discarded by the gateway, so nothing can answer it in the target's name.
+        let dest_ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1));
+        let options = ProbeOptions {
+            ttl: 1,
+            retries: 2,
+            ..Default::default()
+        };
+
+        let (exchange, attempts) = Tcp::send_tcp_packet(
+            iface,
+            src_ip,
+            12345,
+            dest_ip,
+            80,
+            TcpFlags::SYN,
+            options,
+            Duration::from_millis(200),
+        )?;
+
+        // The probe was sent once and resent twice before giving up.
+        assert!(matches!(exchange, Exchange::Sent(None, ..)));
+        assert_eq!(attempts, 3);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_send_syn_packet_async() -> Result<()> {
+        let iface = Interface::new()?;
+        let src_ip = IpAddr::V4(iface.ip.addr);
+        let dest_ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let options = ProbeOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+
+        // Several probes are awaited side by side, without blocking the runtime.
+        let (first, second) = tokio::join!(
+            Tcp::send_syn_packet_async(
+                iface,
+                src_ip,
+                40000,
+                dest_ip,
+                80,
+                options.clone(),
+                Duration::from_secs(1)
+            ),
+            Tcp::send_syn_packet_async(
+                iface,
+                src_ip,
+                40001,
+                dest_ip,
+                443,
+                options,
+                Duration::from_secs(1)
+            ),
+        );
+
+        for (exchange, attempts) in [first?, second?] {
+            assert!(matches!(exchange, Exchange::Built { .. }));
+            assert_eq!(attempts, 1);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_random_source_port() {
+        for _ in 0..1000 {
+            assert!(EPHEMERAL_PORTS.contains(&Tcp::random_source_port(None)));
+        }
+
+        // A given range is kept, also if it holds a single port.
+        for _ in 0..1000 {
+            assert!((40000..=40009).contains(&Tcp::random_source_port(Some((40000, 40009)))));
+        }
+        assert_eq!(Tcp::random_source_port(Some((1, 1))), 1);
+    }
+
+    #[test]
+    fn test_connect() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let local_addr = listener.local_addr()?;
+        let timeout = Duration::from_secs(1);
+
+        // A listening socket accepts the connection.
+        let (stream, _rtt) = Tcp::connect(local_addr.ip(), local_addr.port(), timeout);
+        assert!(stream.is_ok());
+
+        // Once the listener is gone, the connection is refused.
+        drop(listener);
+        let (stream, _rtt) = Tcp::connect(local_addr.ip(), local_addr.port(), timeout);
+        assert_eq!(
+            stream.map_err(|e| e.kind()).err(),
+            Some(io::ErrorKind::ConnectionRefused)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reset_on_close() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let local_addr = listener.local_addr()?;
+        let timeout = Duration::from_secs(1);
+
+        let (stream, _rtt) = Tcp::connect(local_addr.ip(), local_addr.port(), timeout);
+        let stream = stream?;
+        Tcp::reset_on_close(&stream)?;
+        let (mut peer, _) = listener.accept()?;
+        peer.set_read_timeout(Some(timeout))?;
+        drop(stream);
+
+        // The peer is reset right away instead of reading the end of the stream.
+        let mut buffer = [0u8; 1];
+        let error = peer.read(&mut buffer).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_grab_banner() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let local_addr = listener.local_addr()?;
+        let timeout = Duration::from_millis(200);
+
+        let server = std::thread::spawn(move || -> io::Result<()> {
+            // A service that greets first.
+            let (mut stream, _) = listener.accept()?;
+            stream.write_all(b"SSH-2.0-Test\r\n")?;
+            drop(stream);
+
+            // A service that answers a request and stays connected.
+            let (mut stream, _) = listener.accept()?;
+            let mut request = [0u8; 4];
+            stream.read_exact(&mut request)?;
+            stream.write_all(b"HTTP/1.0 200 OK\r\n")?;
+            std::thread::sleep(Duration::from_millis(500));
+
+            // A service that closes immediately.
+            let (stream, _) = listener.accept()?;
+            drop(stream);
+            Ok(())
+        });
+
+        let banner = Tcp::grab_banner(local_addr.ip(), local_addr.port(), timeout, None)?;
+        assert_eq!(banner, "SSH-2.0-Test\r\n");
+
+        let banner = Tcp::grab_banner(local_addr.ip(), local_addr.port(), timeout, Some(b"GET "))?;
+        assert_eq!(banner, "HTTP/1.0 200 OK\r\n");
+
+        let banner = Tcp::grab_banner(local_addr.ip(), local_addr.port(), timeout, None)?;
+        assert_eq!(banner, "");
+
+        server.join().unwrap()?;
 
         Ok(())
     }
+
+    #[test]
+    fn test_build_packet_with_bad_checksum() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+        let build = |bad_checksum| {
+            let options = ProbeOptions {
+                bad_checksum,
+                ..Default::default()
+            };
+            Tcp::build_tcp_packet(
+                src_ip,
+                12345,
+                dest_ip,
+                80,
+                TcpFlags::SYN,
+                &[],
+                &[],
+                &options,
+            )
+            .unwrap()
+            .0
+        };
+
+        // The checksum is valid by default.
+        let packet = build(false);
+        let tcp_packet = TcpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
+        let checksum = packet::tcp::ipv4_checksum(&tcp_packet, &src_ip, &dest_ip);
+        assert_eq!(tcp_packet.get_checksum(), checksum);
+
+        // The bad checksum is wrong, but the IP header stays valid.
+        let packet = build(true);
+        let ip_packet = Ipv4Packet::new(&packet).unwrap();
+        assert_eq!(ip_packet.get_checksum(), ipv4::checksum(&ip_packet));
+        let tcp_packet = TcpPacket::new(ip_packet.payload()).unwrap();
+        let checksum = packet::tcp::ipv4_checksum(&tcp_packet, &src_ip, &dest_ip);
+        assert_ne!(tcp_packet.get_checksum(), checksum);
+    }
+
+    #[test]
+    fn test_build_tcp_packet_with_seeded_rng() {
+        let build = |seed| {
+            Tcp::build_tcp_packet_with_rng(
+                Ipv4Addr::new(192, 168, 1, 1),
+                12345,
+                Ipv4Addr::new(192, 168, 1, 2),
+                80,
+                TcpFlags::ACK,
+                &[],
+                &[],
+                &ProbeOptions::default(),
+                &mut StdRng::seed_from_u64(seed),
+            )
+            .unwrap()
+        };
+
+        // The same seed produces the same packet.
+        let (packet, sequence) = build(7);
+        assert_eq!(build(7).0, packet);
+        assert_ne!(build(8).0, packet);
+
+        // The random fields are drawn in a fixed order.
+        let mut rng = StdRng::seed_from_u64(7);
+        let identification: u16 = rng.gen();
+        let expected_sequence: u32 = rng.gen();
+        let expected_acknowledgement: u32 = rng.gen();
+
+        let ip_packet = Ipv4Packet::new(&packet).unwrap();
+        assert_eq!(ip_packet.get_identification(), identification);
+
+        let tcp_packet = TcpPacket::new(ip_packet.payload()).unwrap();
+        assert_eq!(tcp_packet.get_sequence(), expected_sequence);
+        assert_eq!(tcp_packet.get_acknowledgement(), expected_acknowledgement);
+        assert_eq!(sequence.sequence, expected_sequence);
+        assert_eq!(sequence.acknowledgement, expected_acknowledgement);
+    }
 }
@@ -1,84 +1,152 @@
 use super::{
     interface::Interface,
-    osi_layers::{Layer, NetworkLayer, TransportLayer},
+    osi_layers::{Exchange, Layer, NetworkLayer, TransportLayer},
+    probe::{build_ipv4_header, has_consistent_ip_header, ProbeOptions, IPV4_HEADER_SIZE},
 };
+use crate::errors::ScannerError;
 use anyhow::Result;
 use pnet::packet::{
     ip::IpNextHeaderProtocols,
-    ipv4::{self, Ipv4Flags, MutableIpv4Packet},
     udp::{ipv4_checksum, MutableUdpPacket},
 };
-use rand::Rng;
 use std::{net::Ipv4Addr, time::Duration};
 
-const IPV4_HEADER_SIZE: usize = 20;
 const UDP_HEADER_SIZE: usize = 8;
 const UDP_DATA_SIZE: usize = 20;
-const TTL: u8 = 64;
+
+/// Default payload of UDP probes.
+pub const UDP_PAYLOAD: [u8; UDP_DATA_SIZE] = [0x41; UDP_DATA_SIZE];
 
 pub struct Udp;
 
 impl Udp {
-    /// Constructs an IP datagram with a UDP header.
+    /// Constructs an IP datagram with a UDP header and the given payload.
+    ///
+    /// May fail if the packet can't hold its headers.
     pub fn build_udp_packet(
         src_ip: Ipv4Addr,
         src_port: u16,
         dest_ip: Ipv4Addr,
         dest_port: u16,
-    ) -> [u8; IPV4_HEADER_SIZE + UDP_HEADER_SIZE + UDP_DATA_SIZE] {
-        let mut rng = rand::thread_rng();
-        let mut ip_packet = [0u8; IPV4_HEADER_SIZE + UDP_HEADER_SIZE + UDP_DATA_SIZE];
-
-        let mut ip_header = MutableIpv4Packet::new(&mut ip_packet).unwrap();
-        ip_header.set_version(4);
-        ip_header.set_header_length(5);
-        ip_header.set_source(src_ip);
-        ip_header.set_destination(dest_ip);
-        ip_header.set_total_length((IPV4_HEADER_SIZE + UDP_HEADER_SIZE + UDP_DATA_SIZE) as u16);
-        ip_header.set_identification(rng.gen());
-        ip_header.set_flags(Ipv4Flags::DontFragment);
-        ip_header.set_ttl(TTL);
-        ip_header.set_next_level_protocol(IpNextHeaderProtocols::Udp);
-        let ip_checksum = ipv4::checksum(&ip_header.to_immutable());
-        ip_header.set_checksum(ip_checksum);
-
-        let mut udp_header = MutableUdpPacket::new(&mut ip_packet[IPV4_HEADER_SIZE..]).unwrap();
+        payload: &[u8],
+        options: &ProbeOptions,
+    ) -> Result<Vec<u8>> {
+        let mut ip_packet = vec![0u8; IPV4_HEADER_SIZE + UDP_HEADER_SIZE + payload.len()];
+
+        build_ipv4_header(
+            &mut ip_packet,
+            src_ip,
+            dest_ip,
+            IpNextHeaderProtocols::Udp,
+            options,
+        )?;
+
+        let mut udp_header = MutableUdpPacket::new(&mut ip_packet[IPV4_HEADER_SIZE..])
+            .ok_or(ScannerError::CantCreateUdpPacket)?;
         udp_header.set_source(src_port);
         udp_header.set_destination(dest_port);
-        udp_header.set_length((UDP_HEADER_SIZE + UDP_DATA_SIZE) as u16);
-        udp_header.set_payload(&[0x41; UDP_DATA_SIZE]);
+        udp_header.set_length((UDP_HEADER_SIZE + payload.len()) as u16);
+        udp_header.set_payload(payload);
         let udp_checksum = ipv4_checksum(&udp_header.to_immutable(), &src_ip, &dest_ip);
         udp_header.set_checksum(udp_checksum);
 
-        ip_packet
+        debug_assert!(
+            has_consistent_ip_header(&ip_packet),
+            "Built an inconsistent UDP packet."
+        );
+
+        Ok(ip_packet)
     }
 
+    /// Sends a UDP packet with the given payload.
+    ///
+    /// The packet is handed over to the network layer. Matches either a UDP response
+    /// or an ICMP error that quotes the sent datagram.
+    #[allow(clippy::too_many_arguments)]
     pub fn send_udp_packet(
         interface: Interface,
         src_ip: Ipv4Addr,
         src_port: u16,
         dest_ip: Ipv4Addr,
         dest_port: u16,
+        payload: &[u8],
+        options: ProbeOptions,
         timeout: Duration,
-    ) -> Result<(Option<Vec<u8>>, Duration)> {
-        let packet = Udp::build_udp_packet(src_ip, src_port, dest_ip, dest_port);
+    ) -> Result<Exchange> {
+        let packet =
+            Udp::build_udp_packet(src_ip, src_port, dest_ip, dest_port, payload, &options)?;
 
         let network_layer = NetworkLayer {
             datalink_layer: None,
             src_addr: Some(dest_ip.into()),
             dest_addr: Some(src_ip.into()),
+            protocol: None,
         };
 
         let transport_layer = TransportLayer {
             network_layer: Some(network_layer),
             src_port: Some(dest_port),
             dest_port: Some(src_port),
+            tcp_sequence: None,
+            icmp_echo: None,
         };
 
         let layers = Layer::Four(transport_layer);
 
-        let (response, rtt) = NetworkLayer::send_and_receive(interface, &packet, layers, timeout)?;
+        NetworkLayer::send_and_receive(interface, &[packet], layers, &options, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pnet::packet::{ipv4::Ipv4Packet, udp::UdpPacket, Packet};
+
+    #[test]
+    fn test_build_udp_packet() {
+        let src_ip = Ipv4Addr::new(192, 168, 1, 1);
+        let src_port = 12345;
+        let dest_ip = Ipv4Addr::new(192, 168, 1, 2);
+        let dest_port = 53;
+        let payload = b"probe";
+
+        // Build a UDP packet.
+        let packet = Udp::build_udp_packet(
+            src_ip,
+            src_port,
+            dest_ip,
+            dest_port,
+            payload,
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+
+        // Create the IP packet.
+        let ip_packet = Ipv4Packet::new(&packet).unwrap();
+
+        // Verify the IP packet.
+        assert_eq!(ip_packet.get_source(), src_ip);
+        assert_eq!(ip_packet.get_destination(), dest_ip);
+        assert_eq!(ip_packet.get_total_length() as usize, packet.len());
+        assert_eq!(
+            ip_packet.get_next_level_protocol(),
+            IpNextHeaderProtocols::Udp
+        );
+
+        // Create the UDP packet.
+        let udp_packet = UdpPacket::new(&packet[IPV4_HEADER_SIZE..]).unwrap();
 
-        Ok((response, rtt))
+        // Verify the UDP packet.
+        assert_eq!(udp_packet.get_source(), src_port);
+        assert_eq!(udp_packet.get_destination(), dest_port);
+        assert_eq!(
+            udp_packet.get_length() as usize,
+            UDP_HEADER_SIZE + payload.len()
+        );
+        assert_eq!(udp_packet.payload(), payload);
+        assert_eq!(
+            udp_packet.get_checksum(),
+            ipv4_checksum(&udp_packet, &src_ip, &dest_ip)
+        );
     }
 }
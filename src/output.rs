@@ -1,12 +1,19 @@
-use crate::{errors::ScannerError, scanner::engine::ScanResult};
+use crate::{
+    errors::ScannerError,
+    networking::icmp::IcmpProbe,
+    scanner::{
+        ip_id::IpIdPattern,
+        os_detection::OsGuess,
+        profile::{Banner, HostProfile},
+        results::{PortState, Protocol, Reason, ScanResult},
+        traceroute::Hop,
+        version_detection::ServiceInfo,
+    },
+    vendors::vendor_for_mac,
+};
 use anyhow::Result;
 use pnet::util::MacAddr;
-use std::{
-    env,
-    io::Write,
-    net::{IpAddr, SocketAddr},
-    time::Duration,
-};
+use std::{env, io::BufRead, net::IpAddr, time::Duration};
 use tokio::{fs::File, io::AsyncWriteExt};
 
 pub trait ToCsv {
@@ -14,17 +21,215 @@ pub trait ToCsv {
     fn to_csv(&self) -> String;
 }
 
-impl ToCsv for (SocketAddr, ScanResult, Duration) {
+/// Port scan results have one row per port, so that they can be processed with tools like awk and cut.
+/// The column names match the keys of the JSON output.
+impl ToCsv for ScanResult {
     fn header() -> &'static str {
-        "Socket,Status,RTT"
+        "host,port,protocol,state,reason,rtt_ms,elapsed_ms,src_port,attempts,error,banner"
     }
 
     fn to_csv(&self) -> String {
-        format!("{},{:?},{:?}", self.0, self.1, self.2)
+        let rtt = self
+            .rtt_millis()
+            .map(|rtt| format!("{:.3}", rtt))
+            .unwrap_or_default();
+        let error = self.error.as_deref().map(escape_csv).unwrap_or_default();
+        let banner = self.banner.as_deref().map(escape_csv).unwrap_or_default();
+        format!(
+            "{},{},{},{:?},{},{},{:.3},{},{},{},{}",
+            self.ip,
+            self.port,
+            self.protocol,
+            self.state,
+            self.reason,
+            rtt,
+            self.elapsed_millis(),
+            self.src_port,
+            self.attempts,
+            error,
+            banner
+        )
+    }
+}
+
+impl ToCsv for (IpAddr, IpIdPattern) {
+    fn header() -> &'static str {
+        "IP Address,IP ID Pattern"
+    }
+
+    fn to_csv(&self) -> String {
+        format!("{},{}", self.0, self.1)
+    }
+}
+
+impl ToCsv for (IpAddr, OsGuess) {
+    fn header() -> &'static str {
+        "IP Address,OS,Confidence"
+    }
+
+    fn to_csv(&self) -> String {
+        format!(
+            "{},{},{:.0}%",
+            self.0,
+            self.1.family,
+            self.1.confidence * 100.0
+        )
+    }
+}
+
+impl ToCsv for (IpAddr, u16, ServiceInfo) {
+    fn header() -> &'static str {
+        "IP Address,Port,Service,Version,Extra"
+    }
+
+    fn to_csv(&self) -> String {
+        let version = self
+            .2
+            .version
+            .as_deref()
+            .map(escape_csv)
+            .unwrap_or_default();
+        let extra = self.2.extra.as_deref().map(escape_csv).unwrap_or_default();
+        format!(
+            "{},{},{},{},{}",
+            self.0, self.1, self.2.name, version, extra
+        )
+    }
+}
+
+impl ToCsv for (IpAddr, Hop) {
+    fn header() -> &'static str {
+        "IP Address,Hop,Router,RTT"
+    }
+
+    fn to_csv(&self) -> String {
+        let router = self.1.ip.map(|ip| ip.to_string()).unwrap_or_default();
+        let rtt = self
+            .1
+            .rtt
+            .map(|rtt| format!("{:?}", rtt))
+            .unwrap_or_default();
+        format!("{},{},{},{}", self.0, self.1.ttl, router, rtt)
     }
 }
 
-impl ToCsv for (IpAddr, ScanResult, Duration) {
+impl ToCsv for (IpAddr, u16, Banner) {
+    fn header() -> &'static str {
+        "IP Address,Port,Status,Banner"
+    }
+
+    fn to_csv(&self) -> String {
+        let banner = match &self.2 {
+            Banner::Grabbed(banner) | Banner::Failed(banner) => escape_csv(banner),
+            Banner::Closed => String::new(),
+        };
+        format!("{},{},{},{}", self.0, self.1, self.2, banner)
+    }
+}
+
+/// Quotes a field if it contains commas, quotes or line breaks, as described in RFC 4180.
+fn escape_csv(field: &str) -> String {
+    match field.contains([',', '"', '\r', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+/// Reads port scan results as written by `save_port_results`, e.g. to compare them with a new scan.
+///
+/// Only the columns that `diff` compares are read: host, port, protocol, state and error.
+/// The reason of each result is `Reason::Other`. The first line is the header row and skipped.
+///
+/// Returns the results and a warning with the line number of each row that can't be parsed.
+pub fn read_port_results(reader: impl BufRead) -> (Vec<ScanResult>, Vec<String>) {
+    let mut results = Vec::new();
+    let mut warnings = Vec::new();
+
+    let mut lines = reader.lines().map_while(Result::ok).enumerate().skip(1);
+    while let Some((index, mut row)) = lines.next() {
+        // Quoted banners may span several lines, which leaves a quote open at the end of a line.
+        while row.matches('"').count() % 2 == 1 {
+            let Some((_, line)) = lines.next() else {
+                break;
+            };
+            row.push('\n');
+            row.push_str(&line);
+        }
+        if row.trim().is_empty() {
+            continue;
+        }
+        match parse_port_result(&row) {
+            Some(scan) => results.push(scan),
+            None => warnings.push(format!("line {}: malformed result '{}'", index + 1, row)),
+        }
+    }
+
+    (results, warnings)
+}
+
+/// Parses a row of `read_port_results`. The columns up to the error never contain commas.
+fn parse_port_result(row: &str) -> Option<ScanResult> {
+    let mut fields = row.splitn(10, ',');
+    let ip = fields.next()?.parse().ok()?;
+    let port = fields.next()?.parse().ok()?;
+    let protocol = match fields.next()? {
+        "tcp" => Protocol::Tcp,
+        "udp" => Protocol::Udp,
+        "sctp" => Protocol::Sctp,
+        "ip" => Protocol::Ip,
+        _ => return None,
+    };
+    let state = match fields.next()? {
+        "Open" => PortState::Open,
+        "Closed" => PortState::Closed,
+        "Filtered" => PortState::Filtered,
+        "Unfiltered" => PortState::Unfiltered,
+        "OpenOrFiltered" => PortState::OpenOrFiltered,
+        "ClosedOrFiltered" => PortState::ClosedOrFiltered,
+        "Unknown" => PortState::Unknown,
+        "Up" => PortState::Up,
+        "Down" => PortState::Down,
+        _ => return None,
+    };
+    // Skips the reason, round-trip time, elapsed time, source port and attempts.
+    let error = unescape_csv(fields.nth(5)?);
+
+    let mut scan = ScanResult::new(ip, port, protocol, 0, state, Reason::Other, None, 0);
+    scan.error = (!error.is_empty()).then_some(error);
+    Some(scan)
+}
+
+/// Returns the first field of the rest of a row, reversing `escape_csv`.
+fn unescape_csv(rest: &str) -> String {
+    let Some(quoted) = rest.strip_prefix('"') else {
+        return rest.split(',').next().unwrap_or_default().to_string();
+    };
+    let mut field = String::new();
+    let mut chars = quoted.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('"', Some('"')) => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => break,
+            _ => field.push(c),
+        }
+    }
+    field
+}
+
+/// Serializes the results to CSV with a header row, which is the only row if there are no results.
+pub fn to_csv<T: ToCsv>(results: &[T]) -> String {
+    let mut csv = format!("{}\n", T::header());
+    for result in results {
+        csv.push_str(&result.to_csv());
+        csv.push('\n');
+    }
+    csv
+}
+
+impl ToCsv for (IpAddr, PortState, Duration) {
     fn header() -> &'static str {
         "IP Address,Status,RTT"
     }
@@ -36,25 +241,33 @@ impl ToCsv for (IpAddr, ScanResult, Duration) {
 
 impl ToCsv for (IpAddr, MacAddr, Duration) {
     fn header() -> &'static str {
-        "IP Address,MAC Address,RTT"
+        "IP Address,MAC Address,Vendor,RTT"
     }
 
     fn to_csv(&self) -> String {
-        format!("{},{:?},{:?}", self.0, self.1, self.2)
+        let vendor = vendor_for_mac(self.1).unwrap_or_default();
+        format!("{},{:?},{},{:?}", self.0, self.1, vendor, self.2)
     }
 }
 
+/// Serializes port scan results to a JSON array, e.g. to process them in other tools.
+///
+/// Round-trip times are given in milliseconds.
+#[cfg(feature = "json")]
+pub fn to_json(results: &[ScanResult]) -> String {
+    serde_json::to_string_pretty(results).expect("scan results are always serializable")
+}
+
 pub async fn save_scan_results<T: ToCsv>(hosts: Vec<T>, file_name: &str) -> Result<String> {
+    write_results(to_csv(&hosts).as_bytes(), file_name).await
+}
+
+/// Writes the results to a file in the current directory and returns its path.
+async fn write_results(buffer: &[u8], file_name: &str) -> Result<String> {
     let output_path = env::current_dir()?.join(file_name);
     let mut file = File::create(&output_path).await?;
 
-    let mut buffer = Vec::with_capacity(hosts.len());
-    writeln!(buffer, "{}", T::header())?;
-    for host in hosts {
-        writeln!(buffer, "{}", host.to_csv())?;
-    }
-
-    file.write_all(&buffer).await?;
+    file.write_all(buffer).await?;
 
     output_path
         .to_str()
@@ -62,14 +275,197 @@ pub async fn save_scan_results<T: ToCsv>(hosts: Vec<T>, file_name: &str) -> Resu
         .ok_or_else(|| ScannerError::CouldNotWriteResults.into())
 }
 
-pub async fn save_port_results(hosts: Vec<(SocketAddr, ScanResult, Duration)>) -> Result<String> {
+pub async fn save_port_results(hosts: Vec<ScanResult>) -> Result<String> {
     save_scan_results(hosts, "port_scan_results.csv").await
 }
 
-pub async fn save_icmp_results(hosts: Vec<(IpAddr, ScanResult, Duration)>) -> Result<String> {
-    save_scan_results(hosts, "icmp_scan_results.csv").await
+#[cfg(feature = "json")]
+pub async fn save_port_results_json(hosts: Vec<ScanResult>) -> Result<String> {
+    write_results(to_json(&hosts).as_bytes(), "port_scan_results.json").await
+}
+
+pub async fn save_os_results(hosts: Vec<(IpAddr, OsGuess)>) -> Result<String> {
+    save_scan_results(hosts, "os_detection_results.csv").await
+}
+
+pub async fn save_version_results(services: Vec<(IpAddr, u16, ServiceInfo)>) -> Result<String> {
+    save_scan_results(services, "version_detection_results.csv").await
+}
+
+/// Saves one row per open port of the profiled hosts.
+pub async fn save_profile_results(profiles: Vec<HostProfile>) -> Result<String> {
+    let ports = profiles
+        .into_iter()
+        .flat_map(|profile| {
+            let ip = profile.ip;
+            profile
+                .ports
+                .into_iter()
+                .map(move |(port, banner)| (ip, port, banner))
+        })
+        .collect();
+    save_scan_results(ports, "host_profiles.csv").await
+}
+
+pub async fn save_ip_id_results(hosts: Vec<(IpAddr, IpIdPattern)>) -> Result<String> {
+    save_scan_results(hosts, "ip_id_results.csv").await
+}
+
+pub async fn save_icmp_results(
+    hosts: Vec<(IpAddr, PortState, Duration)>,
+    probe: IcmpProbe,
+) -> Result<String> {
+    let file_name = match probe {
+        IcmpProbe::Echo => "icmp_scan_results.csv",
+        IcmpProbe::Timestamp => "icmp_timestamp_scan_results.csv",
+        IcmpProbe::AddressMask => "icmp_address_mask_scan_results.csv",
+    };
+    save_scan_results(hosts, file_name).await
+}
+
+pub async fn save_tcp_ping_results(hosts: Vec<(IpAddr, PortState, Duration)>) -> Result<String> {
+    save_scan_results(hosts, "tcp_ping_results.csv").await
+}
+
+pub async fn save_traceroute_results(hosts: Vec<(IpAddr, Hop)>) -> Result<String> {
+    save_scan_results(hosts, "traceroute_results.csv").await
 }
 
 pub async fn save_arp_results(hosts: Vec<(IpAddr, MacAddr, Duration)>) -> Result<String> {
     save_scan_results(hosts, "arp_scan_results.csv").await
 }
+
+pub async fn save_ndp_results(hosts: Vec<(IpAddr, MacAddr, Duration)>) -> Result<String> {
+    save_scan_results(hosts, "ndp_scan_results.csv").await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::results::{Protocol, Reason};
+
+    #[test]
+    fn test_to_csv() {
+        let ip = IpAddr::from([192, 168, 1, 1]);
+        let mut open = ScanResult::new(
+            ip,
+            80,
+            Protocol::Tcp,
+            50000,
+            PortState::Open,
+            Reason::SynAck,
+            Some(Duration::from_micros(1500)),
+            1,
+        );
+        open.banner = Some("HTTP/1.0 200 OK\r\nServer: \"a, b\"".to_string());
+        let mut filtered = ScanResult::new(
+            ip,
+            53,
+            Protocol::Udp,
+            50001,
+            PortState::Filtered,
+            Reason::IcmpUnreachable(13),
+            None,
+            1,
+        );
+        open.elapsed = Duration::from_micros(1600);
+        filtered.elapsed = Duration::from_secs(2);
+        let error = ScannerError::UnsupportedIpVersion.into();
+        let failed = ScanResult::failed(ip, 132, Protocol::Sctp, 50002, &error);
+
+        assert_eq!(
+            to_csv(&[open, filtered, failed]),
+            "host,port,protocol,state,reason,rtt_ms,elapsed_ms,src_port,attempts,error,banner\n\
+             192.168.1.1,80,tcp,Open,syn-ack,1.500,1.600,50000,1,,\"HTTP/1.0 200 OK\r\nServer: \"\"a, b\"\"\"\n\
+             192.168.1.1,53,udp,Filtered,icmp-unreachable-13,,2000.000,50001,1,,\n\
+             192.168.1.1,132,sctp,Unknown,build-failed,,0.000,50002,0,This IP protocol version is not supported.,\n"
+        );
+
+        // Empty results only have the header row.
+        assert_eq!(
+            to_csv::<ScanResult>(&[]),
+            format!("{}\n", ScanResult::header())
+        );
+    }
+
+    #[test]
+    fn test_read_port_results() {
+        let ip = IpAddr::from([192, 168, 1, 1]);
+        let mut open = ScanResult::new(
+            ip,
+            80,
+            Protocol::Tcp,
+            50000,
+            PortState::Open,
+            Reason::SynAck,
+            None,
+            1,
+        );
+        open.banner = Some("HTTP/1.0 200 OK\r\nServer: \"a, b\"".to_string());
+        let error = ScannerError::TooManyDecoys(16).into();
+        let failed = ScanResult::failed(ip, 443, Protocol::Tcp, 50001, &error);
+        let csv = format!(
+            "{}192.168.1.1,not a port,tcp,Open,syn-ack,,0.000,0,1,,\n",
+            to_csv(&[open, failed])
+        );
+
+        let (results, warnings) = read_port_results(csv.as_bytes());
+        let sockets = results
+            .iter()
+            .map(|scan| (scan.ip, scan.port, scan.protocol, scan.state))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sockets,
+            vec![
+                (ip, 80, Protocol::Tcp, PortState::Open),
+                (ip, 443, Protocol::Tcp, PortState::Unknown)
+            ]
+        );
+        // The banner spans two lines, so the malformed row is the fifth line.
+        assert_eq!(results[0].error, None);
+        assert_eq!(results[1].error, Some(error.to_string()));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("line 5:"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_to_json() {
+        let ip = IpAddr::from([192, 168, 1, 1]);
+        let results = [
+            ScanResult::new(
+                ip,
+                80,
+                Protocol::Tcp,
+                50000,
+                PortState::Open,
+                Reason::SynAck,
+                Some(Duration::from_micros(1500)),
+                1,
+            ),
+            ScanResult::new(
+                ip,
+                81,
+                Protocol::Tcp,
+                50001,
+                PortState::Filtered,
+                Reason::NoResponse,
+                None,
+                2,
+            ),
+        ];
+
+        let json: serde_json::Value = serde_json::from_str(&to_json(&results)).unwrap();
+        assert_eq!(json[0]["host"], "192.168.1.1");
+        assert_eq!(json[0]["port"], 80);
+        assert_eq!(json[0]["protocol"], "tcp");
+        assert_eq!(json[0]["state"], "Open");
+        assert_eq!(json[0]["reason"], "syn-ack");
+        assert_eq!(json[0]["rtt_ms"], 1.5);
+        assert_eq!(json[1]["state"], "Filtered");
+        assert!(json[1]["rtt_ms"].is_null());
+        assert_eq!(json[1]["elapsed_ms"], 0.0);
+
+        assert_eq!(to_json(&[]), "[]");
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{errors::ScannerError, scanner::results::Protocol};
+use anyhow::Result;
+
+/// The 100 most common open TCP ports, ordered by frequency as measured by nmap.
+/// See: https://nmap.org/book/nmap-services.html
+const TOP_PORTS: [u16; 100] = [
+    80, 23, 443, 21, 22, 25, 3389, 110, 445, 139, 143, 53, 135, 3306, 8080, 1723, 111, 995, 993,
+    5900, 1025, 587, 8888, 199, 1720, 465, 548, 113, 81, 6001, 10000, 514, 5060, 179, 1026, 2000,
+    8443, 8000, 32768, 554, 26, 1433, 49152, 2001, 515, 8008, 49154, 1027, 5666, 646, 5000, 5631,
+    631, 49153, 8081, 2049, 88, 79, 5800, 106, 2121, 1110, 49155, 6000, 513, 990, 5357, 427, 49156,
+    543, 544, 5101, 144, 7, 389, 8009, 3128, 444, 9999, 5009, 7070, 5190, 3000, 5432, 1900, 3986,
+    13, 1029, 9, 5051, 6646, 49157, 1028, 873, 1755, 2717, 4899, 9100, 119, 37,
+];
+
+/// Names of well-known services by port number and protocol, a subset of the IANA registry.
+/// IP protocols are listed by their protocol number instead of a port.
+/// Sorted by port number, then protocol. See:
+/// https://www.iana.org/assignments/service-names-port-numbers/service-names-port-numbers.xhtml
+const SERVICES: [(u16, Protocol, &str); 88] = [
+    (1, Protocol::Ip, "icmp"),
+    (2, Protocol::Ip, "igmp"),
+    (4, Protocol::Ip, "ipv4"),
+    (6, Protocol::Ip, "tcp"),
+    (7, Protocol::Tcp, "echo"),
+    (7, Protocol::Udp, "echo"),
+    (9, Protocol::Tcp, "discard"),
+    (9, Protocol::Udp, "discard"),
+    (13, Protocol::Tcp, "daytime"),
+    (17, Protocol::Ip, "udp"),
+    (21, Protocol::Tcp, "ftp"),
+    (22, Protocol::Tcp, "ssh"),
+    (22, Protocol::Sctp, "ssh"),
+    (23, Protocol::Tcp, "telnet"),
+    (25, Protocol::Tcp, "smtp"),
+    (37, Protocol::Tcp, "time"),
+    (41, Protocol::Ip, "ipv6"),
+    (47, Protocol::Ip, "gre"),
+    (50, Protocol::Ip, "esp"),
+    (51, Protocol::Ip, "ah"),
+    (53, Protocol::Tcp, "domain"),
+    (53, Protocol::Udp, "domain"),
+    (67, Protocol::Udp, "bootps"),
+    (68, Protocol::Udp, "bootpc"),
+    (69, Protocol::Udp, "tftp"),
+    (79, Protocol::Tcp, "finger"),
+    (80, Protocol::Tcp, "http"),
+    (80, Protocol::Sctp, "http"),
+    (88, Protocol::Tcp, "kerberos"),
+    (88, Protocol::Udp, "kerberos"),
+    (89, Protocol::Ip, "ospf"),
+    (110, Protocol::Tcp, "pop3"),
+    (111, Protocol::Tcp, "sunrpc"),
+    (111, Protocol::Udp, "sunrpc"),
+    (119, Protocol::Tcp, "nntp"),
+    (123, Protocol::Udp, "ntp"),
+    (132, Protocol::Ip, "sctp"),
+    (135, Protocol::Tcp, "epmap"),
+    (137, Protocol::Udp, "netbios-ns"),
+    (138, Protocol::Udp, "netbios-dgm"),
+    (139, Protocol::Tcp, "netbios-ssn"),
+    (143, Protocol::Tcp, "imap"),
+    (161, Protocol::Udp, "snmp"),
+    (162, Protocol::Udp, "snmptrap"),
+    (179, Protocol::Tcp, "bgp"),
+    (389, Protocol::Tcp, "ldap"),
+    (443, Protocol::Tcp, "https"),
+    (443, Protocol::Udp, "https"),
+    (443, Protocol::Sctp, "https"),
+    (445, Protocol::Tcp, "microsoft-ds"),
+    (465, Protocol::Tcp, "submissions"),
+    (500, Protocol::Udp, "isakmp"),
+    (514, Protocol::Tcp, "shell"),
+    (514, Protocol::Udp, "syslog"),
+    (515, Protocol::Tcp, "printer"),
+    (520, Protocol::Udp, "router"),
+    (548, Protocol::Tcp, "afpovertcp"),
+    (554, Protocol::Tcp, "rtsp"),
+    (587, Protocol::Tcp, "submission"),
+    (631, Protocol::Tcp, "ipp"),
+    (636, Protocol::Tcp, "ldaps"),
+    (873, Protocol::Tcp, "rsync"),
+    (993, Protocol::Tcp, "imaps"),
+    (995, Protocol::Tcp, "pop3s"),
+    (1433, Protocol::Tcp, "ms-sql-s"),
+    (1723, Protocol::Tcp, "pptp"),
+    (1900, Protocol::Udp, "ssdp"),
+    (2049, Protocol::Tcp, "nfs"),
+    (2049, Protocol::Udp, "nfs"),
+    (2905, Protocol::Sctp, "m3ua"),
+    (3306, Protocol::Tcp, "mysql"),
+    (3389, Protocol::Tcp, "ms-wbt-server"),
+    (3868, Protocol::Tcp, "diameter"),
+    (3868, Protocol::Sctp, "diameter"),
+    (4500, Protocol::Udp, "ipsec-nat-t"),
+    (5060, Protocol::Tcp, "sip"),
+    (5060, Protocol::Udp, "sip"),
+    (5060, Protocol::Sctp, "sip"),
+    (5353, Protocol::Udp, "mdns"),
+    (5432, Protocol::Tcp, "postgresql"),
+    (5900, Protocol::Tcp, "rfb"),
+    (6379, Protocol::Tcp, "redis"),
+    (8080, Protocol::Tcp, "http-alt"),
+    (8443, Protocol::Tcp, "pcsync-https"),
+    (9100, Protocol::Tcp, "jetdirect"),
+    (11211, Protocol::Tcp, "memcache"),
+    (27017, Protocol::Tcp, "mongodb"),
+    (36412, Protocol::Sctp, "s1-control"),
+];
+
+/// Returns the name of the service that usually listens on the port, e.g. `ssh` for TCP port 22.
+///
+/// Services may run on arbitrary ports, so the name is only a hint.
+/// For `Protocol::Ip` the port is a protocol number, e.g. `gre` for 47.
+pub fn service_name(port: u16, protocol: Protocol) -> Option<&'static str> {
+    SERVICES
+        .binary_search_by_key(&(port, protocol), |(port, protocol, _)| (*port, *protocol))
+        .ok()
+        .map(|index| SERVICES[index].2)
+}
+
+/// Returns the `n` most commonly open TCP ports, most common first.
+///
+/// Returns all known ports if `n` exceeds their number.
+pub fn top_ports(n: usize) -> Vec<u16> {
+    TOP_PORTS[..n.min(TOP_PORTS.len())].to_vec()
+}
+
+/// Parses a port specification like `"1-1024,8080,9000-9100"`.
+///
+/// Accepts comma-separated port numbers and hyphenated ranges of port numbers.
+/// `top-N` adds the N most common ports, see `top_ports`.
+/// Returns the ports sorted and without duplicates.
+pub fn parse_port_spec(spec: &str) -> Result<Vec<u16>> {
+    let mut ports = Vec::new();
+
+    for part in spec.split(',').map(str::trim) {
+        if let Some(n) = part.strip_prefix("top-") {
+            let n = n
+                .parse()
+                .map_err(|_| ScannerError::InvalidPortSpec(part.to_string()))?;
+            ports.extend(top_ports(n));
+            continue;
+        }
+
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start = parse_port(start)?;
+                let end = parse_port(end)?;
+                if start > end {
+                    Err(ScannerError::InvalidPortSpec(part.to_string()))?;
+                }
+                ports.extend(start..=end);
+            }
+            None => ports.push(parse_port(part)?),
+        }
+    }
+
+    ports.sort_unstable();
+    ports.dedup();
+
+    Ok(ports)
+}
+
+/// Parses a single port number. Port 0 is reserved and can't be scanned.
+fn parse_port(port: &str) -> Result<u16, ScannerError> {
+    match port.trim().parse::<u16>() {
+        Ok(port) if port != 0 => Ok(port),
+        _ => Err(ScannerError::InvalidPortSpec(port.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_ports_and_ranges() -> Result<()> {
+        assert_eq!(parse_port_spec("80")?, vec![80]);
+        assert_eq!(parse_port_spec("1-3")?, vec![1, 2, 3]);
+        assert_eq!(parse_port_spec("443, 22,80")?, vec![22, 80, 443]);
+        assert_eq!(
+            parse_port_spec("8080,1-2,9000-9001")?,
+            vec![1, 2, 8080, 9000, 9001]
+        );
+        assert_eq!(parse_port_spec("65535")?, vec![65535]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicates_are_removed() -> Result<()> {
+        assert_eq!(parse_port_spec("80,80,79-81")?, vec![79, 80, 81]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_service_name() {
+        assert_eq!(service_name(22, Protocol::Tcp), Some("ssh"));
+        assert_eq!(service_name(80, Protocol::Tcp), Some("http"));
+        assert_eq!(service_name(53, Protocol::Udp), Some("domain"));
+        assert_eq!(service_name(3868, Protocol::Sctp), Some("diameter"));
+
+        // The protocol tells apart services on the same port.
+        assert_eq!(service_name(514, Protocol::Tcp), Some("shell"));
+        assert_eq!(service_name(514, Protocol::Udp), Some("syslog"));
+        assert_eq!(service_name(47, Protocol::Ip), Some("gre"));
+
+        // Uncommon ports and protocols are unknown.
+        assert_eq!(service_name(12345, Protocol::Tcp), None);
+        assert_eq!(service_name(22, Protocol::Udp), None);
+
+        // The lookup relies on the services being sorted.
+        assert!(SERVICES
+            .windows(2)
+            .all(|pair| (pair[0].0, pair[0].1) < (pair[1].0, pair[1].1)));
+    }
+
+    #[test]
+    fn test_top_ports() -> Result<()> {
+        let ports = top_ports(100);
+        assert_eq!(ports.len(), 100);
+        for port in [22, 80, 443] {
+            assert!(ports.contains(&port));
+        }
+
+        // The most common ports come first.
+        assert_eq!(top_ports(3), vec![80, 23, 443]);
+        assert_eq!(top_ports(1000).len(), 100);
+
+        assert_eq!(parse_port_spec("top-3,22")?, vec![22, 23, 80, 443]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_specs() {
+        for spec in [
+            "", "abc", "0", "100-50", "1-", "-5", "65536", "1,,2", "1-2-3", "top-", "top-x",
+        ] {
+            assert!(
+                parse_port_spec(spec).is_err(),
+                "{spec:?} should be rejected"
+            );
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::networking::osi_layers::Traffic;
+use std::time::Instant;
+
+/// Upper limits on the traffic a program sends, e.g. to scan from metered or shared links,
+/// and on the time it takes, e.g. for scheduled scans that must finish on time.
+///
+/// Limits that are `None` are not enforced. The budget is measured against the frames a scan
+/// sends from its start, see `Scanner::scan`, so scans running before it don't use it up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Budget {
+    /// Maximum number of frames sent.
+    pub packets: Option<u64>,
+    /// Maximum number of bytes sent, counting each frame with its Ethernet header.
+    pub bytes: Option<u64>,
+    /// Point in time after which no further probes are sent.
+    pub deadline: Option<Instant>,
+}
+
+impl Budget {
+    /// Returns whether the traffic sent so far reached any of the limits, or the deadline passed.
+    pub fn is_exhausted(&self, sent: Traffic) -> bool {
+        self.packets.is_some_and(|packets| sent.packets >= packets)
+            || self.bytes.is_some_and(|bytes| sent.bytes >= bytes)
+            || self.is_expired()
+    }
+
+    /// Returns the budget that is left after `sent`, e.g. for a scan that follows another.
+    pub fn remaining(&self, sent: Traffic) -> Budget {
+        Budget {
+            packets: self
+                .packets
+                .map(|packets| packets.saturating_sub(sent.packets)),
+            bytes: self.bytes.map(|bytes| bytes.saturating_sub(sent.bytes)),
+            deadline: self.deadline,
+        }
+    }
+
+    /// Returns whether the deadline passed.
+    pub fn is_expired(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_budget_is_exhausted() {
+        let sent = Traffic {
+            packets: 10,
+            bytes: 600,
+        };
+
+        assert!(!Budget::default().is_exhausted(sent));
+
+        let budget = Budget {
+            packets: Some(11),
+            bytes: Some(1000),
+            deadline: None,
+        };
+        assert!(!budget.is_exhausted(sent));
+
+        let budget = Budget {
+            packets: Some(10),
+            bytes: None,
+            deadline: None,
+        };
+        assert!(budget.is_exhausted(sent));
+
+        let budget = Budget {
+            packets: None,
+            bytes: Some(600),
+            deadline: None,
+        };
+        assert!(budget.is_exhausted(sent));
+    }
+
+    #[test]
+    fn test_budget_deadline() {
+        let sent = Traffic::default();
+        let now = Instant::now();
+
+        let budget = Budget {
+            deadline: Some(now + Duration::from_secs(60)),
+            ..Budget::default()
+        };
+        assert!(!budget.is_expired());
+        assert!(!budget.is_exhausted(sent));
+
+        let budget = Budget {
+            deadline: Some(now),
+            ..Budget::default()
+        };
+        assert!(budget.is_expired());
+        assert!(budget.is_exhausted(sent));
+
+        assert!(!Budget::default().is_expired());
+    }
+
+    #[test]
+    fn test_remaining_budget() {
+        let sent = Traffic {
+            packets: 10,
+            bytes: 600,
+        };
+        let budget = Budget {
+            packets: Some(15),
+            bytes: Some(500),
+            deadline: None,
+        };
+
+        let remaining = budget.remaining(sent);
+        assert_eq!(remaining.packets, Some(5));
+        assert_eq!(remaining.bytes, Some(0));
+        assert!(remaining.is_exhausted(Traffic::default()));
+
+        assert_eq!(Budget::default().remaining(sent), Budget::default());
+    }
+}
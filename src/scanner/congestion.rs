@@ -0,0 +1,87 @@
+/// Number of probes in flight at the start of a scan, unless the maximum is lower.
+const INITIAL_WINDOW: usize = 16;
+
+/// Adapts the number of probes in flight to the observed loss, like the timing engine of nmap.
+///
+/// Uses additive increase, multiplicative decrease (AIMD) as known from TCP congestion control.
+/// Each response grows the window by one probe, up to the maximum. A timeout halves it, but
+/// at most once per window of completed probes, since the probes of a congested window tend
+/// to time out together. This keeps scans fast on good networks and reliable on lossy ones.
+///
+/// Note that filtered ports also time out, so scans of heavily filtered hosts stay slow.
+#[derive(Debug, Clone)]
+pub struct CongestionWindow {
+    size: usize,
+    max_size: usize,
+    completed_since_decrease: usize,
+}
+
+impl CongestionWindow {
+    /// Creates a window that never exceeds `max_size` probes in flight, but holds at least one.
+    pub fn new(max_size: usize) -> Self {
+        let max_size = max_size.max(1);
+        CongestionWindow {
+            size: INITIAL_WINDOW.min(max_size),
+            max_size,
+            completed_since_decrease: 0,
+        }
+    }
+
+    /// Returns the number of probes that may currently be in flight.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Grows the window after a probe received a response.
+    pub fn on_response(&mut self) {
+        self.completed_since_decrease += 1;
+        self.size = (self.size + 1).min(self.max_size);
+    }
+
+    /// Shrinks the window after a probe timed out, unless it shrank within the current window.
+    pub fn on_timeout(&mut self) {
+        self.completed_since_decrease += 1;
+        if self.completed_since_decrease >= self.size {
+            self.size = (self.size / 2).max(1);
+            self.completed_since_decrease = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_increases_and_decreases() {
+        let mut window = CongestionWindow::new(20);
+        assert_eq!(window.size(), INITIAL_WINDOW);
+
+        // Responses grow the window up to the maximum.
+        for _ in 0..10 {
+            window.on_response();
+        }
+        assert_eq!(window.size(), 20);
+
+        // A burst of timeouts halves the window once per window of completed probes,
+        // which includes the probes that received a response.
+        for _ in 0..10 {
+            window.on_timeout();
+        }
+        assert_eq!(window.size(), 10);
+        for _ in 0..10 {
+            window.on_timeout();
+        }
+        assert_eq!(window.size(), 5);
+
+        // The window never drops below one probe.
+        for _ in 0..100 {
+            window.on_timeout();
+        }
+        assert_eq!(window.size(), 1);
+
+        // A small maximum caps the initial window, and an empty one still allows a probe.
+        assert_eq!(CongestionWindow::new(4).size(), 4);
+        assert_eq!(CongestionWindow::new(0).size(), 1);
+    }
+}
@@ -0,0 +1,174 @@
+use super::results::{PortState, Protocol, ScanResult};
+use std::{collections::BTreeMap, fmt, net::IpAddr};
+
+/// Changes of port states between an earlier and a later scan, see `diff`.
+///
+/// Each list is sorted by host, port and protocol.
+#[derive(Debug, Default)]
+pub struct ScanDiff {
+    /// Ports that are open now, but were not open or not scanned before.
+    pub opened: Vec<ScanResult>,
+    /// Ports that were open before, but are not open anymore. Holds the later results.
+    pub closed: Vec<ScanResult>,
+    /// Ports whose state changed otherwise, e.g. from closed to filtered.
+    /// Holds the earlier state along with the later result.
+    pub changed: Vec<(PortState, ScanResult)>,
+}
+
+impl ScanDiff {
+    /// Returns whether the state of no port changed.
+    pub fn is_empty(&self) -> bool {
+        self.opened.is_empty() && self.closed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for ScanDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} opened, {} closed, {} changed",
+            self.opened.len(),
+            self.closed.len(),
+            self.changed.len()
+        )
+    }
+}
+
+/// Compares the results of a scan with the results of an earlier one, e.g. to monitor hosts.
+///
+/// Sockets are matched by host, port and protocol. Only sockets of the later scan are compared,
+/// since it may cover fewer ports, so sockets missing from it count as unchanged. Failed results
+/// reveal no state and are skipped, as are all but the last result of a socket.
+pub fn diff(old: &[ScanResult], new: &[ScanResult]) -> ScanDiff {
+    let old = by_socket(old);
+    let mut diff = ScanDiff::default();
+
+    for (socket, scan) in by_socket(new) {
+        let old_state = old.get(&socket).map(|scan| scan.state);
+        match (old_state, scan.state) {
+            (Some(old_state), state) if old_state == state => {}
+            (Some(PortState::Open), _) => diff.closed.push(scan.clone()),
+            (_, PortState::Open) => diff.opened.push(scan.clone()),
+            (Some(old_state), _) => diff.changed.push((old_state, scan.clone())),
+            // A socket that wasn't scanned before and isn't open has nothing to report.
+            (None, _) => {}
+        }
+    }
+
+    diff
+}
+
+/// Indexes the successful results by their socket.
+fn by_socket(results: &[ScanResult]) -> BTreeMap<(IpAddr, u16, Protocol), &ScanResult> {
+    results
+        .iter()
+        .filter(|scan| scan.error.is_none())
+        .map(|scan| ((scan.ip, scan.port, scan.protocol), scan))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{errors::ScannerError, scanner::results::Reason};
+    use std::net::Ipv4Addr;
+
+    const HOST: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1));
+    const OTHER_HOST: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 2));
+
+    fn scan(ip: IpAddr, port: u16, protocol: Protocol, state: PortState) -> ScanResult {
+        ScanResult::new(ip, port, protocol, 0, state, Reason::Other, None, 1)
+    }
+
+    fn tcp(port: u16, state: PortState) -> ScanResult {
+        scan(HOST, port, Protocol::Tcp, state)
+    }
+
+    fn ports(results: &[ScanResult]) -> Vec<u16> {
+        results.iter().map(|scan| scan.port).collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = [
+            tcp(22, PortState::Open),
+            tcp(80, PortState::Closed),
+            tcp(443, PortState::Open),
+            tcp(8080, PortState::Closed),
+            tcp(9000, PortState::Filtered),
+        ];
+        let new = [
+            tcp(22, PortState::Open),
+            tcp(80, PortState::Open),
+            tcp(443, PortState::Filtered),
+            tcp(8080, PortState::Filtered),
+            tcp(9000, PortState::Filtered),
+            tcp(3000, PortState::Open),
+            tcp(3001, PortState::Closed),
+        ];
+
+        let diff = diff(&old, &new);
+        // Ports that weren't scanned before count as opened if open, otherwise they are skipped.
+        assert_eq!(ports(&diff.opened), vec![80, 3000]);
+        assert_eq!(ports(&diff.closed), vec![443]);
+        assert_eq!(diff.closed[0].state, PortState::Filtered);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].0, PortState::Closed);
+        assert_eq!(diff.changed[0].1.port, 8080);
+        assert_eq!(diff.changed[0].1.state, PortState::Filtered);
+        assert_eq!(diff.to_string(), "2 opened, 1 closed, 1 changed");
+    }
+
+    #[test]
+    fn test_diff_of_identical_or_empty_scans() {
+        let results = [tcp(22, PortState::Open), tcp(80, PortState::Closed)];
+        assert!(diff(&results, &results).is_empty());
+        assert!(diff(&[], &[]).is_empty());
+
+        // Ports missing from the later scan are not compared.
+        assert!(diff(&results, &[]).is_empty());
+
+        // Everything open is new compared to no scan at all.
+        assert_eq!(ports(&diff(&[], &results).opened), vec![22]);
+    }
+
+    #[test]
+    fn test_diff_matches_sockets() {
+        // The same port of another host or protocol is another socket.
+        let old = [tcp(53, PortState::Open)];
+        let new = [
+            tcp(53, PortState::Open),
+            scan(OTHER_HOST, 53, Protocol::Tcp, PortState::Open),
+            scan(HOST, 53, Protocol::Udp, PortState::Open),
+        ];
+        let diff = diff(&old, &new);
+        let sockets = diff
+            .opened
+            .iter()
+            .map(|scan| (scan.ip, scan.protocol))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sockets,
+            vec![(HOST, Protocol::Udp), (OTHER_HOST, Protocol::Tcp)]
+        );
+        assert!(diff.closed.is_empty());
+    }
+
+    #[test]
+    fn test_diff_skips_failed_and_repeated_results() {
+        let error = ScannerError::CantResolveMac(HOST).into();
+        let failed = ScanResult::failed(HOST, 22, Protocol::Tcp, 0, &error);
+
+        // A failed socket reveals no state, neither before nor after.
+        let open = [tcp(22, PortState::Open)];
+        let failed = [failed];
+        assert!(diff(&open, &failed).is_empty());
+        let diff_failed = diff(&failed, &open);
+        assert_eq!(ports(&diff_failed.opened), vec![22]);
+
+        // The last result of a socket wins.
+        let old = [tcp(22, PortState::Closed), tcp(22, PortState::Open)];
+        let new = [tcp(22, PortState::Open), tcp(22, PortState::Closed)];
+        assert_eq!(ports(&diff(&old, &new).closed), vec![22]);
+    }
+}
@@ -1,108 +1,1387 @@
 use super::tcp_scan::tcp_syn_scan;
 use crate::{
-    networking::{interface::Interface, socket_iterator::SocketIterator},
+    errors::{ChannelError, ScannerError},
+    networking::{
+        dns::{reverse_lookup, system_nameserver},
+        icmp::IcmpProbe,
+        interface::{Interface, Routes, ROUTED_SOURCE},
+        matcher::ControlMessage,
+        ndp::Ndp,
+        osi_layers::DatalinkLayer,
+        probe::ProbeOptions,
+        socket_iterator::SocketIterator,
+        tcp::Tcp,
+    },
     scanner::{
         arp_scan::arp_scan,
+        budget::Budget,
+        congestion::CongestionWindow,
         icmp_scan::icmp_scan,
+        idle_scan::tcp_idle_scan,
+        ip_id::{analyze_ip_id, sample_ip_ids, IpIdPattern, IP_ID_SAMPLES},
+        ndp_scan::ndp_scan,
+        os_detection::{detect_os, OsGuess},
+        profile::{Banner, HostProfile},
+        protocol_scan::ip_protocol_scan,
+        rate_limiter::RateLimiter,
+        results::{HostNote, HostReport, PortState, Protocol, Reason, ScanResult, ScanStats},
+        rst_limit::{RstLimitDetector, RST_LIMITED_RATE},
+        rtt_estimator::RttEstimator,
+        sctp_scan::sctp_init_scan,
+        tarpit::{is_suspected_tarpit, DEFAULT_TARPIT_THRESHOLD},
         tcp_scan::{
-            tcp_ack_scan, tcp_connect_scan, tcp_fin_scan, tcp_maimon_scan, tcp_null_scan,
+            tcp_ack_scan, tcp_connect_scan, tcp_fin_scan, tcp_maimon_scan, tcp_null_scan, tcp_ping,
             tcp_window_scan, tcp_xmas_scan,
         },
+        traceroute::{traceroute, Hop},
         udp_scan::udp_scan,
+        version_detection::{detect_version, ServiceInfo},
     },
 };
-use futures::{stream::FuturesUnordered, StreamExt};
-use log::info;
+use anyhow::Result;
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use log::{debug, info, warn};
+use netdev::ip::Ipv4Net;
 use pnet::util::MacAddr;
+use rand::{seq::SliceRandom, Rng};
 use std::{
+    collections::HashMap,
     net::{IpAddr, SocketAddr},
-    time::Duration,
+    ops::RangeInclusive,
+    slice,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Number of sockets scanned at the same time if not configured otherwise.
+pub const DEFAULT_CONCURRENCY: usize = 256;
+
+/// Time to wait for a response to a probe if not configured otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Whether ports are probed in a random order if not configured otherwise, like nmap.
+pub const DEFAULT_RANDOMIZE_ORDER: bool = true;
+
+/// Range of the dynamic ports, which are unlikely to be open and thus calibrate scans.
+const CALIBRATION_PORTS: RangeInclusive<u16> = 49152..=65535;
+
+/// Kind of probe a port scan sends, selected at runtime from the CLI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanMethod {
+    TcpSyn,
+    TcpConnect,
+    TcpAck,
+    TcpFin,
+    TcpXmas,
+    TcpNull,
+    TcpWindow,
+    TcpMaimon,
+    TcpIdle,
+    Udp,
+    SctpInit,
+    IpProtocol,
+}
+
+impl ScanMethod {
+    /// Sends the probe of this method to a single socket and classifies the response.
+    ///
+    /// Measures the total time spent on the socket as `elapsed`, so that silent ports also show
+    /// how long was waited for them.
+    ///
+    /// In a dry run the probe is only built, so the state of the socket is unknown.
+    ///
+    /// Never fails, so that a single bad socket doesn't abort a scan. If the probe can't be built
+    /// or sent, the error is kept in the result instead, see `ScanResult::failed`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn probe(
+        self,
+        interface: Interface,
+        src_ip: IpAddr,
+        src_port: u16,
+        dest_ip: IpAddr,
+        dest_port: u16,
+        options: ProbeOptions,
+        timeout: Duration,
+    ) -> ScanResult {
+        let scan_method = match self {
+            ScanMethod::TcpSyn => tcp_syn_scan,
+            ScanMethod::TcpConnect => tcp_connect_scan,
+            ScanMethod::TcpAck => tcp_ack_scan,
+            ScanMethod::TcpFin => tcp_fin_scan,
+            ScanMethod::TcpXmas => tcp_xmas_scan,
+            ScanMethod::TcpNull => tcp_null_scan,
+            ScanMethod::TcpWindow => tcp_window_scan,
+            ScanMethod::TcpMaimon => tcp_maimon_scan,
+            ScanMethod::TcpIdle => tcp_idle_scan,
+            ScanMethod::Udp => udp_scan,
+            ScanMethod::SctpInit => sctp_init_scan,
+            ScanMethod::IpProtocol => ip_protocol_scan,
+        };
+
+        let start_time = Instant::now();
+        let result = scan_method(
+            interface, src_ip, src_port, dest_ip, dest_port, options, timeout,
+        );
+
+        let mut scan = match result {
+            Ok(scan) => scan,
+            Err(e) => {
+                debug!("Probing {}:{} failed: {:#}", dest_ip, dest_port, e);
+                ScanResult::failed(dest_ip, dest_port, self.protocol(), src_port, &e)
+            }
+        };
+
+        scan.elapsed = start_time.elapsed();
+        scan
+    }
+
+    /// Returns whether closed or unfiltered ports answer the probes with a RST flag, so that
+    /// hosts which rate limit their RSTs misreport them, see `RstLimitDetector`.
+    pub fn answers_with_rst(self) -> bool {
+        matches!(
+            self,
+            ScanMethod::TcpSyn
+                | ScanMethod::TcpAck
+                | ScanMethod::TcpFin
+                | ScanMethod::TcpXmas
+                | ScanMethod::TcpNull
+                | ScanMethod::TcpWindow
+                | ScanMethod::TcpMaimon
+        )
+    }
+
+    /// Returns the transport protocol of the probes.
+    pub fn protocol(self) -> Protocol {
+        match self {
+            ScanMethod::Udp => Protocol::Udp,
+            ScanMethod::SctpInit => Protocol::Sctp,
+            ScanMethod::IpProtocol => Protocol::Ip,
+            _ => Protocol::Tcp,
+        }
+    }
+
+    /// Describes how responses to the probes of this method are interpreted.
+    ///
+    /// Each method draws different conclusions from a RST flag, silence or an ICMP error.
+    /// See the documentation of the scan functions for the reasoning.
+    pub fn interpretation(self) -> &'static str {
+        match self {
+            ScanMethod::TcpSyn => {
+                "SYN/ACK -> open, RST -> closed, no response or ICMP unreachable -> filtered"
+            }
+            ScanMethod::TcpConnect => {
+                "connection established -> open, refused -> closed, timeout -> filtered"
+            }
+            ScanMethod::TcpAck => "RST -> unfiltered, no response or ICMP unreachable -> filtered",
+            ScanMethod::TcpFin
+            | ScanMethod::TcpXmas
+            | ScanMethod::TcpNull
+            | ScanMethod::TcpMaimon => {
+                "RST -> closed, no response -> open or filtered, ICMP unreachable -> filtered"
+            }
+            ScanMethod::TcpWindow => {
+                "RST with non-zero window -> open, RST with zero window -> closed, \
+                 no response or ICMP unreachable -> filtered"
+            }
+            ScanMethod::TcpIdle => {
+                "IP identification of the zombie increased by 2 -> open, \
+                 by 1 -> closed or filtered"
+            }
+            ScanMethod::Udp => {
+                "UDP response -> open, ICMP port unreachable -> closed, \
+                 other ICMP unreachable -> filtered, no response -> open or filtered"
+            }
+            ScanMethod::SctpInit => {
+                "INIT-ACK -> open, ABORT -> closed, no response or ICMP unreachable -> filtered"
+            }
+            ScanMethod::IpProtocol => {
+                "packet of the protocol -> open, ICMP protocol unreachable -> closed, \
+                 other ICMP unreachable -> filtered, no response -> open or filtered"
+            }
+        }
+    }
+}
+
+/// Asynchronous API to run scans on the tokio runtime.
+///
+/// The pnet crate only offers blocking I/O, so every probe runs in a blocking thread
+/// via `spawn_blocking`. This way the runtime is never blocked, while the probes can
+/// still be awaited, joined or raced like any other future.
+///
+/// Port scans are configured with the builder methods and started with `run`:
+///
+/// ```ignore
+/// let (results, stats) = Scanner::new(interface)
+///     .scan_type(ScanMethod::TcpSyn)
+///     .ports(vec![22, 80, 443])
+///     .timeout(Duration::from_secs(2))
+///     .run(&ip_addresses)
+///     .await?;
+/// ```
+///
+/// Options that are not set fall back to the same defaults as the config.
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    interface: Interface,
+    method: ScanMethod,
+    src_ip: IpAddr,
+    src_port: u16,
+    port_numbers: Vec<u16>,
+    options: ProbeOptions,
+    concurrency: usize,
+    rate: Option<u32>,
+    timeout: Duration,
+    ping_first: bool,
+    calibrate: bool,
+    resolve: bool,
+    randomize_order: bool,
+    budget: Budget,
+    tarpit_threshold: f64,
+    exclude_hosts: Vec<Ipv4Net>,
+    exclude_ports: Vec<u16>,
+    stop: Arc<AtomicBool>,
+    results: Option<UnboundedSender<ScanResult>>,
+    banner_probe: Option<Vec<u8>>,
+}
+
+impl Scanner {
+    /// Creates a new `Scanner` that sends TCP SYN probes.
+    ///
+    /// Unless a source is set, each target is probed from the source of its route, see `Routes`.
+    /// The interface is only used for sources that are set.
+    ///
+    /// Probes are sent from random ephemeral ports, and no ports are scanned until they are set.
+    pub fn new(interface: Interface) -> Self {
+        Scanner {
+            interface,
+            method: ScanMethod::TcpSyn,
+            src_ip: ROUTED_SOURCE,
+            src_port: 0,
+            port_numbers: Vec::new(),
+            options: ProbeOptions::default(),
+            concurrency: DEFAULT_CONCURRENCY,
+            rate: None,
+            timeout: DEFAULT_TIMEOUT,
+            ping_first: false,
+            calibrate: false,
+            resolve: false,
+            randomize_order: DEFAULT_RANDOMIZE_ORDER,
+            budget: Budget::default(),
+            tarpit_threshold: DEFAULT_TARPIT_THRESHOLD,
+            exclude_hosts: Vec::new(),
+            exclude_ports: Vec::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+            results: None,
+            banner_probe: None,
+        }
+    }
+
+    /// Sets the source IP address of probes, along with the interface given to `new`.
+    ///
+    /// An unspecified address, like `ROUTED_SOURCE`, probes each target from the source of its route.
+    pub fn source(mut self, src_ip: IpAddr) -> Self {
+        self.src_ip = src_ip;
+        self
+    }
+
+    /// Sets the source port of probes. `0` sends each probe from a random ephemeral port.
+    pub fn source_port(mut self, src_port: u16) -> Self {
+        self.src_port = src_port;
+        self
+    }
+
+    /// Sets the scan method.
+    pub fn scan_type(mut self, method: ScanMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Sets the port numbers to scan on each host, e.g. as parsed by `parse_port_spec`.
+    pub fn ports(mut self, port_numbers: Vec<u16>) -> Self {
+        self.port_numbers = port_numbers;
+        self
+    }
+
+    /// Sets the header fields of probes and how they are sent, e.g. the TTL and retries.
+    pub fn options(mut self, options: ProbeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the maximum number of sockets scanned at the same time, see `Scanner::scan`.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Sets the maximum number of sockets probed per second. `None` means no limit.
+    pub fn rate(mut self, rate: Option<u32>) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// Sets the time to wait for a response to a probe.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets whether `scan_targets` skips hosts that don't answer an ICMP echo request.
+    pub fn ping_first(mut self, ping_first: bool) -> Self {
+        self.ping_first = ping_first;
+        self
+    }
+
+    /// Sets whether TCP scans probe a likely closed port of each host first, see `run`.
+    pub fn calibrate(mut self, calibrate: bool) -> Self {
+        self.calibrate = calibrate;
+        self
+    }
+
+    /// Sets whether `scan_targets` looks up the host names of the hosts, see `reverse_lookup`.
+    pub fn resolve(mut self, resolve: bool) -> Self {
+        self.resolve = resolve;
+        self
+    }
+
+    /// Sets whether the ports are probed in a random order instead of the given one, see `run`.
+    pub fn randomize_order(mut self, randomize_order: bool) -> Self {
+        self.randomize_order = randomize_order;
+        self
+    }
+
+    /// Sets the limits on the traffic sent, after which no further probes are started, see `scan`.
+    ///
+    /// One budget covers the calibration probes of `run`, the scan and its retries together.
+    pub fn budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Sets the fraction of open ports above which `scan_targets` suspects a host to be a tarpit,
+    /// see `is_suspected_tarpit`.
+    pub fn tarpit_threshold(mut self, tarpit_threshold: f64) -> Self {
+        self.tarpit_threshold = tarpit_threshold;
+        self
+    }
+
+    /// Sets the hosts that are never probed, e.g. fragile devices in a scanned subnet.
+    /// Single hosts are subnets with a prefix length of 32.
+    pub fn exclude_hosts(mut self, exclude_hosts: Vec<Ipv4Net>) -> Self {
+        self.exclude_hosts = exclude_hosts;
+        self
+    }
+
+    /// Sets the ports that are never probed on any host.
+    pub fn exclude_ports(mut self, exclude_ports: Vec<u16>) -> Self {
+        self.exclude_ports = exclude_ports;
+        self
+    }
+
+    /// Sets the flag that stops a running scan, e.g. when the user presses Ctrl-C.
+    ///
+    /// Once the flag is set, no further probes are started, and the results gathered so far
+    /// are returned. Installing a signal handler that sets the flag is up to the caller.
+    pub fn stop_flag(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    /// Sets a channel that receives each scan result as soon as its socket is classified,
+    /// e.g. to show the progress of long scans. `run` still returns all results at the end.
+    ///
+    /// Results arrive in the order the sockets complete, with calibration and spoofing already
+    /// taken into account. Each socket arrives once, with its final result. Silent ports of scans
+    /// that rely on RST flags are held back until the scan is done, since they are probed again
+    /// if their host rate limits its RSTs, see `run`. If the receiver is dropped, no further
+    /// probes are started and the scan fails once the sockets in flight are done.
+    pub fn stream(mut self, results: UnboundedSender<ScanResult>) -> Self {
+        self.results = Some(results);
+        self
+    }
+
+    /// Sets the probe that `profile` sends to services that wait for a request, see `Tcp::grab_banner`.
+    pub fn banner_probe(mut self, probe: Option<Vec<u8>>) -> Self {
+        self.banner_probe = probe;
+        self
+    }
+
+    /// Scans the configured ports on each of the given hosts and reports the results per host.
+    ///
+    /// The ports of all hosts are scanned concurrently, like with `run`. If pinging first,
+    /// hosts that don't answer are skipped beforehand, which speeds up scans of sparse subnets.
+    /// Note that firewalls often drop pings, and that only IPv4 hosts can be pinged.
+    /// The statistics only cover the port scan.
+    ///
+    /// If resolving, the host names of the reports are looked up afterwards, all at the same time.
+    /// Hosts whose lookup fails or times out are reported without a name.
+    ///
+    /// Hosts with implausibly many open ports get a `HostNote::SuspectedTarpit`, see
+    /// `tarpit_threshold`. Their results are kept as they are.
+    ///
+    /// Excluded hosts are neither pinged nor reported.
+    ///
+    /// May fail if no ports are set.
+    pub async fn scan_targets(&self, targets: &[IpAddr]) -> Result<(Vec<HostReport>, ScanStats)> {
+        let targets = &self.included_hosts(targets);
+
+        let hosts = match self.ping_first {
+            true => {
+                let hosts = Scanner::ping(
+                    self.interface,
+                    self.src_ip,
+                    targets.to_vec(),
+                    IcmpProbe::Echo,
+                    self.options.clone(),
+                    self.timeout,
+                )
+                .await
+                .into_iter()
+                .filter(|(_, state, _)| *state == PortState::Up)
+                .map(|(host, _, _)| host)
+                .collect::<Vec<_>>();
+
+                info!(
+                    "{} of {} hosts are up and will be scanned.",
+                    hosts.len(),
+                    targets.len()
+                );
+
+                hosts
+            }
+            false => targets.to_vec(),
+        };
+
+        let (results, stats) = self.run(&hosts).await?;
+
+        let mut reports = HostReport::group(&hosts, results);
+        for report in &mut reports {
+            if is_suspected_tarpit(&report.results, self.tarpit_threshold) {
+                warn!(
+                    "{} seems to be a tarpit or honeypot, almost all of its ports appear open.",
+                    report.host
+                );
+                report.notes.push(HostNote::SuspectedTarpit);
+            }
+        }
+        if self.resolve {
+            resolve_hostnames(&mut reports).await;
+        }
+
+        Ok((reports, stats))
+    }
+
+    /// Scans the configured ports on the given IP addresses, see `Scanner::scan`.
+    ///
+    /// FIN, NULL, XMAS and Maimon scans rely on closed ports answering with a RST flag, so
+    /// silence means open or filtered. If calibrating, a SYN probe to a likely closed port
+    /// first checks whether a host sends RST flags at all. Silent ports of hosts that don't
+    /// are marked as filtered, since e.g. a firewall drops the probes. The calibration probes
+    /// are not counted in the statistics.
+    ///
+    /// Idle scans probe one socket at a time regardless of the concurrency, since every probe
+    /// increases the IP identification of the zombie.
+    ///
+    /// If randomizing the order, the ports are shuffled before each scan, every order being equally
+    /// likely. Sequential probes are easy to spot for IDS and hit rate limits of hosts in a
+    /// predictable pattern. The results are sorted by port regardless.
+    ///
+    /// Probes from a source address that is not assigned to a local interface are sent anyway,
+    /// e.g. to test filters on the path. Their responses can't be captured, so silent sockets
+    /// are marked as unknown instead of e.g. filtered. Connect scans always use a local address.
+    ///
+    /// Excluded hosts and ports are dropped before any probe is sent, see `exclude_hosts`.
+    /// IP protocol scans take the ports as protocol numbers, so ports above 255 are dropped too.
+    ///
+    /// Hosts that answer with RST flags at first and then keep silent likely rate limit their RSTs,
+    /// see `RstLimitDetector`. Their silent ports are probed again at a slow rate once the scan is
+    /// done, and ports that stay silent are marked as possibly rate limited.
+    ///
+    /// Each result is also sent to the channel set with `stream`, if any. Silent ports that may
+    /// be probed again are only sent once the retries are done, with their final result.
+    ///
+    /// May fail if no ports are set, if all of them are excluded, or if the receiver of the
+    /// streamed results was dropped.
+    pub async fn run(&self, ip_addresses: &[IpAddr]) -> Result<(Vec<ScanResult>, ScanStats)> {
+        let start_traffic = DatalinkLayer::traffic();
+        let ip_addresses = &self.included_hosts(ip_addresses);
+        let mut port_numbers = self
+            .port_numbers
+            .iter()
+            .copied()
+            .filter(|port| !self.exclude_ports.contains(port))
+            .collect::<Vec<_>>();
+        if port_numbers.len() < self.port_numbers.len() {
+            info!(
+                "{} ports are excluded and will not be scanned.",
+                self.port_numbers.len() - port_numbers.len()
+            );
+        }
+        if self.method == ScanMethod::IpProtocol && port_numbers.iter().any(|port| *port > 255) {
+            port_numbers.retain(|port| *port <= 255);
+            info!("IP protocol scans take ports as protocol numbers, ports above 255 are skipped.");
+        }
+        if port_numbers.is_empty() {
+            Err(ScannerError::NoPortNumbers)?;
+        }
+
+        let responsive_hosts = match self.calibrate && self.method.protocol() == Protocol::Tcp {
+            true => Some(self.find_responsive_hosts(ip_addresses).await),
+            false => None,
+        };
+
+        let is_spoofed = self.method != ScanMethod::TcpConnect
+            && !self.src_ip.is_unspecified()
+            && !Interface::is_local_ip(self.src_ip);
+        if is_spoofed {
+            warn!(
+                "{} is not assigned to a local interface, responses to the probes can't be captured.",
+                self.src_ip
+            );
+        }
+
+        let scans_loopback = !matches!(self.method, ScanMethod::TcpSyn | ScanMethod::TcpConnect)
+            && ip_addresses.iter().any(IpAddr::is_loopback);
+        if scans_loopback {
+            warn!("Loopback addresses can only be scanned with TCP SYN or connect scans, responses to other probes can't be captured.");
+        }
+
+        if self.options.proxy.is_some() && self.method != ScanMethod::TcpConnect {
+            warn!("Only TCP connect scans can be proxied, the proxy is ignored.");
+        }
+
+        let concurrency = match self.method {
+            ScanMethod::TcpIdle => 1,
+            _ => self.concurrency,
+        };
+
+        if self.randomize_order {
+            port_numbers.shuffle(&mut rand::thread_rng());
+        }
+
+        let finish = |scan: &mut ScanResult| {
+            if let Some(responsive_hosts) = &responsive_hosts {
+                apply_calibration(slice::from_mut(scan), responsive_hosts);
+            }
+            if is_spoofed {
+                mark_spoofed(slice::from_mut(scan));
+            }
+        };
+
+        let mut stream = ResultStream::new(self.results.as_ref(), self.method.answers_with_rst());
+        let mut detector = RstLimitDetector::default();
+        let (mut results, mut stats) = Scanner::scan(
+            self.interface,
+            self.method,
+            self.src_ip,
+            self.src_port,
+            ip_addresses,
+            &port_numbers,
+            self.options.clone(),
+            concurrency,
+            self.rate,
+            self.timeout,
+            self.budget
+                .remaining(DatalinkLayer::traffic().since(start_traffic)),
+            &self.stop,
+            |scan| {
+                finish(scan);
+                stream.send(scan)?;
+                if self.method.answers_with_rst() && detector.record(scan) {
+                    warn!(
+                        "{} seems to rate limit RST flags, its silent ports will be probed again more slowly.",
+                        scan.ip
+                    );
+                }
+                Ok(())
+            },
+        )
+        .await?;
+
+        for host in detector.limited_hosts() {
+            stats.rst_rate_limits += 1;
+            let silent_ports = results
+                .iter()
+                .filter(|scan| scan.ip == host && scan.reason == Reason::NoResponse)
+                .map(|scan| scan.port)
+                .collect::<Vec<_>>();
+
+            let (retried, retry_stats) = Scanner::scan(
+                self.interface,
+                self.method,
+                self.src_ip,
+                self.src_port,
+                &[host],
+                &silent_ports,
+                self.options.clone(),
+                concurrency,
+                Some(
+                    self.rate
+                        .map_or(RST_LIMITED_RATE, |rate| rate.min(RST_LIMITED_RATE)),
+                ),
+                self.timeout,
+                self.budget
+                    .remaining(DatalinkLayer::traffic().since(start_traffic)),
+                &self.stop,
+                |scan| {
+                    // Ports that stay silent may still be closed, if the host throttles again.
+                    if scan.reason == Reason::NoResponse {
+                        scan.reason = Reason::PossiblyRateLimited;
+                    }
+                    finish(scan);
+                    Ok(())
+                },
+            )
+            .await?;
+
+            stats.record_retry(&retry_stats);
+            for scan in retried {
+                if let Ok(index) =
+                    results.binary_search_by_key(&(scan.ip, scan.port), |old| (old.ip, old.port))
+                {
+                    results[index] = scan;
+                }
+            }
+        }
+        stream.release(&results)?;
+
+        Ok((results, stats))
+    }
+
+    /// Returns the hosts that are not excluded, in the given order.
+    fn included_hosts(&self, ip_addresses: &[IpAddr]) -> Vec<IpAddr> {
+        let hosts = ip_addresses
+            .iter()
+            .copied()
+            .filter(|ip| match ip {
+                IpAddr::V4(ip) => !self.exclude_hosts.iter().any(|net| net.contains(*ip)),
+                IpAddr::V6(_) => true,
+            })
+            .collect::<Vec<_>>();
+
+        if hosts.len() < ip_addresses.len() {
+            info!(
+                "{} hosts are excluded and will not be scanned.",
+                ip_addresses.len() - hosts.len()
+            );
+        }
+
+        hosts
+    }
+
+    /// Returns the hosts that answer a SYN probe to a likely closed port.
+    async fn find_responsive_hosts(&self, ip_addresses: &[IpAddr]) -> Vec<IpAddr> {
+        let calibration_port = rand::thread_rng().gen_range(CALIBRATION_PORTS);
+
+        let (results, _) = Scanner::scan(
+            self.interface,
+            ScanMethod::TcpSyn,
+            self.src_ip,
+            self.src_port,
+            ip_addresses,
+            &[calibration_port],
+            self.options.clone(),
+            self.concurrency,
+            self.rate,
+            self.timeout,
+            self.budget,
+            &self.stop,
+            |_| Ok(()),
+        )
+        .await
+        .unwrap_or_default();
+
+        let responsive_hosts = results
+            .into_iter()
+            .filter(|scan| matches!(scan.state, PortState::Closed | PortState::Open))
+            .map(|scan| scan.ip)
+            .collect::<Vec<_>>();
+
+        info!(
+            "{} of {} hosts answered the calibration probe to port {}.",
+            responsive_hosts.len(),
+            ip_addresses.len(),
+            calibration_port
+        );
+
+        responsive_hosts
+    }
+
+    /// Scans the given IP addresses and port numbers with the specified scan method.
+    ///
+    /// If `src_ip` is unspecified, each host is probed from the source of its route, see `Routes`.
+    /// Sockets of hosts without a route fail. If `src_port` is `0`, each probe is sent from a
+    /// random ephemeral port.
+    ///
+    /// At most `concurrency` sockets are scanned at the same time, each in a blocking thread.
+    /// This prevents large scans from exhausting threads and file descriptors. Below that cap,
+    /// the number of sockets in flight adapts to timeouts, see `CongestionWindow`.
+    ///
+    /// `timeout` applies until a host responds. Later probes to that host wait for a multiple of
+    /// its smoothed round-trip time instead, see `RttEstimator`.
+    ///
+    /// If a `rate` is given, at most that many sockets are probed per second.
+    /// Otherwise probes are sent as fast as the concurrency allows. A random delay of the
+    /// options is waited for on top, see `ProbeOptions::delay`.
+    ///
+    /// Once `stop` is set, no further sockets are scanned. The sockets in flight are still
+    /// awaited, which takes at most the timeout and its retries.
+    ///
+    /// Likewise, no further sockets are scanned once the scan sent as much traffic as the
+    /// `budget` allows. Since the sockets in flight still complete, a scan may exceed the budget
+    /// by their probes. The traffic of the scan is counted on the wire, including e.g. ARP requests.
+    /// The same goes for the deadline of the budget, which the sockets in flight may exceed by
+    /// their timeout and retries.
+    ///
+    /// A socket whose probe failed, or whose thread panicked, doesn't stop the scan. Its result
+    /// holds the error and counts as an error in the statistics, but not as a timeout of the
+    /// congestion window.
+    ///
+    /// `on_result` is called with the result of each socket as soon as it completes, and may
+    /// adjust it before it is kept. If it fails, no further sockets are scanned and the scan
+    /// fails with its error once the sockets in flight are done.
+    ///
+    /// Returns the scan results of all probed sockets, sorted by IP address and port,
+    /// and statistics about the probes, responses and timing of the scan.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn scan(
+        interface: Interface,
+        method: ScanMethod,
+        src_ip: IpAddr,
+        src_port: u16,
+        ip_addresses: &[IpAddr],
+        port_numbers: &[u16],
+        options: ProbeOptions,
+        concurrency: usize,
+        rate: Option<u32>,
+        timeout: Duration,
+        budget: Budget,
+        stop: &AtomicBool,
+        mut on_result: impl FnMut(&mut ScanResult) -> Result<()>,
+    ) -> Result<(Vec<ScanResult>, ScanStats)> {
+        let total_sockets = ip_addresses.len() * port_numbers.len();
+        let start_time = Instant::now();
+        let start_traffic = DatalinkLayer::traffic();
+        let start_control_messages = ControlMessage::captured();
+        let mut failure = None;
+        let should_stop = |failure: &Option<anyhow::Error>| {
+            failure.is_some()
+                || stop.load(Ordering::Relaxed)
+                || budget.is_exhausted(DatalinkLayer::traffic().since(start_traffic))
+        };
+
+        let mut sockets = SocketIterator::new(ip_addresses, port_numbers);
+        let routes = Routes::resolve(interface, src_ip, ip_addresses);
+
+        info!("{:?} scan: {}.", method, method.interpretation());
+
+        let spawn_scan = |socket: SocketAddr, timeout: Duration| {
+            let src_port = match src_port {
+                0 => Tcp::random_source_port(options.source_port_range),
+                port => port,
+            };
+
+            // Run the scan for each socket in a separate blocking thread.
+            // This is a limitation introduced by the pnet crate, which does not support async.
+            let route = routes.source(socket.ip());
+            let options = options.clone();
+            let probe = tokio::task::spawn_blocking(move || match route {
+                Ok((src_ip, interface)) => method.probe(
+                    interface,
+                    src_ip,
+                    src_port,
+                    socket.ip(),
+                    socket.port(),
+                    options,
+                    timeout,
+                ),
+                Err(e) => {
+                    ScanResult::failed(socket.ip(), socket.port(), method.protocol(), src_port, &e)
+                }
+            });
+
+            // A thread that panicked still accounts for its socket, which then failed.
+            probe.map(move |scan| {
+                scan.unwrap_or_else(|e| {
+                    let e = anyhow::Error::from(e);
+                    ScanResult::failed(socket.ip(), socket.port(), method.protocol(), src_port, &e)
+                })
+            })
+        };
+
+        // Set of futures that complete in any order.
+        // See: https://github.com/tokio-rs/tokio/issues/5564 -> faster than JoinSet.
+        let mut futures = FuturesUnordered::new();
+        let mut rate_limiter = RateLimiter::new(rate).with_delay(options.delay);
+        let mut window = CongestionWindow::new(concurrency);
+        let mut estimators: HashMap<IpAddr, RttEstimator> = HashMap::new();
+
+        // Check before taking a socket, so that a stopped scan leaves it over instead of losing it.
+        while futures.len() < window.size() && !should_stop(&failure) {
+            let Some(socket) = sockets.next() else {
+                break;
+            };
+            rate_limiter.wait().await;
+            futures.push(spawn_scan(socket, timeout));
+        }
+
+        let mut scanned_sockets = Vec::with_capacity(total_sockets);
+        let mut unreachable = 0;
+        let mut responses = 0;
+
+        // The results of all threads arrive here one after another, so no locking is needed.
+        let mut stats = ScanStats::default();
+
+        while let Some(mut scan) = futures.next().await {
+            if let Err(e) = on_result(&mut scan) {
+                failure.get_or_insert(e);
+            }
+            match scan.error {
+                // Failed sockets are reported, but tell nothing about congestion or timing.
+                Some(_) => {
+                    stats.record_error();
+                    scanned_sockets.push(scan);
+                    unreachable += 1;
+                }
+                None => {
+                    match scan.rtt {
+                        Some(rtt) => {
+                            window.on_response();
+                            estimators
+                                .entry(scan.ip)
+                                .or_insert_with(|| RttEstimator::new(timeout))
+                                .record(rtt);
+                        }
+                        None => window.on_timeout(),
+                    }
+                    stats.record(&scan);
+                    scanned_sockets.push(scan);
+                    responses += 1;
+                }
+            }
+
+            // Start the next scans as soon as the window has room, unless the scan was stopped.
+            while futures.len() < window.size() && !should_stop(&failure) {
+                let Some(socket) = sockets.next() else {
+                    break;
+                };
+                let timeout = estimators
+                    .get(&socket.ip())
+                    .map_or(timeout, RttEstimator::timeout);
+                rate_limiter.wait().await;
+                futures.push(spawn_scan(socket, timeout));
+            }
+        }
+
+        if let Some(e) = failure {
+            return Err(e);
+        }
+
+        if stop.load(Ordering::Relaxed) {
+            warn!(
+                "Scan stopped, {} of {} sockets were scanned.",
+                scanned_sockets.len(),
+                total_sockets
+            );
+        } else if sockets.next().is_some() {
+            // Sockets are only left over if the scan was stopped or ran out of budget.
+            match budget.is_expired() {
+                true => {
+                    stats.deadline_exceeded = true;
+                    warn!(
+                        "Deadline exceeded, {} of {} sockets were scanned.",
+                        scanned_sockets.len(),
+                        total_sockets
+                    );
+                }
+                false => {
+                    stats.budget_exhausted = true;
+                    warn!(
+                        "Budget exhausted, {} of {} sockets were scanned.",
+                        scanned_sockets.len(),
+                        total_sockets
+                    );
+                }
+            }
+        }
+
+        stats.traffic = DatalinkLayer::traffic().since(start_traffic);
+        stats.control_messages = ControlMessage::captured().saturating_sub(start_control_messages);
+        stats.window = window.size();
+        stats.timeout = estimators.values().map(RttEstimator::timeout).max();
+        stats.elapsed = start_time.elapsed();
+
+        info!("{} sockets have been scanned.", responses);
+
+        info!(
+            "{} of {} sockets ran on an error.",
+            unreachable, total_sockets
+        );
+
+        scanned_sockets.sort_by_key(|scan| (scan.ip, scan.port));
+
+        Ok((scanned_sockets, stats))
+    }
+
+    /// Grabs the banners of open ports in the scan results, see `Tcp::grab_banner`.
+    ///
+    /// Like scans, at most `concurrency` connections are open at the same time.
+    /// Ports whose banner could not be grabbed keep `None` as banner.
+    pub async fn grab_banners(
+        mut results: Vec<ScanResult>,
+        probe: Option<Vec<u8>>,
+        concurrency: usize,
+        timeout: Duration,
+    ) -> Vec<ScanResult> {
+        let (indices, sockets): (Vec<_>, Vec<_>) = results
+            .iter()
+            .enumerate()
+            .filter(|(_, scan)| scan.state == PortState::Open)
+            .map(|(index, scan)| (index, SocketAddr::new(scan.ip, scan.port)))
+            .unzip();
+
+        let grabs = grab_bounded(&sockets, probe, concurrency, timeout).await;
+
+        let mut banners = 0;
+        for (index, grab) in indices.into_iter().zip(grabs) {
+            if let Ok(banner) = grab {
+                results[index].banner = Some(banner);
+                banners += 1;
+            }
+        }
+
+        info!("{} banners have been grabbed.", banners);
+
+        results
+    }
+
+    /// Profiles a host in one go: scans the configured ports with `run`, then grabs the banners
+    /// of the open ones with the probe set with `banner_probe`.
+    ///
+    /// The scan is a SYN scan unless configured otherwise, and honors the stop flag, the budget,
+    /// the rate, the excludes and the source ports like any other. Both steps keep at most
+    /// `concurrency` sockets busy at the same time, so the grabs start once the scan is done
+    /// rather than all at once, see `grab_profile_banners`.
+    ///
+    /// May fail like `run`, e.g. without privileges for raw sockets.
+    pub async fn profile(&self, ip: IpAddr) -> Result<HostProfile> {
+        let (results, stats) = self.run(&[ip]).await?;
+
+        let open_ports = results
+            .iter()
+            .filter(|scan| scan.state == PortState::Open)
+            .map(|scan| scan.port)
+            .collect::<Vec<_>>();
+        let ports = Scanner::grab_profile_banners(
+            ip,
+            &open_ports,
+            self.banner_probe.clone(),
+            self.concurrency,
+            self.timeout,
+        )
+        .await;
+
+        Ok(HostProfile { ip, ports, stats })
+    }
+
+    /// Grabs the banners of the open ports of a host for its profile, see `Tcp::grab_banner`.
+    ///
+    /// Like `grab_banners`, at most `concurrency` connections are open at the same time. Unlike it,
+    /// every port gets an outcome, so ports that closed since the scan show up as closed.
+    ///
+    /// Returns the ports and their banners, sorted by port.
+    pub async fn grab_profile_banners(
+        ip: IpAddr,
+        open_ports: &[u16],
+        probe: Option<Vec<u8>>,
+        concurrency: usize,
+        timeout: Duration,
+    ) -> Vec<(u16, Banner)> {
+        let sockets = open_ports
+            .iter()
+            .map(|port| SocketAddr::new(ip, *port))
+            .collect::<Vec<_>>();
+        let grabs = grab_bounded(&sockets, probe, concurrency, timeout).await;
+
+        let mut banners = open_ports
+            .iter()
+            .copied()
+            .zip(grabs.into_iter().map(Banner::of_grab))
+            .collect::<Vec<_>>();
+        banners.sort_by_key(|(port, _)| *port);
+
+        for (port, _) in banners
+            .iter()
+            .filter(|(_, banner)| *banner == Banner::Closed)
+        {
+            warn!("Port {} of {} closed since it was scanned.", port, ip);
+        }
+
+        banners
+    }
+
+    /// Identifies the services on open TCP ports in the scan results, see `detect_version`.
+    ///
+    /// Like banners, at most `concurrency` ports are probed at the same time.
+    ///
+    /// Returns IP addresses, ports and services of ports that were identified.
+    pub async fn detect_versions(
+        results: &[ScanResult],
+        concurrency: usize,
+        timeout: Duration,
+    ) -> Vec<(IpAddr, u16, ServiceInfo)> {
+        let mut open_ports = results
+            .iter()
+            .filter(|scan| scan.protocol == Protocol::Tcp && scan.state == PortState::Open)
+            .map(|scan| (scan.ip, scan.port))
+            .collect::<Vec<_>>()
+            .into_iter();
+
+        let spawn_detect = |(dest_ip, dest_port): (IpAddr, u16)| {
+            tokio::task::spawn_blocking(move || {
+                detect_version(dest_ip, dest_port, timeout)
+                    .map(|service| service.map(|service| (dest_ip, dest_port, service)))
+            })
+        };
+
+        let mut futures = FuturesUnordered::new();
+
+        open_ports
+            .by_ref()
+            .take(concurrency.max(1))
+            .for_each(|port| futures.push(spawn_detect(port)));
+
+        let mut services = Vec::new();
+
+        while let Some(result) = futures.next().await {
+            if let Some(port) = open_ports.next() {
+                futures.push(spawn_detect(port));
+            }
+
+            if let Ok(Ok(Some(service))) = result {
+                services.push(service);
+            }
+        }
+
+        services.sort_by_key(|(ip, port, _)| (*ip, *port));
+
+        info!("{} services have been identified.", services.len());
+
+        services
+    }
+
+    /// Classifies the IP identifications of hosts with open or closed TCP ports in the scan
+    /// results, see `analyze_ip_id`.
+    ///
+    /// Each host is sampled with `IP_ID_SAMPLES` probes to one of these ports, which answers
+    /// with a RST flag either way. Hosts are sampled concurrently, their probes one at a time.
+    ///
+    /// Returns IP addresses and patterns of hosts that responded.
+    pub async fn analyze_ip_ids(
+        interface: Interface,
+        src_ip: IpAddr,
+        src_port: u16,
+        results: &[ScanResult],
+        options: ProbeOptions,
+        timeout: Duration,
+    ) -> Vec<(IpAddr, IpIdPattern)> {
+        let mut targets = results
+            .iter()
+            .filter(|scan| scan.protocol == Protocol::Tcp)
+            .filter(|scan| matches!(scan.state, PortState::Open | PortState::Closed))
+            .map(|scan| SocketAddr::new(scan.ip, scan.port))
+            .collect::<Vec<_>>();
+        targets.dedup_by_key(|target| target.ip());
+
+        let routes = Routes::resolve(
+            interface,
+            src_ip,
+            &targets.iter().map(SocketAddr::ip).collect::<Vec<_>>(),
+        );
+        let mut futures = FuturesUnordered::new();
+
+        for target in targets {
+            let src_port = match src_port {
+                0 => Tcp::random_source_port(options.source_port_range),
+                port => port,
+            };
+
+            let route = routes.source(target.ip());
+            let options = options.clone();
+            futures.push(tokio::task::spawn_blocking(move || {
+                route
+                    .and_then(|(src_ip, interface)| {
+                        sample_ip_ids(
+                            interface,
+                            src_ip,
+                            src_port,
+                            target,
+                            IP_ID_SAMPLES,
+                            options,
+                            timeout,
+                        )
+                    })
+                    .map(|samples| (target.ip(), samples))
+            }));
+        }
+
+        let mut patterns = Vec::with_capacity(futures.len());
+
+        while let Some(result) = futures.next().await {
+            if let Ok(Ok((dest_ip, samples))) = result {
+                if samples.is_empty() {
+                    continue;
+                }
+                let pattern = analyze_ip_id(&samples);
+                if pattern == IpIdPattern::PerHost {
+                    info!("{} answers with several IP identification counters, it may be a load balancer.", dest_ip);
+                }
+                patterns.push((dest_ip, pattern));
+            }
+        }
+
+        info!(
+            "The IP identifications of {} hosts have been classified.",
+            patterns.len()
+        );
+
+        patterns.sort_by_key(|(ip, _)| *ip);
+
+        patterns
+    }
+
+    /// Guesses the operating systems of hosts with open TCP ports in the scan results, see `detect_os`.
+    ///
+    /// If `src_port` is `0`, each host is probed from a random ephemeral port.
+    ///
+    /// Returns IP addresses and guesses of hosts that responded.
+    pub async fn detect_os(
+        interface: Interface,
+        src_ip: IpAddr,
+        src_port: u16,
+        results: &[ScanResult],
+        options: ProbeOptions,
+        timeout: Duration,
+    ) -> Vec<(IpAddr, OsGuess)> {
+        let port_with_state = |ip: IpAddr, state: PortState| {
+            results
+                .iter()
+                .find(|scan| scan.ip == ip && scan.protocol == Protocol::Tcp && scan.state == state)
+                .map(|scan| scan.port)
+        };
+
+        let mut hosts = results.iter().map(|scan| scan.ip).collect::<Vec<_>>();
+        hosts.dedup();
+        let routes = Routes::resolve(interface, src_ip, &hosts);
+
+        let mut futures = FuturesUnordered::new();
+
+        for dest_ip in hosts {
+            let Some(open_port) = port_with_state(dest_ip, PortState::Open) else {
+                continue;
+            };
+            let closed_port = port_with_state(dest_ip, PortState::Closed);
+
+            let src_port = match src_port {
+                0 => Tcp::random_source_port(options.source_port_range),
+                port => port,
+            };
+
+            let route = routes.source(dest_ip);
+            let options = options.clone();
+            futures.push(tokio::task::spawn_blocking(move || {
+                route
+                    .and_then(|(src_ip, interface)| {
+                        detect_os(
+                            interface,
+                            src_ip,
+                            src_port,
+                            dest_ip,
+                            open_port,
+                            closed_port,
+                            options,
+                            timeout,
+                        )
+                    })
+                    .map(|guess| (dest_ip, guess))
+            }));
+        }
+
+        let mut guesses = Vec::with_capacity(futures.len());
+
+        while let Some(result) = futures.next().await {
+            if let Ok(Ok((dest_ip, Some(guess)))) = result {
+                guesses.push((dest_ip, guess));
+            }
+        }
+
+        info!(
+            "The operating systems of {} hosts have been guessed.",
+            guesses.len()
+        );
+
+        guesses.sort_by_key(|(ip, _)| *ip);
+
+        guesses
+    }
+
+    /// Sends ICMP requests of the given kind to the given IP addresses.
+    ///
+    /// Returns IP addresses, scan results, and round-trip times of hosts that responded.
+    pub async fn ping(
+        interface: Interface,
+        src_ip: IpAddr,
+        ip_addresses: Vec<IpAddr>,
+        probe: IcmpProbe,
+        options: ProbeOptions,
+        timeout: Duration,
+    ) -> Vec<(IpAddr, PortState, Duration)> {
+        let total_hosts = ip_addresses.len();
+
+        let mut hosts = Vec::with_capacity(total_hosts);
+        let mut unreachable = 0;
+        let mut responses = 0;
+
+        let routes = Routes::resolve(interface, src_ip, &ip_addresses);
+        let mut futures = FuturesUnordered::new();
+
+        ip_addresses.into_iter().for_each(|dest_ip| {
+            let route = routes.source(dest_ip);
+            let options = options.clone();
+            futures.push(tokio::task::spawn_blocking(move || {
+                route
+                    .and_then(|(src_ip, interface)| {
+                        icmp_scan(interface, src_ip, dest_ip, probe, options, timeout)
+                    })
+                    .map(|scan| (dest_ip, scan))
+            }));
+        });
+
+        while let Some(result) = futures.next().await {
+            match result {
+                Ok(Ok((dest_ip, (scan, rtt)))) => {
+                    hosts.push((dest_ip, scan, rtt));
+                    responses += 1;
+                }
+                _ => {
+                    unreachable += 1;
+                }
+            }
+        }
+
+        info!("{} hosts have been sent an ICMP echo request.", responses);
+
+        info!(
+            "{} of {} IP addresses unreachable.",
+            unreachable, total_hosts
+        );
+
+        hosts
+    }
+
+    /// Sends TCP ping probes to the given ports of the given IP addresses, see `tcp_ping`.
+    ///
+    /// If `src_port` is `0`, each host is probed from a random ephemeral port.
+    ///
+    /// Returns IP addresses, scan results, and round-trip times of hosts that could be probed.
+    pub async fn tcp_ping(
+        interface: Interface,
+        src_ip: IpAddr,
+        src_port: u16,
+        ip_addresses: Vec<IpAddr>,
+        ports: &[u16],
+        options: ProbeOptions,
+        timeout: Duration,
+    ) -> Vec<(IpAddr, PortState, Duration)> {
+        let total_hosts = ip_addresses.len();
+
+        let mut hosts = Vec::with_capacity(total_hosts);
+        let mut unreachable = 0;
+        let mut responses = 0;
+
+        let routes = Routes::resolve(interface, src_ip, &ip_addresses);
+        let mut futures = FuturesUnordered::new();
+
+        ip_addresses.into_iter().for_each(|dest_ip| {
+            let src_port = match src_port {
+                0 => Tcp::random_source_port(options.source_port_range),
+                port => port,
+            };
+            let ports = ports.to_vec();
+            let route = routes.source(dest_ip);
+            let options = options.clone();
+            futures.push(tokio::task::spawn_blocking(move || {
+                route
+                    .and_then(|(src_ip, interface)| {
+                        tcp_ping(
+                            interface, src_ip, src_port, dest_ip, &ports, options, timeout,
+                        )
+                    })
+                    .map(|scan| (dest_ip, scan))
+            }));
+        });
+
+        while let Some(result) = futures.next().await {
+            match result {
+                Ok(Ok((dest_ip, (scan, rtt)))) => {
+                    hosts.push((dest_ip, scan, rtt));
+                    responses += 1;
+                }
+                _ => {
+                    unreachable += 1;
+                }
+            }
+        }
 
-#[derive(Debug)]
-pub enum ScanMethod {
-    TcpSyn,
-    TcpConnect,
-    TcpAck,
-    TcpFin,
-    TcpXmas,
-    TcpNull,
-    TcpWindow,
-    TcpMaimon,
-    Udp,
-}
+        info!("{} hosts have been sent a TCP ping.", responses);
 
-#[derive(Debug)]
-pub enum ScanResult {
-    Open,
-    Closed,
-    Filtered,
-    Unfiltered,
-    OpenOrFiltered,
-    Up,
-    Down,
-}
+        info!(
+            "{} of {} IP addresses unreachable.",
+            unreachable, total_hosts
+        );
 
-pub struct Scanner;
+        hosts
+    }
 
-impl Scanner {
-    /// Scans the given IP addresses and port numbers with the specified scan method.
+    /// Maps the paths to the given IP addresses, see `traceroute`.
     ///
-    /// Returns IP addresses, scan results, and round-trip times of hosts that responded.
-    pub async fn scan(
+    /// Returns the IP addresses and hops of hosts that could be traced, one row per hop.
+    pub async fn traceroute(
         interface: Interface,
-        method: ScanMethod,
         src_ip: IpAddr,
-        src_port: u16,
-        ip_addresses: &[IpAddr],
-        port_numbers: &[u16],
+        ip_addresses: Vec<IpAddr>,
+        max_hops: u8,
+        options: ProbeOptions,
         timeout: Duration,
-    ) -> Vec<(SocketAddr, ScanResult, Duration)> {
-        let total_sockets = ip_addresses.len() * port_numbers.len();
-
-        let sockets = SocketIterator::new(ip_addresses, port_numbers);
+    ) -> Vec<(IpAddr, Hop)> {
+        let total_hosts = ip_addresses.len();
 
-        let scan_method = match method {
-            ScanMethod::TcpSyn => tcp_syn_scan,
-            ScanMethod::TcpConnect => tcp_connect_scan,
-            ScanMethod::TcpAck => tcp_ack_scan,
-            ScanMethod::TcpFin => tcp_fin_scan,
-            ScanMethod::TcpXmas => tcp_xmas_scan,
-            ScanMethod::TcpNull => tcp_null_scan,
-            ScanMethod::TcpWindow => tcp_window_scan,
-            ScanMethod::TcpMaimon => tcp_maimon_scan,
-            ScanMethod::Udp => udp_scan,
-        };
+        let mut routes = Vec::with_capacity(total_hosts);
+        let mut unreachable = 0;
 
-        // Set of futures that complete in any order.
-        // See: https://github.com/tokio-rs/tokio/issues/5564 -> faster than JoinSet.
+        let sources = Routes::resolve(interface, src_ip, &ip_addresses);
         let mut futures = FuturesUnordered::new();
 
-        sockets.for_each(|socket| {
-            // Run the scan for each socket in a separate blocking thread.
-            // This is a limitation introduced by the pnet crate, which does not support async.
+        ip_addresses.into_iter().for_each(|dest_ip| {
+            let route = sources.source(dest_ip);
+            let options = options.clone();
             futures.push(tokio::task::spawn_blocking(move || {
-                scan_method(
-                    interface,
-                    src_ip,
-                    src_port,
-                    socket.ip(),
-                    socket.port(),
-                    timeout,
-                )
-                .map(|scan| (socket, scan))
+                route
+                    .and_then(|(src_ip, interface)| {
+                        traceroute(interface, src_ip, dest_ip, max_hops, options, timeout)
+                    })
+                    .map(|hops| (dest_ip, hops))
             }));
         });
 
-        let mut scanned_sockets = Vec::with_capacity(total_sockets);
-        let mut unreachable = 0;
-        let mut responses = 0;
-
         while let Some(result) = futures.next().await {
             match result {
-                Ok(Ok((socket, (scan, rtt)))) => {
-                    scanned_sockets.push((socket, scan, rtt));
-                    responses += 1;
+                Ok(Ok((dest_ip, hops))) => {
+                    routes.extend(hops.into_iter().map(|hop| (dest_ip, hop)));
                 }
                 _ => {
                     unreachable += 1;
@@ -110,43 +1389,62 @@ impl Scanner {
             }
         }
 
-        info!("{} sockets have been scanned.", responses);
-
         info!(
-            "{} of {} sockets ran on an error.",
-            unreachable, total_sockets
+            "{} of {} IP addresses could not be traced.",
+            unreachable, total_hosts
         );
 
-        scanned_sockets
+        routes.sort_by_key(|(ip, hop)| (*ip, hop.ttl));
+
+        routes
     }
 
-    /// Sends ICMP echo requests to the given IP addresses.
+    /// Scans the local network with ARP requests.
     ///
-    /// Returns IP addresses, scan results, and round-trip times of hosts that responded.
-    pub async fn ping(
+    /// Only hosts on the subnet of the interface can answer, so other IP addresses are skipped.
+    ///
+    /// Returns IP addresses, MAC addresses, and round-trip times of hosts that responded.
+    pub async fn arp(
         interface: Interface,
         src_ip: IpAddr,
         ip_addresses: Vec<IpAddr>,
         timeout: Duration,
-    ) -> Vec<(IpAddr, ScanResult, Duration)> {
+    ) -> Vec<(IpAddr, MacAddr, Duration)> {
         let total_hosts = ip_addresses.len();
 
-        let mut hosts = Vec::with_capacity(total_hosts);
-        let mut unreachable = 0;
+        let (local_addresses, remote_addresses): (Vec<_>, Vec<_>) =
+            ip_addresses.into_iter().partition(|ip| match ip {
+                IpAddr::V4(ip) => interface.is_on_link(*ip),
+                IpAddr::V6(_) => false,
+            });
+
+        if !remote_addresses.is_empty() {
+            info!(
+                "{} IP addresses are not in your local network and were skipped.",
+                remote_addresses.len()
+            );
+        }
+
+        let mut hosts = Vec::with_capacity(local_addresses.len());
+        let mut unreachable = remote_addresses.len();
         let mut responses = 0;
 
+        let routes = Routes::resolve(interface, src_ip, &local_addresses);
         let mut futures = FuturesUnordered::new();
 
-        ip_addresses.into_iter().for_each(|dest_ip| {
+        local_addresses.into_iter().for_each(|dest_ip| {
+            let route = routes.source(dest_ip);
             futures.push(tokio::task::spawn_blocking(move || {
-                icmp_scan(interface, src_ip, dest_ip, timeout).map(|scan| (dest_ip, scan))
+                route
+                    .and_then(|(src_ip, interface)| arp_scan(interface, src_ip, dest_ip, timeout))
+                    .map(|scan| (dest_ip, scan))
             }));
         });
 
         while let Some(result) = futures.next().await {
             match result {
-                Ok(Ok((dest_ip, (scan, rtt)))) => {
-                    hosts.push((dest_ip, scan, rtt));
+                Ok(Ok((dest_ip, (Some(mac), rtt)))) => {
+                    hosts.push((dest_ip, mac, rtt));
                     responses += 1;
                 }
                 _ => {
@@ -155,8 +1453,10 @@ impl Scanner {
             }
         }
 
-        info!("{} hosts have been sent an ICMP echo request.", responses);
-
+        info!(
+            "{} hosts in your local network answered with an ARP response.",
+            responses
+        );
         info!(
             "{} of {} IP addresses unreachable.",
             unreachable, total_hosts
@@ -165,26 +1465,40 @@ impl Scanner {
         hosts
     }
 
-    /// Scans the local network with ARP requests.
+    /// Scans the local link with IPv6 neighbor solicitations, the IPv6 counterpart to `Scanner::arp`.
+    ///
+    /// Only link-local IPv6 addresses are on the link for sure, so other IP addresses are skipped.
     ///
     /// Returns IP addresses, MAC addresses, and round-trip times of hosts that responded.
-    pub async fn arp(
+    pub async fn ndp(
         interface: Interface,
-        src_ip: IpAddr,
         ip_addresses: Vec<IpAddr>,
         timeout: Duration,
     ) -> Vec<(IpAddr, MacAddr, Duration)> {
         let total_hosts = ip_addresses.len();
 
-        let mut hosts = Vec::with_capacity(total_hosts);
-        let mut unreachable = 0;
+        let (local_addresses, remote_addresses): (Vec<_>, Vec<_>) =
+            ip_addresses.into_iter().partition(|ip| match ip {
+                IpAddr::V4(_) => false,
+                IpAddr::V6(ip) => Ndp::is_link_local(*ip),
+            });
+
+        if !remote_addresses.is_empty() {
+            info!(
+                "{} IP addresses are not IPv6 link-local addresses and were skipped.",
+                remote_addresses.len()
+            );
+        }
+
+        let mut hosts = Vec::with_capacity(local_addresses.len());
+        let mut unreachable = remote_addresses.len();
         let mut responses = 0;
 
         let mut futures = FuturesUnordered::new();
 
-        ip_addresses.into_iter().for_each(|dest_ip| {
+        local_addresses.into_iter().for_each(|dest_ip| {
             futures.push(tokio::task::spawn_blocking(move || {
-                arp_scan(interface, src_ip, dest_ip, timeout).map(|scan| (dest_ip, scan))
+                ndp_scan(interface, dest_ip, timeout).map(|scan| (dest_ip, scan))
             }));
         });
 
@@ -201,7 +1515,7 @@ impl Scanner {
         }
 
         info!(
-            "{} hosts in your local network answered with an ARP response.",
+            "{} hosts on your local link answered with a neighbor advertisement.",
             responses
         );
         info!(
@@ -212,3 +1526,800 @@ impl Scanner {
         hosts
     }
 }
+
+/// Sends results to the channel set with `Scanner::stream`, each socket once.
+///
+/// If holding silent ports, their results are only sent on `release`, since they may still be
+/// replaced by those of a retry, see `RstLimitDetector`.
+struct ResultStream<'a> {
+    results: Option<&'a UnboundedSender<ScanResult>>,
+    hold_silent: bool,
+    held: Vec<(IpAddr, u16)>,
+}
+
+impl<'a> ResultStream<'a> {
+    fn new(results: Option<&'a UnboundedSender<ScanResult>>, hold_silent: bool) -> Self {
+        ResultStream {
+            results,
+            hold_silent,
+            held: Vec::new(),
+        }
+    }
+
+    /// Sends a result, unless it is held back.
+    ///
+    /// May fail if the receiver was dropped.
+    fn send(&mut self, scan: &ScanResult) -> Result<()> {
+        let Some(results) = self.results else {
+            return Ok(());
+        };
+        if self.hold_silent && scan.reason == Reason::NoResponse {
+            self.held.push((scan.ip, scan.port));
+            return Ok(());
+        }
+        results
+            .send(scan.clone())
+            .map_err(|_| ChannelError::SendError.into())
+    }
+
+    /// Sends the held back sockets with their final results, which are sorted by IP address and port.
+    ///
+    /// May fail if the receiver was dropped.
+    fn release(self, final_results: &[ScanResult]) -> Result<()> {
+        let Some(results) = self.results else {
+            return Ok(());
+        };
+        for socket in self.held {
+            if let Ok(index) =
+                final_results.binary_search_by_key(&socket, |scan| (scan.ip, scan.port))
+            {
+                results
+                    .send(final_results[index].clone())
+                    .map_err(|_| ChannelError::SendError)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Marks silent ports of hosts that don't answer probes to closed ports as filtered.
+///
+/// Silence only hints at an open port if closed ports of the same host answer.
+fn apply_calibration(results: &mut [ScanResult], responsive_hosts: &[IpAddr]) {
+    for scan in results {
+        if scan.state == PortState::OpenOrFiltered && !responsive_hosts.contains(&scan.ip) {
+            scan.state = PortState::Filtered;
+        }
+    }
+}
+
+/// Marks sockets that didn't respond to probes from a spoofed source as unknown.
+///
+/// Their responses were sent to the spoofed address, so silence tells nothing about the port.
+fn mark_spoofed(results: &mut [ScanResult]) {
+    for scan in results
+        .iter_mut()
+        .filter(|scan| scan.rtt.is_none() && scan.error.is_none())
+    {
+        scan.state = PortState::Unknown;
+        scan.reason = Reason::SpoofedSource;
+    }
+}
+
+/// Grabs the banners of the given sockets, see `Tcp::grab_banner`.
+///
+/// Each grab blocks a thread until the service talks or the timeout passes, so at most
+/// `concurrency` connections are open at the same time. The next grab starts as soon as one is done.
+///
+/// Returns the outcome of each socket in the given order. Grabs whose thread panicked fail.
+async fn grab_bounded(
+    sockets: &[SocketAddr],
+    probe: Option<Vec<u8>>,
+    concurrency: usize,
+    timeout: Duration,
+) -> Vec<Result<String>> {
+    let mut pending = sockets.iter().copied().enumerate();
+
+    let spawn_grab = |(index, socket): (usize, SocketAddr)| {
+        let probe = probe.clone();
+        tokio::task::spawn_blocking(move || {
+            Tcp::grab_banner(socket.ip(), socket.port(), timeout, probe.as_deref())
+        })
+        .map(move |grab| (index, grab))
+    };
+
+    let mut futures = FuturesUnordered::new();
+
+    pending
+        .by_ref()
+        .take(concurrency.max(1))
+        .for_each(|socket| futures.push(spawn_grab(socket)));
+
+    let mut grabs = Vec::with_capacity(sockets.len());
+
+    while let Some((index, grab)) = futures.next().await {
+        if let Some(socket) = pending.next() {
+            futures.push(spawn_grab(socket));
+        }
+
+        grabs.push((
+            index,
+            grab.map_err(anyhow::Error::from).and_then(|grab| grab),
+        ));
+    }
+
+    grabs.sort_by_key(|(index, _)| *index);
+    grabs.into_iter().map(|(_, grab)| grab).collect()
+}
+
+/// Looks up the host names of the reports in blocking threads, so that slow lookups overlap.
+///
+/// Warns and leaves the host names out if the system has no name server, see `system_nameserver`.
+async fn resolve_hostnames(reports: &mut [HostReport]) {
+    let Some(nameserver) = system_nameserver() else {
+        warn!("Found no name server in /etc/resolv.conf, host names are not looked up.");
+        return;
+    };
+
+    let lookups = reports
+        .iter()
+        .map(|report| {
+            let host = report.host;
+            tokio::task::spawn_blocking(move || reverse_lookup(nameserver, host))
+        })
+        .collect::<Vec<_>>();
+
+    for (report, lookup) in reports.iter_mut().zip(lookups) {
+        report.hostname = lookup.await.ok().flatten();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::{osi_layers::Exchange, probe::has_consistent_ip_header};
+    use pnet::packet::{
+        ethernet::{EtherTypes, EthernetPacket},
+        tcp::TcpFlags,
+        Packet,
+    };
+    use std::net::{Ipv4Addr, TcpListener};
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_excluded_targets_are_not_probed() -> Result<()> {
+        let interface = Interface::new()?;
+        let hosts = [[192, 0, 2, 1], [192, 0, 2, 2], [198, 51, 100, 7]].map(IpAddr::from);
+
+        // A dry run builds a probe for every socket that is scanned, but sends none of them.
+        let scanner = Scanner::new(interface)
+            .options(ProbeOptions {
+                dry_run: true,
+                ..Default::default()
+            })
+            .ports(vec![22, 80, 443])
+            .exclude_hosts(vec![
+                Ipv4Net::from(Ipv4Addr::new(192, 0, 2, 2)),
+                Ipv4Net::new(Ipv4Addr::new(198, 51, 100, 0), 24),
+            ])
+            .exclude_ports(vec![22]);
+
+        let (reports, stats) = scanner.scan_targets(&hosts).await?;
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].host, hosts[0]);
+        let ports = reports[0]
+            .results
+            .iter()
+            .map(|scan| scan.port)
+            .collect::<Vec<_>>();
+        assert_eq!(ports, vec![80, 443]);
+        assert_eq!(stats.responses + stats.timeouts, 2);
+
+        // Nothing is left to scan if all ports are excluded.
+        let scanner = scanner.exclude_ports(vec![22, 80, 443]);
+        assert!(scanner.run(&hosts).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_probes_do_not_abort_the_scan() -> Result<()> {
+        let interface = Interface::new()?;
+        let hosts = [
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            "2001:db8::1".parse()?,
+        ];
+
+        // UDP probes from an IPv4 source can't be built for the IPv6 host.
+        let scanner = Scanner::new(interface)
+            .scan_type(ScanMethod::Udp)
+            .options(ProbeOptions {
+                dry_run: true,
+                ..Default::default()
+            })
+            .ports(vec![53, 123]);
+
+        let (results, stats) = scanner.run(&hosts).await?;
+        assert_eq!(results.len(), 4);
+        assert_eq!(stats.errors, 2);
+        for scan in &results {
+            assert_eq!(scan.state, PortState::Unknown);
+            match scan.ip {
+                IpAddr::V4(_) => assert_eq!(
+                    (scan.reason, scan.error.as_deref()),
+                    (Reason::NotSent, None)
+                ),
+                IpAddr::V6(_) => {
+                    assert_eq!(scan.reason, Reason::BuildFailed);
+                    assert!(scan.error.is_some());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_results() -> Result<()> {
+        let interface = Interface::new()?;
+        let hosts = [IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))];
+        let options = ProbeOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let scanner = Scanner::new(interface)
+            .scan_type(ScanMethod::Udp)
+            .options(options)
+            .ports((1..=20).collect());
+
+        // Every result is streamed as well as returned.
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let (results, _) = scanner.clone().stream(sender).run(&hosts).await?;
+        let mut streamed = Vec::new();
+        while let Some(scan) = receiver.recv().await {
+            streamed.push(scan);
+        }
+        streamed.sort_by_key(|scan| scan.port);
+        assert_eq!(streamed.len(), 20);
+        assert!(streamed
+            .iter()
+            .zip(&results)
+            .all(|(streamed, returned)| streamed.port == returned.port
+                && streamed.state == returned.state));
+
+        // Nobody listens anymore, so the scan fails.
+        let (sender, receiver) = mpsc::unbounded_channel();
+        drop(receiver);
+        let error = scanner.stream(sender).run(&hosts).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ChannelError>(),
+            Some(ChannelError::SendError)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_holds_back_silent_ports() {
+        let host = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1));
+        let scan = |port, reason| {
+            ScanResult::new(
+                host,
+                port,
+                Protocol::Tcp,
+                0,
+                PortState::Filtered,
+                reason,
+                None,
+                1,
+            )
+        };
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut stream = ResultStream::new(Some(&sender), true);
+
+        // Answered ports are final right away, silent ones may still be probed again.
+        stream.send(&scan(1, Reason::Rst)).unwrap();
+        stream.send(&scan(2, Reason::NoResponse)).unwrap();
+        stream.send(&scan(3, Reason::NoResponse)).unwrap();
+        assert_eq!(receiver.try_recv().unwrap().port, 1);
+        assert!(receiver.try_recv().is_err());
+
+        // The retry replaced the result of port 2, which is streamed once in its final form.
+        let final_results = [
+            scan(1, Reason::Rst),
+            scan(2, Reason::PossiblyRateLimited),
+            scan(3, Reason::NoResponse),
+        ];
+        stream.release(&final_results).unwrap();
+        let released = [receiver.try_recv().unwrap(), receiver.try_recv().unwrap()];
+        assert_eq!(released[0].port, 2);
+        assert_eq!(released[0].reason, Reason::PossiblyRateLimited);
+        assert_eq!(released[1].port, 3);
+        assert!(receiver.try_recv().is_err());
+
+        // Without holding, silent ports are streamed right away.
+        let mut stream = ResultStream::new(Some(&sender), false);
+        stream.send(&scan(2, Reason::NoResponse)).unwrap();
+        assert_eq!(receiver.try_recv().unwrap().port, 2);
+        stream.release(&final_results).unwrap();
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_source_ports_stay_within_range() -> Result<()> {
+        let interface = Interface::new()?;
+        let hosts = [IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))];
+
+        let scanner = Scanner::new(interface)
+            .scan_type(ScanMethod::Udp)
+            .options(ProbeOptions {
+                source_port_range: Some((40000, 40009)),
+                dry_run: true,
+                ..Default::default()
+            })
+            .ports((1..=200).collect());
+
+        let (results, _) = scanner.run(&hosts).await?;
+        assert_eq!(results.len(), 200);
+        assert!(results
+            .iter()
+            .all(|scan| (40000..=40009).contains(&scan.src_port)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_syn_scan_of_loopback_port() -> Result<()> {
+        let interface = Interface::new()?;
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let listener = TcpListener::bind((localhost, 0))?;
+        let open_port = listener.local_addr()?.port();
+        let closed_port = TcpListener::bind((localhost, 0))?.local_addr()?.port();
+
+        // Raw packets to loopback addresses can't be captured, so they fall back to connects.
+        let (results, _) = Scanner::new(interface)
+            .source(localhost)
+            .scan_type(ScanMethod::TcpSyn)
+            .ports(vec![open_port, closed_port])
+            .timeout(Duration::from_secs(1))
+            .run(&[localhost])
+            .await?;
+
+        let state = |port| {
+            results
+                .iter()
+                .find(|scan| scan.port == port)
+                .map(|scan| scan.state)
+        };
+        assert_eq!(state(open_port), Some(PortState::Open));
+        assert_eq!(state(closed_port), Some(PortState::Closed));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_profile() -> Result<()> {
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let listener = TcpListener::bind((localhost, 0))?;
+        let open_port = listener.local_addr()?.port();
+        let closed_port = TcpListener::bind((localhost, 0))?.local_addr()?.port();
+
+        // Greets both the connect of the scan and the one of the banner grab.
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().take(2).flatten() {
+                let _ = std::io::Write::write_all(&mut stream, b"SSH-2.0-Test\r\n");
+            }
+        });
+
+        let scanner = Scanner::new(Interface::new()?)
+            .source(localhost)
+            .ports(vec![open_port, closed_port])
+            .concurrency(1)
+            .timeout(Duration::from_secs(1));
+        let profile = scanner.profile(localhost).await?;
+
+        assert_eq!(profile.ip, localhost);
+        assert_eq!(profile.ports.len(), 1);
+        assert_eq!(profile.ports[0].0, open_port);
+        assert!(
+            matches!(&profile.ports[0].1, Banner::Grabbed(banner) if banner.starts_with("SSH-2.0"))
+        );
+
+        // A port that closed since the scan is reported as closed rather than failing the profile.
+        let banners = Scanner::grab_profile_banners(
+            localhost,
+            &[closed_port],
+            None,
+            1,
+            Duration::from_secs(1),
+        )
+        .await;
+        assert_eq!(banners, vec![(closed_port, Banner::Closed)]);
+
+        // Like any scan, a profile skips excluded ports.
+        let profile = scanner
+            .exclude_ports(vec![open_port])
+            .profile(localhost)
+            .await?;
+        assert!(profile.ports.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scan_with_bounded_concurrency() -> Result<()> {
+        let interface = Interface::new()?;
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        // Open a few ports and pick closed ports by binding and dropping listeners.
+        let listeners = (0..3)
+            .map(|_| TcpListener::bind((localhost, 0)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let closed_ports = (0..3)
+            .map(|_| Ok(TcpListener::bind((localhost, 0))?.local_addr()?.port()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut port_numbers = closed_ports.clone();
+        for listener in &listeners {
+            port_numbers.push(listener.local_addr()?.port());
+        }
+
+        let (results, stats) = Scanner::scan(
+            interface,
+            ScanMethod::TcpConnect,
+            localhost,
+            0,
+            &[localhost],
+            &port_numbers,
+            ProbeOptions::default(),
+            2,
+            None,
+            Duration::from_secs(1),
+            Budget::default(),
+            &AtomicBool::new(false),
+            |_| Ok(()),
+        )
+        .await?;
+
+        // Every socket is scanned even though only two run at the same time.
+        assert_eq!(results.len(), port_numbers.len());
+        assert_eq!(stats.responses, port_numbers.len() as u64);
+        assert_eq!(stats.probes_sent, port_numbers.len() as u64);
+        assert_eq!(stats.errors, 0);
+        assert_eq!(stats.window, 2);
+        assert!(results.windows(2).all(|pair| pair[0].port < pair[1].port));
+
+        for result in results {
+            let expected = match closed_ports.contains(&result.port) {
+                true => PortState::Closed,
+                false => PortState::Open,
+            };
+            assert_eq!(result.state, expected);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scan_stops_with_partial_results() -> Result<()> {
+        let interface = Interface::new()?;
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let port_numbers = (1..=100).collect::<Vec<u16>>();
+
+        // Stop the scan while the rate limit still holds back most of the sockets.
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stop);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            flag.store(true, Ordering::Relaxed);
+        });
+
+        let (results, stats) = Scanner::scan(
+            interface,
+            ScanMethod::TcpConnect,
+            localhost,
+            0,
+            &[localhost],
+            &port_numbers,
+            ProbeOptions::default(),
+            1,
+            Some(20),
+            Duration::from_secs(1),
+            Budget::default(),
+            &stop,
+            |_| Ok(()),
+        )
+        .await?;
+
+        assert!(!results.is_empty());
+        assert!(results.len() < port_numbers.len());
+        assert_eq!(stats.probes_sent, results.len() as u64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_calibration() {
+        let responsive = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1));
+        let silent = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2));
+        let result = |ip, state| {
+            ScanResult::new(
+                ip,
+                80,
+                Protocol::Tcp,
+                50000,
+                state,
+                Reason::NoResponse,
+                None,
+                1,
+            )
+        };
+
+        let mut results = [
+            result(responsive, PortState::OpenOrFiltered),
+            result(silent, PortState::OpenOrFiltered),
+            result(silent, PortState::Closed),
+        ];
+        apply_calibration(&mut results, &[responsive]);
+
+        assert_eq!(results[0].state, PortState::OpenOrFiltered);
+        assert_eq!(results[1].state, PortState::Filtered);
+        assert_eq!(results[2].state, PortState::Closed);
+    }
+
+    #[test]
+    fn test_mark_spoofed() {
+        let host = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1));
+        let result = |state, reason, rtt| {
+            ScanResult::new(host, 80, Protocol::Tcp, 50000, state, reason, rtt, 1)
+        };
+
+        let mut results = [
+            result(PortState::Filtered, Reason::NoResponse, None),
+            result(
+                PortState::Open,
+                Reason::SynAck,
+                Some(Duration::from_millis(5)),
+            ),
+        ];
+        mark_spoofed(&mut results);
+
+        // Silence is unknown, but a response that made it back still counts.
+        assert_eq!(results[0].state, PortState::Unknown);
+        assert_eq!(results[0].reason, Reason::SpoofedSource);
+        assert_eq!(results[1].state, PortState::Open);
+        assert_eq!(results[1].reason, Reason::SynAck);
+    }
+
+    #[tokio::test]
+    async fn test_scanner_builder() -> Result<()> {
+        let interface = Interface::new()?;
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let listener = TcpListener::bind((localhost, 0))?;
+        let open_port = listener.local_addr()?.port();
+
+        let scanner = Scanner::new(interface)
+            .source(localhost)
+            .scan_type(ScanMethod::TcpConnect)
+            .options(ProbeOptions {
+                retries: 1,
+                ..Default::default()
+            })
+            .timeout(Duration::from_secs(1));
+
+        // Nothing to scan without ports.
+        assert!(scanner.run(&[localhost]).await.is_err());
+
+        let (results, _) = scanner.ports(vec![open_port]).run(&[localhost]).await?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].port, open_port);
+        assert_eq!(results[0].state, PortState::Open);
+        // The time spent probing includes, but may exceed, the round-trip time.
+        let rtt = results[0].rtt.expect("an open port responds");
+        assert!(results[0].elapsed >= rtt);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dry_run_probe() -> Result<()> {
+        let interface = Interface::new()?;
+        let src_ip = IpAddr::V4(interface.ip.addr);
+        let dest_ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let options = ProbeOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+
+        for method in [
+            ScanMethod::TcpSyn,
+            ScanMethod::TcpConnect,
+            ScanMethod::Udp,
+            ScanMethod::SctpInit,
+            ScanMethod::IpProtocol,
+        ] {
+            let scan = method.probe(
+                interface,
+                src_ip,
+                40000,
+                dest_ip,
+                80,
+                options.clone(),
+                DEFAULT_TIMEOUT,
+            );
+            assert_eq!(scan.state, PortState::Unknown);
+            assert_eq!(scan.reason, Reason::NotSent);
+            assert_eq!(scan.attempts, 0);
+        }
+
+        // Pings and traceroutes of a dry run are not failures either.
+        let (state, _) = icmp_scan(
+            interface,
+            src_ip,
+            dest_ip,
+            IcmpProbe::Echo,
+            options.clone(),
+            DEFAULT_TIMEOUT,
+        )?;
+        assert_eq!(state, PortState::Unknown);
+        let hops = traceroute(
+            interface,
+            src_ip,
+            dest_ip,
+            3,
+            options.clone(),
+            DEFAULT_TIMEOUT,
+        )?;
+        assert_eq!(hops.len(), 3);
+        assert!(hops.iter().all(|hop| hop.ip.is_none()));
+
+        // The network layer hands back the built probe instead of a response.
+        let (exchange, attempts) = Tcp::send_tcp_packet(
+            interface,
+            src_ip,
+            40000,
+            dest_ip,
+            80,
+            TcpFlags::SYN,
+            options,
+            DEFAULT_TIMEOUT,
+        )?;
+        assert_eq!(attempts, 1);
+        match exchange {
+            Exchange::Built { packets, frames } => {
+                assert_eq!(packets.len(), 1);
+                assert!(has_consistent_ip_header(&packets[0]));
+
+                // The frame that would go on the wire carries the packet to the gateway.
+                let ethernet_packet = EthernetPacket::new(&frames[0]).unwrap();
+                assert_eq!(ethernet_packet.get_destination(), interface.gateway.mac);
+                assert_eq!(ethernet_packet.get_ethertype(), EtherTypes::Ipv4);
+                assert_eq!(ethernet_packet.payload(), packets[0]);
+            }
+            Exchange::Sent(..) => panic!("Expected a dry run, but the probe was sent."),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_randomized_port_order() -> Result<()> {
+        let interface = Interface::new()?;
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let listener = TcpListener::bind((localhost, 0))?;
+        let open_port = listener.local_addr()?.port();
+        let port_numbers = (open_port..=open_port.saturating_add(20)).collect::<Vec<_>>();
+
+        let scanner = Scanner::new(interface)
+            .source(localhost)
+            .scan_type(ScanMethod::TcpConnect)
+            .ports(port_numbers.clone())
+            .timeout(Duration::from_secs(1));
+
+        // The same ports are reported in the same order, however they were probed.
+        let ports =
+            |results: &[ScanResult]| results.iter().map(|scan| scan.port).collect::<Vec<_>>();
+        let (ordered, _) = scanner
+            .clone()
+            .randomize_order(false)
+            .run(&[localhost])
+            .await?;
+        let (shuffled, _) = scanner.randomize_order(true).run(&[localhost]).await?;
+        assert_eq!(ports(&ordered), port_numbers);
+        assert_eq!(ports(&shuffled), port_numbers);
+        assert_eq!(shuffled[0].state, PortState::Open);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scan_stops_when_budget_is_exhausted() -> Result<()> {
+        let interface = Interface::new()?;
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        // No budget left before the first probe, so nothing is scanned.
+        let budget = Budget {
+            packets: Some(0),
+            bytes: None,
+            deadline: None,
+        };
+        let (results, stats) = Scanner::new(interface)
+            .source(localhost)
+            .scan_type(ScanMethod::TcpConnect)
+            .ports(vec![1, 2, 3])
+            .budget(budget)
+            .run(&[localhost])
+            .await?;
+
+        assert!(results.is_empty());
+        assert!(stats.budget_exhausted);
+        assert!(stats.to_string().ends_with("budget exhausted"));
+
+        // A single socket is left over as well, rather than taken and dropped.
+        let (results, stats) = Scanner::new(interface)
+            .source(localhost)
+            .scan_type(ScanMethod::TcpConnect)
+            .ports(vec![1])
+            .budget(budget)
+            .run(&[localhost])
+            .await?;
+
+        assert!(results.is_empty());
+        assert!(stats.budget_exhausted);
+
+        // Without limits, every socket is scanned.
+        let (results, stats) = Scanner::new(interface)
+            .source(localhost)
+            .scan_type(ScanMethod::TcpConnect)
+            .ports(vec![1, 2, 3])
+            .budget(Budget::default())
+            .run(&[localhost])
+            .await?;
+
+        assert_eq!(results.len(), 3);
+        assert!(!stats.budget_exhausted);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scan_stops_at_deadline() -> Result<()> {
+        // Addresses reserved for documentation, which never answer.
+        let unreachable = (1..=254)
+            .map(|host| IpAddr::V4(Ipv4Addr::new(192, 0, 2, host)))
+            .collect::<Vec<_>>();
+        let budget = Budget {
+            deadline: Some(Instant::now() + Duration::from_millis(200)),
+            ..Budget::default()
+        };
+
+        // At 50 probes per second, the 254 sockets would take more than 5 seconds.
+        let start = Instant::now();
+        let (results, stats) = Scanner::scan(
+            Interface::new()?,
+            ScanMethod::TcpConnect,
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            0,
+            &unreachable,
+            &[80],
+            ProbeOptions::default(),
+            4,
+            Some(50),
+            Duration::from_millis(200),
+            budget,
+            &AtomicBool::new(false),
+            |_| Ok(()),
+        )
+        .await?;
+
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(results.len() < unreachable.len());
+        assert!(stats.deadline_exceeded);
+        assert!(!stats.budget_exhausted);
+        assert!(stats.to_string().ends_with("deadline exceeded"));
+
+        Ok(())
+    }
+}
@@ -1,7 +1,12 @@
-use super::engine::ScanResult;
+use super::results::PortState;
 use crate::{
     errors::ScannerError,
-    networking::{icmp::Icmp, interface::Interface},
+    networking::{
+        icmp::{Icmp, IcmpProbe},
+        interface::Interface,
+        osi_layers::Exchange,
+        probe::ProbeOptions,
+    },
 };
 use anyhow::Result;
 use pnet::packet::{
@@ -12,15 +17,19 @@ use pnet::packet::{
 };
 use std::{net::IpAddr, time::Duration};
 
-/// Scans a host using ICMP echo requests. Also known as a ping scan.
+/// Scans a host using ICMP requests of the given kind. Also known as a ping scan.
 ///
 /// Determines if a host is up or down. Might not work behind a firewall.
+/// Hosts that drop echo requests may still answer timestamp or address mask requests.
+/// A dry run can't tell, so the state is unknown then.
 pub fn icmp_scan(
     interface: Interface,
     src_ip: IpAddr,
     dest_ip: IpAddr,
+    probe: IcmpProbe,
+    options: ProbeOptions,
     timeout: Duration,
-) -> Result<(ScanResult, Duration)> {
+) -> Result<(PortState, Duration)> {
     let ipv4_src = match src_ip {
         IpAddr::V4(ip) => ip,
         _ => Err(ScannerError::UnsupportedIpVersion)?,
@@ -31,12 +40,16 @@ pub fn icmp_scan(
         _ => Err(ScannerError::UnsupportedIpVersion)?,
     };
 
-    let (response, rtt) = Icmp::send_icmp_packet(interface, ipv4_src, ipv4_dest, timeout)?;
+    let exchange = Icmp::send_icmp_packet(interface, ipv4_src, ipv4_dest, probe, options, timeout)?;
+
+    let Exchange::Sent(response, _, rtt) = exchange else {
+        return Ok((PortState::Unknown, Duration::ZERO));
+    };
 
     // No response -> down.
     let packet = match response {
         Some(packet) => packet,
-        None => return Ok((ScanResult::Down, rtt)),
+        None => return Ok((PortState::Down, rtt)),
     };
 
     let ethernet_packet =
@@ -63,10 +76,12 @@ pub fn icmp_scan(
     match (icmp_type, icmp_code) {
         // Unreachable -> down.
         (IcmpTypes::DestinationUnreachable, code) if icmp_codes.contains(&code) => {
-            Ok((ScanResult::Down, rtt))
+            Ok((PortState::Down, rtt))
+        }
+        // Echo, timestamp or address mask reply -> up.
+        (reply_type, echo_reply::IcmpCodes::NoCode) if reply_type == probe.reply_type() => {
+            Ok((PortState::Up, rtt))
         }
-        // Echo reply -> up.
-        (IcmpTypes::EchoReply, echo_reply::IcmpCodes::NoCode) => Ok((ScanResult::Up, rtt)),
         // Unexpected response.
         _ => Err(ScannerError::UnexpectedIcmpResponse.into()),
     }
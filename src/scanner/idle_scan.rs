@@ -0,0 +1,200 @@
+use super::{
+    ip_id::probe_ip_id,
+    results::{PortState, Protocol, Reason, ScanResult},
+};
+use crate::{
+    errors::ScannerError,
+    networking::{
+        arp::Arp,
+        interface::Interface,
+        osi_layers::DatalinkLayer,
+        probe::{ProbeOptions, MAX_DECOYS},
+        tcp::Tcp,
+    },
+};
+use anyhow::Result;
+use pnet::packet::{ethernet::EtherTypes, tcp::TcpFlags};
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    thread,
+    time::Duration,
+};
+
+/// TCP idle determines the status of ports on a target machine without revealing the scanner.
+///
+/// Also known as zombie scan. Many hosts draw the IP identification field of all their packets
+/// from a single counter, which increases by one with each sent packet. An idle third-party host,
+/// the zombie, thus reveals how many packets it has sent in the meantime.
+///
+/// Scan sends a SYN/ACK probe to the zombie, which answers with a RST flag that discloses its
+/// IP identification. Then a SYN packet with the forged source address of the zombie is sent to
+/// the target. An open port answers the zombie with SYN/ACK flags, to which the zombie responds
+/// with a RST flag. A closed port answers with a RST flag, which the zombie ignores. Filtered ports
+/// don't answer at all. Finally, a second probe to the zombie discloses its IP identification again.
+///
+/// An increase by two means the zombie has sent a RST flag to the target -> open.
+/// An increase by one means the zombie has only answered our probes -> closed or filtered.
+///
+/// The target only ever sees packets from the zombie. Ports must be scanned one at a time,
+/// since concurrent probes would increase the counter as well. Only IPv4 is supported,
+/// because the IPv6 header has no identification field.
+///
+/// A dry run has no zombie to observe, so the port is reported as not sent without any probe.
+pub fn tcp_idle_scan(
+    interface: Interface,
+    src_ip: IpAddr,
+    src_port: u16,
+    dest_ip: IpAddr,
+    dest_port: u16,
+    options: ProbeOptions,
+    timeout: Duration,
+) -> Result<ScanResult> {
+    let zombie = options.zombie.ok_or(ScannerError::MissingZombie)?;
+
+    if options.dry_run {
+        return Ok(ScanResult::not_sent(
+            dest_ip,
+            dest_port,
+            Protocol::Tcp,
+            src_port,
+        ));
+    }
+
+    let ipv4_zombie = match zombie.ip() {
+        IpAddr::V4(ip) => ip,
+        _ => Err(ScannerError::UnsupportedIpVersion)?,
+    };
+
+    let ipv4_dest = match dest_ip {
+        IpAddr::V4(ip) => ip,
+        _ => Err(ScannerError::UnsupportedIpVersion)?,
+    };
+
+    // Decoys would only add noise to the counter of the zombie.
+    let options = ProbeOptions {
+        decoys: [None; MAX_DECOYS],
+        ..options
+    };
+
+    let zombie_ip_id = || -> Result<u16> {
+        let ip_id = probe_ip_id(
+            interface,
+            src_ip,
+            src_port,
+            zombie,
+            options.clone(),
+            timeout,
+        )?;
+        Ok(ip_id.ok_or(ScannerError::ZombieNotResponding(zombie.ip()))?)
+    };
+
+    let before = zombie_ip_id()?;
+
+    send_spoofed_syn(
+        interface,
+        ipv4_zombie,
+        src_port,
+        ipv4_dest,
+        dest_port,
+        options.clone(),
+        timeout,
+    )?;
+
+    // Give the target and the zombie time to answer each other.
+    thread::sleep(timeout);
+
+    let after = zombie_ip_id()?;
+
+    let (state, reason) = classify_idle_response(before, after)?;
+
+    Ok(ScanResult::new(
+        dest_ip,
+        dest_port,
+        Protocol::Tcp,
+        src_port,
+        state,
+        reason,
+        None,
+        1,
+    ))
+}
+
+/// Sends a SYN packet from the forged address of the zombie to the target.
+///
+/// Does not listen for a response, since the target answers the zombie.
+fn send_spoofed_syn(
+    interface: Interface,
+    zombie_ip: Ipv4Addr,
+    src_port: u16,
+    dest_ip: Ipv4Addr,
+    dest_port: u16,
+    options: ProbeOptions,
+    timeout: Duration,
+) -> Result<()> {
+    let dest_mac = Arp::next_hop_mac(interface, dest_ip, timeout)?;
+
+    let iface = interface.convert_interface()?;
+
+    let (packet, _) = Tcp::build_tcp_packet(
+        zombie_ip,
+        src_port,
+        dest_ip,
+        dest_port,
+        TcpFlags::SYN,
+        &[],
+        &[],
+        &options,
+    )?;
+
+    DatalinkLayer::send_flood(iface, &packet, 1, dest_mac, EtherTypes::Ipv4)
+}
+
+/// Interprets the IP identification of the zombie before and after the spoofed SYN packet.
+///
+/// Increase by two -> open. Increase by one -> closed or filtered. The counter wraps around.
+/// Any other increase means the zombie is not idle or does not use a global counter,
+/// e.g. because it draws the identification at random or sets it to zero.
+fn classify_idle_response(before: u16, after: u16) -> Result<(PortState, Reason)> {
+    let increment = after.wrapping_sub(before);
+
+    let state = match increment {
+        // RST flag sent to the target -> open.
+        2 => PortState::Open,
+        // Only our probes were answered -> closed or filtered.
+        1 => PortState::ClosedOrFiltered,
+        _ => Err(ScannerError::UnsuitableZombie(increment))?,
+    };
+
+    Ok((state, Reason::IpIdIncrement(increment)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_idle_response() -> Result<()> {
+        assert_eq!(
+            classify_idle_response(1000, 1002)?,
+            (PortState::Open, Reason::IpIdIncrement(2))
+        );
+        assert_eq!(
+            classify_idle_response(1000, 1001)?,
+            (PortState::ClosedOrFiltered, Reason::IpIdIncrement(1))
+        );
+
+        // The counter wraps around.
+        assert_eq!(classify_idle_response(u16::MAX, 1)?.0, PortState::Open);
+        assert_eq!(
+            classify_idle_response(u16::MAX, 0)?.0,
+            PortState::ClosedOrFiltered
+        );
+
+        // Constant, random or busy counters can't tell the port state.
+        assert!(classify_idle_response(0, 0).is_err());
+        assert!(classify_idle_response(1000, 1005).is_err());
+        assert!(classify_idle_response(1000, 999).is_err());
+
+        Ok(())
+    }
+}
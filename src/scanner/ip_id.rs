@@ -0,0 +1,185 @@
+use crate::{
+    errors::ScannerError,
+    networking::{interface::Interface, osi_layers::Exchange, probe::ProbeOptions, tcp::Tcp},
+};
+use anyhow::Result;
+use pnet::packet::{ethernet::EthernetPacket, ipv4::Ipv4Packet, tcp::TcpFlags, Packet};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+/// Number of probes whose responses are sampled to classify the IP identification of a host.
+pub const IP_ID_SAMPLES: usize = 6;
+
+/// Largest step between two samples of the same counter. A busy host sends many packets
+/// between our probes, while random values are usually much farther apart.
+const MAX_INCREMENT: u16 = 1000;
+
+/// Most counters that samples are attributed to before they count as random.
+const MAX_COUNTERS: usize = 3;
+
+/// How a host chooses the IP identification of its packets, like the TI test of nmap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpIdPattern {
+    /// A single counter that increases with each packet, e.g. of older Windows versions.
+    /// Such hosts are suitable zombies for idle scans.
+    Incremental,
+    /// Several counters that increase independently, e.g. of hosts behind a load balancer
+    /// that share an IP address.
+    PerHost,
+    /// Values that follow no counter.
+    Random,
+    /// The field is always zero, e.g. of Linux if the DontFragment flag is set.
+    Zero,
+}
+
+impl fmt::Display for IpIdPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IpIdPattern::Incremental => write!(f, "incremental"),
+            IpIdPattern::PerHost => write!(f, "per-host"),
+            IpIdPattern::Random => write!(f, "random"),
+            IpIdPattern::Zero => write!(f, "zero"),
+        }
+    }
+}
+
+/// Classifies the IP identifications of consecutive responses of a host.
+///
+/// Each sample is attributed to the counter whose last value it follows most closely, both
+/// wrapping around. All samples on one counter -> incremental. Samples on a few counters,
+/// each of which is sampled more than once -> per host. Otherwise -> random.
+///
+/// Fewer than two samples can't reveal a counter, so they count as random.
+pub fn analyze_ip_id(samples: &[u16]) -> IpIdPattern {
+    if !samples.is_empty() && samples.iter().all(|id| *id == 0) {
+        return IpIdPattern::Zero;
+    }
+    if samples.len() < 2 {
+        return IpIdPattern::Random;
+    }
+
+    // The last value of each counter and its number of samples.
+    let mut counters: Vec<(u16, usize)> = Vec::new();
+    for &id in samples {
+        let next = counters
+            .iter_mut()
+            .map(|counter| (id.wrapping_sub(counter.0), counter))
+            .filter(|(increment, _)| (1..=MAX_INCREMENT).contains(increment))
+            .min_by_key(|(increment, _)| *increment);
+        match next {
+            Some((_, counter)) => *counter = (id, counter.1 + 1),
+            None => counters.push((id, 1)),
+        }
+    }
+
+    match counters.len() {
+        1 => IpIdPattern::Incremental,
+        n if n <= MAX_COUNTERS && counters.iter().all(|(_, samples)| *samples > 1) => {
+            IpIdPattern::PerHost
+        }
+        _ => IpIdPattern::Random,
+    }
+}
+
+/// Sends `count` SYN/ACK probes to the target one after another and collects the
+/// IP identifications of the answered ones, see `probe_ip_id`.
+///
+/// May fail if a probe can't be sent.
+pub fn sample_ip_ids(
+    interface: Interface,
+    src_ip: IpAddr,
+    src_port: u16,
+    target: SocketAddr,
+    count: usize,
+    options: ProbeOptions,
+    timeout: Duration,
+) -> Result<Vec<u16>> {
+    let mut samples = Vec::with_capacity(count);
+    for _ in 0..count {
+        if let Some(id) = probe_ip_id(
+            interface,
+            src_ip,
+            src_port,
+            target,
+            options.clone(),
+            timeout,
+        )? {
+            samples.push(id);
+        }
+    }
+    Ok(samples)
+}
+
+/// Sends a SYN/ACK probe to the target and returns the IP identification of its RST response.
+///
+/// Returns `None` if the target doesn't answer within the timeout, or in a dry run.
+pub fn probe_ip_id(
+    interface: Interface,
+    src_ip: IpAddr,
+    src_port: u16,
+    target: SocketAddr,
+    options: ProbeOptions,
+    timeout: Duration,
+) -> Result<Option<u16>> {
+    let (exchange, _attempts) = Tcp::send_tcp_packet(
+        interface,
+        src_ip,
+        src_port,
+        target.ip(),
+        target.port(),
+        TcpFlags::SYN | TcpFlags::ACK,
+        options,
+        timeout,
+    )?;
+
+    let Exchange::Sent(Some(packet), ..) = exchange else {
+        return Ok(None);
+    };
+
+    let ethernet_packet =
+        EthernetPacket::new(&packet).ok_or(ScannerError::CantCreateEthernetPacket)?;
+
+    let ipv4_packet =
+        Ipv4Packet::new(ethernet_packet.payload()).ok_or(ScannerError::CantCreateIpv4Packet)?;
+
+    Ok(Some(ipv4_packet.get_identification()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze_ip_id() {
+        assert_eq!(analyze_ip_id(&[0, 0, 0, 0]), IpIdPattern::Zero);
+
+        // A single counter, also if it wraps around or the host sent other packets meanwhile.
+        assert_eq!(
+            analyze_ip_id(&[1000, 1001, 1002, 1003]),
+            IpIdPattern::Incremental
+        );
+        assert_eq!(
+            analyze_ip_id(&[65534, 65535, 0, 40, 41]),
+            IpIdPattern::Incremental
+        );
+
+        // Two backends answer in turns.
+        assert_eq!(
+            analyze_ip_id(&[1000, 30000, 1001, 30001, 1003, 30002]),
+            IpIdPattern::PerHost
+        );
+
+        assert_eq!(
+            analyze_ip_id(&[48211, 3017, 60129, 21455, 9982, 37760]),
+            IpIdPattern::Random
+        );
+
+        // Repeated or too few values follow no counter.
+        assert_eq!(analyze_ip_id(&[7, 7, 7, 7]), IpIdPattern::Random);
+        assert_eq!(analyze_ip_id(&[1000]), IpIdPattern::Random);
+        assert_eq!(analyze_ip_id(&[]), IpIdPattern::Random);
+    }
+}
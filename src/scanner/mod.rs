@@ -1,5 +1,22 @@
 pub mod arp_scan;
+pub mod budget;
+pub mod congestion;
+pub mod diff;
 pub mod engine;
 pub mod icmp_scan;
+pub mod idle_scan;
+pub mod ip_id;
+pub mod ndp_scan;
+pub mod os_detection;
+pub mod profile;
+pub mod protocol_scan;
+pub mod rate_limiter;
+pub mod results;
+pub mod rst_limit;
+pub mod rtt_estimator;
+pub mod sctp_scan;
+pub mod tarpit;
 pub mod tcp_scan;
+pub mod traceroute;
 pub mod udp_scan;
+pub mod version_detection;
@@ -0,0 +1,30 @@
+use crate::{
+    errors::ScannerError,
+    networking::{interface::Interface, ndp::Ndp},
+};
+use anyhow::Result;
+use pnet::util::MacAddr;
+use std::{net::IpAddr, time::Duration};
+
+/// Sends neighbor solicitations to determine the MAC addresses of IPv6 hosts on the local link.
+///
+/// Solicitations are sent from the link-local address of the interface.
+/// Only hosts that are online will respond with neighbor advertisements.
+pub fn ndp_scan(
+    interface: Interface,
+    dest_ip: IpAddr,
+    timeout: Duration,
+) -> Result<(Option<MacAddr>, Duration)> {
+    let ipv6_src = interface
+        .link_local
+        .ok_or(ScannerError::CantFindInterfaceIp)?;
+
+    let ipv6_dest = match dest_ip {
+        IpAddr::V6(ip) => ip,
+        _ => Err(ScannerError::UnsupportedIpVersion)?,
+    };
+
+    let (response, rtt) = Ndp::send_solicitation(interface, ipv6_src, ipv6_dest, timeout)?;
+
+    Ok((response, rtt))
+}
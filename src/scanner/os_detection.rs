@@ -0,0 +1,407 @@
+use crate::{
+    errors::ScannerError,
+    networking::{
+        interface::Interface,
+        osi_layers::{ipv6_upper_layer, Exchange},
+        probe::ProbeOptions,
+        tcp::Tcp,
+    },
+};
+use anyhow::Result;
+use pnet::packet::{
+    ethernet::{EtherTypes, EthernetPacket},
+    ip::IpNextHeaderProtocols,
+    ipv4::{Ipv4Flags, Ipv4Packet},
+    ipv6::Ipv6Packet,
+    tcp::{TcpFlags, TcpPacket},
+    Packet,
+};
+use std::{net::IpAddr, time::Duration};
+
+/// Initial TTLs used by operating systems. Routers decrement the TTL, so the
+/// initial TTL of a response is the smallest of these that is not below the received TTL.
+const INITIAL_TTLS: [u8; 4] = [32, 64, 128, 255];
+
+/// Window size of the ACK probe, which is unusual so that it isn't echoed by accident.
+const ACK_PROBE_WINDOW: u16 = 4096;
+
+/// Weights of the characteristics of a response. The TTL and the order of TCP options
+/// differ the most between operating systems, the rest merely helps to break ties.
+const TTL_WEIGHT: u32 = 3;
+const DF_WEIGHT: u32 = 1;
+const WINDOW_WEIGHT: u32 = 1;
+const OPTIONS_WEIGHT: u32 = 3;
+
+/// Score of the responses to all probes: a SYN/ACK and two RST responses.
+/// Guesses from fewer responses are less certain, even if everything matches.
+const FULL_SCORE: u32 = 3 * (TTL_WEIGHT + DF_WEIGHT) + WINDOW_WEIGHT + OPTIONS_WEIGHT;
+
+/// Characteristics of the TCP/IP stack of an operating system family.
+struct Signature {
+    family: &'static str,
+    /// Initial TTL of sent packets.
+    ttl: u8,
+    /// Whether the DontFragment flag is set in IPv4 headers.
+    df: bool,
+    /// Window sizes commonly advertised in SYN/ACK responses.
+    windows: &'static [u16],
+    /// Kinds of TCP options in SYN/ACK responses, in order.
+    options: &'static [u8],
+}
+
+/// Signatures of SYN/ACK responses to SYN probes that carry the options of `Tcp::syn_options`.
+const SIGNATURES: [Signature; 3] = [
+    Signature {
+        family: "Linux",
+        ttl: 64,
+        df: true,
+        windows: &[65160, 64240, 43690, 29200, 28960, 14600, 5840],
+        // MSS, SACK permitted, timestamps, NOP, window scale.
+        options: &[2, 4, 8, 1, 3],
+    },
+    Signature {
+        family: "Windows",
+        ttl: 128,
+        df: true,
+        windows: &[65535, 64240, 8192],
+        // MSS, NOP, window scale, NOP, NOP, SACK permitted. Timestamps are disabled by default.
+        options: &[2, 1, 3, 1, 1, 4],
+    },
+    Signature {
+        family: "BSD",
+        ttl: 64,
+        df: true,
+        windows: &[65535, 65228],
+        // MSS, NOP, window scale, SACK permitted, timestamps.
+        options: &[2, 1, 3, 4, 8],
+    },
+];
+
+/// Best guess of the operating system family of a host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsGuess {
+    /// Operating system family, e.g. `Linux`.
+    pub family: &'static str,
+    /// Share of the characteristics that match the signature, between 0 and 1.
+    /// Missing responses count as mismatches.
+    pub confidence: f32,
+}
+
+/// Characteristics of a single response, decoded from the received Ethernet frame.
+#[derive(Debug, PartialEq)]
+struct Fingerprint {
+    ttl: u8,
+    /// Is `None` for IPv6, which has no DontFragment flag.
+    df: Option<bool>,
+    flags: u8,
+    window: u16,
+    options: Vec<u8>,
+}
+
+/// Guesses the operating system of a host from the characteristics of its TCP/IP stack.
+///
+/// Sends three probes and compares the responses against the built-in signatures:
+/// - A SYN with the options of a real operating system to the open port. The SYN/ACK reveals
+///   the TTL, the DontFragment flag, the window size and the order of the TCP options.
+/// - An ACK with an unusual window size to the open port, which is answered with a RST.
+/// - A SYN without options to the closed port, which is answered with a RST.
+///
+/// Without a closed port, the last probe is skipped and the guess is less certain.
+///
+/// Returns `None` if the host did not respond to any probe.
+#[allow(clippy::too_many_arguments)]
+pub fn detect_os(
+    interface: Interface,
+    src_ip: IpAddr,
+    src_port: u16,
+    dest_ip: IpAddr,
+    open_port: u16,
+    closed_port: Option<u16>,
+    options: ProbeOptions,
+    timeout: Duration,
+) -> Result<Option<OsGuess>> {
+    let mut probes = vec![
+        (
+            open_port,
+            TcpFlags::SYN,
+            ProbeOptions {
+                tcp_options: true,
+                ..options.clone()
+            },
+        ),
+        (
+            open_port,
+            TcpFlags::ACK,
+            ProbeOptions {
+                window: ACK_PROBE_WINDOW,
+                ..options.clone()
+            },
+        ),
+    ];
+
+    if let Some(closed_port) = closed_port {
+        let options = ProbeOptions {
+            tcp_options: false,
+            ..options
+        };
+        probes.push((closed_port, TcpFlags::SYN, options));
+    }
+
+    let mut fingerprints = Vec::with_capacity(probes.len());
+
+    for (dest_port, flags, options) in probes {
+        let (exchange, _attempts) = Tcp::send_tcp_packet(
+            interface, src_ip, src_port, dest_ip, dest_port, flags, options, timeout,
+        )?;
+
+        // Probes of a dry run have no response to fingerprint.
+        if let Exchange::Sent(Some(response), ..) = exchange {
+            fingerprints.push(parse_fingerprint(&response)?);
+        }
+    }
+
+    Ok(match_signatures(&fingerprints))
+}
+
+/// Decodes the characteristics of a TCP response.
+fn parse_fingerprint(response: &[u8]) -> Result<Fingerprint> {
+    let ethernet_packet =
+        EthernetPacket::new(response).ok_or(ScannerError::CantCreateEthernetPacket)?;
+
+    let (ttl, df, segment) = match ethernet_packet.get_ethertype() {
+        EtherTypes::Ipv6 => {
+            let ipv6_packet = Ipv6Packet::new(ethernet_packet.payload())
+                .ok_or(ScannerError::CantCreateIpv6Packet)?;
+            let segment =
+                match ipv6_upper_layer(ipv6_packet.get_next_header(), ipv6_packet.payload()) {
+                    Some((IpNextHeaderProtocols::Tcp, segment)) => segment.to_vec(),
+                    _ => Err(ScannerError::UnexpectedProtocolResponse)?,
+                };
+            let hop_limit = ipv6_packet.get_hop_limit();
+            (hop_limit, None, segment)
+        }
+        _ => {
+            let ipv4_packet = Ipv4Packet::new(ethernet_packet.payload())
+                .ok_or(ScannerError::CantCreateIpv4Packet)?;
+            if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
+                Err(ScannerError::UnexpectedProtocolResponse)?;
+            }
+            let df = ipv4_packet.get_flags() & Ipv4Flags::DontFragment != 0;
+            let ttl = ipv4_packet.get_ttl();
+            (ttl, Some(df), ipv4_packet.payload().to_vec())
+        }
+    };
+
+    let tcp_packet = TcpPacket::new(&segment).ok_or(ScannerError::CantCreateTcpPacket)?;
+
+    Ok(Fingerprint {
+        ttl,
+        df,
+        flags: tcp_packet.get_flags(),
+        window: tcp_packet.get_window(),
+        options: tcp_packet
+            .get_options_iter()
+            .map(|option| option.get_number().0)
+            .collect(),
+    })
+}
+
+/// Returns the initial TTL a received TTL was most likely decremented from.
+fn initial_ttl(ttl: u8) -> u8 {
+    INITIAL_TTLS
+        .into_iter()
+        .find(|initial_ttl| *initial_ttl >= ttl)
+        .unwrap_or(u8::MAX)
+}
+
+/// Scores the fingerprints against each signature and returns the best match.
+///
+/// The window size and the TCP options are only compared for SYN/ACK responses,
+/// since a RST carries no options and usually a window size of zero.
+fn match_signatures(fingerprints: &[Fingerprint]) -> Option<OsGuess> {
+    if fingerprints.is_empty() {
+        return None;
+    }
+
+    let score = |signature: &Signature| {
+        let mut score = 0;
+        let mut max_score = 0;
+
+        for fingerprint in fingerprints {
+            max_score += TTL_WEIGHT;
+            if initial_ttl(fingerprint.ttl) == signature.ttl {
+                score += TTL_WEIGHT;
+            }
+
+            if let Some(df) = fingerprint.df {
+                max_score += DF_WEIGHT;
+                if df == signature.df {
+                    score += DF_WEIGHT;
+                }
+            }
+
+            let syn_ack = TcpFlags::SYN | TcpFlags::ACK;
+            if fingerprint.flags & syn_ack == syn_ack {
+                max_score += WINDOW_WEIGHT + OPTIONS_WEIGHT;
+                if signature.windows.contains(&fingerprint.window) {
+                    score += WINDOW_WEIGHT;
+                }
+                if fingerprint.options == signature.options {
+                    score += OPTIONS_WEIGHT;
+                }
+            }
+        }
+
+        score as f32 / max_score.max(FULL_SCORE) as f32
+    };
+
+    // The first signature wins a tie, so the order of signatures matters.
+    SIGNATURES
+        .iter()
+        .map(|signature| OsGuess {
+            family: signature.family,
+            confidence: score(signature),
+        })
+        .reduce(|best, guess| match guess.confidence > best.confidence {
+            true => guess,
+            false => best,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::osi_layers::DatalinkLayer;
+    use pnet::{packet::tcp::TcpOption, util::MacAddr};
+    use std::net::Ipv4Addr;
+
+    /// Builds an Ethernet frame carrying a canned TCP response of a host `hops` routers away.
+    fn tcp_response(
+        initial_ttl: u8,
+        hops: u8,
+        flags: u8,
+        window: u16,
+        tcp_options: &[TcpOption],
+    ) -> Vec<u8> {
+        let options = ProbeOptions {
+            ttl: initial_ttl - hops,
+            window,
+            ..Default::default()
+        };
+        let (packet, _) = Tcp::build_tcp_packet(
+            Ipv4Addr::new(192, 168, 0, 2),
+            80,
+            Ipv4Addr::new(192, 168, 0, 1),
+            12345,
+            flags,
+            tcp_options,
+            &[],
+            &options,
+        )
+        .unwrap();
+
+        DatalinkLayer::build_ethernet_frame(
+            MacAddr::zero(),
+            MacAddr::zero(),
+            EtherTypes::Ipv4,
+            &packet,
+        )
+    }
+
+    /// Decodes and matches canned responses.
+    fn match_responses(responses: &[Vec<u8>]) -> Result<Option<OsGuess>> {
+        let fingerprints = responses
+            .iter()
+            .map(|response| parse_fingerprint(response))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(match_signatures(&fingerprints))
+    }
+
+    #[test]
+    fn test_initial_ttl() {
+        assert_eq!(initial_ttl(64), 64);
+        assert_eq!(initial_ttl(57), 64);
+        assert_eq!(initial_ttl(113), 128);
+        assert_eq!(initial_ttl(250), 255);
+        assert_eq!(initial_ttl(20), 32);
+    }
+
+    #[test]
+    fn test_parse_fingerprint() -> Result<()> {
+        let response = tcp_response(
+            64,
+            0,
+            TcpFlags::SYN | TcpFlags::ACK,
+            29200,
+            &Tcp::syn_options(),
+        );
+        let fingerprint = parse_fingerprint(&response)?;
+
+        assert_eq!(
+            fingerprint,
+            Fingerprint {
+                ttl: 64,
+                df: Some(true),
+                flags: TcpFlags::SYN | TcpFlags::ACK,
+                window: 29200,
+                options: vec![2, 4, 8, 1, 3],
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_match_signatures() -> Result<()> {
+        let syn_ack = TcpFlags::SYN | TcpFlags::ACK;
+        let rst = TcpFlags::RST | TcpFlags::ACK;
+
+        let linux = [
+            tcp_response(64, 3, syn_ack, 65160, &Tcp::syn_options()),
+            tcp_response(64, 3, TcpFlags::RST, 0, &[]),
+            tcp_response(64, 3, rst, 0, &[]),
+        ];
+        let guess = match_responses(&linux)?.unwrap();
+        assert_eq!(guess.family, "Linux");
+        assert_eq!(guess.confidence, 1.0);
+
+        let windows_options = [
+            TcpOption::mss(1460),
+            TcpOption::nop(),
+            TcpOption::wscale(8),
+            TcpOption::nop(),
+            TcpOption::nop(),
+            TcpOption::sack_perm(),
+        ];
+        let windows = [
+            tcp_response(128, 10, syn_ack, 64240, &windows_options),
+            tcp_response(128, 10, rst, 0, &[]),
+        ];
+        let guess = match_responses(&windows)?.unwrap();
+        assert_eq!(guess.family, "Windows");
+        assert_eq!(guess.confidence, 0.75);
+
+        // BSD shares the initial TTL with Linux, but orders the options differently.
+        let bsd_options = [
+            TcpOption::mss(1460),
+            TcpOption::nop(),
+            TcpOption::wscale(6),
+            TcpOption::sack_perm(),
+            TcpOption::timestamp(1, 0),
+        ];
+        let bsd = [tcp_response(64, 1, syn_ack, 65535, &bsd_options)];
+        assert_eq!(match_responses(&bsd)?.unwrap().family, "BSD");
+
+        // A RST alone only reveals the TTL and DF bit, so the guess is less certain.
+        let guess = match_responses(&[tcp_response(128, 0, rst, 0, &[])])?.unwrap();
+        assert_eq!(guess.family, "Windows");
+        assert!(guess.confidence < 0.5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_match_no_responses() {
+        assert_eq!(match_signatures(&[]), None);
+    }
+}
@@ -0,0 +1,94 @@
+use super::results::ScanStats;
+use std::{fmt, io, net::IpAddr};
+
+/// Outcome of grabbing the banner of a port that the scan of a profile found open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Banner {
+    /// Text the service sent, which is empty if the service waits for a request.
+    Grabbed(String),
+    /// The connection was refused, so the port closed between the scan and the grab.
+    Closed,
+    /// The banner could not be grabbed otherwise, e.g. since the connection timed out.
+    Failed(String),
+}
+
+impl fmt::Display for Banner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Banner::Grabbed(_) => write!(f, "grabbed"),
+            Banner::Closed => write!(f, "closed"),
+            Banner::Failed(_) => write!(f, "failed"),
+        }
+    }
+}
+
+impl Banner {
+    /// Interprets the outcome of `Tcp::grab_banner`.
+    pub fn of_grab(grab: anyhow::Result<String>) -> Banner {
+        match grab {
+            Ok(banner) => Banner::Grabbed(banner),
+            Err(e) => match e.downcast_ref::<io::Error>() {
+                Some(e) if e.kind() == io::ErrorKind::ConnectionRefused => Banner::Closed,
+                _ => Banner::Failed(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Open TCP ports of a host along with their banners, see `Scanner::profile`.
+#[derive(Debug, Clone)]
+pub struct HostProfile {
+    pub ip: IpAddr,
+    /// Ports that the scan found open, sorted, and their banners.
+    pub ports: Vec<(u16, Banner)>,
+    /// Statistics of the scan that found the open ports.
+    pub stats: ScanStats,
+}
+
+/// Displays the host with its number of open ports and grabbed banners, e.g. `192.168.0.1 (3 open ports, 2 banners)`.
+impl fmt::Display for HostProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let banners = self
+            .ports
+            .iter()
+            .filter(|(_, banner)| matches!(banner, Banner::Grabbed(_)))
+            .count();
+        write!(
+            f,
+            "{} ({} open ports, {} banners)",
+            self.ip,
+            self.ports.len(),
+            banners
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ScannerError;
+
+    #[test]
+    fn test_banner_of_grab() {
+        let banner = Banner::of_grab(Ok("SSH-2.0-OpenSSH_9.6".to_string()));
+        assert_eq!(banner, Banner::Grabbed("SSH-2.0-OpenSSH_9.6".to_string()));
+
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        assert_eq!(Banner::of_grab(Err(refused.into())), Banner::Closed);
+
+        let timeout = io::Error::from(io::ErrorKind::TimedOut);
+        assert!(matches!(
+            Banner::of_grab(Err(timeout.into())),
+            Banner::Failed(_)
+        ));
+        let error = ScannerError::CantFindInterface.into();
+        assert!(matches!(Banner::of_grab(Err(error)), Banner::Failed(_)));
+
+        let profile = HostProfile {
+            ip: IpAddr::from([192, 168, 0, 1]),
+            ports: vec![(22, Banner::Grabbed(String::new())), (80, Banner::Closed)],
+            stats: ScanStats::default(),
+        };
+        assert_eq!(profile.to_string(), "192.168.0.1 (2 open ports, 1 banners)");
+    }
+}
@@ -0,0 +1,209 @@
+use super::results::{PortState, Protocol, Reason, ScanResult};
+use crate::{
+    errors::ScannerError,
+    networking::{
+        interface::Interface,
+        osi_layers::{Exchange, Layer, NetworkLayer},
+        probe::{build_protocol_probe, ProbeOptions},
+    },
+};
+use anyhow::Result;
+use pnet::packet::{
+    ethernet::EthernetPacket,
+    icmp::{destination_unreachable::IcmpCodes, IcmpPacket, IcmpTypes},
+    ip::{IpNextHeaderProtocol, IpNextHeaderProtocols},
+    ipv4::Ipv4Packet,
+    Packet,
+};
+use std::{net::IpAddr, time::Duration};
+
+/// IP protocol scan determines which IP protocols a host supports, like `nmap -sO`.
+///
+/// Instead of ports, the port numbers are taken as protocol numbers, e.g. 47 for GRE or 50 for
+/// ESP. The scan sends bare IP datagrams of each protocol, without any header of that protocol.
+///
+/// RFC 1122 expected behavior is that a host answers protocols it doesn't implement with an ICMP
+/// protocol unreachable error. Supported protocols usually drop the empty datagram silently.
+pub fn ip_protocol_scan(
+    interface: Interface,
+    src_ip: IpAddr,
+    src_port: u16,
+    dest_ip: IpAddr,
+    dest_port: u16,
+    options: ProbeOptions,
+    timeout: Duration,
+) -> Result<ScanResult> {
+    let ipv4_src = match src_ip {
+        IpAddr::V4(ip) => ip,
+        _ => Err(ScannerError::UnsupportedIpVersion)?,
+    };
+
+    let ipv4_dest = match dest_ip {
+        IpAddr::V4(ip) => ip,
+        _ => Err(ScannerError::UnsupportedIpVersion)?,
+    };
+
+    let protocol =
+        u8::try_from(dest_port).map_err(|_| ScannerError::InvalidProtocolNumber(dest_port))?;
+
+    let packet = build_protocol_probe(ipv4_src, ipv4_dest, protocol, &options)?;
+
+    let network_layer = NetworkLayer {
+        datalink_layer: None,
+        src_addr: Some(dest_ip),
+        dest_addr: Some(src_ip),
+        protocol: Some(IpNextHeaderProtocol(protocol)),
+    };
+
+    let layers = Layer::Three(network_layer);
+
+    let exchange = NetworkLayer::send_and_receive(interface, &[packet], layers, &options, timeout)?;
+
+    let Exchange::Sent(response, _, rtt) = exchange else {
+        return Ok(ScanResult::not_sent(
+            dest_ip,
+            dest_port,
+            Protocol::Ip,
+            src_port,
+        ));
+    };
+
+    let rtt = response.as_ref().map(|_| rtt);
+    let (state, reason) = classify_protocol_response(response, protocol)?;
+
+    Ok(ScanResult::new(
+        dest_ip,
+        dest_port,
+        Protocol::Ip,
+        src_port,
+        state,
+        reason,
+        rtt,
+        1,
+    ))
+}
+
+/// Interprets the response to a protocol probe.
+///
+/// Packet of the protocol -> open. ICMP protocol unreachable -> closed.
+/// Other ICMP unreachable errors -> filtered. No response -> open or filtered.
+fn classify_protocol_response(
+    response: Option<Vec<u8>>,
+    protocol: u8,
+) -> Result<(PortState, Reason)> {
+    // No response -> open or filtered.
+    let packet = match response {
+        Some(packet) => packet,
+        None => return Ok((PortState::OpenOrFiltered, Reason::NoResponse)),
+    };
+
+    let ethernet_packet =
+        EthernetPacket::new(&packet).ok_or(ScannerError::CantCreateEthernetPacket)?;
+
+    let ipv4_packet =
+        Ipv4Packet::new(ethernet_packet.payload()).ok_or(ScannerError::CantCreateIpv4Packet)?;
+
+    match ipv4_packet.get_next_level_protocol() {
+        // Any packet of the protocol -> open.
+        IpNextHeaderProtocol(next) if next == protocol => {
+            Ok((PortState::Open, Reason::ProtocolResponse))
+        }
+        IpNextHeaderProtocols::Icmp => {
+            let icmp_packet =
+                IcmpPacket::new(ipv4_packet.payload()).ok_or(ScannerError::CantCreateIcmpPacket)?;
+            match icmp_packet.get_icmp_type() {
+                // ICMP protocol unreachable -> closed. Other ICMP unreachable errors -> filtered.
+                IcmpTypes::DestinationUnreachable => {
+                    let code = icmp_packet.get_icmp_code();
+                    let state = match code {
+                        IcmpCodes::DestinationProtocolUnreachable => PortState::Closed,
+                        _ => PortState::Filtered,
+                    };
+                    Ok((state, Reason::IcmpUnreachable(code.0)))
+                }
+                // Unexpected ICMP response.
+                _ => Err(ScannerError::UnexpectedIcmpResponse.into()),
+            }
+        }
+        // Unexpected response.
+        _ => Err(ScannerError::UnexpectedProtocolResponse.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::{
+        osi_layers::DatalinkLayer,
+        probe::{build_ipv4_header, IPV4_HEADER_SIZE},
+    };
+    use pnet::{
+        packet::{
+            ethernet::EtherTypes,
+            icmp::{destination_unreachable::MutableDestinationUnreachablePacket, IcmpCode},
+        },
+        util::MacAddr,
+    };
+    use std::net::Ipv4Addr;
+
+    const GRE: u8 = 47;
+
+    /// Builds an Ethernet frame carrying a bare IP datagram of the protocol from the target.
+    fn protocol_response(protocol: u8) -> Vec<u8> {
+        let packet = build_protocol_probe(
+            Ipv4Addr::new(192, 168, 0, 2),
+            Ipv4Addr::new(192, 168, 0, 1),
+            protocol,
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        DatalinkLayer::build_ethernet_frame(
+            MacAddr::zero(),
+            MacAddr::zero(),
+            EtherTypes::Ipv4,
+            &packet,
+        )
+    }
+
+    /// Builds an Ethernet frame carrying an ICMP unreachable error with the given code.
+    fn icmp_unreachable(code: u8) -> Vec<u8> {
+        let mut packet = vec![0u8; IPV4_HEADER_SIZE + 8];
+        build_ipv4_header(
+            &mut packet,
+            Ipv4Addr::new(192, 168, 0, 2),
+            Ipv4Addr::new(192, 168, 0, 1),
+            IpNextHeaderProtocols::Icmp,
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let mut icmp_packet =
+            MutableDestinationUnreachablePacket::new(&mut packet[IPV4_HEADER_SIZE..]).unwrap();
+        icmp_packet.set_icmp_type(IcmpTypes::DestinationUnreachable);
+        icmp_packet.set_icmp_code(IcmpCode(code));
+        DatalinkLayer::build_ethernet_frame(
+            MacAddr::zero(),
+            MacAddr::zero(),
+            EtherTypes::Ipv4,
+            &packet,
+        )
+    }
+
+    #[test]
+    fn test_classify_protocol_response() -> Result<()> {
+        let result = classify_protocol_response(None, GRE)?;
+        assert_eq!(result, (PortState::OpenOrFiltered, Reason::NoResponse));
+
+        let result = classify_protocol_response(Some(protocol_response(GRE)), GRE)?;
+        assert_eq!(result, (PortState::Open, Reason::ProtocolResponse));
+
+        let result = classify_protocol_response(Some(icmp_unreachable(2)), GRE)?;
+        assert_eq!(result, (PortState::Closed, Reason::IcmpUnreachable(2)));
+
+        let result = classify_protocol_response(Some(icmp_unreachable(13)), GRE)?;
+        assert_eq!(result, (PortState::Filtered, Reason::IcmpUnreachable(13)));
+
+        assert!(classify_protocol_response(Some(protocol_response(50)), GRE).is_err());
+
+        Ok(())
+    }
+}
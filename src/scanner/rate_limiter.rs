@@ -0,0 +1,125 @@
+use rand::Rng;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+
+/// Paces probes to a maximum number per second.
+///
+/// Sending as fast as possible overwhelms targets and gets scanners blocked by IDS.
+/// Each call to `wait` reserves the next slot, so that probes are evenly spaced.
+/// Slots that were missed are not caught up on, which prevents bursts after a pause.
+///
+/// Evenly spaced probes are easy to spot for timing-based IDS though, so a random delay
+/// may be added to each slot, see `with_delay`.
+pub struct RateLimiter {
+    interval: Option<Duration>,
+    delay: Option<(Duration, Duration)>,
+    next_slot: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a limiter for `rate` probes per second. `None` or `0` means unlimited.
+    pub fn new(rate: Option<u32>) -> Self {
+        RateLimiter {
+            interval: rate
+                .filter(|rate| *rate > 0)
+                .map(|rate| Duration::from_secs(1) / rate),
+            delay: None,
+            next_slot: Instant::now(),
+        }
+    }
+
+    /// Adds a random delay between the shortest and longest given delay, both included,
+    /// to the time between two probes. `None` adds no delay.
+    pub fn with_delay(mut self, delay: Option<(Duration, Duration)>) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Waits until the next probe may be sent.
+    pub async fn wait(&mut self) {
+        if self.interval.is_none() && self.delay.is_none() {
+            return;
+        }
+
+        let now = Instant::now();
+        if self.next_slot > now {
+            time::sleep_until(self.next_slot).await;
+        } else {
+            self.next_slot = now;
+        }
+        self.next_slot += self.interval.unwrap_or_default();
+
+        // The delay counts from when the probe is actually sent, so oversleeping never shortens it.
+        if let Some((shortest, longest)) = self.delay {
+            let delay = rand::thread_rng().gen_range(shortest..=longest);
+            self.next_slot = self.next_slot.max(Instant::now()) + delay;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rate_stays_under_cap() {
+        let rate = 50;
+        let probes = 10;
+        let mut limiter = RateLimiter::new(Some(rate));
+
+        let start = Instant::now();
+        for _ in 0..probes {
+            limiter.wait().await;
+        }
+        let elapsed = start.elapsed();
+
+        // The first probe is sent immediately, each following one waits for its slot.
+        let expected = Duration::from_secs(1) / rate * (probes - 1);
+        assert!(
+            elapsed >= expected,
+            "{elapsed:?} is faster than {expected:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delays_stay_within_bounds() {
+        let shortest = Duration::from_millis(10);
+        let longest = Duration::from_millis(20);
+        let mut limiter = RateLimiter::new(None).with_delay(Some((shortest, longest)));
+
+        let mut probes = Vec::new();
+        for _ in 0..20 {
+            limiter.wait().await;
+            probes.push(Instant::now());
+        }
+
+        // Timers may fire late, but never early.
+        let slack = Duration::from_millis(15);
+        let gaps = probes
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect::<Vec<_>>();
+        for gap in &gaps {
+            assert!(
+                (shortest..=longest + slack).contains(gap),
+                "{gap:?} is out of bounds"
+            );
+        }
+
+        // The delays are random, not a fixed interval.
+        assert!(gaps.iter().any(|gap| *gap != gaps[0]));
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_rate() {
+        for rate in [None, Some(0)] {
+            let mut limiter = RateLimiter::new(rate);
+
+            let start = Instant::now();
+            for _ in 0..1000 {
+                limiter.wait().await;
+            }
+            assert!(start.elapsed() < Duration::from_millis(100));
+        }
+    }
+}
@@ -0,0 +1,682 @@
+use crate::{errors::ScannerError, networking::osi_layers::Traffic, ports::service_name};
+use pnet::packet::icmp::{destination_unreachable::IcmpCodes, IcmpCode};
+#[cfg(feature = "json")]
+use serde::{Serialize, Serializer};
+use std::{fmt, net::IpAddr, time::Duration};
+
+/// State of a port as determined by a scan.
+///
+/// Host discovery scans use `Up` and `Down` to describe the state of the host itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub enum PortState {
+    Open,
+    Closed,
+    Filtered,
+    Unfiltered,
+    OpenOrFiltered,
+    ClosedOrFiltered,
+    /// The probes were sent from a spoofed address, so responses can't be captured,
+    /// or were not sent at all.
+    Unknown,
+    Up,
+    Down,
+}
+
+/// Formats the state like nmap, e.g. `open|filtered`. Honors the width, so that tables align.
+impl fmt::Display for PortState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            PortState::Open => "open",
+            PortState::Closed => "closed",
+            PortState::Filtered => "filtered",
+            PortState::Unfiltered => "unfiltered",
+            PortState::OpenOrFiltered => "open|filtered",
+            PortState::ClosedOrFiltered => "closed|filtered",
+            PortState::Unknown => "unknown",
+            PortState::Up => "up",
+            PortState::Down => "down",
+        })
+    }
+}
+
+/// Interprets the code of an ICMP destination unreachable error in response to a UDP probe.
+///
+/// Port unreachable (3) is sent by the host itself if nothing listens on the port -> closed.
+/// Host or protocol unreachable (1, 2) and administratively prohibited (9, 10, 13) are typically
+/// sent by firewalls and routers -> filtered. Other codes are treated alike.
+///
+/// Closed TCP ports answer with a RST flag instead, so an ICMP error to a TCP probe always comes
+/// from a firewall, e.g. iptables rejects with port unreachable by default. TCP scans thus treat
+/// any ICMP response as filtered.
+pub fn interpret_icmp_unreachable(code: u8) -> PortState {
+    match IcmpCode(code) {
+        IcmpCodes::DestinationPortUnreachable => PortState::Closed,
+        _ => PortState::Filtered,
+    }
+}
+
+/// Evidence a port state is based on, like the `--reason` flag of nmap.
+///
+/// Lets users audit why a port was classified as it was, since e.g. a filtered port may
+/// have stayed silent or answered with an ICMP error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// TCP packet with the SYN and ACK flags set.
+    SynAck,
+    /// TCP packet with the RST and ACK flags set.
+    RstAck,
+    /// TCP packet with the RST flag set, but not the ACK flag.
+    Rst,
+    /// ICMP destination unreachable error with the given code.
+    IcmpUnreachable(u8),
+    /// ICMPv6 destination unreachable error with the given code.
+    Icmpv6Unreachable(u8),
+    /// UDP packet from the scanned port.
+    UdpResponse,
+    /// SCTP packet with an INIT-ACK chunk.
+    InitAck,
+    /// SCTP packet with an ABORT chunk.
+    Abort,
+    /// IP packet of the probed protocol, see `ip_protocol_scan`.
+    ProtocolResponse,
+    /// The connect system call established a connection.
+    ConnectionAccepted,
+    /// The connect system call was refused.
+    ConnectionRefused,
+    /// No response arrived before the timeout.
+    NoResponse,
+    /// IP identification of the zombie of an idle scan increased by the given amount.
+    IpIdIncrement(u16),
+    /// The source address is not assigned to a local interface, so responses go elsewhere.
+    SpoofedSource,
+    /// The probes were built, but not sent, because of a dry run.
+    NotSent,
+    /// No response arrived, even when probed again, from a host that rate limits its RSTs.
+    /// The port may still be closed, see `RstLimitDetector`.
+    PossiblyRateLimited,
+    /// The probe could not be built, e.g. for an IP version the scan doesn't support.
+    BuildFailed,
+    /// The probe could not be sent or its response not received, e.g. on a socket error.
+    SendFailed,
+    /// Any other response, e.g. an ICMP error that is no unreachable error.
+    Other,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::SynAck => write!(f, "syn-ack"),
+            Reason::RstAck => write!(f, "rst-ack"),
+            Reason::Rst => write!(f, "rst"),
+            Reason::IcmpUnreachable(code) => write!(f, "icmp-unreachable-{}", code),
+            Reason::Icmpv6Unreachable(code) => write!(f, "icmpv6-unreachable-{}", code),
+            Reason::UdpResponse => write!(f, "udp-response"),
+            Reason::InitAck => write!(f, "init-ack"),
+            Reason::Abort => write!(f, "abort"),
+            Reason::ProtocolResponse => write!(f, "proto-response"),
+            Reason::ConnectionAccepted => write!(f, "conn-accepted"),
+            Reason::ConnectionRefused => write!(f, "conn-refused"),
+            Reason::NoResponse => write!(f, "no-response"),
+            Reason::IpIdIncrement(increment) => write!(f, "ipid-increment-{}", increment),
+            Reason::SpoofedSource => write!(f, "spoofed-source"),
+            Reason::NotSent => write!(f, "not-sent"),
+            Reason::PossiblyRateLimited => write!(f, "possibly-rate-limited"),
+            Reason::BuildFailed => write!(f, "build-failed"),
+            Reason::SendFailed => write!(f, "send-failed"),
+            Reason::Other => write!(f, "other"),
+        }
+    }
+}
+
+impl Reason {
+    /// Returns the stage a probe failed at, so that a failed socket can be told from a silent one.
+    ///
+    /// Errors of the packet builders -> build failed. Responses the scan can't interpret -> other.
+    /// All other errors, e.g. of the channel, ARP or the socket -> send failed.
+    pub fn of_error(error: &anyhow::Error) -> Reason {
+        match error.downcast_ref() {
+            Some(
+                ScannerError::CantCreateEthernetPacket
+                | ScannerError::CantCreateIpv4Packet
+                | ScannerError::CantCreateIpv6Packet
+                | ScannerError::CantCreateTcpPacket
+                | ScannerError::CantCreateIcmpPacket
+                | ScannerError::CantCreateUdpPacket
+                | ScannerError::UnsupportedIpVersion
+                | ScannerError::InvalidProtocolNumber(_)
+                | ScannerError::TooManyDecoys(_),
+            ) => Reason::BuildFailed,
+            Some(
+                ScannerError::UnexpectedTcpFlags
+                | ScannerError::UnexpectedIcmpResponse
+                | ScannerError::UnexpectedProtocolResponse,
+            ) => Reason::Other,
+            _ => Reason::SendFailed,
+        }
+    }
+}
+
+/// Serialized like it is displayed, so that JSON and CSV output agree.
+#[cfg(feature = "json")]
+impl Serialize for Reason {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Transport protocol of a scanned port.
+///
+/// Ordered to sort the table of `service_name`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "json", derive(Serialize), serde(rename_all = "lowercase"))]
+pub enum Protocol {
+    Tcp,
+    Udp,
+    Sctp,
+    /// IP protocol of an IP protocol scan, whose port numbers are protocol numbers.
+    Ip,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Protocol::Tcp => write!(f, "tcp"),
+            Protocol::Udp => write!(f, "udp"),
+            Protocol::Sctp => write!(f, "sctp"),
+            Protocol::Ip => write!(f, "ip"),
+        }
+    }
+}
+
+/// Column names of the table rows that `ScanResult` is displayed as.
+pub const RESULT_TABLE_HEADER: &str = "PORT      STATE            SERVICE";
+
+/// Result of scanning a single socket.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct ScanResult {
+    /// IP address of the scanned host.
+    #[cfg_attr(feature = "json", serde(rename = "host"))]
+    pub ip: IpAddr,
+    /// Port number of the scanned socket.
+    pub port: u16,
+    /// Transport protocol of the scanned socket.
+    pub protocol: Protocol,
+    /// Source port the probe was sent from. Is `0` if it is unknown.
+    pub src_port: u16,
+    /// State of the port.
+    pub state: PortState,
+    /// Evidence the state is based on.
+    pub reason: Reason,
+    /// Round-trip time. Is `None` if the host did not respond.
+    #[cfg_attr(
+        feature = "json",
+        serde(rename = "rtt_ms", serialize_with = "serialize_millis")
+    )]
+    pub rtt: Option<Duration>,
+    /// Total time spent probing the socket, including retransmissions and waiting for a
+    /// response that never came. Unlike `rtt` it is also known for silent ports.
+    #[cfg_attr(
+        feature = "json",
+        serde(rename = "elapsed_ms", serialize_with = "serialize_elapsed_millis")
+    )]
+    pub elapsed: Duration,
+    /// Number of probes sent, including retransmissions.
+    pub attempts: u16,
+    /// Banner of the service. Is `None` unless it was grabbed from an open port.
+    pub banner: Option<String>,
+    /// Error the scan of the socket failed with. Is `None` unless the reason is a failure,
+    /// see `Reason::of_error`.
+    pub error: Option<String>,
+}
+
+impl ScanResult {
+    /// Creates a new `ScanResult` struct.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        ip: IpAddr,
+        port: u16,
+        protocol: Protocol,
+        src_port: u16,
+        state: PortState,
+        reason: Reason,
+        rtt: Option<Duration>,
+        attempts: u16,
+    ) -> Self {
+        ScanResult {
+            ip,
+            port,
+            protocol,
+            src_port,
+            state,
+            reason,
+            rtt,
+            elapsed: Duration::ZERO,
+            attempts,
+            banner: None,
+            error: None,
+        }
+    }
+
+    /// Creates the result of a socket whose probe failed, which tells the error apart from
+    /// silence. The state is unknown, since no probe or no interpretable response got through.
+    pub fn failed(
+        ip: IpAddr,
+        port: u16,
+        protocol: Protocol,
+        src_port: u16,
+        error: &anyhow::Error,
+    ) -> Self {
+        ScanResult {
+            error: Some(error.to_string()),
+            ..ScanResult::new(
+                ip,
+                port,
+                protocol,
+                src_port,
+                PortState::Unknown,
+                Reason::of_error(error),
+                None,
+                0,
+            )
+        }
+    }
+
+    /// Creates the result of a socket whose probe was only built in a dry run, see
+    /// `ProbeOptions::dry_run`. The state is unknown, since nothing was sent.
+    pub fn not_sent(ip: IpAddr, port: u16, protocol: Protocol, src_port: u16) -> Self {
+        ScanResult::new(
+            ip,
+            port,
+            protocol,
+            src_port,
+            PortState::Unknown,
+            Reason::NotSent,
+            None,
+            0,
+        )
+    }
+
+    /// Returns the round-trip time in fractional milliseconds.
+    pub fn rtt_millis(&self) -> Option<f64> {
+        self.rtt.map(|rtt| rtt.as_secs_f64() * 1000.0)
+    }
+
+    /// Returns the total time spent probing in fractional milliseconds.
+    pub fn elapsed_millis(&self) -> f64 {
+        self.elapsed.as_secs_f64() * 1000.0
+    }
+}
+
+/// Displays the result as a table row like nmap, e.g. `22/tcp    open             ssh`.
+/// Ports without a well-known service show `unknown`, see `RESULT_TABLE_HEADER` for the columns.
+impl fmt::Display for ScanResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let socket = format!("{}/{}", self.port, self.protocol);
+        let service = service_name(self.port, self.protocol).unwrap_or("unknown");
+        write!(f, "{:<9} {:<16} {}", socket, self.state, service)
+    }
+}
+
+/// Summary of a port scan, e.g. to find out why a scan is slow or lossy.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScanStats {
+    /// Number of probes sent, including retransmissions.
+    pub probes_sent: u64,
+    /// Number of sockets that responded.
+    pub responses: u64,
+    /// Number of sockets that did not respond within the timeout.
+    pub timeouts: u64,
+    /// Number of probes that were resent, since no response arrived in time.
+    pub retransmits: u64,
+    /// Number of sockets that could not be scanned due to an error.
+    pub errors: u64,
+    /// Shortest round-trip time of a response.
+    pub min_rtt: Option<Duration>,
+    /// Longest round-trip time of a response.
+    pub max_rtt: Option<Duration>,
+    /// Sum of the round-trip times of all responses.
+    total_rtt: Duration,
+    /// Number of probes in flight at the end of the scan, see `CongestionWindow`.
+    pub window: usize,
+    /// Longest timeout derived from the round-trip times of a host, see `RttEstimator`.
+    /// Is `None` if no host responded, so all probes waited for the configured timeout.
+    pub timeout: Option<Duration>,
+    /// Time from the first probe until the last socket was scanned.
+    pub elapsed: Duration,
+    /// Frames and bytes sent during the scan, which count against the budget, see `Budget`.
+    pub traffic: Traffic,
+    /// Whether the scan stopped early because the budget was exhausted.
+    pub budget_exhausted: bool,
+    /// Whether the scan stopped early because the deadline of the budget passed.
+    pub deadline_exceeded: bool,
+    /// Number of hosts found to rate limit their RSTs, whose silent ports were probed again.
+    pub rst_rate_limits: u64,
+    /// Number of ICMP redirects and source quenches captured during the scan. They don't count
+    /// as responses, see `ControlMessage`.
+    pub control_messages: u64,
+}
+
+impl ScanStats {
+    /// Adds the probes and the response of a scanned socket.
+    pub fn record(&mut self, result: &ScanResult) {
+        self.probes_sent += result.attempts as u64;
+        self.retransmits += result.attempts.saturating_sub(1) as u64;
+
+        match result.rtt {
+            Some(rtt) => {
+                self.responses += 1;
+                self.total_rtt += rtt;
+                self.min_rtt = Some(self.min_rtt.map_or(rtt, |min| min.min(rtt)));
+                self.max_rtt = Some(self.max_rtt.map_or(rtt, |max| max.max(rtt)));
+            }
+            None => self.timeouts += 1,
+        }
+    }
+
+    /// Adds a socket that could not be scanned due to an error.
+    pub fn record_error(&mut self) {
+        self.errors += 1;
+    }
+
+    /// Adds the statistics of silent sockets that were probed again. Their first probes are
+    /// already counted as timeouts, so sockets that answered or failed this time no longer are.
+    pub fn record_retry(&mut self, retry: &ScanStats) {
+        self.probes_sent += retry.probes_sent;
+        self.retransmits += retry.retransmits;
+        self.responses += retry.responses;
+        self.errors += retry.errors;
+        self.timeouts = self.timeouts.saturating_sub(retry.responses + retry.errors);
+        self.total_rtt += retry.total_rtt;
+        self.min_rtt = match (self.min_rtt, retry.min_rtt) {
+            (Some(min), Some(retry_min)) => Some(min.min(retry_min)),
+            (min, retry_min) => min.or(retry_min),
+        };
+        self.max_rtt = self.max_rtt.max(retry.max_rtt);
+        self.elapsed += retry.elapsed;
+        self.traffic.packets += retry.traffic.packets;
+        self.traffic.bytes += retry.traffic.bytes;
+        self.control_messages += retry.control_messages;
+    }
+
+    /// Returns the average round-trip time of the responses.
+    pub fn avg_rtt(&self) -> Option<Duration> {
+        match self.responses {
+            0 => None,
+            responses => Some(self.total_rtt / responses as u32),
+        }
+    }
+
+    /// Returns the share of scanned sockets that did not respond, from 0 to 1.
+    pub fn loss(&self) -> f64 {
+        match self.responses + self.timeouts {
+            0 => 0.0,
+            scanned => self.timeouts as f64 / scanned as f64,
+        }
+    }
+}
+
+impl fmt::Display for ScanStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rtt = |rtt: Option<Duration>| rtt.map_or("-".to_string(), |rtt| format!("{:?}", rtt));
+        write!(
+            f,
+            "{} probes sent, {} retransmitted, {} responses, {} timeouts ({:.1}% loss), \
+             {} errors, RTT min/avg/max {}/{}/{}, window {}, timeout {}, took {:?}, \
+             {} packets and {} bytes sent{}{}{}{}",
+            self.probes_sent,
+            self.retransmits,
+            self.responses,
+            self.timeouts,
+            self.loss() * 100.0,
+            self.errors,
+            rtt(self.min_rtt),
+            rtt(self.avg_rtt()),
+            rtt(self.max_rtt),
+            self.window,
+            rtt(self.timeout),
+            self.elapsed,
+            self.traffic.packets,
+            self.traffic.bytes,
+            match self.rst_rate_limits {
+                0 => String::new(),
+                hosts => format!(", {} hosts rate limit RSTs", hosts),
+            },
+            match self.control_messages {
+                0 => String::new(),
+                messages => format!(", {} ICMP control messages ignored", messages),
+            },
+            match self.budget_exhausted {
+                true => ", budget exhausted",
+                false => "",
+            },
+            match self.deadline_exceeded {
+                true => ", deadline exceeded",
+                false => "",
+            }
+        )
+    }
+}
+
+/// Scan results of a single host.
+#[derive(Debug, Clone)]
+pub struct HostReport {
+    /// IP address of the scanned host.
+    pub host: IpAddr,
+    /// Host name of the PTR record of the address. Is `None` unless it was looked up and found.
+    pub hostname: Option<String>,
+    /// Scan results of the ports of the host, sorted by port.
+    pub results: Vec<ScanResult>,
+    /// Findings about the host as a whole that put its results into perspective.
+    pub notes: Vec<HostNote>,
+}
+
+/// Finding about a scanned host as a whole, see `HostReport::notes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostNote {
+    /// Implausibly many ports are open, as with tarpits and honeypots that answer every probe,
+    /// see `is_suspected_tarpit`. Its open ports are probably not real services.
+    SuspectedTarpit,
+}
+
+impl fmt::Display for HostNote {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HostNote::SuspectedTarpit => write!(f, "suspected tarpit"),
+        }
+    }
+}
+
+/// Formats the host like nmap, e.g. `192.168.1.1 (router.local)` if its host name is known.
+impl fmt::Display for HostReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.hostname {
+            Some(hostname) => write!(f, "{} ({})", self.host, hostname),
+            None => write!(f, "{}", self.host),
+        }
+    }
+}
+
+impl HostReport {
+    /// Groups the scan results by host, with one report for each host in `hosts`.
+    ///
+    /// Hosts without results get a report with no results, e.g. if every probe ran on an error.
+    pub fn group(hosts: &[IpAddr], results: Vec<ScanResult>) -> Vec<HostReport> {
+        let mut reports = hosts
+            .iter()
+            .map(|host| HostReport {
+                host: *host,
+                hostname: None,
+                results: Vec::new(),
+                notes: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+
+        for result in results {
+            if let Some(report) = reports.iter_mut().find(|report| report.host == result.ip) {
+                report.results.push(result);
+            }
+        }
+
+        for report in &mut reports {
+            report.results.sort_by_key(|result| result.port);
+        }
+
+        reports
+    }
+}
+
+/// Serializes a round-trip time as fractional milliseconds, like `ScanResult::rtt_millis`.
+#[cfg(feature = "json")]
+fn serialize_millis<S: Serializer>(
+    rtt: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    rtt.map(|rtt| rtt.as_secs_f64() * 1000.0)
+        .serialize(serializer)
+}
+
+/// Serializes the time spent probing as fractional milliseconds, like `ScanResult::elapsed_millis`.
+#[cfg(feature = "json")]
+fn serialize_elapsed_millis<S: Serializer>(
+    elapsed: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    (elapsed.as_secs_f64() * 1000.0).serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_scan_result() {
+        let host = IpAddr::V4(std::net::Ipv4Addr::new(192, 168, 1, 1));
+        let result = |port, protocol, state| {
+            ScanResult::new(host, port, protocol, 0, state, Reason::NoResponse, None, 1)
+        };
+
+        let row = result(22, Protocol::Tcp, PortState::Open).to_string();
+        assert_eq!(row, "22/tcp    open             ssh");
+
+        // The columns line up with the header.
+        assert_eq!(row.find("open"), RESULT_TABLE_HEADER.find("STATE"));
+        assert_eq!(row.find("ssh"), RESULT_TABLE_HEADER.find("SERVICE"));
+
+        let row = result(12345, Protocol::Udp, PortState::OpenOrFiltered).to_string();
+        assert_eq!(row, "12345/udp open|filtered    unknown");
+    }
+
+    #[test]
+    fn test_group_host_reports() {
+        let first = IpAddr::from([192, 168, 1, 1]);
+        let second = IpAddr::from([192, 168, 1, 2]);
+        let result = |ip, port| {
+            ScanResult::new(
+                ip,
+                port,
+                Protocol::Tcp,
+                50000,
+                PortState::Closed,
+                Reason::RstAck,
+                None,
+                1,
+            )
+        };
+
+        let results = vec![result(second, 80), result(first, 443), result(first, 22)];
+        let mut reports = HostReport::group(&[first, second, IpAddr::from([10, 0, 0, 1])], results);
+
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[0].host, first);
+        let ports = reports[0].results.iter().map(|result| result.port);
+        assert_eq!(ports.collect::<Vec<_>>(), [22, 443]);
+        assert_eq!(reports[1].results.len(), 1);
+        assert!(reports[2].results.is_empty());
+
+        assert_eq!(reports[0].to_string(), "192.168.1.1");
+        reports[0].hostname = Some("router.local".to_string());
+        assert_eq!(reports[0].to_string(), "192.168.1.1 (router.local)");
+    }
+
+    #[test]
+    fn test_scan_stats() {
+        let ip = IpAddr::from([192, 168, 1, 1]);
+        let result = |rtt: Option<u64>, attempts| {
+            ScanResult::new(
+                ip,
+                80,
+                Protocol::Tcp,
+                50000,
+                PortState::Open,
+                Reason::SynAck,
+                rtt.map(Duration::from_millis),
+                attempts,
+            )
+        };
+
+        let mut stats = ScanStats::default();
+        assert_eq!(stats.avg_rtt(), None);
+        assert_eq!(stats.loss(), 0.0);
+
+        stats.record(&result(Some(10), 1));
+        stats.record(&result(Some(30), 2));
+        stats.record(&result(None, 3));
+        stats.record_error();
+
+        assert_eq!(stats.probes_sent, 6);
+        assert_eq!(stats.retransmits, 3);
+        assert_eq!(stats.responses, 2);
+        assert_eq!(stats.timeouts, 1);
+        assert_eq!(stats.errors, 1);
+        assert_eq!(stats.min_rtt, Some(Duration::from_millis(10)));
+        assert_eq!(stats.avg_rtt(), Some(Duration::from_millis(20)));
+        assert_eq!(stats.max_rtt, Some(Duration::from_millis(30)));
+        assert!((stats.loss() - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_record_retry() {
+        let ip = IpAddr::from([192, 168, 1, 1]);
+        let result = |reason, rtt: Option<u64>| {
+            ScanResult::new(
+                ip,
+                80,
+                Protocol::Tcp,
+                50000,
+                PortState::Closed,
+                reason,
+                rtt.map(Duration::from_millis),
+                1,
+            )
+        };
+
+        let mut stats = ScanStats::default();
+        stats.record(&result(Reason::Rst, Some(20)));
+        stats.record(&result(Reason::NoResponse, None));
+        stats.record(&result(Reason::NoResponse, None));
+
+        // One of the silent sockets answers when probed again.
+        let mut retry = ScanStats::default();
+        retry.record(&result(Reason::Rst, Some(5)));
+        retry.record(&result(Reason::PossiblyRateLimited, None));
+        stats.record_retry(&retry);
+        stats.rst_rate_limits += 1;
+
+        assert_eq!(stats.probes_sent, 5);
+        assert_eq!(stats.responses, 2);
+        assert_eq!(stats.timeouts, 1);
+        assert_eq!(stats.min_rtt, Some(Duration::from_millis(5)));
+        assert_eq!(stats.max_rtt, Some(Duration::from_millis(20)));
+        assert!(stats.to_string().ends_with(", 1 hosts rate limit RSTs"));
+    }
+
+    #[test]
+    fn test_interpret_icmp_unreachable() {
+        assert_eq!(interpret_icmp_unreachable(3), PortState::Closed);
+        for code in [0, 1, 2, 9, 10, 13] {
+            assert_eq!(interpret_icmp_unreachable(code), PortState::Filtered);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use super::results::{Reason, ScanResult};
+use std::{collections::HashMap, net::IpAddr};
+
+/// Fewest RST responses of a host before its silence hints at rate limiting, not at a filter.
+const MIN_RSTS: usize = 3;
+
+/// Number of consecutive silent sockets of a host after which its RSTs count as rate limited.
+const SILENCE_THRESHOLD: usize = 8;
+
+/// Probes per second when silent ports of a host that rate limits RSTs are probed again.
+/// Stacks that limit RSTs usually allow tens to hundreds per second, e.g. 200 for FreeBSD.
+pub const RST_LIMITED_RATE: u32 = 10;
+
+/// Detects hosts that rate limit the RST flags they send, like FreeBSD and macOS do by default.
+///
+/// A throttled RST is indistinguishable from a filtered port, so closed ports show up as filtered
+/// in SYN scans and as open or filtered in FIN, NULL and XMAS scans. Firewalls tend to drop probes
+/// from the very first one, whereas a host that throttles RSTs answers at first and then suddenly
+/// keeps silent. Such a streak of silence after a few RSTs is taken as rate limiting.
+#[derive(Debug, Default)]
+pub struct RstLimitDetector {
+    hosts: HashMap<IpAddr, HostRsts>,
+}
+
+/// Responses of a single host so far.
+#[derive(Debug, Default)]
+struct HostRsts {
+    rsts: usize,
+    silent_streak: usize,
+    limited: bool,
+}
+
+impl RstLimitDetector {
+    /// Adds the result of a socket, in the order the sockets complete.
+    ///
+    /// Returns `true` once the host is found to rate limit its RSTs, but not for later results.
+    pub fn record(&mut self, scan: &ScanResult) -> bool {
+        if scan.error.is_some() {
+            return false;
+        }
+
+        let host = self.hosts.entry(scan.ip).or_default();
+        match scan.reason {
+            Reason::Rst | Reason::RstAck => {
+                host.rsts += 1;
+                host.silent_streak = 0;
+            }
+            Reason::NoResponse => host.silent_streak += 1,
+            // Any other response shows that the host still talks.
+            _ => host.silent_streak = 0,
+        }
+
+        if !host.limited && host.rsts >= MIN_RSTS && host.silent_streak >= SILENCE_THRESHOLD {
+            host.limited = true;
+            return true;
+        }
+        false
+    }
+
+    /// Returns the hosts that rate limit their RSTs.
+    pub fn limited_hosts(&self) -> Vec<IpAddr> {
+        let mut hosts = self
+            .hosts
+            .iter()
+            .filter(|(_, host)| host.limited)
+            .map(|(ip, _)| *ip)
+            .collect::<Vec<_>>();
+        hosts.sort();
+        hosts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::results::{PortState, Protocol};
+    use std::{net::Ipv4Addr, time::Duration};
+
+    fn scan(ip: IpAddr, port: u16, reason: Reason) -> ScanResult {
+        let rtt = (reason != Reason::NoResponse).then_some(Duration::from_millis(1));
+        ScanResult::new(
+            ip,
+            port,
+            Protocol::Tcp,
+            0,
+            PortState::Closed,
+            reason,
+            rtt,
+            1,
+        )
+    }
+
+    #[test]
+    fn test_rst_limit_detector() {
+        let limiting = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1));
+        let filtered = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 2));
+        let mut detector = RstLimitDetector::default();
+
+        // The host answers at first, then keeps silent.
+        for port in 1..=MIN_RSTS as u16 {
+            assert!(!detector.record(&scan(limiting, port, Reason::Rst)));
+        }
+        for port in 100..100 + SILENCE_THRESHOLD as u16 - 1 {
+            assert!(!detector.record(&scan(limiting, port, Reason::NoResponse)));
+        }
+        assert!(detector.record(&scan(limiting, 200, Reason::NoResponse)));
+        assert!(!detector.record(&scan(limiting, 201, Reason::NoResponse)));
+
+        // A host that was silent from the start is filtered instead.
+        for port in 1..=100 {
+            assert!(!detector.record(&scan(filtered, port, Reason::NoResponse)));
+        }
+
+        // Responses in between break the streak.
+        let busy = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 3));
+        for port in 1..=100 {
+            let reason = match port % SILENCE_THRESHOLD as u16 {
+                0 => Reason::RstAck,
+                _ => Reason::NoResponse,
+            };
+            assert!(!detector.record(&scan(busy, port, reason)));
+        }
+
+        assert_eq!(detector.limited_hosts(), vec![limiting]);
+    }
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+/// Shortest timeout derived from round-trip times, as used by nmap.
+/// Shorter timeouts would give up on probes that are merely delayed for a moment.
+const MIN_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Longest timeout derived from round-trip times, as used by nmap.
+const MAX_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Estimates the time to wait for a response from the round-trip times of earlier probes.
+///
+/// Follows the computation of the retransmission timeout of TCP (RFC 6298). The smoothed
+/// round-trip time (SRTT) and its variation (RTTVAR) are moving averages of the measurements,
+/// and the timeout is SRTT plus four times RTTVAR. Fast hosts thus get short timeouts, while
+/// distant or jittery hosts get longer ones. Until a round-trip time is measured, the initial
+/// timeout applies.
+#[derive(Debug, Clone)]
+pub struct RttEstimator {
+    initial_timeout: Duration,
+    smoothed_rtt: Option<Duration>,
+    rtt_variation: Duration,
+}
+
+impl RttEstimator {
+    /// Creates an estimator that starts with the given timeout.
+    pub fn new(initial_timeout: Duration) -> Self {
+        RttEstimator {
+            initial_timeout,
+            smoothed_rtt: None,
+            rtt_variation: Duration::ZERO,
+        }
+    }
+
+    /// Adds the round-trip time of a response.
+    pub fn record(&mut self, rtt: Duration) {
+        match self.smoothed_rtt {
+            None => {
+                self.smoothed_rtt = Some(rtt);
+                self.rtt_variation = rtt / 2;
+            }
+            Some(smoothed_rtt) => {
+                let deviation = match smoothed_rtt > rtt {
+                    true => smoothed_rtt - rtt,
+                    false => rtt - smoothed_rtt,
+                };
+                self.rtt_variation = (self.rtt_variation * 3 + deviation) / 4;
+                self.smoothed_rtt = Some((smoothed_rtt * 7 + rtt) / 8);
+            }
+        }
+    }
+
+    /// Returns the time to wait for the response to the next probe.
+    pub fn timeout(&self) -> Duration {
+        match self.smoothed_rtt {
+            Some(smoothed_rtt) => {
+                (smoothed_rtt + self.rtt_variation * 4).clamp(MIN_TIMEOUT, MAX_TIMEOUT)
+            }
+            None => self.initial_timeout,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeout_follows_rtt() {
+        let mut estimator = RttEstimator::new(Duration::from_secs(1));
+        assert_eq!(estimator.timeout(), Duration::from_secs(1));
+
+        // The first measurement sets SRTT to 100ms and RTTVAR to 50ms.
+        estimator.record(Duration::from_millis(100));
+        assert_eq!(estimator.timeout(), Duration::from_millis(300));
+
+        // Steady round-trip times shrink the variation.
+        estimator.record(Duration::from_millis(100));
+        assert_eq!(estimator.timeout(), Duration::from_millis(250));
+
+        // A late response grows both.
+        estimator.record(Duration::from_millis(900));
+        assert_eq!(estimator.timeout(), Duration::from_micros(1_112_500));
+    }
+
+    #[test]
+    fn test_timeout_is_bounded() {
+        let mut fast = RttEstimator::new(Duration::from_secs(1));
+        fast.record(Duration::from_millis(1));
+        assert_eq!(fast.timeout(), MIN_TIMEOUT);
+
+        let mut slow = RttEstimator::new(Duration::from_secs(1));
+        slow.record(Duration::from_secs(5));
+        assert_eq!(slow.timeout(), MAX_TIMEOUT);
+    }
+}
@@ -0,0 +1,167 @@
+use super::results::{PortState, Protocol, Reason, ScanResult};
+use crate::{
+    errors::ScannerError,
+    networking::{
+        interface::Interface,
+        osi_layers::Exchange,
+        probe::ProbeOptions,
+        sctp::{Sctp, CHUNK_ABORT, CHUNK_INIT_ACK},
+    },
+};
+use anyhow::Result;
+use pnet::packet::{
+    ethernet::EthernetPacket,
+    icmp::{IcmpPacket, IcmpTypes},
+    ip::IpNextHeaderProtocols,
+    ipv4::Ipv4Packet,
+    Packet,
+};
+use std::{net::IpAddr, time::Duration};
+
+/// SCTP INIT determines the status of SCTP ports on a target machine.
+///
+/// SCTP is used by telecom services like Diameter and SIGTRAN, which often run on no TCP port.
+///
+/// Scan sends SCTP packets with an INIT chunk, which opens an association like a TCP SYN opens
+/// a connection. Like a SYN scan, it never completes the four-way handshake.
+///
+/// RFC 4960 expected behavior is that an open port will respond with an INIT-ACK chunk.
+/// A closed port will respond with an ABORT chunk. No response or an ICMP unreachable error
+/// indicates a filtered port.
+pub fn sctp_init_scan(
+    interface: Interface,
+    src_ip: IpAddr,
+    src_port: u16,
+    dest_ip: IpAddr,
+    dest_port: u16,
+    options: ProbeOptions,
+    timeout: Duration,
+) -> Result<ScanResult> {
+    let ipv4_src = match src_ip {
+        IpAddr::V4(ip) => ip,
+        _ => Err(ScannerError::UnsupportedIpVersion)?,
+    };
+
+    let ipv4_dest = match dest_ip {
+        IpAddr::V4(ip) => ip,
+        _ => Err(ScannerError::UnsupportedIpVersion)?,
+    };
+
+    let exchange = Sctp::send_init_packet(
+        interface, ipv4_src, src_port, ipv4_dest, dest_port, options, timeout,
+    )?;
+
+    let Exchange::Sent(response, _, rtt) = exchange else {
+        return Ok(ScanResult::not_sent(
+            dest_ip,
+            dest_port,
+            Protocol::Sctp,
+            src_port,
+        ));
+    };
+
+    let rtt = response.as_ref().map(|_| rtt);
+    let (state, reason) = classify_init_response(response)?;
+
+    Ok(ScanResult::new(
+        dest_ip,
+        dest_port,
+        Protocol::Sctp,
+        src_port,
+        state,
+        reason,
+        rtt,
+        1,
+    ))
+}
+
+/// Interprets the response to an INIT probe.
+///
+/// INIT-ACK chunk -> open. ABORT chunk -> closed. No response or ICMP unreachable -> filtered.
+fn classify_init_response(response: Option<Vec<u8>>) -> Result<(PortState, Reason)> {
+    // No response -> filtered.
+    let packet = match response {
+        Some(packet) => packet,
+        None => return Ok((PortState::Filtered, Reason::NoResponse)),
+    };
+
+    let ethernet_packet =
+        EthernetPacket::new(&packet).ok_or(ScannerError::CantCreateEthernetPacket)?;
+
+    let ipv4_packet =
+        Ipv4Packet::new(ethernet_packet.payload()).ok_or(ScannerError::CantCreateIpv4Packet)?;
+
+    match ipv4_packet.get_next_level_protocol() {
+        IpNextHeaderProtocols::Sctp => match Sctp::chunk_type(ipv4_packet.payload()) {
+            // INIT-ACK chunk -> open.
+            Some(CHUNK_INIT_ACK) => Ok((PortState::Open, Reason::InitAck)),
+            // ABORT chunk -> closed.
+            Some(CHUNK_ABORT) => Ok((PortState::Closed, Reason::Abort)),
+            // Unexpected chunk.
+            _ => Err(ScannerError::UnexpectedProtocolResponse.into()),
+        },
+        IpNextHeaderProtocols::Icmp => {
+            let icmp_packet =
+                IcmpPacket::new(ipv4_packet.payload()).ok_or(ScannerError::CantCreateIcmpPacket)?;
+            match icmp_packet.get_icmp_type() {
+                // ICMP unreachable -> filtered, e.g. protocol unreachable if SCTP is not supported.
+                IcmpTypes::DestinationUnreachable => Ok((
+                    PortState::Filtered,
+                    Reason::IcmpUnreachable(icmp_packet.get_icmp_code().0),
+                )),
+                // Unexpected ICMP response.
+                _ => Err(ScannerError::UnexpectedIcmpResponse.into()),
+            }
+        }
+        // Unexpected response.
+        _ => Err(ScannerError::UnexpectedProtocolResponse.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::{
+        osi_layers::DatalinkLayer,
+        probe::IPV4_HEADER_SIZE,
+        sctp::{CHUNK_INIT, SCTP_HEADER_SIZE},
+    };
+    use pnet::{packet::ethernet::EtherTypes, util::MacAddr};
+    use std::net::Ipv4Addr;
+
+    /// Builds an Ethernet frame carrying an SCTP packet whose first chunk has the given type.
+    fn sctp_response(chunk_type: u8) -> Vec<u8> {
+        let mut packet = Sctp::build_init_packet(
+            Ipv4Addr::new(192, 168, 0, 2),
+            3868,
+            Ipv4Addr::new(192, 168, 0, 1),
+            12345,
+            1,
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        packet[IPV4_HEADER_SIZE + SCTP_HEADER_SIZE] = chunk_type;
+        DatalinkLayer::build_ethernet_frame(
+            MacAddr::zero(),
+            MacAddr::zero(),
+            EtherTypes::Ipv4,
+            &packet,
+        )
+    }
+
+    #[test]
+    fn test_classify_init_response() -> Result<()> {
+        let result = classify_init_response(None)?;
+        assert_eq!(result, (PortState::Filtered, Reason::NoResponse));
+
+        let result = classify_init_response(Some(sctp_response(CHUNK_INIT_ACK)))?;
+        assert_eq!(result, (PortState::Open, Reason::InitAck));
+
+        let result = classify_init_response(Some(sctp_response(CHUNK_ABORT)))?;
+        assert_eq!(result, (PortState::Closed, Reason::Abort));
+
+        assert!(classify_init_response(Some(sctp_response(CHUNK_INIT))).is_err());
+
+        Ok(())
+    }
+}
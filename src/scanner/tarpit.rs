@@ -0,0 +1,102 @@
+use super::results::{PortState, ScanResult};
+
+/// Fraction of the scanned ports of a host that must be open to suspect a tarpit, if not
+/// configured otherwise. Real hosts rarely open more than a few dozen ports.
+pub const DEFAULT_TARPIT_THRESHOLD: f64 = 0.9;
+
+/// Fewest ports with a known state before a host may be suspected, so that hosts scanned
+/// on a handful of ports, e.g. just 80 and 443, are never flagged.
+const MIN_PORTS: usize = 20;
+
+/// Returns whether the results of a host look like a tarpit or honeypot, which answer every
+/// probe with a SYN/ACK, so that all ports appear open.
+///
+/// A host is suspected if at least `threshold` of its successfully scanned ports are open.
+/// Failed results reveal no state and are skipped.
+pub fn is_suspected_tarpit(results: &[ScanResult], threshold: f64) -> bool {
+    let scanned = results.iter().filter(|scan| scan.error.is_none()).count();
+    let open = results
+        .iter()
+        .filter(|scan| scan.error.is_none() && scan.state == PortState::Open)
+        .count();
+
+    scanned >= MIN_PORTS && open as f64 >= threshold * scanned as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        errors::ScannerError,
+        scanner::results::{Protocol, Reason},
+    };
+    use std::net::{IpAddr, Ipv4Addr};
+
+    const HOST: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1));
+
+    /// Returns results of the ports `1..=ports`, of which the first `open` are open.
+    fn results(ports: u16, open: u16) -> Vec<ScanResult> {
+        (1..=ports)
+            .map(|port| {
+                let (state, reason) = match port <= open {
+                    true => (PortState::Open, Reason::SynAck),
+                    false => (PortState::Closed, Reason::RstAck),
+                };
+                ScanResult::new(HOST, port, Protocol::Tcp, 0, state, reason, None, 1)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_is_suspected_tarpit() {
+        // Every port open is the classic signature.
+        assert!(is_suspected_tarpit(
+            &results(1000, 1000),
+            DEFAULT_TARPIT_THRESHOLD
+        ));
+        assert!(is_suspected_tarpit(
+            &results(1000, 900),
+            DEFAULT_TARPIT_THRESHOLD
+        ));
+
+        // A busy server still closes most ports.
+        assert!(!is_suspected_tarpit(
+            &results(1000, 899),
+            DEFAULT_TARPIT_THRESHOLD
+        ));
+        assert!(!is_suspected_tarpit(
+            &results(1000, 30),
+            DEFAULT_TARPIT_THRESHOLD
+        ));
+        assert!(!is_suspected_tarpit(&[], DEFAULT_TARPIT_THRESHOLD));
+
+        // A few open ports out of a few scanned ones say nothing.
+        assert!(!is_suspected_tarpit(
+            &results(MIN_PORTS as u16 - 1, MIN_PORTS as u16 - 1),
+            DEFAULT_TARPIT_THRESHOLD
+        ));
+        assert!(is_suspected_tarpit(
+            &results(MIN_PORTS as u16, MIN_PORTS as u16),
+            DEFAULT_TARPIT_THRESHOLD
+        ));
+
+        // The threshold is configurable.
+        assert!(is_suspected_tarpit(&results(100, 50), 0.5));
+        assert!(!is_suspected_tarpit(&results(100, 99), 1.0));
+    }
+
+    #[test]
+    fn test_tarpit_skips_failed_results() {
+        let error = ScannerError::CantResolveMac(HOST).into();
+        let mut scans = results(MIN_PORTS as u16, MIN_PORTS as u16);
+        assert!(is_suspected_tarpit(&scans, DEFAULT_TARPIT_THRESHOLD));
+
+        // Failed results neither count as scanned nor as open.
+        scans.extend(
+            (1000..1100).map(|port| ScanResult::failed(HOST, port, Protocol::Tcp, 0, &error)),
+        );
+        assert!(is_suspected_tarpit(&scans, DEFAULT_TARPIT_THRESHOLD));
+        scans.truncate(MIN_PORTS - 1);
+        assert!(!is_suspected_tarpit(&scans, DEFAULT_TARPIT_THRESHOLD));
+    }
+}
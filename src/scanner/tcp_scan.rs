@@ -1,20 +1,26 @@
-use super::engine::ScanResult;
+use super::results::{PortState, Protocol, Reason, ScanResult};
 use crate::{
     errors::ScannerError,
-    networking::{interface::Interface, tcp::Tcp},
+    networking::{
+        interface::Interface,
+        osi_layers::{ipv6_upper_layer, Exchange},
+        probe::ProbeOptions,
+        socks::Socks5,
+        tcp::Tcp,
+    },
 };
 use anyhow::Result;
 use pnet::packet::{
-    ethernet::EthernetPacket,
+    ethernet::{EtherTypes, EthernetPacket},
+    icmp::{IcmpPacket, IcmpTypes},
+    icmpv6::{Icmpv6Packet, Icmpv6Types},
     ip::IpNextHeaderProtocols,
     ipv4::Ipv4Packet,
+    ipv6::Ipv6Packet,
     tcp::{TcpFlags, TcpPacket},
     Packet,
 };
-use std::{
-    net::{IpAddr, SocketAddr, TcpStream},
-    time::{Duration, Instant},
-};
+use std::{io::ErrorKind, net::IpAddr, time::Duration};
 
 /// TCP SYN determines the status of ports on a target machine.
 ///
@@ -27,91 +33,184 @@ use std::{
 /// RFC 793 expected behavior is that an open port will respond with a SYN-ACK flag.
 /// A closed port will respond with a RST flag. No response indicates a filtered port.
 /// Filtered ports may also respond with an ICMP Type 3 unreachable error, but we can ignore this.
+///
+/// Packets to loopback addresses never leave the machine, so the data link channel of the
+/// interface would not see them. Loopback ports are scanned with a connect scan instead,
+/// which tells open and closed ports apart just the same.
 pub fn tcp_syn_scan(
     interface: Interface,
     src_ip: IpAddr,
     src_port: u16,
     dest_ip: IpAddr,
     dest_port: u16,
+    options: ProbeOptions,
     timeout: Duration,
-) -> Result<(ScanResult, Duration)> {
-    let ipv4_src = match src_ip {
-        IpAddr::V4(ip) => ip,
-        _ => Err(ScannerError::UnsupportedIpVersion)?,
-    };
-
-    let ipv4_dest = match dest_ip {
-        IpAddr::V4(ip) => ip,
-        _ => Err(ScannerError::UnsupportedIpVersion)?,
-    };
+) -> Result<ScanResult> {
+    if dest_ip.is_loopback() {
+        // A proxy would connect to its own loopback address, not ours.
+        let options = ProbeOptions {
+            proxy: None,
+            ..options
+        };
+        return tcp_connect_scan(
+            interface, src_ip, src_port, dest_ip, dest_port, options, timeout,
+        );
+    }
 
-    let (response, rtt) = Tcp::send_tcp_packet(
+    probe_tcp(
         interface,
-        ipv4_src,
+        src_ip,
         src_port,
-        ipv4_dest,
+        dest_ip,
         dest_port,
         TcpFlags::SYN,
+        options,
         timeout,
-    )?;
-
-    // No response -> filtered.
-    let packet = match response {
-        Some(packet) => packet,
-        None => return Ok((ScanResult::Filtered, rtt)),
-    };
-
-    let ethernet_packet =
-        EthernetPacket::new(&packet).ok_or(ScannerError::CantCreateEthernetPacket)?;
+        classify_syn_response,
+    )
+}
 
-    let ipv4_packet =
-        Ipv4Packet::new(ethernet_packet.payload()).ok_or(ScannerError::CantCreateIpv4Packet)?;
+/// Interprets the response to a TCP probe, or its absence, as the state of the port.
+type Classify = fn(Option<Vec<u8>>) -> Result<(PortState, Reason)>;
 
-    // Not a TCP packet, perhaps ICMP unreachable error -> filtered.
-    if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
-        return Ok((ScanResult::Filtered, rtt));
-    }
+/// Sends a TCP probe with the given flags and interprets the response with `classify`.
+///
+/// A probe that was only built in a dry run is reported as not sent.
+#[allow(clippy::too_many_arguments)]
+fn probe_tcp(
+    interface: Interface,
+    src_ip: IpAddr,
+    src_port: u16,
+    dest_ip: IpAddr,
+    dest_port: u16,
+    flags: u8,
+    options: ProbeOptions,
+    timeout: Duration,
+    classify: Classify,
+) -> Result<ScanResult> {
+    let (exchange, attempts) = Tcp::send_tcp_packet(
+        interface, src_ip, src_port, dest_ip, dest_port, flags, options, timeout,
+    )?;
 
-    let tcp_packet =
-        TcpPacket::new(ipv4_packet.payload()).ok_or(ScannerError::CantCreateTcpPacket)?;
+    let Exchange::Sent(response, _, rtt) = exchange else {
+        return Ok(ScanResult::not_sent(
+            dest_ip,
+            dest_port,
+            Protocol::Tcp,
+            src_port,
+        ));
+    };
 
-    let tcp_flags = tcp_packet.get_flags();
+    let rtt = response.as_ref().map(|_| rtt);
+    let (state, reason) = classify(response)?;
 
-    // SYN/ACK flag set -> open.
-    if tcp_flags & TcpFlags::SYN != 0 && tcp_flags & TcpFlags::ACK != 0 {
-        return Ok((ScanResult::Open, rtt));
-    }
+    Ok(ScanResult::new(
+        dest_ip,
+        dest_port,
+        Protocol::Tcp,
+        src_port,
+        state,
+        reason,
+        rtt,
+        attempts,
+    ))
+}
 
-    // RST flag set -> closed.
-    if tcp_flags & TcpFlags::RST != 0 {
-        return Ok((ScanResult::Closed, rtt));
-    }
+/// Interprets the response to a SYN probe.
+///
+/// SYN/ACK flags -> open. RST flag -> closed. No response or ICMP unreachable -> filtered.
+fn classify_syn_response(response: Option<Vec<u8>>) -> Result<(PortState, Reason)> {
+    let response = parse_tcp_response(response)?;
+
+    let state = match response {
+        // No response -> filtered.
+        TcpResponse::Silent => PortState::Filtered,
+        // Not a TCP packet, perhaps ICMP unreachable error -> filtered.
+        TcpResponse::NotTcp { .. } => PortState::Filtered,
+        // SYN/ACK flag set -> open.
+        TcpResponse::Tcp { flags, .. }
+            if flags & TcpFlags::SYN != 0 && flags & TcpFlags::ACK != 0 =>
+        {
+            PortState::Open
+        }
+        // RST flag set -> closed.
+        TcpResponse::Tcp { flags, .. } if flags & TcpFlags::RST != 0 => PortState::Closed,
+        _ => return Err(ScannerError::UnexpectedTcpFlags.into()),
+    };
 
-    Err(ScannerError::UnexpectedTcpFlags.into())
+    Ok((state, response.reason()))
 }
 
-/// TCP connect determines if a port is open on a target machine.
+/// TCP connect determines the status of ports on a target machine.
 ///
 /// Establishes a full TCP connection, completing the three-way handshake.
-/// If the handshake cannot be established, the port is considered as closed.
-/// It can't distinguish filtered ports.
+/// Uses the connect system call and therefore does not require raw sockets.
+///
+/// A successful connection means the port is open. A refused connection means the
+/// target answered with a RST flag, i.e. the port is closed. If the connection times out
+/// or the target is unreachable, the port is considered as filtered.
 ///
 /// Involves sending a signifcant number of packets and is therefore slower than a SYN scan.
 /// It also causes considerable noise in event logs and is easily detected.
+///
+/// If a SOCKS5 proxy is configured, connections are opened through it, so the scan originates
+/// from the proxy. Fails if the proxy can't be reached or refuses to connect to the target.
+///
+/// The operating system builds the packets, so a dry run has none to show and connects nowhere.
 pub fn tcp_connect_scan(
     _interface: Interface,
     _src_ip: IpAddr,
     _src_port: u16,
     dest_ip: IpAddr,
     dest_port: u16,
+    options: ProbeOptions,
     timeout: Duration,
-) -> Result<(ScanResult, Duration)> {
-    let socket = SocketAddr::new(dest_ip, dest_port);
-    let start_time = Instant::now();
-    match TcpStream::connect_timeout(&socket, timeout) {
-        Ok(_) => Ok((ScanResult::Open, start_time.elapsed())),
-        Err(_) => Ok((ScanResult::Closed, start_time.elapsed())),
+) -> Result<ScanResult> {
+    // The operating system would pick the source port.
+    if options.dry_run {
+        return Ok(ScanResult::not_sent(dest_ip, dest_port, Protocol::Tcp, 0));
     }
+
+    let (stream, rtt) = match options.proxy {
+        Some(proxy) => Socks5::connect(proxy, dest_ip, dest_port, timeout)?,
+        None => Tcp::connect(dest_ip, dest_port, timeout),
+    };
+
+    // The operating system picks the source port, which is only known for established connections.
+    // Proxied connections originate from a port of the proxy, which is unknown.
+    let src_port = stream
+        .as_ref()
+        .ok()
+        .filter(|_| options.proxy.is_none())
+        .and_then(|stream| stream.local_addr().ok())
+        .map_or(0, |socket| socket.port());
+
+    // Dropping the stream closes the connection, with a RST if configured.
+    if let (Ok(stream), true) = (&stream, options.reset_connections) {
+        Tcp::reset_on_close(stream)?;
+    }
+
+    let (state, reason, rtt) = match stream {
+        // Connection established -> open.
+        Ok(_) => (PortState::Open, Reason::ConnectionAccepted, Some(rtt)),
+        // Connection refused -> closed.
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+            (PortState::Closed, Reason::ConnectionRefused, Some(rtt))
+        }
+        // Timeout or unreachable -> filtered.
+        Err(_) => (PortState::Filtered, Reason::NoResponse, None),
+    };
+
+    Ok(ScanResult::new(
+        dest_ip,
+        dest_port,
+        Protocol::Tcp,
+        src_port,
+        state,
+        reason,
+        rtt,
+        1,
+    ))
 }
 
 /// TCP ACK gathers information about the firewall or ACL configuration on a target machine.
@@ -129,56 +228,39 @@ pub fn tcp_ack_scan(
     src_port: u16,
     dest_ip: IpAddr,
     dest_port: u16,
+    options: ProbeOptions,
     timeout: Duration,
-) -> Result<(ScanResult, Duration)> {
-    let ipv4_src = match src_ip {
-        IpAddr::V4(ip) => ip,
-        _ => Err(ScannerError::UnsupportedIpVersion)?,
-    };
-
-    let ipv4_dest = match dest_ip {
-        IpAddr::V4(ip) => ip,
-        _ => Err(ScannerError::UnsupportedIpVersion)?,
-    };
-
-    let (response, rtt) = Tcp::send_tcp_packet(
+) -> Result<ScanResult> {
+    probe_tcp(
         interface,
-        ipv4_src,
+        src_ip,
         src_port,
-        ipv4_dest,
+        dest_ip,
         dest_port,
         TcpFlags::ACK,
+        options,
         timeout,
-    )?;
+        classify_ack_response,
+    )
+}
 
-    // No response -> filtered.
-    let packet = match response {
-        Some(packet) => packet,
-        None => return Ok((ScanResult::Filtered, rtt)),
+/// Interprets the response to an ACK probe.
+///
+/// RST flag -> unfiltered. No response or ICMP unreachable -> filtered.
+fn classify_ack_response(response: Option<Vec<u8>>) -> Result<(PortState, Reason)> {
+    let response = parse_tcp_response(response)?;
+
+    let state = match response {
+        // No response -> filtered.
+        TcpResponse::Silent => PortState::Filtered,
+        // Not a TCP packet, perhaps ICMP unreachable error -> filtered.
+        TcpResponse::NotTcp { .. } => PortState::Filtered,
+        // RST flag set -> unfiltered.
+        TcpResponse::Tcp { flags, .. } if flags & TcpFlags::RST != 0 => PortState::Unfiltered,
+        _ => return Err(ScannerError::UnexpectedTcpFlags.into()),
     };
 
-    let ethernet_packet =
-        EthernetPacket::new(&packet).ok_or(ScannerError::CantCreateEthernetPacket)?;
-
-    let ipv4_packet =
-        Ipv4Packet::new(ethernet_packet.payload()).ok_or(ScannerError::CantCreateIpv4Packet)?;
-
-    // Not a TCP packet, perhaps ICMP unreachable error -> filtered.
-    if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
-        return Ok((ScanResult::Filtered, rtt));
-    }
-
-    let tcp_packet =
-        TcpPacket::new(ipv4_packet.payload()).ok_or(ScannerError::CantCreateTcpPacket)?;
-
-    let tcp_flags = tcp_packet.get_flags();
-
-    // RST flag set -> closed.
-    if tcp_flags & TcpFlags::RST != 0 {
-        return Ok((ScanResult::Unfiltered, rtt));
-    }
-
-    Err(ScannerError::UnexpectedTcpFlags.into())
+    Ok((state, response.reason()))
 }
 
 /// TCP FIN determines if a port is closed on a target machine.
@@ -193,56 +275,41 @@ pub fn tcp_fin_scan(
     src_port: u16,
     dest_ip: IpAddr,
     dest_port: u16,
+    options: ProbeOptions,
     timeout: Duration,
-) -> Result<(ScanResult, Duration)> {
-    let ipv4_src = match src_ip {
-        IpAddr::V4(ip) => ip,
-        _ => Err(ScannerError::UnsupportedIpVersion)?,
-    };
-
-    let ipv4_dest = match dest_ip {
-        IpAddr::V4(ip) => ip,
-        _ => Err(ScannerError::UnsupportedIpVersion)?,
-    };
-
-    let (response, rtt) = Tcp::send_tcp_packet(
+) -> Result<ScanResult> {
+    probe_tcp(
         interface,
-        ipv4_src,
+        src_ip,
         src_port,
-        ipv4_dest,
+        dest_ip,
         dest_port,
         TcpFlags::FIN,
+        options,
         timeout,
-    )?;
+        classify_fin_response,
+    )
+}
 
-    // No response -> open or filtered.
-    let packet = match response {
-        Some(packet) => packet,
-        None => return Ok((ScanResult::OpenOrFiltered, rtt)),
+/// Interprets the response to a probe that carries no SYN flag and is sent to a port
+/// without an established connection, i.e. FIN, NULL, XMAS and Maimon scans.
+///
+/// RFC 793 expected behavior is that a closed port will respond with a RST flag,
+/// while an open port ignores the packet. No response -> open or filtered.
+fn classify_fin_response(response: Option<Vec<u8>>) -> Result<(PortState, Reason)> {
+    let response = parse_tcp_response(response)?;
+
+    let state = match response {
+        // No response -> open or filtered.
+        TcpResponse::Silent => PortState::OpenOrFiltered,
+        // Not a TCP packet, perhaps ICMP unreachable error -> filtered.
+        TcpResponse::NotTcp { .. } => PortState::Filtered,
+        // RST flag set -> closed.
+        TcpResponse::Tcp { flags, .. } if flags & TcpFlags::RST != 0 => PortState::Closed,
+        _ => return Err(ScannerError::UnexpectedTcpFlags.into()),
     };
 
-    let ethernet_packet =
-        EthernetPacket::new(&packet).ok_or(ScannerError::CantCreateEthernetPacket)?;
-
-    let ipv4_packet =
-        Ipv4Packet::new(ethernet_packet.payload()).ok_or(ScannerError::CantCreateIpv4Packet)?;
-
-    // Not a TCP packet, perhaps ICMP unreachable error -> filtered.
-    if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
-        return Ok((ScanResult::Filtered, rtt));
-    }
-
-    let tcp_packet =
-        TcpPacket::new(ipv4_packet.payload()).ok_or(ScannerError::CantCreateTcpPacket)?;
-
-    let tcp_flags = tcp_packet.get_flags();
-
-    // RST flag set -> closed.
-    if tcp_flags & TcpFlags::RST != 0 {
-        return Ok((ScanResult::Closed, rtt));
-    }
-
-    Err(ScannerError::UnexpectedTcpFlags.into())
+    Ok((state, response.reason()))
 }
 
 /// TCP XMAS determines if a port is closed on a target machine.
@@ -259,56 +326,20 @@ pub fn tcp_xmas_scan(
     src_port: u16,
     dest_ip: IpAddr,
     dest_port: u16,
+    options: ProbeOptions,
     timeout: Duration,
-) -> Result<(ScanResult, Duration)> {
-    let ipv4_src = match src_ip {
-        IpAddr::V4(ip) => ip,
-        _ => Err(ScannerError::UnsupportedIpVersion)?,
-    };
-
-    let ipv4_dest = match dest_ip {
-        IpAddr::V4(ip) => ip,
-        _ => Err(ScannerError::UnsupportedIpVersion)?,
-    };
-
-    let (response, rtt) = Tcp::send_tcp_packet(
+) -> Result<ScanResult> {
+    probe_tcp(
         interface,
-        ipv4_src,
+        src_ip,
         src_port,
-        ipv4_dest,
+        dest_ip,
         dest_port,
         TcpFlags::FIN | TcpFlags::PSH | TcpFlags::URG,
+        options,
         timeout,
-    )?;
-
-    // No response -> open or filtered.
-    let packet = match response {
-        Some(packet) => packet,
-        None => return Ok((ScanResult::OpenOrFiltered, rtt)),
-    };
-
-    let ethernet_packet =
-        EthernetPacket::new(&packet).ok_or(ScannerError::CantCreateEthernetPacket)?;
-
-    let ipv4_packet =
-        Ipv4Packet::new(ethernet_packet.payload()).ok_or(ScannerError::CantCreateIpv4Packet)?;
-
-    // Not a TCP packet, perhaps ICMP unreachable error -> filtered.
-    if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
-        return Ok((ScanResult::Filtered, rtt));
-    }
-
-    let tcp_packet =
-        TcpPacket::new(ipv4_packet.payload()).ok_or(ScannerError::CantCreateTcpPacket)?;
-
-    let tcp_flags = tcp_packet.get_flags();
-
-    // RST flag set -> closed.
-    if tcp_flags & TcpFlags::RST != 0 {
-        return Ok((ScanResult::Closed, rtt));
-    }
-
-    Err(ScannerError::UnexpectedTcpFlags.into())
+        classify_fin_response,
+    )
 }
 
 /// TCP NULL determines if a port is closed on a target machine.
@@ -324,50 +355,20 @@ pub fn tcp_null_scan(
     src_port: u16,
     dest_ip: IpAddr,
     dest_port: u16,
+    options: ProbeOptions,
     timeout: Duration,
-) -> Result<(ScanResult, Duration)> {
-    let ipv4_src = match src_ip {
-        IpAddr::V4(ip) => ip,
-        _ => Err(ScannerError::UnsupportedIpVersion)?,
-    };
-
-    let ipv4_dest = match dest_ip {
-        IpAddr::V4(ip) => ip,
-        _ => Err(ScannerError::UnsupportedIpVersion)?,
-    };
-
-    let (response, rtt) = Tcp::send_tcp_packet(
-        interface, ipv4_src, src_port, ipv4_dest, dest_port, 0, timeout,
-    )?;
-
-    // No response -> open or filtered.
-    let packet = match response {
-        Some(packet) => packet,
-        None => return Ok((ScanResult::OpenOrFiltered, rtt)),
-    };
-
-    let ethernet_packet =
-        EthernetPacket::new(&packet).ok_or(ScannerError::CantCreateEthernetPacket)?;
-
-    let ipv4_packet =
-        Ipv4Packet::new(ethernet_packet.payload()).ok_or(ScannerError::CantCreateIpv4Packet)?;
-
-    // Not a TCP packet, perhaps ICMP unreachable error -> filtered.
-    if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
-        return Ok((ScanResult::Filtered, rtt));
-    }
-
-    let tcp_packet =
-        TcpPacket::new(ipv4_packet.payload()).ok_or(ScannerError::CantCreateTcpPacket)?;
-
-    let tcp_flags = tcp_packet.get_flags();
-
-    // RST flag set -> closed.
-    if tcp_flags & TcpFlags::RST != 0 {
-        return Ok((ScanResult::Closed, rtt));
-    }
-
-    Err(ScannerError::UnexpectedTcpFlags.into())
+) -> Result<ScanResult> {
+    probe_tcp(
+        interface,
+        src_ip,
+        src_port,
+        dest_ip,
+        dest_port,
+        0,
+        options,
+        timeout,
+        classify_fin_response,
+    )
 }
 
 /// TCP Window works exactly the same as ACK scans, but examines the window field in the TCP header of RST packets.
@@ -381,62 +382,47 @@ pub fn tcp_window_scan(
     src_port: u16,
     dest_ip: IpAddr,
     dest_port: u16,
+    options: ProbeOptions,
     timeout: Duration,
-) -> Result<(ScanResult, Duration)> {
-    let ipv4_src = match src_ip {
-        IpAddr::V4(ip) => ip,
-        _ => Err(ScannerError::UnsupportedIpVersion)?,
-    };
-
-    let ipv4_dest = match dest_ip {
-        IpAddr::V4(ip) => ip,
-        _ => Err(ScannerError::UnsupportedIpVersion)?,
-    };
-
-    let (response, rtt) = Tcp::send_tcp_packet(
+) -> Result<ScanResult> {
+    probe_tcp(
         interface,
-        ipv4_src,
+        src_ip,
         src_port,
-        ipv4_dest,
+        dest_ip,
         dest_port,
         TcpFlags::ACK,
+        options,
         timeout,
-    )?;
-
-    // No response -> filtered.
-    let packet = match response {
-        Some(packet) => packet,
-        None => return Ok((ScanResult::Filtered, rtt)),
-    };
-
-    let ethernet_packet =
-        EthernetPacket::new(&packet).ok_or(ScannerError::CantCreateEthernetPacket)?;
-
-    let ipv4_packet =
-        Ipv4Packet::new(ethernet_packet.payload()).ok_or(ScannerError::CantCreateIpv4Packet)?;
-
-    // Not a TCP packet, perhaps ICMP unreachable error -> filtered.
-    if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
-        return Ok((ScanResult::Filtered, rtt));
-    }
-
-    let tcp_packet =
-        TcpPacket::new(ipv4_packet.payload()).ok_or(ScannerError::CantCreateTcpPacket)?;
-
-    let tcp_flags = tcp_packet.get_flags();
+        classify_window_response,
+    )
+}
 
-    // RST flag set.
-    // Non-zero window size -> open.
-    // Zero window size -> closed.
-    if tcp_flags & TcpFlags::RST != 0 {
-        if tcp_packet.get_window() > 0 {
-            return Ok((ScanResult::Open, rtt));
-        } else {
-            return Ok((ScanResult::Closed, rtt));
+/// Interprets the response to an ACK probe by examining the window size of RST packets.
+///
+/// Non-zero window -> open. Zero window -> closed. No response or ICMP unreachable -> filtered.
+fn classify_window_response(response: Option<Vec<u8>>) -> Result<(PortState, Reason)> {
+    let response = parse_tcp_response(response)?;
+
+    let state = match response {
+        // No response -> filtered.
+        TcpResponse::Silent => PortState::Filtered,
+        // Not a TCP packet, perhaps ICMP unreachable error -> filtered.
+        TcpResponse::NotTcp { .. } => PortState::Filtered,
+        // RST flag set.
+        // Non-zero window size -> open.
+        // Zero window size -> closed.
+        TcpResponse::Tcp { flags, window } if flags & TcpFlags::RST != 0 => {
+            if window > 0 {
+                PortState::Open
+            } else {
+                PortState::Closed
+            }
         }
-    }
+        _ => return Err(ScannerError::UnexpectedTcpFlags.into()),
+    };
 
-    Err(ScannerError::UnexpectedTcpFlags.into())
+    Ok((state, response.reason()))
 }
 
 /// TCP Maimon determines if a port is closed on a target machine.
@@ -444,61 +430,432 @@ pub fn tcp_window_scan(
 /// Works exactly the same as NULL, FIN and XMAS scans, but with the FIN and ACK flags set.
 ///
 /// Expected behavior is that a closed port will respond with a RST flag.
-/// An open port should also respond with a RST flag, but many systems ignore this packet.
+/// An open port should also respond with a RST flag, but BSD-derived systems drop the packet.
+/// On such systems no response -> open or filtered, which SYN scans may not reveal behind firewalls.
 pub fn tcp_maimon_scan(
     interface: Interface,
     src_ip: IpAddr,
     src_port: u16,
     dest_ip: IpAddr,
     dest_port: u16,
+    options: ProbeOptions,
     timeout: Duration,
-) -> Result<(ScanResult, Duration)> {
-    let ipv4_src = match src_ip {
-        IpAddr::V4(ip) => ip,
-        _ => Err(ScannerError::UnsupportedIpVersion)?,
-    };
-
-    let ipv4_dest = match dest_ip {
-        IpAddr::V4(ip) => ip,
-        _ => Err(ScannerError::UnsupportedIpVersion)?,
-    };
-
-    let (response, rtt) = Tcp::send_tcp_packet(
+) -> Result<ScanResult> {
+    probe_tcp(
         interface,
-        ipv4_src,
+        src_ip,
         src_port,
-        ipv4_dest,
+        dest_ip,
         dest_port,
         TcpFlags::FIN | TcpFlags::ACK,
+        options,
         timeout,
-    )?;
+        classify_fin_response,
+    )
+}
+
+/// Ports a TCP ping probes if not configured otherwise, as used by nmap.
+pub const DEFAULT_TCP_PING_PORTS: [u16; 2] = [80, 443];
+
+/// TCP ping determines if a host is up when ICMP is blocked.
+///
+/// Sends a SYN and an ACK probe to each of the given ports until one is answered. Stateful
+/// firewalls often drop unsolicited ACK packets, while stateless ones drop SYN packets,
+/// so sending both gets through more filters. Common ports like 80 and 443 work best.
+///
+/// Any SYN/ACK or RST flag means the host is up, since only a live host answers at all.
+/// If no probe is answered, the host is considered as down. A dry run can't tell, so the state
+/// is unknown then.
+#[allow(clippy::too_many_arguments)]
+pub fn tcp_ping(
+    interface: Interface,
+    src_ip: IpAddr,
+    src_port: u16,
+    dest_ip: IpAddr,
+    ports: &[u16],
+    options: ProbeOptions,
+    timeout: Duration,
+) -> Result<(PortState, Duration)> {
+    let mut last_rtt = Duration::ZERO;
+
+    for &dest_port in ports {
+        for flags in [TcpFlags::SYN, TcpFlags::ACK] {
+            let (exchange, _attempts) = Tcp::send_tcp_packet(
+                interface,
+                src_ip,
+                src_port,
+                dest_ip,
+                dest_port,
+                flags,
+                options.clone(),
+                timeout,
+            )?;
+            let Exchange::Sent(response, _, rtt) = exchange else {
+                return Ok((PortState::Unknown, Duration::ZERO));
+            };
+
+            if classify_ping_response(response)? == PortState::Up {
+                return Ok((PortState::Up, rtt));
+            }
+            last_rtt = rtt;
+        }
+    }
 
-    // No response -> open or filtered.
+    Ok((PortState::Down, last_rtt))
+}
+
+/// Interprets the response to a TCP ping probe.
+///
+/// SYN/ACK or RST flag -> up. No response or ICMP unreachable -> down.
+fn classify_ping_response(response: Option<Vec<u8>>) -> Result<PortState> {
+    let state = match parse_tcp_response(response)? {
+        // SYN/ACK or RST flag set -> up.
+        TcpResponse::Tcp { flags, .. }
+            if flags & (TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::SYN | TcpFlags::ACK
+                || flags & TcpFlags::RST != 0 =>
+        {
+            PortState::Up
+        }
+        // No response, an ICMP unreachable error or other flags -> down.
+        _ => PortState::Down,
+    };
+
+    Ok(state)
+}
+
+/// Response to a TCP probe, decoded from the received Ethernet frame.
+#[derive(Clone, Copy)]
+enum TcpResponse {
+    /// No response arrived before the timeout.
+    Silent,
+    /// The response is not a TCP packet, e.g. an ICMP unreachable error.
+    /// Holds the code if it is an ICMP or ICMPv6 destination unreachable error.
+    NotTcp {
+        unreachable: Option<IcmpUnreachable>,
+    },
+    /// The response is a TCP packet with the given flags and window size.
+    Tcp { flags: u8, window: u16 },
+}
+
+/// Code of a destination unreachable error. ICMP and ICMPv6 number their codes differently.
+#[derive(Clone, Copy)]
+enum IcmpUnreachable {
+    V4(u8),
+    V6(u8),
+}
+
+impl TcpResponse {
+    /// Returns the evidence the port state is based on.
+    fn reason(self) -> Reason {
+        match self {
+            TcpResponse::Silent => Reason::NoResponse,
+            TcpResponse::NotTcp {
+                unreachable: Some(IcmpUnreachable::V4(code)),
+            } => Reason::IcmpUnreachable(code),
+            TcpResponse::NotTcp {
+                unreachable: Some(IcmpUnreachable::V6(code)),
+            } => Reason::Icmpv6Unreachable(code),
+            TcpResponse::NotTcp { unreachable: None } => Reason::Other,
+            TcpResponse::Tcp { flags, .. } if flags & TcpFlags::RST != 0 => {
+                match flags & TcpFlags::ACK != 0 {
+                    true => Reason::RstAck,
+                    false => Reason::Rst,
+                }
+            }
+            TcpResponse::Tcp { flags, .. }
+                if flags & TcpFlags::SYN != 0 && flags & TcpFlags::ACK != 0 =>
+            {
+                Reason::SynAck
+            }
+            TcpResponse::Tcp { .. } => Reason::Other,
+        }
+    }
+}
+
+/// Strips the Ethernet and IP headers of a response and decodes the TCP header.
+fn parse_tcp_response(response: Option<Vec<u8>>) -> Result<TcpResponse> {
     let packet = match response {
         Some(packet) => packet,
-        None => return Ok((ScanResult::OpenOrFiltered, rtt)),
+        None => return Ok(TcpResponse::Silent),
     };
 
     let ethernet_packet =
         EthernetPacket::new(&packet).ok_or(ScannerError::CantCreateEthernetPacket)?;
 
-    let ipv4_packet =
-        Ipv4Packet::new(ethernet_packet.payload()).ok_or(ScannerError::CantCreateIpv4Packet)?;
+    match ethernet_packet.get_ethertype() {
+        EtherTypes::Ipv6 => {
+            let ipv6_packet = Ipv6Packet::new(ethernet_packet.payload())
+                .ok_or(ScannerError::CantCreateIpv6Packet)?;
+
+            // The TCP header follows the extension headers, if there are any.
+            let Some((next_header, payload)) =
+                ipv6_upper_layer(ipv6_packet.get_next_header(), ipv6_packet.payload())
+            else {
+                return Ok(TcpResponse::NotTcp { unreachable: None });
+            };
+
+            if next_header != IpNextHeaderProtocols::Tcp {
+                let unreachable = Icmpv6Packet::new(payload)
+                    .filter(|_| next_header == IpNextHeaderProtocols::Icmpv6)
+                    .filter(|icmp| icmp.get_icmpv6_type() == Icmpv6Types::DestinationUnreachable)
+                    .map(|icmp| IcmpUnreachable::V6(icmp.get_icmpv6_code().0));
+                return Ok(TcpResponse::NotTcp { unreachable });
+            }
+
+            parse_tcp_segment(payload)
+        }
+        _ => {
+            let ipv4_packet = Ipv4Packet::new(ethernet_packet.payload())
+                .ok_or(ScannerError::CantCreateIpv4Packet)?;
+
+            if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
+                let unreachable = IcmpPacket::new(ipv4_packet.payload())
+                    .filter(|_| {
+                        ipv4_packet.get_next_level_protocol() == IpNextHeaderProtocols::Icmp
+                    })
+                    .filter(|icmp| icmp.get_icmp_type() == IcmpTypes::DestinationUnreachable)
+                    .map(|icmp| IcmpUnreachable::V4(icmp.get_icmp_code().0));
+                return Ok(TcpResponse::NotTcp { unreachable });
+            }
+
+            // The payload starts after the IPv4 options, as given by the header length.
+            parse_tcp_segment(ipv4_packet.payload())
+        }
+    }
+}
+
+/// Reads the fields of a TCP segment that are needed to classify a response.
+fn parse_tcp_segment(segment: &[u8]) -> Result<TcpResponse> {
+    let tcp_packet = TcpPacket::new(segment).ok_or(ScannerError::CantCreateTcpPacket)?;
 
-    // Not a TCP packet, perhaps ICMP unreachable error -> filtered.
-    if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
-        return Ok((ScanResult::Filtered, rtt));
+    Ok(TcpResponse::Tcp {
+        flags: tcp_packet.get_flags(),
+        window: tcp_packet.get_window(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::{
+        osi_layers::DatalinkLayer,
+        probe::{build_ipv4_header, IPV6_HEADER_SIZE},
+    };
+    use pnet::{
+        packet::{
+            icmp::{IcmpCode, MutableIcmpPacket},
+            ipv4::{self, MutableIpv4Packet},
+            ipv6::MutableIpv6Packet,
+        },
+        util::MacAddr,
+    };
+    use std::net::Ipv4Addr;
+
+    /// Builds an Ethernet frame carrying a TCP packet with the given flags.
+    fn tcp_response(flags: u8) -> Vec<u8> {
+        tcp_response_with_window(flags, ProbeOptions::default().window)
     }
 
-    let tcp_packet =
-        TcpPacket::new(ipv4_packet.payload()).ok_or(ScannerError::CantCreateTcpPacket)?;
+    /// Builds an Ethernet frame carrying a TCP packet with the given flags and window size.
+    fn tcp_response_with_window(flags: u8, window: u16) -> Vec<u8> {
+        let options = ProbeOptions {
+            window,
+            ..Default::default()
+        };
+        let (packet, _) = Tcp::build_tcp_packet(
+            Ipv4Addr::new(192, 168, 0, 2),
+            80,
+            Ipv4Addr::new(192, 168, 0, 1),
+            12345,
+            flags,
+            &[],
+            &[],
+            &options,
+        )
+        .unwrap();
+        ethernet_frame(&packet)
+    }
 
-    let tcp_flags = tcp_packet.get_flags();
+    /// Builds an Ethernet frame carrying an ICMP destination unreachable error.
+    fn icmp_unreachable_response(code: u8) -> Vec<u8> {
+        let mut packet = [0u8; 20 + 8 + 28];
+        build_ipv4_header(
+            &mut packet,
+            Ipv4Addr::new(192, 168, 0, 2),
+            Ipv4Addr::new(192, 168, 0, 1),
+            IpNextHeaderProtocols::Icmp,
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let mut icmp_packet = MutableIcmpPacket::new(&mut packet[20..]).unwrap();
+        icmp_packet.set_icmp_type(IcmpTypes::DestinationUnreachable);
+        icmp_packet.set_icmp_code(IcmpCode(code));
+        ethernet_frame(&packet)
+    }
 
-    // RST flag set -> closed.
-    if tcp_flags & TcpFlags::RST != 0 {
-        return Ok((ScanResult::Closed, rtt));
+    /// Encapsulates an IP packet in an Ethernet frame.
+    fn ethernet_frame(packet: &[u8]) -> Vec<u8> {
+        let ethertype = match packet[0] >> 4 {
+            6 => EtherTypes::Ipv6,
+            _ => EtherTypes::Ipv4,
+        };
+        DatalinkLayer::build_ethernet_frame(MacAddr::zero(), MacAddr::zero(), ethertype, packet)
     }
 
-    Err(ScannerError::UnexpectedTcpFlags.into())
+    #[test]
+    fn test_classify_ping_response() -> Result<()> {
+        assert_eq!(classify_ping_response(None)?, PortState::Down);
+
+        let response = Some(tcp_response(TcpFlags::SYN | TcpFlags::ACK));
+        assert_eq!(classify_ping_response(response)?, PortState::Up);
+
+        let response = Some(tcp_response(TcpFlags::RST));
+        assert_eq!(classify_ping_response(response)?, PortState::Up);
+
+        let response = Some(icmp_unreachable_response(3));
+        assert_eq!(classify_ping_response(response)?, PortState::Down);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_classify_fin_response() -> Result<()> {
+        let result = classify_fin_response(None)?;
+        assert_eq!(result, (PortState::OpenOrFiltered, Reason::NoResponse));
+
+        let result = classify_fin_response(Some(tcp_response(TcpFlags::RST)))?;
+        assert_eq!(result, (PortState::Closed, Reason::Rst));
+
+        let response = Some(tcp_response(TcpFlags::SYN | TcpFlags::ACK));
+        assert!(classify_fin_response(response).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_classify_syn_response() -> Result<()> {
+        let result = classify_syn_response(None)?;
+        assert_eq!(result, (PortState::Filtered, Reason::NoResponse));
+
+        let response = Some(tcp_response(TcpFlags::SYN | TcpFlags::ACK));
+        let result = classify_syn_response(response)?;
+        assert_eq!(result, (PortState::Open, Reason::SynAck));
+
+        let response = Some(tcp_response(TcpFlags::RST | TcpFlags::ACK));
+        let result = classify_syn_response(response)?;
+        assert_eq!(result, (PortState::Closed, Reason::RstAck));
+
+        let result = classify_syn_response(Some(icmp_unreachable_response(3)))?;
+        assert_eq!(result, (PortState::Filtered, Reason::IcmpUnreachable(3)));
+
+        // IPv6 responses are classified the same way.
+        let (packet, _) = Tcp::build_tcp_packet_v6(
+            "2001:db8::2".parse()?,
+            80,
+            "2001:db8::1".parse()?,
+            12345,
+            TcpFlags::SYN | TcpFlags::ACK,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        let result = classify_syn_response(Some(ethernet_frame(&packet)))?;
+        assert_eq!(result, (PortState::Open, Reason::SynAck));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_classify_response_with_ip_options() -> Result<()> {
+        let (segment, _) = Tcp::build_tcp_packet(
+            Ipv4Addr::new(192, 168, 0, 2),
+            80,
+            Ipv4Addr::new(192, 168, 0, 1),
+            12345,
+            TcpFlags::SYN | TcpFlags::ACK,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+
+        // Insert a 4-byte option of NOPs and an end of option list into the IPv4 header.
+        let mut packet = segment[..20].to_vec();
+        packet.extend_from_slice(&[1, 1, 1, 0]);
+        packet.extend_from_slice(&segment[20..]);
+        let total_length = packet.len() as u16;
+        let mut ip_header = MutableIpv4Packet::new(&mut packet).unwrap();
+        ip_header.set_header_length(6);
+        ip_header.set_total_length(total_length);
+        let ip_checksum = ipv4::checksum(&ip_header.to_immutable());
+        ip_header.set_checksum(ip_checksum);
+
+        // The TCP header is read after the options, 24 bytes into the packet.
+        let result = classify_syn_response(Some(ethernet_frame(&packet)))?;
+        assert_eq!(result, (PortState::Open, Reason::SynAck));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_classify_response_with_ipv6_extension_header() -> Result<()> {
+        let (segment, _) = Tcp::build_tcp_packet_v6(
+            "2001:db8::2".parse()?,
+            80,
+            "2001:db8::1".parse()?,
+            12345,
+            TcpFlags::SYN | TcpFlags::ACK,
+            &[],
+            &[],
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+
+        // Insert an 8-byte hop-by-hop options header that holds a PadN option and leads to TCP.
+        let mut packet = segment[..IPV6_HEADER_SIZE].to_vec();
+        packet.extend_from_slice(&[IpNextHeaderProtocols::Tcp.0, 0, 1, 4, 0, 0, 0, 0]);
+        packet.extend_from_slice(&segment[IPV6_HEADER_SIZE..]);
+        let payload_length = (packet.len() - IPV6_HEADER_SIZE) as u16;
+        let mut ip_header = MutableIpv6Packet::new(&mut packet).unwrap();
+        ip_header.set_next_header(IpNextHeaderProtocols::Hopopt);
+        ip_header.set_payload_length(payload_length);
+
+        // The TCP header is read after the extension header, 48 bytes into the packet.
+        let result = classify_syn_response(Some(ethernet_frame(&packet)))?;
+        assert_eq!(result, (PortState::Open, Reason::SynAck));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_classify_ack_response() -> Result<()> {
+        let result = classify_ack_response(None)?;
+        assert_eq!(result, (PortState::Filtered, Reason::NoResponse));
+
+        let result = classify_ack_response(Some(icmp_unreachable_response(13)))?;
+        assert_eq!(result, (PortState::Filtered, Reason::IcmpUnreachable(13)));
+
+        let result = classify_ack_response(Some(tcp_response(TcpFlags::RST)))?;
+        assert_eq!(result, (PortState::Unfiltered, Reason::Rst));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_classify_window_response() -> Result<()> {
+        let result = classify_window_response(None)?;
+        assert_eq!(result, (PortState::Filtered, Reason::NoResponse));
+
+        let result = classify_window_response(Some(tcp_response_with_window(TcpFlags::RST, 512)))?;
+        assert_eq!(result, (PortState::Open, Reason::Rst));
+
+        let result = classify_window_response(Some(tcp_response_with_window(TcpFlags::RST, 0)))?;
+        assert_eq!(result, (PortState::Closed, Reason::Rst));
+
+        let result = classify_window_response(Some(icmp_unreachable_response(13)))?;
+        assert_eq!(result, (PortState::Filtered, Reason::IcmpUnreachable(13)));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,192 @@
+use crate::{
+    errors::ScannerError,
+    networking::{
+        icmp::Icmp,
+        interface::Interface,
+        osi_layers::{Exchange, IcmpEcho},
+        probe::ProbeOptions,
+    },
+};
+use anyhow::Result;
+use pnet::packet::{
+    ethernet::EthernetPacket,
+    icmp::{IcmpPacket, IcmpTypes},
+    ipv4::Ipv4Packet,
+    Packet,
+};
+use rand::Rng;
+use std::{net::IpAddr, time::Duration};
+
+/// Maximum number of hops if not configured otherwise, as used by the traceroute utility.
+pub const DEFAULT_MAX_HOPS: u8 = 30;
+
+/// Router or host that answered a traceroute probe.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hop {
+    /// TTL of the probe, i.e. the distance to the responder.
+    pub ttl: u8,
+    /// IP address of the responder. Is `None` if nobody answered within the timeout.
+    pub ip: Option<IpAddr>,
+    /// Round-trip time. Is `None` if nobody answered within the timeout.
+    pub rtt: Option<Duration>,
+}
+
+/// What a response to a traceroute probe tells about the path.
+#[derive(Debug, PartialEq)]
+enum Answer {
+    /// A router discarded the probe, since its TTL ran out.
+    TimeExceeded,
+    /// The destination or a router on the path can't deliver the probe any further.
+    Final,
+}
+
+/// Maps the path to a host by sending ICMP echo requests with increasing TTL.
+///
+/// Each router on the path decrements the TTL and discards the probe once it reaches zero,
+/// answering with an ICMP time exceeded error. The probe with TTL n thus reveals the n-th hop.
+/// Stops once the destination itself replies, or an ICMP unreachable error shows that the
+/// probes can't get any further.
+///
+/// Returns a hop for each sent probe. Routers that don't answer are listed without an IP address,
+/// like all hops of a dry run, which builds the probes of every TTL.
+pub fn traceroute(
+    interface: Interface,
+    src_ip: IpAddr,
+    dest_ip: IpAddr,
+    max_hops: u8,
+    options: ProbeOptions,
+    timeout: Duration,
+) -> Result<Vec<Hop>> {
+    let ipv4_src = match src_ip {
+        IpAddr::V4(ip) => ip,
+        _ => Err(ScannerError::UnsupportedIpVersion)?,
+    };
+
+    let ipv4_dest = match dest_ip {
+        IpAddr::V4(ip) => ip,
+        _ => Err(ScannerError::UnsupportedIpVersion)?,
+    };
+
+    let identifier = rand::thread_rng().gen();
+    let mut hops = Vec::with_capacity(max_hops as usize);
+
+    for ttl in 1..=max_hops {
+        // The sequence number tells apart the responses to the probes of each TTL.
+        let echo = IcmpEcho {
+            identifier,
+            sequence: ttl as u16,
+        };
+        let options = ProbeOptions {
+            ttl,
+            ..options.clone()
+        };
+
+        let exchange =
+            Icmp::send_traceroute_probe(interface, ipv4_src, ipv4_dest, echo, options, timeout)?;
+
+        let Exchange::Sent(response, responder, rtt) = exchange else {
+            hops.push(Hop {
+                ttl,
+                ip: None,
+                rtt: None,
+            });
+            continue;
+        };
+
+        let answer = response
+            .map(|response| parse_answer(&response))
+            .transpose()?;
+
+        // The responder is the hop, which is a router unless the probe reached the destination.
+        let ip = answer.as_ref().and(responder);
+        hops.push(Hop {
+            ttl,
+            ip,
+            rtt: ip.map(|_| rtt),
+        });
+
+        if answer == Some(Answer::Final) {
+            break;
+        }
+    }
+
+    Ok(hops)
+}
+
+/// Interprets the response to a traceroute probe.
+fn parse_answer(response: &[u8]) -> Result<Answer> {
+    let ethernet_packet =
+        EthernetPacket::new(response).ok_or(ScannerError::CantCreateEthernetPacket)?;
+
+    let ipv4_packet =
+        Ipv4Packet::new(ethernet_packet.payload()).ok_or(ScannerError::CantCreateIpv4Packet)?;
+
+    let icmp_packet =
+        IcmpPacket::new(ipv4_packet.payload()).ok_or(ScannerError::CantCreateIcmpPacket)?;
+
+    match icmp_packet.get_icmp_type() {
+        // TTL ran out on the way -> router.
+        IcmpTypes::TimeExceeded => Ok(Answer::TimeExceeded),
+        // Echo reply -> destination reached. Unreachable -> no way further.
+        IcmpTypes::EchoReply | IcmpTypes::DestinationUnreachable => Ok(Answer::Final),
+        // Unexpected ICMP response.
+        _ => Err(ScannerError::UnexpectedIcmpResponse.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::{osi_layers::DatalinkLayer, probe::build_ipv4_header};
+    use pnet::{
+        packet::{
+            ethernet::EtherTypes,
+            icmp::{IcmpType, MutableIcmpPacket},
+            ip::IpNextHeaderProtocols,
+        },
+        util::MacAddr,
+    };
+    use std::net::Ipv4Addr;
+
+    /// Builds an Ethernet frame carrying an ICMP packet of the given type from `src_ip`.
+    fn icmp_response(src_ip: Ipv4Addr, icmp_type: IcmpType) -> Vec<u8> {
+        let mut packet = [0u8; 20 + 8];
+        build_ipv4_header(
+            &mut packet,
+            src_ip,
+            Ipv4Addr::new(192, 168, 0, 1),
+            IpNextHeaderProtocols::Icmp,
+            &ProbeOptions::default(),
+        )
+        .unwrap();
+        MutableIcmpPacket::new(&mut packet[20..])
+            .unwrap()
+            .set_icmp_type(icmp_type);
+
+        DatalinkLayer::build_ethernet_frame(
+            MacAddr::zero(),
+            MacAddr::zero(),
+            EtherTypes::Ipv4,
+            &packet,
+        )
+    }
+
+    #[test]
+    fn test_parse_answer() -> Result<()> {
+        let router = Ipv4Addr::new(10, 0, 0, 1);
+        let response = icmp_response(router, IcmpTypes::TimeExceeded);
+        assert_eq!(parse_answer(&response)?, Answer::TimeExceeded);
+
+        let host = Ipv4Addr::new(192, 168, 0, 2);
+        let response = icmp_response(host, IcmpTypes::EchoReply);
+        assert_eq!(parse_answer(&response)?, Answer::Final);
+
+        let response = icmp_response(router, IcmpTypes::DestinationUnreachable);
+        assert_eq!(parse_answer(&response)?, Answer::Final);
+
+        let response = icmp_response(host, IcmpTypes::EchoRequest);
+        assert!(parse_answer(&response).is_err());
+
+        Ok(())
+    }
+}
@@ -1,12 +1,17 @@
-use super::engine::ScanResult;
+use super::results::{interpret_icmp_unreachable, PortState, Protocol, Reason, ScanResult};
 use crate::{
     errors::ScannerError,
-    networking::{interface::Interface, udp::Udp},
+    networking::{
+        interface::Interface,
+        osi_layers::Exchange,
+        probe::ProbeOptions,
+        udp::{Udp, UDP_PAYLOAD},
+    },
 };
 use anyhow::Result;
 use pnet::packet::{
     ethernet::EthernetPacket,
-    icmp::{destination_unreachable::IcmpCodes, IcmpPacket},
+    icmp::{IcmpPacket, IcmpTypes},
     ip::IpNextHeaderProtocols,
     ipv4::Ipv4Packet,
     Packet,
@@ -24,8 +29,9 @@ pub fn udp_scan(
     src_port: u16,
     dest_ip: IpAddr,
     dest_port: u16,
+    options: ProbeOptions,
     timeout: Duration,
-) -> Result<(ScanResult, Duration)> {
+) -> Result<ScanResult> {
     let ipv4_src = match src_ip {
         IpAddr::V4(ip) => ip,
         _ => Err(ScannerError::UnsupportedIpVersion)?,
@@ -36,13 +42,50 @@ pub fn udp_scan(
         _ => Err(ScannerError::UnsupportedIpVersion)?,
     };
 
-    let (response, rtt) =
-        Udp::send_udp_packet(interface, ipv4_src, src_port, ipv4_dest, dest_port, timeout)?;
+    let exchange = Udp::send_udp_packet(
+        interface,
+        ipv4_src,
+        src_port,
+        ipv4_dest,
+        dest_port,
+        &UDP_PAYLOAD,
+        options,
+        timeout,
+    )?;
 
+    let Exchange::Sent(response, _, rtt) = exchange else {
+        return Ok(ScanResult::not_sent(
+            dest_ip,
+            dest_port,
+            Protocol::Udp,
+            src_port,
+        ));
+    };
+
+    let rtt = response.as_ref().map(|_| rtt);
+    let (state, reason) = classify_udp_response(response)?;
+
+    Ok(ScanResult::new(
+        dest_ip,
+        dest_port,
+        Protocol::Udp,
+        src_port,
+        state,
+        reason,
+        rtt,
+        1,
+    ))
+}
+
+/// Interprets the response to a UDP probe.
+///
+/// UDP response -> open. ICMP port unreachable -> closed.
+/// Other ICMP unreachable errors -> filtered. No response -> open or filtered.
+fn classify_udp_response(response: Option<Vec<u8>>) -> Result<(PortState, Reason)> {
     // No response -> open or filtered.
     let packet = match response {
         Some(packet) => packet,
-        None => return Ok((ScanResult::OpenOrFiltered, rtt)),
+        None => return Ok((PortState::OpenOrFiltered, Reason::NoResponse)),
     };
 
     let ethernet_packet =
@@ -53,24 +96,19 @@ pub fn udp_scan(
 
     match ipv4_packet.get_next_level_protocol() {
         // Any response -> open.
-        IpNextHeaderProtocols::Udp => Ok((ScanResult::Open, rtt)),
+        IpNextHeaderProtocols::Udp => Ok((PortState::Open, Reason::UdpResponse)),
         IpNextHeaderProtocols::Icmp => {
             let icmp_packet =
                 IcmpPacket::new(ipv4_packet.payload()).ok_or(ScannerError::CantCreateIcmpPacket)?;
-            let codes_1 = &[IcmpCodes::DestinationPortUnreachable];
-            let codes_2 = &[
-                IcmpCodes::DestinationHostUnreachable,
-                IcmpCodes::DestinationProtocolUnreachable,
-                IcmpCodes::NetworkAdministrativelyProhibited,
-                IcmpCodes::HostAdministrativelyProhibited,
-                IcmpCodes::CommunicationAdministrativelyProhibited,
-            ];
-            let icmp_code = icmp_packet.get_icmp_code();
-            match icmp_code {
-                // ICMP port unreachable -> closed.
-                code if codes_1.contains(&code) => Ok((ScanResult::Closed, rtt)),
-                // Other ICMP unreachable errors -> filtered.
-                code if codes_2.contains(&code) => Ok((ScanResult::Filtered, rtt)),
+            match icmp_packet.get_icmp_type() {
+                // ICMP port unreachable -> closed. Other ICMP unreachable errors -> filtered.
+                IcmpTypes::DestinationUnreachable => {
+                    let code = icmp_packet.get_icmp_code().0;
+                    Ok((
+                        interpret_icmp_unreachable(code),
+                        Reason::IcmpUnreachable(code),
+                    ))
+                }
                 // Unexpected ICMP response.
                 _ => Err(ScannerError::UnexpectedIcmpResponse.into()),
             }
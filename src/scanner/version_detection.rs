@@ -0,0 +1,374 @@
+use crate::networking::tcp::Tcp;
+use anyhow::Result;
+use std::{fmt, net::IpAddr, time::Duration};
+
+/// Service that listens on a port, as identified from its response to a probe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceInfo {
+    /// Name of the service, like the names of `service_name`, e.g. `ssh`.
+    pub name: &'static str,
+    /// Software and version of the service, e.g. `OpenSSH_8.9p1`, if it reveals them.
+    pub version: Option<String>,
+    /// Further details like the host name or the status of the response.
+    pub extra: Option<String>,
+}
+
+impl fmt::Display for ServiceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(version) = &self.version {
+            write!(f, " {}", version)?;
+        }
+        if let Some(extra) = &self.extra {
+            write!(f, " ({})", extra)?;
+        }
+        Ok(())
+    }
+}
+
+/// Request that makes a service reveal its version, and how to read it from the response.
+struct VersionProbe {
+    /// Ports the service usually listens on.
+    ports: &'static [u16],
+    /// Request sent after connecting, or `None` for services that greet on their own.
+    request: Option<&'static [u8]>,
+    /// Extracts the service from the response, or returns `None` if the response doesn't match.
+    parse: fn(&[u8]) -> Option<ServiceInfo>,
+}
+
+/// Probes of common services, a small subset of the probes of `nmap -sV`.
+///
+/// Services on other ports are only identified if they greet on their own, so the order of the
+/// probes without a request decides which parser gets the greeting first.
+///
+/// See: https://nmap.org/book/vscan-fileformat.html
+const VERSION_PROBES: [VersionProbe; 7] = [
+    VersionProbe {
+        ports: &[22, 2222],
+        request: None,
+        parse: parse_ssh,
+    },
+    VersionProbe {
+        ports: &[25, 587],
+        request: None,
+        parse: parse_smtp,
+    },
+    VersionProbe {
+        ports: &[21],
+        request: None,
+        parse: parse_ftp,
+    },
+    VersionProbe {
+        ports: &[110],
+        request: None,
+        parse: parse_pop3,
+    },
+    VersionProbe {
+        ports: &[3306],
+        request: None,
+        parse: parse_mysql,
+    },
+    VersionProbe {
+        ports: &[80, 8000, 8080],
+        request: Some(b"HEAD / HTTP/1.0\r\n\r\n"),
+        parse: parse_http,
+    },
+    VersionProbe {
+        ports: &[6379],
+        request: Some(b"INFO server\r\n"),
+        parse: parse_redis,
+    },
+];
+
+/// Connects to an open TCP port and identifies the service that listens on it.
+///
+/// The probes of the port are sent in turn, each on a new connection, until a response matches.
+/// Ports without probes are only listened to, since many services greet on their own.
+///
+/// Returns `None` if no response matches. May fail if the port can't be connected to.
+pub fn detect_version(
+    dest_ip: IpAddr,
+    dest_port: u16,
+    timeout: Duration,
+) -> Result<Option<ServiceInfo>> {
+    let probes = VERSION_PROBES
+        .iter()
+        .filter(|probe| probe.ports.contains(&dest_port))
+        .collect::<Vec<_>>();
+
+    if probes.is_empty() {
+        let greeting = Tcp::read_response(dest_ip, dest_port, timeout, None)?;
+        return Ok(identify_greeting(&greeting));
+    }
+
+    for probe in probes {
+        let response = Tcp::read_response(dest_ip, dest_port, timeout, probe.request)?;
+        if let Some(service) = (probe.parse)(&response) {
+            return Ok(Some(service));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Identifies a service from the greeting it sent without a request.
+fn identify_greeting(greeting: &[u8]) -> Option<ServiceInfo> {
+    VERSION_PROBES
+        .iter()
+        .filter(|probe| probe.request.is_none())
+        .find_map(|probe| (probe.parse)(greeting))
+}
+
+/// Returns the first line of a text response without its line break.
+fn first_line(response: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(response);
+    let line = text.lines().next()?.trim();
+    (!line.is_empty()).then(|| line.to_string())
+}
+
+/// Returns `None` for an empty string, so that missing details aren't reported as empty.
+fn non_empty(text: &str) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Parses the identification string of RFC 4253, e.g. `SSH-2.0-OpenSSH_8.9p1 Ubuntu-3ubuntu0.1`.
+fn parse_ssh(response: &[u8]) -> Option<ServiceInfo> {
+    let line = first_line(response)?;
+    let identification = line.strip_prefix("SSH-")?;
+    let (_protocol, rest) = identification.split_once('-')?;
+    let (software, comments) = rest.split_once(' ').unwrap_or((rest, ""));
+    Some(ServiceInfo {
+        name: "ssh",
+        version: non_empty(software),
+        extra: non_empty(comments),
+    })
+}
+
+/// Parses the greeting of RFC 5321, e.g. `220 mail.example.com ESMTP Postfix (Ubuntu)`.
+fn parse_smtp(response: &[u8]) -> Option<ServiceInfo> {
+    let line = first_line(response)?;
+    let greeting = line.strip_prefix("220")?.trim_start_matches(['-', ' ']);
+    let words = greeting.split_whitespace().collect::<Vec<_>>();
+    let smtp = words
+        .iter()
+        .position(|word| *word == "ESMTP" || *word == "SMTP")?;
+    Some(ServiceInfo {
+        name: "smtp",
+        version: non_empty(&words[smtp + 1..].join(" ")),
+        extra: smtp.checked_sub(1).map(|host| words[host].to_string()),
+    })
+}
+
+/// Parses the greeting of RFC 959, e.g. `220 (vsFTPd 3.0.3)`.
+///
+/// Unlike SMTP, FTP has no keyword in its greeting, so the server must mention FTP in it.
+fn parse_ftp(response: &[u8]) -> Option<ServiceInfo> {
+    let line = first_line(response)?;
+    let greeting = line.strip_prefix("220")?.trim_start_matches(['-', ' ']);
+    if !greeting.to_ascii_lowercase().contains("ftp") {
+        return None;
+    }
+    Some(ServiceInfo {
+        name: "ftp",
+        version: non_empty(greeting.trim_start_matches('(').trim_end_matches(')')),
+        extra: None,
+    })
+}
+
+/// Parses the greeting of RFC 1939, e.g. `+OK Dovecot ready.`.
+fn parse_pop3(response: &[u8]) -> Option<ServiceInfo> {
+    let line = first_line(response)?;
+    let greeting = line.strip_prefix("+OK")?;
+    Some(ServiceInfo {
+        name: "pop3",
+        version: non_empty(greeting.trim_end_matches("ready.")),
+        extra: None,
+    })
+}
+
+/// Parses the handshake packet that MySQL and MariaDB servers greet with.
+///
+/// It consists of a 3 byte length, a sequence number of 0, the protocol version 10 and the
+/// version of the server as a null-terminated string, e.g. `8.0.32`.
+fn parse_mysql(response: &[u8]) -> Option<ServiceInfo> {
+    match response.get(3..5)? {
+        [0, 10] => {}
+        _ => return None,
+    }
+    let version = response[5..].split(|byte| *byte == 0).next()?;
+    if version.is_empty() || !version.is_ascii() {
+        return None;
+    }
+    Some(ServiceInfo {
+        name: "mysql",
+        version: non_empty(&String::from_utf8_lossy(version)),
+        extra: None,
+    })
+}
+
+/// Parses the response to a HEAD request, e.g. `HTTP/1.1 200 OK` with a `Server` header.
+fn parse_http(response: &[u8]) -> Option<ServiceInfo> {
+    let text = String::from_utf8_lossy(response);
+    let mut lines = text.lines();
+    let status = lines.next()?.strip_prefix("HTTP/")?;
+    let (_version, status) = status.split_once(' ')?;
+    let server = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("server"))
+        .and_then(|(_, value)| non_empty(value));
+    Some(ServiceInfo {
+        name: "http",
+        version: server,
+        extra: non_empty(status),
+    })
+}
+
+/// Parses the response to `INFO server`, e.g. a bulk string with `redis_version:7.0.5`.
+///
+/// Servers that require authentication refuse the command, which identifies them all the same.
+fn parse_redis(response: &[u8]) -> Option<ServiceInfo> {
+    let text = String::from_utf8_lossy(response);
+    if text.starts_with("-NOAUTH") {
+        return Some(ServiceInfo {
+            name: "redis",
+            version: None,
+            extra: Some("authentication required".to_string()),
+        });
+    }
+    if !text.starts_with('$') {
+        return None;
+    }
+    let version = text
+        .lines()
+        .find_map(|line| line.strip_prefix("redis_version:"))?;
+    let mode = text
+        .lines()
+        .find_map(|line| line.strip_prefix("redis_mode:"))
+        .and_then(non_empty);
+    Some(ServiceInfo {
+        name: "redis",
+        version: non_empty(version),
+        extra: mode,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Write, net::TcpListener, thread};
+
+    /// Returns the service that the probe of the port identifies in the response.
+    fn parse(port: u16, response: &[u8]) -> Option<ServiceInfo> {
+        let probe = VERSION_PROBES
+            .iter()
+            .find(|probe| probe.ports.contains(&port))
+            .unwrap();
+        (probe.parse)(response)
+    }
+
+    fn service(name: &'static str, version: Option<&str>, extra: Option<&str>) -> ServiceInfo {
+        ServiceInfo {
+            name,
+            version: version.map(String::from),
+            extra: extra.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_parse_responses() {
+        assert_eq!(
+            parse(22, b"SSH-2.0-OpenSSH_8.9p1 Ubuntu-3ubuntu0.1\r\n"),
+            Some(service(
+                "ssh",
+                Some("OpenSSH_8.9p1"),
+                Some("Ubuntu-3ubuntu0.1")
+            ))
+        );
+        assert_eq!(
+            parse(25, b"220 mail.example.com ESMTP Postfix (Ubuntu)\r\n"),
+            Some(service(
+                "smtp",
+                Some("Postfix (Ubuntu)"),
+                Some("mail.example.com")
+            ))
+        );
+        assert_eq!(
+            parse(21, b"220 (vsFTPd 3.0.3)\r\n"),
+            Some(service("ftp", Some("vsFTPd 3.0.3"), None))
+        );
+        assert_eq!(
+            parse(110, b"+OK Dovecot ready.\r\n"),
+            Some(service("pop3", Some("Dovecot"), None))
+        );
+        assert_eq!(
+            parse(3306, b"\x4a\x00\x00\x00\x0a8.0.32\x00\x08\x00\x00\x00"),
+            Some(service("mysql", Some("8.0.32"), None))
+        );
+        assert_eq!(
+            parse(
+                80,
+                b"HTTP/1.1 301 Moved Permanently\r\nserver: nginx/1.18.0\r\nLocation: /\r\n\r\n"
+            ),
+            Some(service(
+                "http",
+                Some("nginx/1.18.0"),
+                Some("301 Moved Permanently")
+            ))
+        );
+        assert_eq!(
+            parse(
+                6379,
+                b"$91\r\n# Server\r\nredis_version:7.0.5\r\nredis_mode:standalone\r\n"
+            ),
+            Some(service("redis", Some("7.0.5"), Some("standalone")))
+        );
+
+        // Responses of other services or without details.
+        assert_eq!(parse(22, b"220 (vsFTPd 3.0.3)\r\n"), None);
+        assert_eq!(parse(80, b"SSH-2.0-OpenSSH_8.9p1\r\n"), None);
+        assert_eq!(parse(3306, b"\x4a\x00\x00\x00\x09"), None);
+        assert_eq!(parse(21, b""), None);
+        assert_eq!(
+            parse(80, b"HTTP/1.0 200 OK\r\n\r\nServer: in the body"),
+            Some(service("http", None, Some("200 OK")))
+        );
+    }
+
+    #[test]
+    fn test_identify_greeting() {
+        // An SMTP greeting also starts with 220, but isn't taken for FTP.
+        assert_eq!(
+            identify_greeting(b"220 mx.example.com ESMTP\r\n"),
+            Some(service("smtp", None, Some("mx.example.com")))
+        );
+        assert_eq!(
+            identify_greeting(b"220 Microsoft FTP Service\r\n"),
+            Some(service("ftp", Some("Microsoft FTP Service"), None))
+        );
+        assert_eq!(identify_greeting(b"HTTP/1.1 400 Bad Request\r\n"), None);
+    }
+
+    #[test]
+    fn test_detect_version() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let local_addr = listener.local_addr()?;
+
+        thread::spawn(move || {
+            if let Ok((mut stream, _)) = listener.accept() {
+                let _ = stream.write_all(b"SSH-2.0-dropbear_2022.83\r\n");
+            }
+        });
+
+        // The port has no probes, so the greeting is listened to.
+        let timeout = Duration::from_millis(500);
+        let detected = detect_version(local_addr.ip(), local_addr.port(), timeout)?;
+        assert_eq!(
+            detected,
+            Some(service("ssh", Some("dropbear_2022.83"), None))
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use pnet::util::MacAddr;
+
+/// Vendors of common network devices by their organizationally unique identifier (OUI), the
+/// first three bytes of a MAC address. A small subset of the IEEE registry, sorted by OUI. See:
+/// https://standards-oui.ieee.org/
+const VENDORS: [([u8; 3], &str); 90] = [
+    ([0x00, 0x00, 0x0c], "Cisco"),
+    ([0x00, 0x00, 0x48], "Epson"),
+    ([0x00, 0x00, 0x5e], "IANA"),
+    ([0x00, 0x01, 0x42], "Cisco"),
+    ([0x00, 0x01, 0xe6], "Hewlett-Packard"),
+    ([0x00, 0x03, 0x93], "Apple"),
+    ([0x00, 0x04, 0x0e], "AVM"),
+    ([0x00, 0x04, 0x4b], "Nvidia"),
+    ([0x00, 0x05, 0x02], "Apple"),
+    ([0x00, 0x05, 0x5d], "D-Link"),
+    ([0x00, 0x05, 0x69], "VMware"),
+    ([0x00, 0x08, 0x9b], "QNAP"),
+    ([0x00, 0x09, 0x0f], "Fortinet"),
+    ([0x00, 0x09, 0x5b], "Netgear"),
+    ([0x00, 0x0a, 0x95], "Apple"),
+    ([0x00, 0x0c, 0x29], "VMware"),
+    ([0x00, 0x0d, 0x93], "Apple"),
+    ([0x00, 0x0d, 0xb9], "PC Engines"),
+    ([0x00, 0x0e, 0x58], "Sonos"),
+    ([0x00, 0x0f, 0xb5], "Netgear"),
+    ([0x00, 0x10, 0x18], "Broadcom"),
+    ([0x00, 0x11, 0x32], "Synology"),
+    ([0x00, 0x12, 0xfb], "Samsung"),
+    ([0x00, 0x13, 0x02], "Intel"),
+    ([0x00, 0x14, 0x22], "Dell"),
+    ([0x00, 0x14, 0x6c], "Netgear"),
+    ([0x00, 0x14, 0xbf], "Linksys"),
+    ([0x00, 0x15, 0x17], "Intel"),
+    ([0x00, 0x15, 0x5d], "Microsoft"),
+    ([0x00, 0x15, 0x6d], "Ubiquiti"),
+    ([0x00, 0x16, 0x32], "Samsung"),
+    ([0x00, 0x16, 0x3e], "Xen"),
+    ([0x00, 0x17, 0x88], "Philips Hue"),
+    ([0x00, 0x17, 0xf2], "Apple"),
+    ([0x00, 0x18, 0x39], "Linksys"),
+    ([0x00, 0x1a, 0x11], "Google"),
+    ([0x00, 0x1a, 0xa0], "Dell"),
+    ([0x00, 0x1b, 0x21], "Intel"),
+    ([0x00, 0x1b, 0x63], "Apple"),
+    ([0x00, 0x1b, 0xa9], "Brother"),
+    ([0x00, 0x1c, 0x14], "VMware"),
+    ([0x00, 0x1c, 0x42], "Parallels"),
+    ([0x00, 0x1c, 0xb3], "Apple"),
+    ([0x00, 0x1d, 0x0f], "TP-Link"),
+    ([0x00, 0x1d, 0x7e], "Linksys"),
+    ([0x00, 0x1d, 0xd8], "Microsoft"),
+    ([0x00, 0x1e, 0x0b], "Hewlett-Packard"),
+    ([0x00, 0x1e, 0x58], "D-Link"),
+    ([0x00, 0x1e, 0x67], "Intel"),
+    ([0x00, 0x1e, 0xc2], "Apple"),
+    ([0x00, 0x1e, 0xc9], "Dell"),
+    ([0x00, 0x21, 0x19], "Samsung"),
+    ([0x00, 0x23, 0xdf], "Apple"),
+    ([0x00, 0x24, 0xe8], "Dell"),
+    ([0x00, 0x25, 0x00], "Apple"),
+    ([0x00, 0x25, 0x90], "Supermicro"),
+    ([0x00, 0x26, 0xbb], "Apple"),
+    ([0x00, 0x40, 0x96], "Cisco"),
+    ([0x00, 0x50, 0x56], "VMware"),
+    ([0x00, 0x50, 0xf2], "Microsoft"),
+    ([0x00, 0x80, 0x77], "Brother"),
+    ([0x00, 0xe0, 0x4c], "Realtek"),
+    ([0x08, 0x00, 0x27], "VirtualBox"),
+    ([0x14, 0xcc, 0x20], "TP-Link"),
+    ([0x18, 0x03, 0x73], "Dell"),
+    ([0x18, 0xe8, 0x29], "Ubiquiti"),
+    ([0x24, 0x0a, 0xc4], "Espressif"),
+    ([0x24, 0x5e, 0xbe], "QNAP"),
+    ([0x24, 0xa4, 0x3c], "Ubiquiti"),
+    ([0x28, 0xcd, 0xc1], "Raspberry Pi"),
+    ([0x30, 0xae, 0xa4], "Espressif"),
+    ([0x3c, 0x07, 0x54], "Apple"),
+    ([0x3c, 0x5a, 0xb4], "Google"),
+    ([0x3c, 0xa6, 0x2f], "AVM"),
+    ([0x3c, 0xd9, 0x2b], "Hewlett-Packard"),
+    ([0x44, 0xd9, 0xe7], "Ubiquiti"),
+    ([0x50, 0xc7, 0xbf], "TP-Link"),
+    ([0x52, 0x54, 0x00], "QEMU"),
+    ([0x5c, 0xaa, 0xfd], "Sonos"),
+    ([0x5c, 0xcf, 0x7f], "Espressif"),
+    ([0x74, 0xda, 0x38], "Edimax"),
+    ([0xa0, 0x40, 0xa0], "Netgear"),
+    ([0xb8, 0x27, 0xeb], "Raspberry Pi"),
+    ([0xb8, 0xe9, 0x37], "Sonos"),
+    ([0xc8, 0x0e, 0x14], "AVM"),
+    ([0xdc, 0xa6, 0x32], "Raspberry Pi"),
+    ([0xe4, 0x5f, 0x01], "Raspberry Pi"),
+    ([0xf0, 0x18, 0x98], "Apple"),
+    ([0xf4, 0xf5, 0xd8], "Google"),
+    ([0xfc, 0xfb, 0xfb], "Cisco"),
+];
+
+/// Returns the vendor that the MAC address was assigned to, e.g. `Raspberry Pi` for `b8:27:eb:…`.
+///
+/// Devices may change their MAC address, e.g. phones that randomize it per network,
+/// so the vendor is only a hint.
+pub fn vendor_for_mac(mac: MacAddr) -> Option<&'static str> {
+    let oui = [mac.0, mac.1, mac.2];
+    VENDORS
+        .binary_search_by_key(&oui, |(oui, _)| *oui)
+        .ok()
+        .map(|index| VENDORS[index].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vendors_are_sorted() {
+        assert!(VENDORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn test_vendor_for_mac() {
+        let mac = MacAddr::new(0xb8, 0x27, 0xeb, 0x12, 0x34, 0x56);
+        assert_eq!(vendor_for_mac(mac), Some("Raspberry Pi"));
+        let mac = MacAddr::new(0x00, 0x50, 0x56, 0xc0, 0x00, 0x08);
+        assert_eq!(vendor_for_mac(mac), Some("VMware"));
+        let mac = MacAddr::new(0x08, 0x00, 0x27, 0x00, 0x00, 0x01);
+        assert_eq!(vendor_for_mac(mac), Some("VirtualBox"));
+
+        // Only the first three bytes count.
+        let mac = MacAddr::new(0x00, 0x00, 0x0c, 0xff, 0xff, 0xff);
+        assert_eq!(vendor_for_mac(mac), Some("Cisco"));
+
+        // Randomized and unknown addresses have no vendor.
+        assert_eq!(vendor_for_mac(MacAddr::new(0x02, 0, 0, 0, 0, 1)), None);
+        assert_eq!(vendor_for_mac(MacAddr::zero()), None);
+    }
+}